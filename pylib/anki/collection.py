@@ -125,14 +125,11 @@ class Collection:
         self.modSchema(check=True)
         self.clearUndo()
 
-        v2Sched = V2Scheduler(self)
-
         if ver == 1:
-            v2Sched.moveToV1()
+            V2Scheduler(self).moveToV1()
+            self.conf["schedVer"] = ver
         else:
-            v2Sched.moveToV2()
-
-        self.conf["schedVer"] = ver
+            self.backend.upgrade_scheduler()
         self.setMod()
 
         self._loadScheduler()
@@ -270,6 +267,9 @@ class Collection:
                 media_folder_path=media_dir,
                 media_db_path=media_db,
                 log_path=log_path,
+                # use the default
+                busy_timeout_millis=0,
+                encryption_key="",
             )
         else:
             self.media.connect()
@@ -668,9 +668,10 @@ select id from notes where mid = ?) limit 1"""
 
     def optimize(self) -> None:
         self.save(trx=False)
-        self.db.execute("vacuum")
-        self.db.execute("analyze")
-        self.db.begin()
+        try:
+            self.backend.optimize_database()
+        finally:
+            self.db.begin()
 
     # Logging
     ##########################################################################
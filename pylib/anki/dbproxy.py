@@ -39,6 +39,16 @@ class DBProxy:
     def rollback(self) -> None:
         self._backend.db_rollback()
 
+    # Attached databases
+    ###############
+
+    def attach(self, path: str, alias: str) -> None:
+        "Make PATH's tables available as ALIAS.table. Commits any open transaction."
+        self._backend.attach_database(path=path, alias=alias)
+
+    def detach(self, alias: str) -> None:
+        self._backend.detach_database(alias)
+
     # Querying
     ################
 
@@ -41,6 +41,9 @@ class Anki2Importer(Importer):
             self._import()
         finally:
             self.src.close(save=False, downgrade=False)
+        # the source file can only be attached once it's closed
+        self._importRevlog()
+        self.dst.optimize()
 
     def _prepareFiles(self) -> None:
         importingV2 = self.file.endswith(".anki21")
@@ -65,7 +68,6 @@ class Anki2Importer(Importer):
         self._importCards()
         self._importStaticMedia()
         self._postImport()
-        self.dst.optimize()
 
     # Notes
     ######################################################################
@@ -307,7 +309,8 @@ class Anki2Importer(Importer):
             self._cards[(guid, ord)] = cid
         # loop through src
         cards = []
-        revlog = []
+        # (src cid, dst cid) of added cards, for copying their revlog
+        self._importedCids: List[Tuple[int, int]] = []
         cnt = 0
         usn = self.dst.usn()
         aheadBy = self.src.sched.today - self.dst.sched.today
@@ -365,12 +368,7 @@ class Anki2Importer(Importer):
                 if card[6] == CARD_TYPE_LRN:
                     card[6] = CARD_TYPE_NEW
             cards.append(card)
-            # we need to import revlog, rewriting card ids and bumping usn
-            for rev in self.src.db.execute("select * from revlog where cid = ?", scid):
-                rev = list(rev)
-                rev[1] = card[0]
-                rev[2] = self.dst.usn()
-                revlog.append(rev)
+            self._importedCids.append((scid, card[0]))
             cnt += 1
         # apply
         self.dst.db.executemany(
@@ -378,11 +376,38 @@ class Anki2Importer(Importer):
 insert or ignore into cards values (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)""",
             cards,
         )
-        self.dst.db.executemany(
-            """
-insert or ignore into revlog values (?,?,?,?,?,?,?,?,?)""",
-            revlog,
+
+    # Revlog
+    ######################################################################
+
+    def _importRevlog(self) -> None:
+        "Copy the review history of added cards, rewriting card ids and bumping usn."
+        db = self.dst.db
+        db.execute(
+            "create temp table import_cids (src integer primary key, dst integer)"
         )
+        db.executemany("insert into import_cids values (?,?)", self._importedCids)
+        if self.mustResetLearning:
+            # closing the source discarded its scheduler upgrade, so apply
+            # the upgrade's revlog change here
+            ease = (
+                "(case when r.ease in (2,3) and r.type in (0,2) "
+                "then r.ease+1 else r.ease end)"
+            )
+        else:
+            ease = "r.ease"
+        db.attach(self.file, "import_src")
+        try:
+            db.execute(
+                f"""
+insert or ignore into revlog
+select r.id, c.dst, ?, {ease}, r.ivl, r.lastIvl, r.factor, r.time, r.type
+from import_src.revlog r, import_cids c where r.cid = c.src""",
+                self.dst.usn(),
+            )
+        finally:
+            db.detach("import_src")
+            db.execute("drop table import_cids")
 
     # Media
     ######################################################################
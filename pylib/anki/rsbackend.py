@@ -17,7 +17,7 @@ import enum
 import json
 import os
 from dataclasses import dataclass
-from typing import TYPE_CHECKING, Any, Dict, List, Optional, Sequence, Union
+from typing import TYPE_CHECKING, Any, Dict, List, Optional, Sequence, Tuple, Union
 
 import ankirspy  # pytype: disable=import-error
 
@@ -119,6 +119,15 @@ class InvalidInput(StringError):
     pass
 
 
+class SearchError(InvalidInput):
+    def kind(self) -> pb.SearchError.SearchErrorKindValue:
+        return self.args[1].kind
+
+    def span(self) -> Tuple[int, int]:
+        "Byte offsets of the problematic text in the UTF-8 encoded search."
+        return (self.args[1].start, self.args[1].end)
+
+
 def proto_exception_to_native(err: pb.BackendError) -> Exception:
     val = err.WhichOneof("value")
     if val == "interrupted":
@@ -135,6 +144,8 @@ def proto_exception_to_native(err: pb.BackendError) -> Exception:
         return TemplateError(err.localized)
     elif val == "invalid_input":
         return InvalidInput(err.localized)
+    elif val == "search_error":
+        return SearchError(err.localized, err.search_error)
     elif val == "json_error":
         return StringError(err.localized)
     elif val == "not_found_error":
@@ -156,6 +167,8 @@ NormalSyncProgress = pb.NormalSyncProgress
 DatabaseCheckProgress = pb.DatabaseCheckProgress
 
 FormatTimeSpanContext = pb.FormatTimespanIn.Context
+UnburyCurrentDeckMode = pb.UnburyCardsInCurrentDeckIn.Mode
+CustomStudyRequest = pb.CustomStudyIn
 
 
 class ProgressKind(enum.Enum):
@@ -7,7 +7,7 @@ import pprint
 import random
 import time
 from heapq import *
-from typing import Any, Callable, Dict, List, Optional, Sequence, Set, Tuple, Union
+from typing import Any, Callable, Dict, List, Optional, Sequence, Tuple, Union
 
 import anki  # pylint: disable=unused-import
 from anki import hooks
@@ -19,6 +19,7 @@ from anki.rsbackend import (
     DeckTreeNode,
     FormatTimeSpanContext,
     SchedTimingToday,
+    UnburyCurrentDeckMode,
     from_json_bytes,
 )
 from anki.utils import ids2str, intTime
@@ -1047,11 +1048,7 @@ end)
     def rebuildDyn(self, did: Optional[int] = None) -> Optional[int]:
         "Rebuild a dynamic deck."
         did = did or self.col.decks.selected()
-        deck = self.col.decks.get(did)
-        assert deck["dyn"]
-        # move any existing cards back first, then fill
-        self.emptyDyn(did)
-        cnt = self._fillDyn(deck)
+        cnt = self.col.backend.rebuild_filtered_deck(did)
         if not cnt:
             return None
         # and change to our new deck
@@ -1078,7 +1075,8 @@ end)
 
     def emptyDyn(self, did: Optional[int], lim: Optional[str] = None) -> None:
         if not lim:
-            lim = "did = %s" % did
+            self.col.backend.empty_filtered_deck(did)
+            return
         self.col.log(self.col.db.list("select id from cards where %s" % lim))
 
         self.col.db.execute(
@@ -1461,36 +1459,16 @@ end)
     def suspendCards(self, ids: List[int]) -> None:
         "Suspend cards."
         self.col.log(ids)
-        self.col.db.execute(
-            f"update cards set queue={QUEUE_TYPE_SUSPENDED},mod=?,usn=? where id in "
-            + ids2str(ids),
-            intTime(),
-            self.col.usn(),
-        )
+        self.col.backend.suspend_cards(ids)
 
     def unsuspendCards(self, ids: List[int]) -> None:
         "Unsuspend cards."
         self.col.log(ids)
-        self.col.db.execute(
-            (
-                f"update cards set %s,mod=?,usn=? where queue = {QUEUE_TYPE_SUSPENDED} and id in %s"
-            )
-            % (self._restoreQueueSnippet, ids2str(ids)),
-            intTime(),
-            self.col.usn(),
-        )
+        self.col.backend.unsuspend_cards(ids)
 
     def buryCards(self, cids: List[int], manual: bool = True) -> None:
-        queue = manual and QUEUE_TYPE_MANUALLY_BURIED or QUEUE_TYPE_SIBLING_BURIED
         self.col.log(cids)
-        self.col.db.execute(
-            """
-update cards set queue=?,mod=?,usn=? where id in """
-            + ids2str(cids),
-            queue,
-            intTime(),
-            self.col.usn(),
-        )
+        self.col.backend.bury_cards(card_ids=cids, manual=manual)
 
     def buryNote(self, nid: int) -> None:
         "Bury all cards for note until next session."
@@ -1513,28 +1491,15 @@ update cards set queue=?,mod=?,usn=? where id in """
 
     def unburyCardsForDeck(self, type: str = "all") -> None:
         if type == "all":
-            queue = (
-                f"queue in ({QUEUE_TYPE_SIBLING_BURIED}, {QUEUE_TYPE_MANUALLY_BURIED})"
-            )
+            mode = UnburyCurrentDeckMode.ALL
         elif type == "manual":
-            queue = f"queue = {QUEUE_TYPE_MANUALLY_BURIED}"
+            mode = UnburyCurrentDeckMode.USER_ONLY
         elif type == "siblings":
-            queue = f"queue = {QUEUE_TYPE_SIBLING_BURIED}"
+            mode = UnburyCurrentDeckMode.SCHED_ONLY
         else:
             raise Exception("unknown type")
 
-        self.col.log(
-            self.col.db.list(
-                "select id from cards where %s and did in %s"
-                % (queue, self._deckLimit())
-            )
-        )
-        self.col.db.execute(
-            "update cards set mod=?,usn=?,%s where %s and did in %s"
-            % (self._restoreQueueSnippet, queue, self._deckLimit()),
-            intTime(),
-            self.col.usn(),
-        )
+        self.col.backend.unbury_cards_in_current_deck(mode)
 
     # Sibling spacing
     ##########################################################################
@@ -1577,35 +1542,12 @@ and (queue={QUEUE_TYPE_NEW} or (queue={QUEUE_TYPE_REV} and due<=?))""",
 
     def forgetCards(self, ids: List[int]) -> None:
         "Put cards at the end of the new queue."
-        self.remFromDyn(ids)
-        self.col.db.execute(
-            f"update cards set type={CARD_TYPE_NEW},queue={QUEUE_TYPE_NEW},ivl=0,due=0,odue=0,factor=?"
-            " where id in " + ids2str(ids),
-            STARTING_FACTOR,
-        )
-        pmax = (
-            self.col.db.scalar(f"select max(due) from cards where type={CARD_TYPE_NEW}")
-            or 0
-        )
-        # takes care of mod + usn
-        self.sortCards(ids, start=pmax + 1)
+        self.col.backend.forget_cards(ids)
         self.col.log(ids)
 
     def reschedCards(self, ids: List[int], imin: int, imax: int) -> None:
         "Put cards in review queue with a new interval in days (min, max)."
-        d = []
-        t = self.today
-        mod = intTime()
-        for id in ids:
-            r = random.randint(imin, imax)
-            d.append((max(1, r), r + t, self.col.usn(), mod, STARTING_FACTOR, id,))
-        self.remFromDyn(ids)
-        self.col.db.executemany(
-            f"""
-update cards set type={CARD_TYPE_REV},queue={QUEUE_TYPE_REV},ivl=?,due=?,odue=0,
-usn=?,mod=?,factor=? where id=?""",
-            d,
-        )
+        self.col.backend.set_due_date(card_ids=ids, days=f"{imin}-{imax}")
         self.col.log(ids)
 
     def resetCards(self, ids: List[int]) -> None:
@@ -1636,52 +1578,13 @@ usn=?,mod=?,factor=? where id=?""",
         shuffle: bool = False,
         shift: bool = False,
     ) -> None:
-        scids = ids2str(cids)
-        now = intTime()
-        nids = []
-        nidsSet: Set[int] = set()
-        for id in cids:
-            nid = self.col.db.scalar("select nid from cards where id = ?", id)
-            if nid not in nidsSet:
-                nids.append(nid)
-                nidsSet.add(nid)
-        if not nids:
-            # no new cards
-            return
-        # determine nid ordering
-        due = {}
-        if shuffle:
-            random.shuffle(nids)
-        for c, nid in enumerate(nids):
-            due[nid] = start + c * step
-        # pylint: disable=undefined-loop-variable
-        high = start + c * step
-        # shift?
-        if shift:
-            low = self.col.db.scalar(
-                f"select min(due) from cards where due >= ? and type = {CARD_TYPE_NEW} "
-                "and id not in %s" % scids,
-                start,
-            )
-            if low is not None:
-                shiftby = high - low + 1
-                self.col.db.execute(
-                    f"""
-update cards set mod=?, usn=?, due=due+? where id not in %s
-and due >= ? and queue = {QUEUE_TYPE_NEW}"""
-                    % scids,
-                    now,
-                    self.col.usn(),
-                    shiftby,
-                    low,
-                )
-        # reorder cards
-        d = []
-        for id, nid in self.col.db.execute(
-            f"select id, nid from cards where type = {CARD_TYPE_NEW} and id in " + scids
-        ):
-            d.append((due[nid], now, self.col.usn(), id))
-        self.col.db.executemany("update cards set due=?,mod=?,usn=? where id = ?", d)
+        self.col.backend.reposition_new_cards(
+            card_ids=cids,
+            starting_from=start,
+            step_size=step,
+            randomize=shuffle,
+            shift_existing=shift,
+        )
 
     def randomizeCards(self, did: int) -> None:
         cids = self.col.db.list("select id from cards where did = ?", did)
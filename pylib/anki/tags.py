@@ -71,6 +71,10 @@ class TagManager:
             clear=clear,
         )
 
+    def clearUnused(self) -> int:
+        "Remove tags not used by any note. Returns the number removed."
+        return self.col.backend.clear_unused_tags()
+
     def byDeck(self, did, children=False) -> List[str]:
         basequery = "select n.tags from cards c, notes n WHERE c.nid = n.id"
         if not children:
@@ -75,6 +75,26 @@ def test_anki2_mediadupes():
     assert "_" in n.fields[0]
 
 
+def test_anki2_revlog():
+    tmp = getEmptyCol()
+    n = tmp.newNote()
+    n["Front"] = "one"
+    tmp.addNote(n)
+    tmp.reset()
+    tmp.sched.answerCard(tmp.sched.getCard(), 3)
+    tmp.close()
+    empty = getEmptyCol()
+    imp = Anki2Importer(empty, tmp.path)
+    imp.run()
+    # the review is attached to the imported card
+    cid = empty.db.scalar("select id from cards")
+    assert empty.db.list("select cid from revlog") == [cid]
+    # and importing again doesn't duplicate it
+    imp = Anki2Importer(empty, tmp.path)
+    imp.run()
+    assert empty.db.scalar("select count() from revlog") == 1
+
+
 def test_apkg():
     tmp = getEmptyCol()
     apkg = str(os.path.join(testDir, "support/media.apkg"))
@@ -1653,7 +1653,7 @@ update cards set usn=?, mod=?, did=? where id in """
         self.editor.saveNow(self._clearUnusedTags)
 
     def _clearUnusedTags(self):
-        self.col.tags.registerNotes()
+        self.col.tags.clearUnused()
 
     # Suspending
     ######################################################################
@@ -5,6 +5,7 @@
 import aqt
 from anki.consts import *
 from anki.lang import _
+from anki.rsbackend import CustomStudyRequest, InvalidInput
 from aqt.qt import *
 from aqt.utils import showInfo, showWarning
 
@@ -124,69 +125,47 @@ class CustomStudy(QDialog):
         i = self.radioIdx
         spin = f.spin.value()
         if i == RADIO_NEW:
-            self.deck["extendNew"] = spin
-            self.mw.col.decks.save(self.deck)
-            self.mw.col.sched.extendLimits(spin, 0)
-            self.mw.reset()
-            return QDialog.accept(self)
+            request = CustomStudyRequest(new_limit_delta=spin)
         elif i == RADIO_REV:
-            self.deck["extendRev"] = spin
-            self.mw.col.decks.save(self.deck)
-            self.mw.col.sched.extendLimits(0, spin)
-            self.mw.reset()
-            return QDialog.accept(self)
-        elif i == RADIO_CRAM:
-            tags = self._getTags()
-        # the rest create a filtered deck
-        cur = self.mw.col.decks.byName(_("Custom Study Session"))
-        if cur:
-            if not cur["dyn"]:
-                showInfo("Please rename the existing Custom Study deck first.")
-                return QDialog.accept(self)
-            else:
-                # safe to empty
-                self.mw.col.sched.emptyDyn(cur["id"])
-                # reuse; don't delete as it may have children
-                dyn = cur
-                self.mw.col.decks.select(cur["id"])
-        else:
-            did = self.mw.col.decks.newDyn(_("Custom Study Session"))
-            dyn = self.mw.col.decks.get(did)
-        # and then set various options
-        if i == RADIO_FORGOT:
-            dyn["terms"][0] = ["rated:%d:1" % spin, DYN_MAX_SIZE, DYN_RANDOM]
-            dyn["resched"] = False
+            request = CustomStudyRequest(review_limit_delta=spin)
+        elif i == RADIO_FORGOT:
+            request = CustomStudyRequest(forgot_days=spin)
         elif i == RADIO_AHEAD:
-            dyn["terms"][0] = ["prop:due<=%d" % spin, DYN_MAX_SIZE, DYN_DUE]
-            dyn["resched"] = True
+            request = CustomStudyRequest(review_ahead_days=spin)
         elif i == RADIO_PREVIEW:
-            dyn["terms"][0] = ["is:new added:%s" % spin, DYN_MAX_SIZE, DYN_OLDEST]
-            dyn["resched"] = False
-        elif i == RADIO_CRAM:
+            request = CustomStudyRequest(preview_days=spin)
+        else:
+            self._getTags()
             type = f.cardType.currentRow()
             if type == TYPE_NEW:
-                terms = "is:new "
-                ord = DYN_ADDED
-                dyn["resched"] = True
+                kind = CustomStudyRequest.Cram.CRAM_KIND_NEW
             elif type == TYPE_DUE:
-                terms = "is:due "
-                ord = DYN_DUE
-                dyn["resched"] = True
+                kind = CustomStudyRequest.Cram.CRAM_KIND_DUE
             elif type == TYPE_REVIEW:
-                terms = "-is:new "
-                ord = DYN_RANDOM
-                dyn["resched"] = True
+                kind = CustomStudyRequest.Cram.CRAM_KIND_REVIEW
             else:
-                terms = ""
-                ord = DYN_RANDOM
-                dyn["resched"] = False
-            dyn["terms"][0] = [(terms + tags).strip(), spin, ord]
-        # add deck limit
-        dyn["terms"][0][0] = 'deck:"%s" %s ' % (self.deck["name"], dyn["terms"][0][0])
-        self.mw.col.decks.save(dyn)
-        # generate cards
+                kind = CustomStudyRequest.Cram.CRAM_KIND_ALL
+            deck = self.mw.col.decks.get(self.deck["id"])
+            request = CustomStudyRequest(
+                cram=CustomStudyRequest.Cram(
+                    kind=kind,
+                    card_limit=spin,
+                    tags_to_include=deck.get("activeTags", []),
+                    tags_to_exclude=deck.get("inactiveTags", []),
+                )
+            )
+
+        try:
+            count = self.mw.col.backend.custom_study(request)
+        except InvalidInput as e:
+            showInfo(str(e))
+            return QDialog.accept(self)
+
+        if i in (RADIO_NEW, RADIO_REV):
+            self.mw.reset()
+            return QDialog.accept(self)
         self.created_custom_study = True
-        if not self.mw.col.sched.rebuildDyn():
+        if not count:
             return showWarning(_("No cards matched the criteria you provided."))
         self.mw.moveToState("overview")
         QDialog.accept(self)
@@ -4,7 +4,7 @@
 from __future__ import annotations
 
 import aqt
-from anki.rsbackend import DatabaseCheckProgress, ProgressKind
+from anki.rsbackend import DatabaseCheckProgress, Interrupted, ProgressKind
 from aqt.qt import *
 from aqt.utils import showText, tooltip
 
@@ -15,6 +15,8 @@ def on_progress(mw: aqt.main.AnkiQt):
         return
 
     assert isinstance(progress.val, DatabaseCheckProgress)
+    if mw.progress.want_cancel():
+        mw.col.backend.set_wants_abort()
     mw.progress.update(
         process=False,
         label=progress.val.stage,
@@ -33,12 +35,16 @@ def check_db(mw: aqt.AnkiQt) -> None:
 
     def on_future_done(fut):
         timer.stop()
-        ret, ok = fut.result()
-
-        if not ok:
-            showText(ret)
+        try:
+            ret, ok = fut.result()
+        except Interrupted:
+            # cancelled by the user; the changes were rolled back
+            pass
         else:
-            tooltip(ret)
+            if not ok:
+                showText(ret)
+            else:
+                tooltip(ret)
 
         # if an error has directed the user to check the database,
         # silently clean up any broken reset hooks which distract from
@@ -3,11 +3,12 @@
 # License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 from operator import itemgetter
-from typing import Dict, Union
+from typing import Dict
 
 import aqt
 from anki.consts import NEW_CARDS_RANDOM
 from anki.lang import _, ngettext
+from anki.rsbackend import InvalidInput
 from aqt import gui_hooks
 from aqt.qt import *
 from aqt.utils import (
@@ -172,13 +173,7 @@ class DeckConf(QDialog):
     ##################################################
 
     def listToUser(self, l):
-        def num_to_user(n: Union[int, float]):
-            if n == round(n):
-                return str(int(n))
-            else:
-                return str(n)
-
-        return " ".join(map(num_to_user, l))
+        return self.mw.col.backend.format_learning_steps(l)
 
     def parentLimText(self, type="new"):
         # top level?
@@ -258,21 +253,16 @@ class DeckConf(QDialog):
     ##################################################
 
     def updateList(self, conf, key, w, minSize=1):
-        items = str(w.text()).split(" ")
-        ret = []
-        for i in items:
-            if not i:
-                continue
-            try:
-                i = float(i)
-                assert i > 0
-                if i == int(i):
-                    i = int(i)
-                ret.append(i)
-            except:
-                # invalid, don't update
-                showWarning(_("Steps must be numbers."))
-                return
+        try:
+            ret = self.mw.col.backend.parse_learning_steps(str(w.text()))
+        except InvalidInput:
+            # invalid, don't update
+            showWarning(
+                _("Steps must be numbers, optionally followed by s, m, h or d.")
+            )
+            return
+        # store whole minutes as integers, like older versions
+        ret = [int(i) if i == int(i) else i for i in ret]
         if len(ret) < minSize:
             showWarning(_("At least one step is required."))
             return
@@ -9,11 +9,8 @@ import gc
 import os
 import re
 import signal
-import time
 import weakref
-import zipfile
 from argparse import Namespace
-from threading import Thread
 from typing import Any, Callable, Dict, List, Optional, Sequence, Tuple
 
 import anki
@@ -59,6 +56,7 @@ from aqt.utils import (
     saveGeom,
     saveSplitter,
     showInfo,
+    showText,
     showWarning,
     tooltip,
     tr,
@@ -332,15 +330,28 @@ Replace your collection with an earlier backup?"""
             self.profileDiag,
             _("Revert to backup"),
             cb=doOpen,
-            filter="*.colpkg",
+            filter="*.colpkg *.zst",
             dir=self.pm.backupFolder(),
         )
 
     def _openBackup(self, path):
+        restored = None
+        if path.endswith(".zst"):
+            # restore next to the collection first, so a bad backup doesn't
+            # cost the user their current collection
+            restored = self.pm.collectionPath() + ".restore"
+            try:
+                self.backend.restore_backup(backup_path=path, collection_path=restored)
+            except Exception as e:
+                showWarning(str(e))
+                return
+
         try:
             # move the existing collection to the trash, as it may not open
             self.pm.trashCollection()
         except:
+            if restored:
+                os.unlink(restored)
             showWarning(
                 _(
                     "Unable to move existing file to trash - please try restarting your computer."
@@ -348,7 +359,10 @@ Replace your collection with an earlier backup?"""
             )
             return
 
-        self.pendingImport = path
+        if restored:
+            os.replace(restored, self.pm.collectionPath())
+        else:
+            self.pendingImport = path
         self.restoringBackup = True
 
         showInfo(
@@ -555,70 +569,57 @@ close the profile or restart Anki."""
             self.col = None
             self.progress.finish()
         if corrupt:
-            showWarning(
-                _(
-                    "Your collection file appears to be corrupt. \
+            msg = _(
+                "Your collection file appears to be corrupt. \
 This can happen when the file is copied or moved while Anki is open, or \
 when the collection is stored on a network or cloud drive. If problems \
 persist after restarting your computer, please open an automatic backup \
 from the profile screen."
-                )
             )
+            if askUser(
+                msg + "\n\n" + tr(TR.DATABASE_CHECK_RECOVER_PROMPT), defaultno=True
+            ):
+                self._recoverCollection()
         if not corrupt and not self.restoringBackup:
             self.backup()
 
+    def _recoverCollection(self) -> None:
+        "Copy what can be read from a corrupt collection into a new file."
+        self.progress.start(immediate=True)
+        try:
+            problems = self.backend.recover_collection(self.pm.collectionPath())
+        except Exception as e:
+            showWarning(str(e))
+            return
+        finally:
+            self.progress.finish()
+        showText("\n".join(problems))
+
     # Backup and auto-optimize
     ##########################################################################
 
-    class BackupThread(Thread):
-        def __init__(self, path, data):
-            Thread.__init__(self)
-            self.path = path
-            self.data = data
-            # create the file in calling thread to ensure the same
-            # file is not created twice
-            with open(self.path, "wb") as file:
-                pass
-
-        def run(self):
-            z = zipfile.ZipFile(self.path, "w", zipfile.ZIP_DEFLATED)
-            z.writestr("collection.anki2", self.data)
-            z.writestr("media", "{}")
-            z.close()
-
     def backup(self) -> None:
         nbacks = self.pm.profile["numBackups"]
         if not nbacks or devMode:
             return
-        dir = self.pm.backupFolder()
-        path = self.pm.collectionPath()
 
-        # do backup
-        fname = time.strftime(
-            "backup-%Y-%m-%d-%H.%M.%S.colpkg", time.localtime(time.time())
-        )
-        newpath = os.path.join(dir, fname)
-        with open(path, "rb") as f:
-            data = f.read()
-        b = self.BackupThread(newpath, data)
-        b.start()
-
-        # find existing backups
-        backups = []
-        for file in os.listdir(dir):
-            # only look for new-style format
-            m = re.match(r"backup-\d{4}-\d{2}-.+.colpkg", file)
-            if not m:
-                continue
-            backups.append(file)
-        backups.sort()
-
-        # remove old ones
-        while len(backups) > nbacks:
-            fname = backups.pop(0)
-            path = os.path.join(dir, fname)
-            os.unlink(path)
-        gui_hooks.backup_did_complete()
+        def do_backup():
+            # the collection can't be reopened until this completes
+            self.backend.backup_collection(
+                collection_path=self.pm.collectionPath(),
+                backup_folder=self.pm.backupFolder(),
+                max_backups=nbacks,
+            )
+
+        def on_done(future):
+            try:
+                future.result()
+            except Exception as e:
+                showWarning(str(e))
+                return
+            gui_hooks.backup_did_complete()
+
+        self.taskman.run_in_background(do_backup, on_done)
 
     def maybeOptimize(self) -> None:
         # have two weeks passed?
@@ -1226,6 +1227,8 @@ Difference to correct time: %s."""
         self.progress.timer(10 * 60 * 1000, self.onRefreshTimer, True)
         # check media sync every 5 minutes
         self.progress.timer(5 * 60 * 1000, self.on_autosync_timer, True)
+        # rebuild the queues soon after the day rolls over
+        self.progress.timer(60 * 1000, self.on_day_rollover_timer, True)
 
     def onRefreshTimer(self):
         if self.state == "deckBrowser":
@@ -1233,6 +1236,14 @@ Difference to correct time: %s."""
         elif self.state == "overview":
             self.overview.refresh()
 
+    def on_day_rollover_timer(self):
+        if not self.col.backend.day_rolled_over():
+            return
+        # the reviewer checks for itself when the next card is fetched
+        if self.state in ("deckBrowser", "overview"):
+            self.col.reset()
+            self.onRefreshTimer()
+
     def on_autosync_timer(self):
         elap = self.media_syncer.seconds_since_last_sync()
         minutes = self.pm.auto_sync_media_minutes()
@@ -654,7 +654,10 @@ time = %(time)d;
     def _buttonTime(self, i: int) -> str:
         if not self.mw.col.conf["estTimes"]:
             return "<div class=spacer></div>"
-        txt = self.mw.col.sched.nextIvlStr(self.card, i, True) or "&nbsp;"
+        if self.mw.col.schedVer() == 2:
+            txt = self.mw.col.backend.answer_button_labels(self.card.id)[i - 1]
+        else:
+            txt = self.mw.col.sched.nextIvlStr(self.card, i, True) or "&nbsp;"
         return "<span class=nobold>%s</span><br>" % txt
 
     # Leeches
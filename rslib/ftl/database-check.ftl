@@ -35,11 +35,20 @@ database-check-missing-decks =
         [one] Fixed { $count } missing deck.
        *[other] Fixed { $count } missing decks.
     }
+database-check-notetypes-recovered = One or more note types were missing. The notes that used them have been given new note types starting with "db-check", but field names and card design have been lost, so you may be better off restoring from an automatic backup.
 database-check-revlog-properties =
     { $count ->
         [one] Fixed { $count } review entry with invalid properties.
        *[other] Fixed { $count } review entries with invalid properties.
     }
+database-check-recover-prompt = Would you like Anki to try to repair it? Anything that can't be read from the file will be lost.
+database-check-recovered =
+    { $count ->
+        [one] Recovered { $count } item from the damaged collection.
+       *[other] Recovered { $count } items from the damaged collection.
+    }
+database-check-recovery-incomplete = Some items could not be read from: { $tables }. Restoring from an automatic backup may recover more.
+database-check-damaged-file-kept = The damaged file has been kept as { $path }.
 
 ## Progress info
 
@@ -68,3 +68,15 @@ scheduling-learn-remaining = { $remaining ->
     [one]   There is one remaining learning card due later today.
    *[other] There are {$remaining} learning cards due later today.
   }
+
+## Custom study
+
+# The name of the filtered deck created by the custom study screen.
+scheduling-custom-study-session = Custom Study Session
+scheduling-custom-study-rename-existing-deck = Please rename the existing Custom Study deck first.
+
+## Answer buttons
+
+# Shown above an answer button when the card will leave a filtered deck
+# instead of being shown again.
+scheduling-end = (end)
@@ -1,4 +1,10 @@
 search-invalid = Invalid search - please check for typing mistakes.
+search-unclosed-quote = The search contains a " without a closing ", starting at: { $term }
+search-unclosed-group = The search contains a ( without a closing ), starting at: { $term }
+search-unopened-group = The search contains a ) without an opening (.
+search-misplaced-boolean = "{ $term }" must be placed between two search terms.
+search-invalid-argument = "{ $term }" is not a valid search.
+search-invalid-property-operator = "{ $term }" must compare the property with one of: <, <=, =, !=, >, >=
 
 ## Column labels in browse screen
 
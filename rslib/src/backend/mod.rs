@@ -9,15 +9,16 @@ use crate::{
     backend_proto::{
         AddOrUpdateDeckConfigLegacyIn, BackendResult, Empty, RenderedTemplateReplacement,
     },
+    backup::{backup_collection, list_backups, restore_backup},
     card::{Card, CardID},
     card::{CardQueue, CardType},
     cloze::add_cloze_numbers_in_string,
-    collection::{open_collection, Collection},
+    collection::{open_collection_with_options, Collection},
     config::SortKind,
     dbcheck::DatabaseCheckProgress,
     deckconf::{DeckConf, DeckConfID, DeckConfSchema11},
     decks::{Deck, DeckID, DeckSchema11},
-    err::{AnkiError, NetworkErrorKind, Result, SyncErrorKind},
+    err::{AnkiError, NetworkErrorKind, Result, SearchErrorKind, SyncErrorKind},
     i18n::{tr_args, I18n, TR},
     latex::{extract_latex, extract_latex_expanding_clozes, ExtractedLatex},
     log,
@@ -30,16 +31,26 @@ use crate::{
         all_stock_notetypes, CardTemplateSchema11, NoteType, NoteTypeID, NoteTypeSchema11,
         RenderCardOutput,
     },
+    sched::answering::{CardAnswer, Rating},
+    sched::bury::UnburyMode,
+    sched::custom_study::{CramKind, CramRequest, CustomStudyRequest},
     sched::cutoff::local_minutes_west_for_stamp,
+    sched::queue::{QueueEntryKind, QueuedCards},
+    sched::simulate::SimulationRequest,
+    sched::states::SchedulingState,
+    sched::steps::{learning_steps_to_string, parse_learning_steps},
     sched::timespan::{answer_button_time, learning_congrats, studied_today, time_span},
-    search::SortMode,
+    search::{
+        concatenate_searches, normalize_search, replace_search_node, BoolSeparator, SortMode,
+    },
+    storage::{recover_collection, StorageOptions},
     sync::{
         get_remote_sync_meta, sync_abort, sync_login, FullSyncProgress, NormalSyncProgress,
         SyncActionRequired, SyncAuth, SyncMeta, SyncOutput, SyncStage,
     },
     template::RenderedNode,
     text::{extract_av_tags, strip_av_tags, AVTag},
-    timestamp::TimestampSecs,
+    timestamp::{TimestampMillis, TimestampSecs},
     types::Usn,
 };
 use fluent::FluentValue;
@@ -51,8 +62,14 @@ use serde_json::Value as JsonValue;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::{
+    panic::RefUnwindSafe,
+    path::Path,
     result,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
 };
 use tokio::runtime::{self, Runtime};
 
@@ -77,6 +94,14 @@ impl ThrottlingProgressHandler {
         guard.want_abort = false;
         !want_abort
     }
+
+    /// A closure that returns true if should continue. Unlike update(), it
+    /// leaves the abort request in place, so it can be polled by SQLite
+    /// while update() is used for the same operation.
+    fn abort_check(&self) -> impl FnMut() -> bool + Send + RefUnwindSafe + 'static {
+        let state = self.state.clone();
+        move || !state.lock().unwrap().want_abort
+    }
 }
 
 struct ProgressState {
@@ -90,6 +115,9 @@ pub struct Backend {
     server: bool,
     sync_abort: Option<AbortHandle>,
     progress_state: Arc<Mutex<ProgressState>>,
+    /// Set to stop the running search, without touching the progress of
+    /// other operations.
+    search_abort: Arc<AtomicBool>,
     runtime: Option<Runtime>,
     state: Arc<Mutex<BackendState>>,
 }
@@ -145,7 +173,15 @@ fn anki_error_to_proto_error(err: AnkiError, i18n: &I18n) -> pb::BackendError {
         AnkiError::NotFound => V::NotFoundError(Empty {}),
         AnkiError::Existing => V::Exists(Empty {}),
         AnkiError::DeckIsFiltered => V::DeckIsFiltered(Empty {}),
-        AnkiError::SearchError(_) => V::InvalidInput(pb::Empty {}),
+        AnkiError::SearchError { info, kind, span } => {
+            let span = span.unwrap_or(0..0);
+            V::SearchError(pb::SearchError {
+                kind: kind.into(),
+                info,
+                start: span.start as u32,
+                end: span.end as u32,
+            })
+        }
     };
 
     pb::BackendError {
@@ -166,6 +202,22 @@ impl std::convert::From<NetworkErrorKind> for i32 {
     }
 }
 
+impl std::convert::From<SearchErrorKind> for i32 {
+    fn from(e: SearchErrorKind) -> Self {
+        use pb::search_error::SearchErrorKind as V;
+        (match e {
+            SearchErrorKind::UnclosedQuote => V::UnclosedQuote,
+            SearchErrorKind::UnclosedGroup => V::UnclosedGroup,
+            SearchErrorKind::UnopenedGroup => V::UnopenedGroup,
+            SearchErrorKind::MisplacedBoolean => V::MisplacedBoolean,
+            SearchErrorKind::InvalidArgument => V::InvalidArgument,
+            SearchErrorKind::InvalidPropertyOperator => V::InvalidPropertyOperator,
+            SearchErrorKind::Regex => V::Regex,
+            SearchErrorKind::Other => V::Other,
+        }) as i32
+    }
+}
+
 impl std::convert::From<SyncErrorKind> for i32 {
     fn from(e: SyncErrorKind) -> Self {
         use pb::sync_error::SyncErrorKind as V;
@@ -384,6 +436,7 @@ impl BackendService for Backend {
     //-----------------------------------------------
 
     fn search_cards(&mut self, input: pb::SearchCardsIn) -> Result<pb::SearchCardsOut> {
+        let should_continue = self.new_search_abort_check();
         self.with_col(|col| {
             let order = if let Some(order) = input.order {
                 use pb::sort_order::Value as V;
@@ -400,7 +453,17 @@ impl BackendService for Backend {
             } else {
                 SortMode::FromConfig
             };
-            let cids = col.search_cards(&input.search, order)?;
+            let (search, offset, limit) = (input.search, input.offset, input.limit);
+            let cids = col.cancellable(should_continue, |col| {
+                if limit > 0 {
+                    col.search_cards_page(&search, order, offset, limit)
+                } else {
+                    col.search_cards(&search, order)
+                }
+            })?;
+            if input.add_to_history {
+                col.transact(None, |col| col.add_to_search_history(&search))?;
+            }
             Ok(pb::SearchCardsOut {
                 card_ids: cids.into_iter().map(|v| v.0).collect(),
             })
@@ -408,14 +471,82 @@ impl BackendService for Backend {
     }
 
     fn search_notes(&mut self, input: pb::SearchNotesIn) -> Result<pb::SearchNotesOut> {
+        let should_continue = self.new_search_abort_check();
         self.with_col(|col| {
-            let nids = col.search_notes(&input.search)?;
+            let nids = col.cancellable(should_continue, |col| col.search_notes(&input.search))?;
             Ok(pb::SearchNotesOut {
                 note_ids: nids.into_iter().map(|v| v.0).collect(),
             })
         })
     }
 
+    fn search_notes_with_matches(
+        &mut self,
+        input: pb::String,
+    ) -> BackendResult<pb::SearchNotesWithMatchesOut> {
+        let should_continue = self.new_search_abort_check();
+        self.with_col(|col| {
+            let notes = col.cancellable(should_continue, |col| {
+                col.search_notes_with_matches(&input.val)
+            })?;
+            Ok(pb::SearchNotesWithMatchesOut {
+                notes: notes
+                    .into_iter()
+                    .map(|note| pb::search_notes_with_matches_out::Note {
+                        note_id: note.note_id.0,
+                        matches: note
+                            .matches
+                            .into_iter()
+                            .map(|m| pb::search_notes_with_matches_out::FieldMatch {
+                                field_ord: m.field_ord as u32,
+                                start: m.start as u32,
+                                end: m.end as u32,
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+        })
+    }
+
+    /// Only available when the DEBUGSEARCH environment variable is set.
+    fn search_sql(&mut self, input: pb::SearchSqlIn) -> BackendResult<pb::SearchSqlOut> {
+        if std::env::var("DEBUGSEARCH").is_err() {
+            return Err(AnkiError::invalid_input(
+                "DEBUGSEARCH must be set to view search SQL",
+            ));
+        }
+        self.with_col(|col| {
+            let (sql, args) = if input.notes {
+                col.search_notes_sql(&input.search)?
+            } else {
+                col.search_cards_sql(&input.search, SortMode::NoOrder)?
+            };
+            Ok(pb::SearchSqlOut { sql, args })
+        })
+    }
+
+    fn abort_search(&mut self, _input: Empty) -> BackendResult<Empty> {
+        self.search_abort.store(true, Ordering::Relaxed);
+        Ok(().into())
+    }
+
+    fn count_cards(&mut self, input: pb::String) -> BackendResult<pb::UInt32> {
+        let should_continue = self.new_search_abort_check();
+        self.with_col(|col| {
+            col.cancellable(should_continue, |col| col.count_cards(&input.val))
+                .map(Into::into)
+        })
+    }
+
+    fn count_notes(&mut self, input: pb::String) -> BackendResult<pb::UInt32> {
+        let should_continue = self.new_search_abort_check();
+        self.with_col(|col| {
+            col.cancellable(should_continue, |col| col.count_notes(&input.val))
+                .map(Into::into)
+        })
+    }
+
     fn find_and_replace(&mut self, input: pb::FindAndReplaceIn) -> BackendResult<pb::UInt32> {
         let mut search = if input.regex {
             input.search
@@ -438,6 +569,94 @@ impl BackendService for Backend {
         })
     }
 
+    fn set_full_text_search(&mut self, input: pb::Bool) -> BackendResult<Empty> {
+        self.with_col(|col| {
+            col.transact(None, |col| {
+                col.set_full_text_search(input.val).map(Into::into)
+            })
+        })
+    }
+
+    fn normalize_search(&mut self, input: pb::String) -> BackendResult<pb::String> {
+        Ok(normalize_search(&input.val)?.into())
+    }
+
+    fn concatenate_searches(
+        &mut self,
+        input: pb::ConcatenateSearchesIn,
+    ) -> BackendResult<pb::String> {
+        let sep = match pb::concatenate_searches_in::Separator::from_i32(input.sep) {
+            Some(pb::concatenate_searches_in::Separator::Or) => BoolSeparator::Or,
+            _ => BoolSeparator::And,
+        };
+        Ok(concatenate_searches(sep, &input.existing, &input.additional)?.into())
+    }
+
+    fn replace_search_node(&mut self, input: pb::ReplaceSearchNodeIn) -> BackendResult<pb::String> {
+        Ok(replace_search_node(&input.existing, &input.replacement)?.into())
+    }
+
+    fn get_saved_searches(&mut self, _input: Empty) -> BackendResult<pb::SavedSearches> {
+        self.with_col(|col| {
+            Ok(pb::SavedSearches {
+                searches: col.saved_searches(),
+            })
+        })
+    }
+
+    fn set_saved_search(&mut self, input: pb::SavedSearch) -> BackendResult<Empty> {
+        self.with_col(|col| {
+            col.transact(None, |col| {
+                col.set_saved_search(&input.name, &input.search)
+                    .map(Into::into)
+            })
+        })
+    }
+
+    fn rename_saved_search(&mut self, input: pb::RenameSavedSearchIn) -> BackendResult<Empty> {
+        self.with_col(|col| {
+            col.transact(None, |col| {
+                col.rename_saved_search(&input.old_name, &input.new_name)
+                    .map(Into::into)
+            })
+        })
+    }
+
+    fn remove_saved_search(&mut self, input: pb::String) -> BackendResult<Empty> {
+        self.with_col(|col| {
+            col.transact(None, |col| {
+                col.remove_saved_search(&input.val).map(Into::into)
+            })
+        })
+    }
+
+    fn get_search_history(&mut self, _input: Empty) -> BackendResult<pb::SearchHistory> {
+        self.with_col(|col| {
+            Ok(pb::SearchHistory {
+                searches: col.get_search_history(),
+            })
+        })
+    }
+
+    fn clear_search_history(&mut self, _input: Empty) -> BackendResult<Empty> {
+        self.with_col(|col| col.transact(None, |col| col.clear_search_history().map(Into::into)))
+    }
+
+    fn find_dupes(&mut self, input: pb::FindDupesIn) -> BackendResult<pb::FindDupesOut> {
+        self.with_col(|col| {
+            let groups = col.find_dupes(&input.field_name, &input.search)?;
+            Ok(pb::FindDupesOut {
+                groups: groups
+                    .into_iter()
+                    .map(|group| pb::find_dupes_out::Group {
+                        text: group.text,
+                        note_ids: group.note_ids.into_iter().map(|nid| nid.0).collect(),
+                    })
+                    .collect(),
+            })
+        })
+    }
+
     // scheduling
     //-----------------------------------------------
 
@@ -445,6 +664,10 @@ impl BackendService for Backend {
         self.with_col(|col| col.timing_today().map(Into::into))
     }
 
+    fn day_rolled_over(&mut self, _input: pb::Empty) -> BackendResult<pb::Bool> {
+        self.with_col(|col| col.day_rolled_over().map(|val| pb::Bool { val }))
+    }
+
     fn local_minutes_west(&mut self, input: pb::Int64) -> BackendResult<pb::Int32> {
         Ok(pb::Int32 {
             val: local_minutes_west_for_stamp(input.val),
@@ -482,18 +705,8 @@ impl BackendService for Backend {
 
     fn extend_limits(&mut self, input: pb::ExtendLimitsIn) -> BackendResult<Empty> {
         self.with_col(|col| {
-            col.transact(None, |col| {
-                let today = col.current_due_day(0)?;
-                let usn = col.usn()?;
-                col.extend_limits(
-                    today,
-                    usn,
-                    input.deck_id.into(),
-                    input.new_delta,
-                    input.review_delta,
-                )
+            col.extend_limits_for_today(input.deck_id.into(), input.new_delta, input.review_delta)
                 .map(Into::into)
-            })
         })
     }
 
@@ -504,6 +717,199 @@ impl BackendService for Backend {
         self.with_col(|col| col.counts_for_deck_today(input.did.into()))
     }
 
+    fn answer_card(&mut self, input: pb::AnswerCardIn) -> BackendResult<pb::AnswerCardOut> {
+        let answer = CardAnswer {
+            card_id: input.card_id.into(),
+            rating: Rating::from_button(input.rating)?,
+            answered_at: TimestampMillis(input.answered_at_millis),
+            milliseconds_taken: input.milliseconds_taken,
+            fuzz_seed: None,
+            custom_state: input
+                .custom_state
+                .map(SchedulingState::try_from)
+                .transpose()?,
+        };
+        self.with_col(|col| {
+            col.answer_card(&answer)
+                .map(|leech| pb::AnswerCardOut { leech })
+        })
+    }
+
+    fn get_queued_cards(
+        &mut self,
+        input: pb::GetQueuedCardsIn,
+    ) -> BackendResult<pb::GetQueuedCardsOut> {
+        self.with_col(|col| col.get_queued_cards(input.fetch_limit).map(Into::into))
+    }
+
+    fn set_due_date(&mut self, input: pb::SetDueDateIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| col.set_due_date(&cids, &input.days).map(Into::into))
+    }
+
+    fn forget_cards(&mut self, input: pb::ForgetCardsIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| col.forget_cards(&cids).map(Into::into))
+    }
+
+    fn shift_due_dates(&mut self, input: pb::ShiftDueDatesIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| col.shift_due_dates(&cids, input.days).map(Into::into))
+    }
+
+    fn reposition_new_cards(&mut self, input: pb::RepositionNewCardsIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| {
+            col.reposition_new_cards(
+                &cids,
+                input.starting_from,
+                input.step_size,
+                input.randomize,
+                input.shift_existing,
+            )
+            .map(|_| ().into())
+        })
+    }
+
+    fn bury_cards(&mut self, input: pb::BuryCardsIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| col.bury_cards(&cids, input.manual).map(Into::into))
+    }
+
+    fn unbury_cards_in_current_deck(
+        &mut self,
+        input: pb::UnburyCardsInCurrentDeckIn,
+    ) -> BackendResult<Empty> {
+        let mode = match input.mode() {
+            pb::unbury_cards_in_current_deck_in::Mode::All => UnburyMode::All,
+            pb::unbury_cards_in_current_deck_in::Mode::UserOnly => UnburyMode::UserBuried,
+            pb::unbury_cards_in_current_deck_in::Mode::SchedOnly => UnburyMode::SchedBuried,
+        };
+        self.with_col(|col| col.unbury_cards_in_current_deck(mode).map(Into::into))
+    }
+
+    fn unbury_on_day_rollover(&mut self, _input: Empty) -> BackendResult<Empty> {
+        self.with_col(|col| col.unbury_on_day_rollover().map(Into::into))
+    }
+
+    fn suspend_cards(&mut self, input: pb::SuspendCardsIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| col.suspend_cards(&cids).map(Into::into))
+    }
+
+    fn unsuspend_cards(&mut self, input: pb::UnsuspendCardsIn) -> BackendResult<Empty> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| col.unsuspend_cards(&cids).map(Into::into))
+    }
+
+    fn rebuild_filtered_deck(&mut self, input: pb::DeckId) -> BackendResult<pb::UInt32> {
+        self.with_col(|col| col.rebuild_filtered_deck(input.into()).map(Into::into))
+    }
+
+    fn empty_filtered_deck(&mut self, input: pb::DeckId) -> BackendResult<Empty> {
+        self.with_col(|col| col.empty_filtered_deck(input.into()).map(Into::into))
+    }
+
+    fn custom_study(&mut self, input: pb::CustomStudyIn) -> BackendResult<pb::UInt32> {
+        let request = match input
+            .value
+            .ok_or_else(|| AnkiError::invalid_input("no custom study preset"))?
+        {
+            pb::custom_study_in::Value::NewLimitDelta(delta) => {
+                CustomStudyRequest::NewLimitDelta(delta)
+            }
+            pb::custom_study_in::Value::ReviewLimitDelta(delta) => {
+                CustomStudyRequest::ReviewLimitDelta(delta)
+            }
+            pb::custom_study_in::Value::ForgotDays(days) => CustomStudyRequest::ForgotDays(days),
+            pb::custom_study_in::Value::ReviewAheadDays(days) => {
+                CustomStudyRequest::ReviewAheadDays(days)
+            }
+            pb::custom_study_in::Value::PreviewDays(days) => CustomStudyRequest::PreviewDays(days),
+            pb::custom_study_in::Value::Cram(cram) => CustomStudyRequest::Cram(CramRequest {
+                kind: match cram.kind() {
+                    pb::custom_study_in::cram::CramKind::New => CramKind::New,
+                    pb::custom_study_in::cram::CramKind::Due => CramKind::Due,
+                    pb::custom_study_in::cram::CramKind::Review => CramKind::Review,
+                    pb::custom_study_in::cram::CramKind::All => CramKind::All,
+                },
+                card_limit: cram.card_limit,
+                tags_to_include: cram.tags_to_include,
+                tags_to_exclude: cram.tags_to_exclude,
+            }),
+        };
+        self.with_col(|col| col.custom_study(request).map(Into::into))
+    }
+
+    fn answer_button_labels(
+        &mut self,
+        input: pb::CardId,
+    ) -> BackendResult<pb::AnswerButtonLabelsOut> {
+        self.with_col(|col| {
+            col.answer_button_labels(input.into())
+                .map(|labels| pb::AnswerButtonLabelsOut { labels })
+        })
+    }
+
+    fn get_scheduling_states(&mut self, input: pb::CardId) -> BackendResult<pb::SchedulingStates> {
+        self.with_col(|col| col.scheduling_states(input.into()).map(Into::into))
+    }
+
+    fn undo_review(&mut self, _input: Empty) -> BackendResult<pb::UndoReviewOut> {
+        self.with_col(|col| {
+            col.undo_review().map(|card| pb::UndoReviewOut {
+                card: card.map(Into::into),
+            })
+        })
+    }
+
+    fn upgrade_scheduler(&mut self, _input: Empty) -> BackendResult<Empty> {
+        self.with_col(|col| col.upgrade_to_v2_scheduler().map(Into::into))
+    }
+
+    fn simulate_reviews(
+        &mut self,
+        input: pb::SimulateReviewsIn,
+    ) -> BackendResult<pb::SimulateReviewsOut> {
+        let req = SimulationRequest {
+            deck_id: input.deck_id.into(),
+            days: input.days,
+            new_per_day: input.new_per_day.map(|limit| limit.val),
+            reviews_per_day: input.reviews_per_day.map(|limit| limit.val),
+            retention: if input.retention > 0.0 {
+                Some(input.retention)
+            } else {
+                None
+            },
+        };
+        self.with_col(|col| {
+            col.simulate_reviews(&req)
+                .map(|days| pb::SimulateReviewsOut {
+                    days: days
+                        .into_iter()
+                        .map(|day| pb::simulate_reviews_out::Day {
+                            due: day.due,
+                            reviewed: day.reviewed,
+                            lapsed: day.lapsed,
+                            introduced: day.introduced,
+                            seconds: day.seconds,
+                        })
+                        .collect(),
+                })
+        })
+    }
+
+    fn reconstruct_cards_from_revlog(
+        &mut self,
+        input: pb::ReconstructCardsFromRevlogIn,
+    ) -> BackendResult<pb::UInt32> {
+        let cids: Vec<_> = input.card_ids.into_iter().map(CardID).collect();
+        self.with_col(|col| {
+            col.reconstruct_cards_from_revlog(&cids)
+                .map(|count| (count as u32).into())
+        })
+    }
+
     // statistics
     //-----------------------------------------------
 
@@ -677,6 +1083,14 @@ impl BackendService for Backend {
         .map(Into::into)
     }
 
+    fn parse_learning_steps(&mut self, input: pb::String) -> BackendResult<pb::LearningSteps> {
+        parse_learning_steps(&input.val).map(|steps| pb::LearningSteps { steps })
+    }
+
+    fn format_learning_steps(&mut self, input: pb::LearningSteps) -> BackendResult<pb::String> {
+        Ok(learning_steps_to_string(&input.steps).into())
+    }
+
     // cards
     //-------------------------------------------------------------------
 
@@ -1024,17 +1438,42 @@ impl BackendService for Backend {
 
     fn check_database(&mut self, _input: pb::Empty) -> BackendResult<pb::CheckDatabaseOut> {
         let mut handler = self.new_progress_handler();
-        let progress_fn = move |progress, throttle| {
-            handler.update(Progress::DatabaseCheck(progress), throttle);
-        };
+        let should_continue = handler.abort_check();
+        let progress_fn =
+            move |progress, throttle| handler.update(Progress::DatabaseCheck(progress), throttle);
         self.with_col(|col| {
-            col.check_database(progress_fn)
+            col.cancellable(should_continue, |col| col.check_database(progress_fn))
                 .map(|problems| pb::CheckDatabaseOut {
                     problems: problems.to_i18n_strings(&col.i18n),
                 })
         })
     }
 
+    fn optimize_database(&mut self, _input: pb::Empty) -> BackendResult<Empty> {
+        let mut handler = self.new_progress_handler();
+        handler.update(
+            Progress::DatabaseCheck(DatabaseCheckProgress::Optimize),
+            false,
+        );
+        let should_continue = handler.abort_check();
+        self.with_col(|col| {
+            col.cancellable(should_continue, |col| col.storage.optimize())
+                .map(Into::into)
+        })
+    }
+
+    fn attach_database(&mut self, input: pb::AttachDatabaseIn) -> BackendResult<Empty> {
+        self.with_col(|col| {
+            col.storage
+                .attach_database(&input.path, &input.alias)
+                .map(Into::into)
+        })
+    }
+
+    fn detach_database(&mut self, input: pb::String) -> BackendResult<Empty> {
+        self.with_col(|col| col.storage.detach_database(&input.val).map(Into::into))
+    }
+
     fn open_collection(&mut self, input: pb::OpenCollectionIn) -> BackendResult<Empty> {
         let mut col = self.col.lock().unwrap();
         if col.is_some() {
@@ -1050,13 +1489,28 @@ impl BackendService for Backend {
         };
         let logger = default_logger(log_path)?;
 
-        let new_col = open_collection(
+        let mut options = StorageOptions::default();
+        if input.busy_timeout_millis > 0 {
+            options.busy_timeout = Duration::from_millis(input.busy_timeout_millis as u64);
+        }
+        if !input.encryption_key.is_empty() {
+            #[cfg(feature = "sqlcipher")]
+            {
+                options.encryption_key = Some(input.encryption_key);
+            }
+            #[cfg(not(feature = "sqlcipher"))]
+            return Err(AnkiError::invalid_input(
+                "encrypted collections require the sqlcipher feature",
+            ));
+        }
+        let new_col = open_collection_with_options(
             input.collection_path,
             input.media_folder_path,
             input.media_db_path,
             self.server,
             self.i18n.clone(),
             logger,
+            &options,
         )?;
 
         *col = Some(new_col);
@@ -1083,6 +1537,41 @@ impl BackendService for Backend {
         Ok(().into())
     }
 
+    fn backup_collection(&mut self, input: pb::BackupCollectionIn) -> BackendResult<pb::String> {
+        let _guard = self.lock_closed_collection()?;
+        backup_collection(
+            Path::new(&input.collection_path),
+            Path::new(&input.backup_folder),
+            input.max_backups,
+        )
+        .map(|path| path.to_string_lossy().to_string().into())
+    }
+
+    fn list_backups(&mut self, input: pb::String) -> BackendResult<pb::ListBackupsOut> {
+        Ok(pb::ListBackupsOut {
+            paths: list_backups(Path::new(&input.val))?
+                .into_iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+        })
+    }
+
+    fn restore_backup(&mut self, input: pb::RestoreBackupIn) -> BackendResult<Empty> {
+        let _guard = self.lock_closed_collection()?;
+        restore_backup(
+            Path::new(&input.backup_path),
+            Path::new(&input.collection_path),
+        )
+        .map(Into::into)
+    }
+
+    fn recover_collection(&mut self, input: pb::String) -> BackendResult<pb::RecoverCollectionOut> {
+        let _guard = self.lock_closed_collection()?;
+        recover_collection(Path::new(&input.val)).map(|out| pb::RecoverCollectionOut {
+            problems: out.to_i18n_strings(&self.i18n),
+        })
+    }
+
     // sync
     //-------------------------------------------------------------------
 
@@ -1185,6 +1674,10 @@ impl BackendService for Backend {
         Ok(pb::AllTagsOut { tags })
     }
 
+    fn clear_unused_tags(&mut self, _input: Empty) -> BackendResult<pb::UInt32> {
+        self.with_col(|col| col.clear_unused_tags().map(|count| (count as u32).into()))
+    }
+
     fn register_tags(&mut self, input: pb::RegisterTagsIn) -> BackendResult<pb::Bool> {
         self.with_col(|col| {
             col.transact(None, |col| {
@@ -1267,6 +1760,7 @@ impl Backend {
                 want_abort: false,
                 last_progress: None,
             })),
+            search_abort: Arc::new(AtomicBool::new(false)),
             runtime: None,
             state: Arc::new(Mutex::new(BackendState::default())),
         }
@@ -1305,6 +1799,18 @@ impl Backend {
         )
     }
 
+    /// Backups must not be taken or restored while the file is in use. The
+    /// returned guard stops the collection being opened until it is dropped,
+    /// so backups can be taken in the background.
+    fn lock_closed_collection(&self) -> Result<MutexGuard<Option<Collection>>> {
+        let guard = self.col.lock().unwrap();
+        if guard.is_some() {
+            Err(AnkiError::CollectionAlreadyOpen)
+        } else {
+            Ok(guard)
+        }
+    }
+
     fn new_progress_handler(&self) -> ThrottlingProgressHandler {
         {
             let mut guard = self.progress_state.lock().unwrap();
@@ -1317,6 +1823,13 @@ impl Backend {
         }
     }
 
+    /// Returns a closure that returns false once AbortSearch has been called.
+    fn new_search_abort_check(&self) -> impl FnMut() -> bool + Send + RefUnwindSafe + 'static {
+        self.search_abort.store(false, Ordering::Relaxed);
+        let abort = self.search_abort.clone();
+        move || !abort.load(Ordering::Relaxed)
+    }
+
     fn runtime_handle(&mut self) -> runtime::Handle {
         if self.runtime.is_none() {
             self.runtime = Some(
@@ -1386,7 +1899,7 @@ impl Backend {
 
         // block until it aborts
         while self.state.lock().unwrap().media_sync_abort.is_some() {
-            std::thread::sleep(std::time::Duration::from_millis(100));
+            std::thread::sleep(Duration::from_millis(100));
             self.progress_state.lock().unwrap().want_abort = true;
         }
     }
@@ -1492,6 +2005,13 @@ impl Backend {
             return Err(AnkiError::CollectionNotOpen);
         }
 
+        if col.as_ref().unwrap().is_in_memory() {
+            // closing it for the sync would discard it
+            return Err(AnkiError::invalid_input(
+                "an in-memory collection can't be fully synced",
+            ));
+        }
+
         let col_inner = col.take().unwrap();
 
         let (abort_handle, abort_reg) = AbortHandle::new_pair();
@@ -1501,6 +2021,7 @@ impl Backend {
         let media_folder_path = col_inner.media_folder.clone();
         let media_db_path = col_inner.media_db.clone();
         let logger = col_inner.log.clone();
+        let storage_options = col_inner.storage_options.clone();
 
         let mut handler = self.new_progress_handler();
         let progress_fn = move |progress: FullSyncProgress, throttle: bool| {
@@ -1519,13 +2040,14 @@ impl Backend {
         self.sync_abort = None;
 
         // ensure re-opened regardless of outcome
-        col.replace(open_collection(
+        col.replace(open_collection_with_options(
             col_path,
             media_folder_path,
             media_db_path,
             self.server,
             self.i18n.clone(),
             logger,
+            &storage_options,
         )?);
 
         match result {
@@ -1706,6 +2228,29 @@ fn sort_kind_from_pb(kind: i32) -> SortKind {
     }
 }
 
+impl From<QueuedCards> for pb::GetQueuedCardsOut {
+    fn from(queued: QueuedCards) -> Self {
+        use pb::get_queued_cards_out::{QueueEntryKind as Kind, QueuedCard};
+        pb::GetQueuedCardsOut {
+            cards: queued
+                .cards
+                .into_iter()
+                .map(|queued| QueuedCard {
+                    card: Some(queued.card.into()),
+                    kind: match queued.kind {
+                        QueueEntryKind::New => Kind::New,
+                        QueueEntryKind::Learning => Kind::Learning,
+                        QueueEntryKind::Review => Kind::Review,
+                    } as i32,
+                })
+                .collect(),
+            new_count: queued.new_count,
+            learning_count: queued.learning_count,
+            review_count: queued.review_count,
+        }
+    }
+}
+
 impl From<Card> for pb::Card {
     fn from(c: Card) -> Self {
         pb::Card {
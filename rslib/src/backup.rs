@@ -0,0 +1,134 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::err::{AnkiError, Result};
+use chrono::Local;
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_SUFFIX: &str = ".anki2.zst";
+
+/// Write a zstd-compressed copy of the collection into the backup folder,
+/// then remove the oldest backups so that at most `max_backups` remain.
+/// The collection must be closed. Returns the path of the new backup.
+pub fn backup_collection(
+    col_path: &Path,
+    backup_folder: &Path,
+    max_backups: u32,
+) -> Result<PathBuf> {
+    let name = Local::now()
+        .format(&format!(
+            "{}%Y-%m-%d-%H.%M.%S{}",
+            BACKUP_PREFIX, BACKUP_SUFFIX
+        ))
+        .to_string();
+    let path = backup_folder.join(name);
+
+    let mut input = File::open(col_path)?;
+    let mut out_file = NamedTempFile::new_in(backup_folder)?;
+    zstd::stream::copy_encode(&mut input, &mut out_file, 0)?;
+    out_file.persist(&path).map_err(|e| AnkiError::IOError {
+        info: format!("backup save failed: {}", e),
+    })?;
+
+    prune_backups(backup_folder, max_backups)?;
+
+    Ok(path)
+}
+
+/// The backups in the folder, newest first.
+pub fn list_backups(backup_folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut backups = vec![];
+    for entry in fs::read_dir(backup_folder)? {
+        let entry = entry?;
+        if let Some(name) = entry.file_name().to_str() {
+            if name.starts_with(BACKUP_PREFIX) && name.ends_with(BACKUP_SUFFIX) {
+                backups.push(entry.path());
+            }
+        }
+    }
+    // the timestamp in the name sorts chronologically
+    backups.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(backups)
+}
+
+fn prune_backups(backup_folder: &Path, max_backups: u32) -> Result<()> {
+    for path in list_backups(backup_folder)?
+        .into_iter()
+        .skip(max_backups as usize)
+    {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Replace the collection file with the contents of a backup. The
+/// collection must be closed. The existing file is only overwritten if the
+/// backup decompresses to an intact database.
+pub fn restore_backup(backup_path: &Path, col_path: &Path) -> Result<()> {
+    let folder = col_path
+        .parent()
+        .ok_or_else(|| AnkiError::invalid_input("invalid collection path"))?;
+    let mut input = File::open(backup_path)?;
+    let mut out_file = NamedTempFile::new_in(folder)?;
+    zstd::stream::copy_decode(&mut input, &mut out_file)?;
+
+    let db = rusqlite::Connection::open(out_file.path())?;
+    let check_result: String = db.pragma_query_value(None, "integrity_check", |r| r.get(0))?;
+    drop(db);
+    if check_result != "ok" {
+        return Err(AnkiError::invalid_input("backup is corrupt"));
+    }
+
+    out_file.persist(col_path).map_err(|e| AnkiError::IOError {
+        info: format!("backup restore failed: {}", e),
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn backups() -> Result<()> {
+        let dir = tempdir()?;
+        let col_path = dir.path().join("collection.anki2");
+        let db = rusqlite::Connection::open(&col_path)?;
+        db.execute_batch("create table t (x); insert into t values (1);")?;
+        drop(db);
+        let backup_folder = dir.path().join("backups");
+        fs::create_dir(&backup_folder)?;
+
+        // older backups are pruned, and other files are left alone
+        for name in &["backup-2000-01-01-10.00.00.anki2.zst", "backup-1.colpkg"] {
+            fs::write(backup_folder.join(name), b"")?;
+        }
+        let backup = backup_collection(&col_path, &backup_folder, 1)?;
+        assert_eq!(list_backups(&backup_folder)?, vec![backup.clone()]);
+        assert!(backup_folder.join("backup-1.colpkg").exists());
+
+        // restoring
+        let db = rusqlite::Connection::open(&col_path)?;
+        db.execute_batch("delete from t")?;
+        drop(db);
+        restore_backup(&backup, &col_path)?;
+        let db = rusqlite::Connection::open(&col_path)?;
+        let count: u32 =
+            db.query_row("select count() from t", rusqlite::NO_PARAMS, |r| r.get(0))?;
+        assert_eq!(count, 1);
+
+        // a bad backup doesn't replace the collection
+        let bad = backup_folder.join("backup-2000-01-01-10.00.00.anki2.zst");
+        fs::write(&bad, zstd::encode_all(&b"junk"[..], 0)?)?;
+        assert!(restore_backup(&bad, &col_path).is_err());
+        assert!(col_path.exists());
+
+        Ok(())
+    }
+}
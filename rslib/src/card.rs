@@ -6,7 +6,10 @@ use crate::define_newtype;
 use crate::err::{AnkiError, Result};
 use crate::notes::NoteID;
 use crate::{
-    collection::Collection, config::SchedulerVersion, timestamp::TimestampSecs, types::Usn,
+    collection::Collection,
+    config::SchedulerVersion,
+    timestamp::{TimestampMillis, TimestampSecs},
+    types::Usn,
     undo::Undoable,
 };
 use num_enum::TryFromPrimitive;
@@ -17,7 +20,7 @@ define_newtype!(CardID, i64);
 
 impl CardID {
     pub fn as_secs(self) -> TimestampSecs {
-        TimestampSecs(self.0 / 1000)
+        TimestampMillis(self.0).as_secs()
     }
 }
 
@@ -149,6 +152,34 @@ impl Card {
             self.ctype = CardType::New;
         }
     }
+
+    /// Move the card back into the queue that matches its type, eg after it
+    /// has been unburied. Learning cards may be due on a given day, or at
+    /// a specific time.
+    pub(crate) fn restore_queue_from_type(&mut self) {
+        self.queue = match self.ctype {
+            CardType::Learn | CardType::Relearn => {
+                let due = if self.odue != 0 { self.odue } else { self.due };
+                if due > 1_000_000_000 {
+                    CardQueue::Learn
+                } else {
+                    CardQueue::DayLearn
+                }
+            }
+            CardType::New => CardQueue::New,
+            CardType::Review => CardQueue::Review,
+        }
+    }
+
+    /// Move the card back to its home deck, discarding the original due
+    /// date. Used when the card is being given a new schedule.
+    pub(crate) fn remove_from_filtered_deck(&mut self) {
+        if self.odid.0 != 0 {
+            self.did = self.odid;
+            self.odid.0 = 0;
+            self.odue = 0;
+        }
+    }
 }
 #[derive(Debug)]
 pub(crate) struct UpdateCardUndo(Card);
@@ -172,6 +203,11 @@ impl Card {
         card.due = due;
         card
     }
+
+    pub(crate) fn set_modified(&mut self, usn: Usn) {
+        self.mtime = TimestampSecs::now();
+        self.usn = usn;
+    }
 }
 impl Collection {
     #[cfg(test)]
@@ -196,8 +232,7 @@ impl Collection {
         self.state
             .undo
             .save_undoable(Box::new(UpdateCardUndo(original.clone())));
-        card.mtime = TimestampSecs::now();
-        card.usn = self.usn()?;
+        card.set_modified(self.usn()?);
         self.storage.update_card(card)
     }
 
@@ -205,8 +240,7 @@ impl Collection {
         if card.id.0 != 0 {
             return Err(AnkiError::invalid_input("card id already set"));
         }
-        card.mtime = TimestampSecs::now();
-        card.usn = self.usn()?;
+        card.set_modified(self.usn()?);
         self.storage.add_card(card)
     }
 
@@ -8,10 +8,11 @@ use crate::types::Usn;
 use crate::{
     decks::{Deck, DeckID},
     notetype::{NoteType, NoteTypeID},
-    storage::SqliteStorage,
+    sched::undo_review::ReviewUndo,
+    storage::{SqliteStorage, StorageOptions},
     undo::UndoManager,
 };
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{collections::HashMap, panic::RefUnwindSafe, path::PathBuf, sync::Arc};
 
 pub fn open_collection<P: Into<PathBuf>>(
     path: P,
@@ -20,9 +21,29 @@ pub fn open_collection<P: Into<PathBuf>>(
     server: bool,
     i18n: I18n,
     log: Logger,
+) -> Result<Collection> {
+    open_collection_with_options(
+        path,
+        media_folder,
+        media_db,
+        server,
+        i18n,
+        log,
+        &StorageOptions::default(),
+    )
+}
+
+pub fn open_collection_with_options<P: Into<PathBuf>>(
+    path: P,
+    media_folder: P,
+    media_db: P,
+    server: bool,
+    i18n: I18n,
+    log: Logger,
+    options: &StorageOptions,
 ) -> Result<Collection> {
     let col_path = path.into();
-    let storage = SqliteStorage::open_or_create(&col_path, &i18n, server)?;
+    let storage = SqliteStorage::open_or_create(&col_path, &i18n, server, options)?;
 
     let col = Collection {
         storage,
@@ -32,17 +53,28 @@ pub fn open_collection<P: Into<PathBuf>>(
         i18n,
         log,
         server,
+        storage_options: options.clone(),
         state: CollectionState::default(),
     };
 
     Ok(col)
 }
 
+/// The path that opens a collection in memory instead of on disk.
+pub const IN_MEMORY_PATH: &str = ":memory:";
+
+/// Open a new collection that is held in memory and discarded when closed,
+/// for tests and previews that shouldn't touch the disk. It has no media
+/// folder.
+pub fn open_in_memory_collection(i18n: I18n, log: Logger) -> Result<Collection> {
+    open_collection(IN_MEMORY_PATH, "", "", false, i18n, log)
+}
+
 #[cfg(test)]
 pub fn open_test_collection() -> Collection {
     use crate::log;
     let i18n = I18n::new(&[""], "", log::terminal());
-    open_collection(":memory:", "", "", false, i18n, log::terminal()).unwrap()
+    open_in_memory_collection(i18n, log::terminal()).unwrap()
 }
 
 #[derive(Debug, Default)]
@@ -50,6 +82,21 @@ pub struct CollectionState {
     pub(crate) undo: UndoManager,
     pub(crate) notetype_cache: HashMap<NoteTypeID, Arc<NoteType>>,
     pub(crate) deck_cache: HashMap<DeckID, Arc<Deck>>,
+    pub(crate) review_undo: Vec<ReviewUndo>,
+    /// the day number when day_rolled_over() was last called
+    pub(crate) last_day_seen: Option<u32>,
+    /// the number of transact() calls in progress
+    pub(crate) transact_depth: u32,
+    /// whether the full text index exists, once it has been checked
+    pub(crate) full_text_index: Option<bool>,
+}
+
+impl CollectionState {
+    /// Forget cached items that may have been changed by rolled back work.
+    fn clear_caches(&mut self) {
+        self.notetype_cache.clear();
+        self.deck_cache.clear();
+    }
 }
 
 pub struct Collection {
@@ -61,25 +108,44 @@ pub struct Collection {
     pub(crate) i18n: I18n,
     pub(crate) log: Logger,
     pub(crate) server: bool,
+    /// the options the collection was opened with, for reopening it
+    pub(crate) storage_options: StorageOptions,
     pub(crate) state: CollectionState,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum CollectionOp {
     UpdateCard,
+    SuspendCards,
+    UnsuspendCards,
 }
 
 impl Collection {
     /// Execute the provided closure in a transaction, rolling back if
     /// an error is returned.
+    ///
+    /// Calls may be nested. A nested call only rolls back its own changes
+    /// on error, and the undo step and modification time are handled by
+    /// the outermost call, so `op` is ignored when nested.
     pub(crate) fn transact<F, R>(&mut self, op: Option<CollectionOp>, func: F) -> Result<R>
     where
         F: FnOnce(&mut Collection) -> Result<R>,
     {
+        if self.state.transact_depth > 0 {
+            return self.transact_nested(func);
+        }
+
         self.storage.begin_rust_trx()?;
+        if op.is_none() {
+            // like the undo queue, answers can't be reverted after other
+            // changes have been made
+            self.state.review_undo.clear();
+        }
         self.state.undo.begin_step(op);
 
+        self.state.transact_depth += 1;
         let mut res = func(self);
+        self.state.transact_depth -= 1;
 
         if res.is_ok() {
             if let Err(e) = self.storage.mark_modified() {
@@ -91,6 +157,7 @@ impl Collection {
 
         if res.is_err() {
             self.state.undo.discard_step();
+            self.state.clear_caches();
             self.storage.rollback_rust_trx()?;
         } else {
             self.state.undo.end_step();
@@ -99,6 +166,55 @@ impl Collection {
         res
     }
 
+    fn transact_nested<F, R>(&mut self, func: F) -> Result<R>
+    where
+        F: FnOnce(&mut Collection) -> Result<R>,
+    {
+        self.storage.begin_rust_trx()?;
+        let undo_len = self.state.undo.current_step_len();
+
+        self.state.transact_depth += 1;
+        let mut res = func(self);
+        self.state.transact_depth -= 1;
+
+        if res.is_ok() {
+            if let Err(e) = self.storage.commit_rust_trx() {
+                res = Err(e);
+            }
+        }
+        if res.is_err() {
+            self.state.undo.truncate_current_step(undo_len);
+            self.state.clear_caches();
+            self.storage.rollback_rust_trx()?;
+        }
+
+        res
+    }
+
+    /// Run func, calling should_continue periodically while SQLite is
+    /// executing queries. If it returns false, the running query is
+    /// abandoned and AnkiError::Interrupted is returned, so that a slow
+    /// search or database check can be cancelled by the user.
+    pub fn cancellable<F, T>(
+        &mut self,
+        mut should_continue: F,
+        func: impl FnOnce(&mut Collection) -> Result<T>,
+    ) -> Result<T>
+    where
+        F: FnMut() -> bool + Send + RefUnwindSafe + 'static,
+    {
+        self.storage
+            .db
+            .progress_handler(1_000, Some(move || !should_continue()));
+        let result = func(self);
+        self.storage.db.progress_handler(0, None::<fn() -> bool>);
+        result
+    }
+
+    pub fn is_in_memory(&self) -> bool {
+        self.col_path.as_os_str() == IN_MEMORY_PATH
+    }
+
     pub(crate) fn close(self, downgrade: bool) -> Result<()> {
         self.storage.close(downgrade)
     }
@@ -108,6 +224,32 @@ impl Collection {
         self.storage.usn(self.server)
     }
 
+    /// Advance the collection's usn, so that changes made from now on are
+    /// distinguished from those already sent.
+    pub(crate) fn increment_usn(&self) -> Result<()> {
+        self.storage.increment_usn()
+    }
+
+    /// Bump the collection's modification time. transact() does this
+    /// when it commits, so this is only required for changes made outside
+    /// of it.
+    pub(crate) fn set_modified(&self) -> Result<()> {
+        self.storage.mark_modified()
+    }
+
+    /// Mark the schema as changed, so that the next sync will be a full
+    /// one.
+    pub(crate) fn set_schema_modified(&self) -> Result<()> {
+        self.storage.set_schema_modified()?;
+        self.set_modified()
+    }
+
+    /// True if a full sync is already required, so a further schema change
+    /// doesn't need the user's confirmation.
+    pub(crate) fn schema_changed_since_sync(&self) -> Result<bool> {
+        Ok(self.storage.get_schema_mtime()? > self.storage.get_last_sync()?)
+    }
+
     /// Prepare for upload. Caller should not create transaction.
     pub(crate) fn before_upload(&mut self) -> Result<()> {
         self.transact(None, |col| {
@@ -119,10 +261,97 @@ impl Collection {
             col.storage.clear_deck_conf_usns()?;
             col.storage.clear_deck_usns()?;
             col.storage.clear_notetype_usns()?;
-            col.storage.increment_usn()?;
-            col.storage.set_schema_modified()?;
+            col.increment_usn()?;
+            col.set_schema_modified()?;
             col.storage.set_last_sync(col.storage.get_schema_mtime()?)
         })?;
         self.storage.optimize()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{card::Card, err::AnkiError, timestamp::TimestampMillis};
+
+    #[test]
+    fn nested_transactions() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let note_count =
+            |col: &Collection| col.storage.db_scalar::<u32>("select count() from notes");
+
+        // a failing inner call only rolls back its own changes
+        col.transact(None, |col| {
+            col.add_note(&mut nt.new_note(), DeckID(1))?;
+            let res: Result<()> = col.transact(None, |col| {
+                col.add_note(&mut nt.new_note(), DeckID(1))?;
+                Err(AnkiError::invalid_input("inner"))
+            });
+            assert!(res.is_err());
+            Ok(())
+        })?;
+        assert_eq!(note_count(&col)?, 1);
+
+        // a failing outer call rolls back everything
+        let res: Result<()> = col.transact(None, |col| {
+            col.transact(None, |col| col.add_note(&mut nt.new_note(), DeckID(1)))?;
+            Err(AnkiError::invalid_input("outer"))
+        });
+        assert!(res.is_err());
+        assert_eq!(note_count(&col)?, 1);
+
+        // the undo entries of a failing inner call are discarded too
+        let mut card = Card::default();
+        col.add_card(&mut card)?;
+        col.transact(Some(CollectionOp::UpdateCard), |col| {
+            let res: Result<()> = col.transact(None, |col| {
+                let original = col.storage.get_card(card.id)?.unwrap();
+                let mut updated = original.clone();
+                updated.ivl = 5;
+                col.update_card(&mut updated, &original)?;
+                Err(AnkiError::invalid_input("inner"))
+            });
+            assert!(res.is_err());
+            Ok(())
+        })?;
+        card.ivl = 7;
+        col.storage.update_card(&card)?;
+        col.undo()?;
+        assert_eq!(col.storage.get_card(card.id)?.unwrap().ivl, 7);
+
+        // no savepoints are left open
+        assert!(col.storage.db.is_autocommit());
+        assert_eq!(col.state.transact_depth, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn in_memory() -> Result<()> {
+        let col = open_test_collection();
+        assert!(col.is_in_memory());
+        // the schema and stock notetypes are created
+        assert!(col.get_notetype_by_name("Basic")?.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn metadata() -> Result<()> {
+        let col = open_test_collection();
+        col.storage
+            .db
+            .execute_batch("update col set mod = 1, scm = 1, ls = 1, usn = 0")?;
+        assert!(!col.schema_changed_since_sync()?);
+
+        col.set_schema_modified()?;
+        assert!(col.schema_changed_since_sync()?);
+        assert!(col.storage.get_modified_time()? > TimestampMillis(1));
+
+        col.increment_usn()?;
+        assert_eq!(col.storage.usn(true)?, Usn(1));
+
+        Ok(())
+    }
+}
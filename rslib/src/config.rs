@@ -36,6 +36,7 @@ pub(crate) enum ConfigKey {
     BrowserSortKind,
     BrowserSortReverse,
     CurrentDeckID,
+    ActiveDecks,
     CreationOffset,
     Rollover,
     LocalOffset,
@@ -49,6 +50,10 @@ pub(crate) enum ConfigKey {
     NewReviewMix,
     AnswerTimeLimitSecs,
     ShowDayLearningCardsFirst,
+    SavedSearches,
+    SearchHistory,
+    LastUnburiedDay,
+    LoadBalance,
 }
 #[derive(PartialEq, Serialize_repr, Deserialize_repr, Clone, Copy)]
 #[repr(u8)]
@@ -63,6 +68,7 @@ impl From<ConfigKey> for &'static str {
             ConfigKey::BrowserSortKind => "sortType",
             ConfigKey::BrowserSortReverse => "sortBackwards",
             ConfigKey::CurrentDeckID => "curDeck",
+            ConfigKey::ActiveDecks => "activeDecks",
             ConfigKey::CreationOffset => "creationOffset",
             ConfigKey::Rollover => "rollover",
             ConfigKey::LocalOffset => "localOffset",
@@ -76,6 +82,10 @@ impl From<ConfigKey> for &'static str {
             ConfigKey::NewReviewMix => "newSpread",
             ConfigKey::AnswerTimeLimitSecs => "timeLim",
             ConfigKey::ShowDayLearningCardsFirst => "dayLearnFirst",
+            ConfigKey::SavedSearches => "savedFilters",
+            ConfigKey::SearchHistory => "searchHistory",
+            ConfigKey::LastUnburiedDay => "lastUnburied",
+            ConfigKey::LoadBalance => "loadBalance",
         }
     }
 }
@@ -139,6 +149,17 @@ impl Collection {
             .unwrap_or(DeckID(1))
     }
 
+    /// Select a deck for study. The deck and its children become the
+    /// active decks.
+    pub(crate) fn set_current_deck(&self, did: DeckID) -> Result<()> {
+        let mut active = vec![did];
+        if let Some(deck) = self.storage.get_deck(did)? {
+            active.extend(self.storage.child_decks(&deck)?.into_iter().map(|d| d.id));
+        }
+        self.set_config(ConfigKey::CurrentDeckID, &did)?;
+        self.set_config(ConfigKey::ActiveDecks, &active)
+    }
+
     pub(crate) fn get_creation_mins_west(&self) -> Option<i32> {
         self.get_config_optional(ConfigKey::CreationOffset)
     }
@@ -256,6 +277,15 @@ impl Collection {
     pub(crate) fn set_day_learn_first(&self, on: bool) -> Result<()> {
         self.set_config(ConfigKey::ShowDayLearningCardsFirst, &on)
     }
+
+    pub(crate) fn get_load_balance(&self) -> bool {
+        self.get_config_optional(ConfigKey::LoadBalance)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn set_load_balance(&self, on: bool) -> Result<()> {
+        self.set_config(ConfigKey::LoadBalance, &on)
+    }
 }
 
 #[derive(Deserialize, PartialEq, Debug, Clone, Copy)]
@@ -324,6 +354,15 @@ mod test {
         // invalid type conversion
         assert_eq!(col.get_config_optional::<i64, _>("test"), None,);
 
+        // setting the same value again does not mark it as modified
+        col.storage.clear_config_usns().unwrap();
+        col.set_config("test", &vec![1, 2]).unwrap();
+        let usn: i32 = col
+            .storage
+            .db_scalar("select usn from config where key='test'")
+            .unwrap();
+        assert_eq!(usn, 0);
+
         // invalid json
         col.storage
             .db
@@ -11,7 +11,7 @@ use crate::{
     timestamp::{TimestampMillis, TimestampSecs},
 };
 use itertools::Itertools;
-use slog::debug;
+use slog::{debug, warn};
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
@@ -27,6 +27,7 @@ pub struct CheckDatabaseOutput {
     templates_missing: usize,
     card_ords_duplicated: usize,
     field_count_mismatch: usize,
+    notetypes_recovered: usize,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -84,6 +85,9 @@ impl CheckDatabaseOutput {
                 tr_args!["count"=>self.templates_missing],
             ));
         }
+        if self.notetypes_recovered > 0 {
+            probs.push(i18n.tr(TR::DatabaseCheckNotetypesRecovered).into());
+        }
         if self.revlog_properties_invalid > 0 {
             probs.push(i18n.trn(
                 TR::DatabaseCheckRevlogProperties,
@@ -97,13 +101,25 @@ impl CheckDatabaseOutput {
 
 impl Collection {
     /// Check the database, returning a list of problems that were fixed.
+    /// progress_fn should return false to cancel the check, in which case
+    /// AnkiError::Interrupted is returned.
     pub(crate) fn check_database<F>(&mut self, mut progress_fn: F) -> Result<CheckDatabaseOutput>
     where
-        F: FnMut(DatabaseCheckProgress, bool),
+        F: FnMut(DatabaseCheckProgress, bool) -> bool,
     {
-        progress_fn(DatabaseCheckProgress::Integrity, false);
+        if !progress_fn(DatabaseCheckProgress::Integrity, false) {
+            return Err(AnkiError::Interrupted);
+        }
         debug!(self.log, "quick check");
-        if self.storage.quick_check_corrupt() {
+        let corrupt = match self.storage.quick_check_corrupt() {
+            Ok(corrupt) => corrupt,
+            Err(AnkiError::Interrupted) => return Err(AnkiError::Interrupted),
+            Err(e) => {
+                warn!(self.log, "quick check could not be run"; "err"=>?e);
+                true
+            }
+        };
+        if corrupt {
             debug!(self.log, "quick check failed");
             return Err(AnkiError::DBError {
                 info: self.i18n.tr(TR::DatabaseCheckCorrupt).into(),
@@ -111,7 +127,9 @@ impl Collection {
             });
         }
 
-        progress_fn(DatabaseCheckProgress::Optimize, false);
+        if !progress_fn(DatabaseCheckProgress::Optimize, false) {
+            return Err(AnkiError::Interrupted);
+        }
         debug!(self.log, "optimize");
         self.storage.optimize()?;
 
@@ -120,12 +138,14 @@ impl Collection {
 
     fn check_database_inner<F>(&mut self, mut progress_fn: F) -> Result<CheckDatabaseOutput>
     where
-        F: FnMut(DatabaseCheckProgress, bool),
+        F: FnMut(DatabaseCheckProgress, bool) -> bool,
     {
         let mut out = CheckDatabaseOutput::default();
 
         // cards first, as we need to be able to read them to process notes
-        progress_fn(DatabaseCheckProgress::Cards, false);
+        if !progress_fn(DatabaseCheckProgress::Cards, false) {
+            return Err(AnkiError::Interrupted);
+        }
         debug!(self.log, "check cards");
         self.check_card_properties(&mut out)?;
         self.check_orphaned_cards(&mut out)?;
@@ -137,7 +157,9 @@ impl Collection {
         debug!(self.log, "check notetypes");
         self.check_notetypes(&mut out, &mut progress_fn)?;
 
-        progress_fn(DatabaseCheckProgress::History, false);
+        if !progress_fn(DatabaseCheckProgress::History, false) {
+            return Err(AnkiError::Interrupted);
+        }
 
         debug!(self.log, "check review log");
         self.check_revlog(&mut out)?;
@@ -167,7 +189,7 @@ impl Collection {
     fn check_orphaned_cards(&mut self, out: &mut CheckDatabaseOutput) -> Result<()> {
         let cnt = self.storage.delete_orphaned_cards()?;
         if cnt > 0 {
-            self.storage.set_schema_modified()?;
+            self.set_schema_modified()?;
             out.cards_missing_note = cnt;
         }
         Ok(())
@@ -205,7 +227,7 @@ impl Collection {
         }
 
         if wrong > 0 {
-            self.storage.set_schema_modified()?;
+            self.set_schema_modified()?;
             out.card_properties_invalid += wrong;
         }
 
@@ -218,7 +240,7 @@ impl Collection {
         mut progress_fn: F,
     ) -> Result<()>
     where
-        F: FnMut(DatabaseCheckProgress, bool),
+        F: FnMut(DatabaseCheckProgress, bool) -> bool,
     {
         let nids_by_notetype = self.storage.all_note_ids_by_notetype()?;
         let norm = self.normalize_note_text();
@@ -237,6 +259,7 @@ impl Collection {
             let nt = match self.get_notetype(ntid)? {
                 None => {
                     let first_note = self.storage.get_note(group.peek().unwrap().1)?.unwrap();
+                    out.notetypes_recovered += 1;
                     self.recover_notetype(stamp, first_note.fields.len())?
                 }
                 Some(nt) => nt,
@@ -244,16 +267,20 @@ impl Collection {
 
             let mut genctx = None;
             for (_, nid) in group {
-                progress_fn(
+                if !progress_fn(
                     DatabaseCheckProgress::Notes {
                         current: checked_notes,
                         total: total_notes,
                     },
                     true,
-                );
+                ) {
+                    return Err(AnkiError::Interrupted);
+                }
                 checked_notes += 1;
 
                 let mut note = self.storage.get_note(nid)?.unwrap();
+                // move notes of a missing notetype to the recovered one
+                note.ntid = nt.id;
 
                 let cards = self.storage.existing_cards_for_note(nid)?;
 
@@ -280,9 +307,12 @@ impl Collection {
             self.add_notetype_inner(&mut nt, usn)?;
         }
 
-        if out.card_ords_duplicated > 0 || out.field_count_mismatch > 0 || out.templates_missing > 0
+        if out.card_ords_duplicated > 0
+            || out.field_count_mismatch > 0
+            || out.templates_missing > 0
+            || out.notetypes_recovered > 0
         {
-            self.storage.set_schema_modified()?;
+            self.set_schema_modified()?;
         }
 
         Ok(())
@@ -343,7 +373,7 @@ impl Collection {
     fn check_revlog(&self, out: &mut CheckDatabaseOutput) -> Result<()> {
         let cnt = self.storage.fix_revlog_properties()?;
         if cnt > 0 {
-            self.storage.set_schema_modified()?;
+            self.set_schema_modified()?;
             out.revlog_properties_invalid = cnt;
         }
 
@@ -357,7 +387,7 @@ impl Collection {
     }
 
     fn update_next_new_position(&self) -> Result<()> {
-        let pos = self.storage.max_new_card_position().unwrap_or(0);
+        let pos = self.storage.max_new_card_position()?;
         self.set_next_card_position(pos)
     }
 }
@@ -367,7 +397,9 @@ mod test {
     use super::*;
     use crate::{collection::open_test_collection, decks::DeckID, search::SortMode};
 
-    fn progress_fn(_progress: DatabaseCheckProgress, _throttle: bool) {}
+    fn progress_fn(_progress: DatabaseCheckProgress, _throttle: bool) -> bool {
+        true
+    }
 
     #[test]
     fn cards() -> Result<()> {
@@ -427,6 +459,25 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn cancelling() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        col.add_note(&mut note, DeckID(1))?;
+        col.storage.db.execute_batch("update cards set did=123")?;
+
+        // cancelling while checking notes rolls back the earlier fixes
+        let res = col.check_database(|progress, _throttle| {
+            !matches!(progress, DatabaseCheckProgress::Notes { .. })
+        });
+        assert_eq!(res, Err(AnkiError::Interrupted));
+        assert!(col.storage.get_deck(DeckID(123))?.is_none());
+        assert_eq!(col.storage.db_scalar::<i64>("select did from cards")?, 123);
+
+        Ok(())
+    }
+
     #[test]
     fn revlog() -> Result<()> {
         let mut col = open_test_collection();
@@ -543,6 +594,42 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn missing_notetype() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields = vec!["a".into(), "b".into()];
+        col.add_note(&mut note, DeckID(1))?;
+
+        for table in &[
+            "notetypes where id",
+            "fields where ntid",
+            "templates where ntid",
+        ] {
+            col.storage
+                .db
+                .execute(&format!("delete from {} = ?", table), &[nt.id])?;
+        }
+        col.state.notetype_cache.clear();
+
+        let out = col.check_database(progress_fn)?;
+        assert_eq!(
+            out,
+            CheckDatabaseOutput {
+                notetypes_recovered: 1,
+                ..Default::default()
+            }
+        );
+        let note = col.storage.get_note(note.id)?.unwrap();
+        let recovered = col.get_notetype(note.ntid)?.unwrap();
+        assert!(recovered.name.starts_with("db-check"));
+        assert_eq!(&note.fields, &["a", "b"]);
+        assert_eq!(col.storage.all_cards_of_note(note.id)?.len(), 1);
+
+        Ok(())
+    }
+
     #[test]
     fn deck_names() -> Result<()> {
         let mut col = open_test_collection();
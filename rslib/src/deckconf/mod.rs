@@ -5,12 +5,13 @@ use crate::{
     collection::Collection,
     define_newtype,
     err::{AnkiError, Result},
+    sched::steps::sanitize_learning_steps,
     timestamp::{TimestampMillis, TimestampSecs},
     types::Usn,
 };
 
 pub use crate::backend_proto::{
-    deck_config_inner::{LeechAction, NewCardOrder},
+    deck_config_inner::{LeechAction, NewCardOrder, SchedulingAlgorithm},
     DeckConfigInner,
 };
 pub use schema11::{DeckConfSchema11, NewCardOrderSchema11};
@@ -58,12 +59,90 @@ impl Default for DeckConf {
                 new_card_order: NewCardOrder::Due as i32,
                 leech_action: LeechAction::TagOnly as i32,
                 leech_threshold: 8,
+                scheduling_algorithm: SchedulingAlgorithm::Sm2 as i32,
                 other: vec![],
             },
         }
     }
 }
 
+impl DeckConf {
+    pub(crate) fn set_modified(&mut self, usn: Usn) {
+        self.mtime_secs = TimestampSecs::now();
+        self.usn = usn;
+    }
+}
+
+/// The default starting ease, used in place of an invalid value.
+const DEFAULT_INITIAL_EASE: f64 = 2.5;
+
+/// The accessors below clamp the stored values to the ranges the options
+/// screen allows, so that configs saved by older clients, add-ons or a
+/// sync can't give cards unusable intervals, or a starting ease at the
+/// minimum of 130% that can never recover.
+impl DeckConfigInner {
+    /// The ease new cards graduate with, eg 2.5.
+    pub fn initial_ease(&self) -> f64 {
+        clamped(self.initial_ease, 1.31, 5.0, DEFAULT_INITIAL_EASE)
+    }
+
+    pub fn easy_multiplier(&self) -> f64 {
+        clamped(self.easy_multiplier, 1.0, 5.0, 1.3)
+    }
+
+    pub fn hard_multiplier(&self) -> f64 {
+        clamped(self.hard_multiplier, 0.5, 1.3, 1.2)
+    }
+
+    /// The proportion of the interval kept when a card lapses.
+    pub fn lapse_multiplier(&self) -> f64 {
+        clamped(self.lapse_multiplier, 0.0, 1.0, 0.0)
+    }
+
+    pub fn interval_multiplier(&self) -> f64 {
+        clamped(self.interval_multiplier, 0.5, 2.0, 1.0)
+    }
+
+    pub fn maximum_review_interval(&self) -> u32 {
+        self.maximum_review_interval.max(1).min(36_500)
+    }
+
+    /// The smallest interval a card can be given when it lapses.
+    pub fn minimum_review_interval(&self) -> u32 {
+        self.minimum_review_interval
+            .max(1)
+            .min(self.maximum_review_interval())
+    }
+
+    /// Store the values the accessors return. Called when saving.
+    pub(crate) fn clamp_values(&mut self) {
+        self.initial_ease = self.initial_ease() as f32;
+        self.easy_multiplier = self.easy_multiplier() as f32;
+        self.hard_multiplier = self.hard_multiplier() as f32;
+        self.lapse_multiplier = self.lapse_multiplier() as f32;
+        self.interval_multiplier = self.interval_multiplier() as f32;
+        self.maximum_review_interval = self.maximum_review_interval();
+        self.minimum_review_interval = self.minimum_review_interval();
+        sanitize_learning_steps(&mut self.learn_steps);
+        sanitize_learning_steps(&mut self.relearn_steps);
+    }
+}
+
+/// Config multipliers are stored as f32; recover the value that was
+/// entered, so that eg 1.3 doesn't become 1.2999999523.
+fn f64_from(val: f32) -> f64 {
+    val.to_string().parse().unwrap_or(val as f64)
+}
+
+fn clamped(val: f32, min: f64, max: f64, default: f64) -> f64 {
+    let val = f64_from(val);
+    if val.is_finite() {
+        val.max(min).min(max)
+    } else {
+        default
+    }
+}
+
 impl Collection {
     /// If fallback is true, guaranteed to return a deck config.
     pub fn get_deck_config(&self, dcid: DeckConfID, fallback: bool) -> Result<Option<DeckConf>> {
@@ -86,9 +165,9 @@ impl Collection {
         conf: &mut DeckConf,
         preserve_usn_and_mtime: bool,
     ) -> Result<()> {
+        conf.inner.clamp_values();
         if !preserve_usn_and_mtime {
-            conf.mtime_secs = TimestampSecs::now();
-            conf.usn = self.usn()?;
+            conf.set_modified(self.usn()?);
         }
         let orig = self.storage.get_deck_config(conf.id)?;
         if let Some(_orig) = orig {
@@ -106,7 +185,38 @@ impl Collection {
         if dcid.0 == 1 {
             return Err(AnkiError::invalid_input("can't delete default conf"));
         }
-        self.storage.set_schema_modified()?;
+        self.set_schema_modified()?;
         self.storage.remove_deck_conf(dcid)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn clamping() -> Result<()> {
+        let col = open_test_collection();
+        let mut conf = col.get_deck_config(DeckConfID(1), false)?.unwrap();
+        conf.inner.initial_ease = 1.3;
+        conf.inner.hard_multiplier = f32::NAN;
+        conf.inner.interval_multiplier = 10.0;
+        conf.inner.maximum_review_interval = 0;
+        conf.inner.minimum_review_interval = 5;
+        conf.inner.learn_steps = vec![0.0, f32::NAN, 10.0];
+        assert_eq!(conf.inner.initial_ease().to_string(), "1.31");
+        assert_eq!(conf.inner.minimum_review_interval(), 1);
+
+        col.add_or_update_deck_config(&mut conf, false)?;
+        let inner = col.get_deck_config(DeckConfID(1), false)?.unwrap().inner;
+        assert_eq!(inner.initial_ease.to_string(), "1.31");
+        assert_eq!(inner.hard_multiplier.to_string(), "1.2");
+        assert_eq!(inner.interval_multiplier.to_string(), "2");
+        assert_eq!(inner.maximum_review_interval, 1);
+        assert_eq!(inner.minimum_review_interval, 1);
+        assert_eq!(inner.learn_steps, &[1.0 / 60.0, 10.0]);
+
+        Ok(())
+    }
+}
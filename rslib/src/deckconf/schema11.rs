@@ -2,7 +2,7 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use super::{DeckConf, DeckConfID};
-use crate::backend_proto::deck_config_inner::NewCardOrder;
+use crate::backend_proto::deck_config_inner::{NewCardOrder, SchedulingAlgorithm};
 use crate::backend_proto::DeckConfigInner;
 use crate::{serde::default_on_invalid, timestamp::TimestampSecs, types::Usn};
 use serde_aux::field_attributes::deserialize_number_from_string;
@@ -39,7 +39,7 @@ pub struct DeckConfSchema11 {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct NewConfSchema11 {
-    #[serde(default)]
+    #[serde(default = "bury_default")]
     bury: bool,
     #[serde(deserialize_with = "default_on_invalid")]
     delays: Vec<f32>,
@@ -85,6 +85,23 @@ impl Default for NewCardOrderSchema11 {
     }
 }
 
+#[derive(Serialize_repr, Deserialize_repr, Debug, PartialEq, Clone)]
+#[repr(u8)]
+pub enum SchedulingAlgorithmSchema11 {
+    Sm2 = 0,
+}
+
+impl Default for SchedulingAlgorithmSchema11 {
+    fn default() -> Self {
+        Self::Sm2
+    }
+}
+
+/// Older clients treat a missing bury option as enabled.
+fn bury_default() -> bool {
+    true
+}
+
 fn hard_factor_default() -> f32 {
     1.2
 }
@@ -92,7 +109,7 @@ fn hard_factor_default() -> f32 {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct RevConfSchema11 {
-    #[serde(default)]
+    #[serde(default = "bury_default")]
     bury: bool,
     ease4: f32,
     ivl_fct: f32,
@@ -101,6 +118,8 @@ pub struct RevConfSchema11 {
     pub(crate) per_day: u32,
     #[serde(default = "hard_factor_default")]
     hard_factor: f32,
+    #[serde(default, deserialize_with = "default_on_invalid")]
+    algorithm: SchedulingAlgorithmSchema11,
 
     #[serde(flatten)]
     other: HashMap<String, Value>,
@@ -143,6 +162,7 @@ impl Default for RevConfSchema11 {
             max_ivl: 36500,
             per_day: 200,
             hard_factor: 1.2,
+            algorithm: SchedulingAlgorithmSchema11::default(),
             other: Default::default(),
         }
     }
@@ -252,6 +272,9 @@ impl From<DeckConfSchema11> for DeckConf {
                 } as i32,
                 leech_action: c.lapse.leech_action as i32,
                 leech_threshold: c.lapse.leech_fails,
+                scheduling_algorithm: match c.rev.algorithm {
+                    SchedulingAlgorithmSchema11::Sm2 => SchedulingAlgorithm::Sm2,
+                } as i32,
                 other: other_bytes,
             },
         }
@@ -285,6 +308,7 @@ impl From<DeckConf> for DeckConfSchema11 {
         }
         let i = c.inner;
         let new_order = i.new_card_order();
+        let algorithm = i.scheduling_algorithm();
         DeckConfSchema11 {
             id: c.id,
             mtime: c.mtime_secs,
@@ -318,6 +342,9 @@ impl From<DeckConf> for DeckConfSchema11 {
                 max_ivl: i.maximum_review_interval,
                 per_day: i.reviews_per_day,
                 hard_factor: i.hard_multiplier,
+                algorithm: match algorithm {
+                    SchedulingAlgorithm::Sm2 => SchedulingAlgorithmSchema11::Sm2,
+                },
                 other: rev_other,
             },
             lapse: LapseConfSchema11 {
@@ -335,3 +362,30 @@ impl From<DeckConf> for DeckConfSchema11 {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn missing_bury_options() {
+        let mut conf = serde_json::to_value(DeckConfSchema11::default()).unwrap();
+        let conf11: DeckConfSchema11 = serde_json::from_value(conf.clone()).unwrap();
+        let conf15: DeckConf = conf11.into();
+        assert!(!conf15.inner.bury_new);
+        assert!(!conf15.inner.bury_reviews);
+
+        conf["new"].as_object_mut().unwrap().remove("bury");
+        conf["rev"] = json!({
+            "ease4": 1.3,
+            "ivlFct": 1.0,
+            "maxIvl": 36500,
+            "perDay": 200,
+        });
+        let conf11: DeckConfSchema11 = serde_json::from_value(conf).unwrap();
+        let conf15: DeckConf = conf11.into();
+        assert!(conf15.inner.bury_new);
+        assert!(conf15.inner.bury_reviews);
+    }
+}
@@ -441,14 +441,17 @@ impl Collection {
 
     fn return_cards_to_home_deck(&mut self, cids: &[CardID]) -> Result<()> {
         let sched = self.sched_ver();
+        let usn = self.usn()?;
+        let mut cards = vec![];
         for cid in cids {
             if let Some(mut card) = self.storage.get_card(*cid)? {
                 // fixme: undo
                 card.return_home(sched);
-                self.storage.update_card(&card)?;
+                card.set_modified(usn);
+                cards.push(card);
             }
         }
-        Ok(())
+        self.storage.update_cards(&cards)
     }
 
     pub fn get_all_deck_names(&self, skip_empty_default: bool) -> Result<Vec<(DeckID, String)>> {
@@ -499,6 +502,22 @@ impl Collection {
         Ok(())
     }
 
+    /// Raise or lower the new and review limits of a deck, its parents
+    /// and its children for today only. The change is stored in the decks'
+    /// 'done today' counts, so it is discarded when the day rolls over.
+    pub fn extend_limits_for_today(
+        &mut self,
+        did: DeckID,
+        new_delta: i32,
+        review_delta: i32,
+    ) -> Result<()> {
+        self.transact(None, |col| {
+            let today = col.current_due_day(0)?;
+            let usn = col.usn()?;
+            col.extend_limits(today, usn, did, new_delta, review_delta)
+        })
+    }
+
     /// Modify the deck's limits by adjusting the 'done today' count.
     /// Positive values increase the limit, negative value decrease it.
     /// Caller should ensure a transaction.
@@ -4,7 +4,7 @@
 use crate::i18n::{tr_strs, I18n, TR};
 pub use failure::{Error, Fail};
 use reqwest::StatusCode;
-use std::io;
+use std::{io, ops::Range};
 
 pub type Result<T> = std::result::Result<T, AnkiError>;
 
@@ -55,8 +55,13 @@ pub enum AnkiError {
     #[fail(display = "Unable to place item in/under a filtered deck.")]
     DeckIsFiltered,
 
-    #[fail(display = "Invalid search.")]
-    SearchError(Option<String>),
+    #[fail(display = "Invalid search: {:?} {}", kind, info)]
+    SearchError {
+        info: String,
+        kind: SearchErrorKind,
+        /// byte offsets of the offending text in the search, if known
+        span: Option<Range<usize>>,
+    },
 }
 
 // error helpers
@@ -113,11 +118,19 @@ impl AnkiError {
                 DBErrorKind::Locked => "Anki already open, or media currently syncing.".into(),
                 _ => format!("{:?}", self),
             },
-            AnkiError::SearchError(details) => {
-                if let Some(details) = details {
-                    details.to_owned()
-                } else {
-                    i18n.tr(TR::SearchInvalid).to_string()
+            AnkiError::SearchError { info, kind, .. } => {
+                let term = tr_strs!["term"=>info];
+                match kind {
+                    SearchErrorKind::Regex => info.into(),
+                    SearchErrorKind::UnclosedQuote => i18n.trn(TR::SearchUnclosedQuote, term),
+                    SearchErrorKind::UnclosedGroup => i18n.trn(TR::SearchUnclosedGroup, term),
+                    SearchErrorKind::UnopenedGroup => i18n.trn(TR::SearchUnopenedGroup, term),
+                    SearchErrorKind::MisplacedBoolean => i18n.trn(TR::SearchMisplacedBoolean, term),
+                    SearchErrorKind::InvalidArgument => i18n.trn(TR::SearchInvalidArgument, term),
+                    SearchErrorKind::InvalidPropertyOperator => {
+                        i18n.trn(TR::SearchInvalidPropertyOperator, term)
+                    }
+                    SearchErrorKind::Other => i18n.tr(TR::SearchInvalid).to_string(),
                 }
             }
             _ => format!("{:?}", self),
@@ -149,6 +162,11 @@ impl From<io::Error> for AnkiError {
 
 impl From<rusqlite::Error> for AnkiError {
     fn from(err: rusqlite::Error) -> Self {
+        if let rusqlite::Error::SqliteFailure(error, _) = &err {
+            if error.code == rusqlite::ErrorCode::OperationInterrupted {
+                return AnkiError::Interrupted;
+            }
+        }
         if let rusqlite::Error::SqliteFailure(error, Some(reason)) = &err {
             if error.code == rusqlite::ErrorCode::DatabaseBusy {
                 return AnkiError::DBError {
@@ -157,7 +175,11 @@ impl From<rusqlite::Error> for AnkiError {
                 };
             }
             if reason.contains("regex parse error") {
-                return AnkiError::SearchError(Some(reason.to_owned()));
+                return AnkiError::SearchError {
+                    info: reason.to_owned(),
+                    kind: SearchErrorKind::Regex,
+                    span: None,
+                };
             }
         }
         AnkiError::DBError {
@@ -176,6 +198,25 @@ impl From<rusqlite::types::FromSqlError> for AnkiError {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SearchErrorKind {
+    /// a " without a closing "
+    UnclosedQuote,
+    /// a ( without a closing )
+    UnclosedGroup,
+    /// a ) without an opening (
+    UnopenedGroup,
+    /// and/or at the start or end of a group, or next to another and/or
+    MisplacedBoolean,
+    /// a qualified search like added:x with an argument that can't be parsed
+    InvalidArgument,
+    /// a prop: search without one of the supported comparisons, like prop:ivl>>3
+    InvalidPropertyOperator,
+    /// an invalid regular expression
+    Regex,
+    Other,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum NetworkErrorKind {
     Offline,
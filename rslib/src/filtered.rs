@@ -0,0 +1,260 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    card::{CardQueue, CardType},
+    config::SchedulerVersion,
+    decks::{Deck, DeckKind, FilteredDeck, FilteredSearchOrder, FilteredSearchTerm},
+    prelude::*,
+    search::SortMode,
+};
+
+/// Cards moved into a filtered deck are given due numbers starting here, so
+/// that they are shown before any other cards in the deck.
+const FIRST_POSITION: i32 = -100_000;
+
+/// Information required when moving cards into a filtered deck.
+pub(crate) struct DeckFilterContext<'a> {
+    pub target_deck: DeckID,
+    pub config: &'a FilteredDeck,
+    pub scheduler: SchedulerVersion,
+    pub today: u32,
+}
+
+impl Card {
+    pub(crate) fn move_into_filtered_deck(&mut self, ctx: &DeckFilterContext, position: i32) {
+        // cards already in a filtered deck are excluded by the search
+        if self.odid.0 != 0 {
+            return;
+        }
+        self.odid = self.did;
+        self.odue = self.due;
+        self.did = ctx.target_deck;
+
+        match ctx.scheduler {
+            SchedulerVersion::V1 => {
+                // due reviews stay in the review queue
+                self.queue = if self.ctype == CardType::Review && self.due <= ctx.today as i32 {
+                    CardQueue::Review
+                } else {
+                    CardQueue::New
+                };
+                self.due = position;
+            }
+            SchedulerVersion::V2 => {
+                // cards with a negative due number keep their position
+                if self.due > 0 {
+                    self.due = position;
+                }
+                if !ctx.config.reschedule {
+                    self.queue = CardQueue::Review;
+                }
+            }
+        }
+    }
+}
+
+impl FilteredSearchOrder {
+    fn order_clause(self, today: u32) -> String {
+        match self {
+            FilteredSearchOrder::OldestFirst => {
+                "(select max(id) from revlog where cid=c.id)".to_string()
+            }
+            FilteredSearchOrder::Random => "random()".to_string(),
+            FilteredSearchOrder::IntervalsAscending => "c.ivl".to_string(),
+            FilteredSearchOrder::IntervalsDescending => "c.ivl desc".to_string(),
+            FilteredSearchOrder::Lapses => "c.lapses desc".to_string(),
+            FilteredSearchOrder::Added => "n.id".to_string(),
+            FilteredSearchOrder::ReverseAdded => "n.id desc".to_string(),
+            FilteredSearchOrder::Due => "c.due, c.ord".to_string(),
+            FilteredSearchOrder::DuePriority => format!(
+                "(case when c.queue={queue} and c.due <= {today} \
+                 then (c.ivl / cast({today}-c.due+0.001 as real)) else 100000+c.due end)",
+                queue = CardQueue::Review as i8,
+                today = today
+            ),
+        }
+    }
+}
+
+impl FilteredSearchTerm {
+    /// The stored search, limited to cards that can be moved into a
+    /// filtered deck.
+    fn search_for_building(&self, scheduler: SchedulerVersion) -> String {
+        let mut search = if self.search.trim().is_empty() {
+            String::new()
+        } else {
+            format!("({}) ", self.search)
+        };
+        search.push_str("-is:suspended -is:buried -deck:filtered");
+        if scheduler == SchedulerVersion::V1 {
+            search.push_str(" -is:learn");
+        }
+        search
+    }
+}
+
+impl Collection {
+    /// Add or update a filtered deck, and then rebuild it. Returns the
+    /// number of cards that were gathered.
+    pub fn add_or_update_filtered_deck(&mut self, deck: &mut Deck) -> Result<u32> {
+        if !deck.is_filtered() {
+            return Err(AnkiError::invalid_input("deck is not filtered"));
+        }
+        self.add_or_update_deck(deck)?;
+        self.rebuild_filtered_deck(deck.id)
+    }
+
+    /// Return any cards in the deck to their home decks, and then gather
+    /// cards again using the deck's searches. Returns the number of cards
+    /// that were gathered.
+    pub fn rebuild_filtered_deck(&mut self, did: DeckID) -> Result<u32> {
+        let deck = self.get_filtered_deck(did)?;
+        let config = match &deck.kind {
+            DeckKind::Filtered(filtered) => filtered,
+            DeckKind::Normal(_) => unreachable!(),
+        };
+        let scheduler = self.sched_ver();
+        let today = self.timing_today()?.days_elapsed;
+
+        self.transact(None, |col| {
+            col.return_all_cards_in_filtered_deck_undoable(did)?;
+            let ctx = DeckFilterContext {
+                target_deck: did,
+                config,
+                scheduler,
+                today,
+            };
+            // the v1 scheduler only supports a single search
+            let term_count = match scheduler {
+                SchedulerVersion::V1 => 1,
+                SchedulerVersion::V2 => config.search_terms.len(),
+            };
+            let mut position = FIRST_POSITION;
+            for term in config.search_terms.iter().take(term_count) {
+                position = col.move_cards_matching_term(&ctx, term, position)?;
+            }
+            Ok((position - FIRST_POSITION) as u32)
+        })
+    }
+
+    /// Return all cards in a filtered deck to their home decks.
+    pub fn empty_filtered_deck(&mut self, did: DeckID) -> Result<()> {
+        self.get_filtered_deck(did)?;
+        self.transact(None, |col| {
+            col.return_all_cards_in_filtered_deck_undoable(did)
+        })
+    }
+
+    fn get_filtered_deck(&mut self, did: DeckID) -> Result<Deck> {
+        match self.storage.get_deck(did)? {
+            Some(deck) if deck.is_filtered() => Ok(deck),
+            Some(_) => Err(AnkiError::invalid_input("deck is not filtered")),
+            None => Err(AnkiError::NotFound),
+        }
+    }
+
+    /// Move cards matching the term into the deck, returning the position
+    /// of the next card.
+    fn move_cards_matching_term(
+        &mut self,
+        ctx: &DeckFilterContext,
+        term: &FilteredSearchTerm,
+        mut position: i32,
+    ) -> Result<i32> {
+        let order = FilteredSearchOrder::from_i32(term.order).unwrap_or(FilteredSearchOrder::Due);
+        let cids = self.search_cards_page(
+            term.search_for_building(ctx.scheduler).as_str(),
+            SortMode::Custom(order.order_clause(ctx.today)),
+            0,
+            term.limit,
+        )?;
+        for cid in cids {
+            if let Some(original) = self.storage.get_card(cid)? {
+                let mut card = original.clone();
+                card.move_into_filtered_deck(ctx, position);
+                self.update_card(&mut card, &original)?;
+                position += 1;
+            }
+        }
+        Ok(position)
+    }
+
+    /// Like return_all_cards_in_filtered_deck(), but records the changes
+    /// for undo. Caller must create transaction.
+    fn return_all_cards_in_filtered_deck_undoable(&mut self, did: DeckID) -> Result<()> {
+        let scheduler = self.sched_ver();
+        for cid in self.storage.all_cards_in_single_deck(did)? {
+            if let Some(original) = self.storage.get_card(cid)? {
+                let mut card = original.clone();
+                card.return_home(scheduler);
+                self.update_card(&mut card, &original)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, config::ConfigKey};
+
+    #[test]
+    fn building() -> Result<()> {
+        let mut col = open_test_collection();
+        col.set_config(ConfigKey::SchedulerVersion, &SchedulerVersion::V2)?;
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        for text in &["one", "two", "three"] {
+            let mut note = nt.new_note();
+            note.fields[0] = (*text).into();
+            col.add_note(&mut note, DeckID(1))?;
+        }
+        let today = col.timing_today()?.days_elapsed as i32;
+        let review = col.search_cards("front:two", SortMode::NoOrder)?[0];
+        col.get_and_update_card(review, |card| {
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Review;
+            card.due = today + 5;
+            card.ivl = 1;
+            Ok(())
+        })?;
+        let suspended = col.search_cards("front:three", SortMode::NoOrder)?;
+        col.suspend_cards(&suspended)?;
+
+        let mut deck = Deck::new_filtered();
+        deck.name = "Filtered".into();
+        if let DeckKind::Filtered(filtered) = &mut deck.kind {
+            filtered.search_terms[0].search = "deck:default".into();
+        }
+        assert_eq!(col.add_or_update_filtered_deck(&mut deck)?, 2);
+
+        let card = col.storage.get_card(review)?.unwrap();
+        assert_eq!(card.did, deck.id);
+        assert_eq!(card.odid, DeckID(1));
+        assert_eq!(card.odue, today + 5);
+        assert_eq!(card.queue, CardQueue::Review);
+        assert_eq!(col.storage.get_card(suspended[0])?.unwrap().did, DeckID(1));
+
+        // rebuilding doesn't duplicate or lose cards
+        assert_eq!(col.rebuild_filtered_deck(deck.id)?, 2);
+        assert_eq!(
+            col.search_cards("deck:filtered", SortMode::NoOrder)?.len(),
+            2
+        );
+
+        // emptying restores the original deck and due number
+        col.empty_filtered_deck(deck.id)?;
+        let card = col.storage.get_card(review)?.unwrap();
+        assert_eq!(card.did, DeckID(1));
+        assert_eq!(card.odid, DeckID(0));
+        assert_eq!(card.due, today + 5);
+        assert_eq!(card.odue, 0);
+        assert_eq!(card.queue, CardQueue::Review);
+
+        // only filtered decks can be rebuilt
+        assert!(col.rebuild_filtered_deck(DeckID(1)).is_err());
+
+        Ok(())
+    }
+}
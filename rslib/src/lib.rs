@@ -5,6 +5,7 @@
 
 pub mod backend;
 mod backend_proto;
+pub mod backup;
 pub mod card;
 pub mod cloze;
 pub mod collection;
@@ -13,6 +14,7 @@ pub mod dbcheck;
 pub mod deckconf;
 pub mod decks;
 pub mod err;
+pub mod filtered;
 pub mod findreplace;
 pub mod i18n;
 pub mod latex;
@@ -261,7 +261,10 @@ impl Collection {
     }
 
     pub fn update_note(&mut self, note: &mut Note) -> Result<()> {
-        if let Some(existing_note) = self.storage.get_note(note.id)? {
+        if let Some(mut existing_note) = self.storage.get_note(note.id)? {
+            // derived fields are not read back from the database
+            existing_note.sort_field = note.sort_field.clone();
+            existing_note.checksum = note.checksum;
             if &existing_note == note {
                 // nothing to do
                 return Ok(());
@@ -322,7 +325,7 @@ impl Collection {
     }
 
     /// Remove provided notes, and any cards that use them.
-    pub(crate) fn remove_notes(&mut self, nids: &[NoteID]) -> Result<()> {
+    pub fn remove_notes(&mut self, nids: &[NoteID]) -> Result<()> {
         let usn = self.usn()?;
         self.transact(None, |col| {
             for nid in nids {
@@ -437,8 +440,13 @@ impl Collection {
 mod test {
     use super::{anki_base91, field_checksum};
     use crate::{
-        collection::open_test_collection, config::ConfigKey, decks::DeckID, err::Result,
+        collection::{open_test_collection, Collection},
+        config::ConfigKey,
+        decks::DeckID,
+        err::Result,
         search::SortMode,
+        timestamp::TimestampSecs,
+        types::Usn,
     };
 
     #[test]
@@ -536,4 +544,51 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn adding_updating_removing() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields = vec!["<b>front</b>".into(), "back".into()];
+        note.tags = vec!["b a".into(), "A".into()];
+        col.add_note(&mut note, DeckID(1))?;
+        assert_eq!(note.tags, vec!["a", "b"]);
+        assert_eq!(note.usn, Usn(-1));
+        let nid = note.id;
+        let stored = |col: &Collection| -> Result<(String, u32)> {
+            col.storage
+                .db
+                .query_row("select sfld, csum from notes where id = ?", &[nid], |r| {
+                    Ok((r.get(0)?, r.get(1)?))
+                })
+                .map_err(Into::into)
+        };
+        assert_eq!(stored(&col)?, ("front".into(), field_checksum("front")));
+        assert_eq!(col.storage.all_cards_of_note(note.id)?.len(), 1);
+
+        // saving an unchanged note does nothing
+        note.mtime = TimestampSecs(0);
+        col.storage.update_note(&note)?;
+        col.update_note(&mut note)?;
+        assert_eq!(
+            col.storage.get_note(note.id)?.unwrap().mtime,
+            TimestampSecs(0)
+        );
+
+        note.fields[0] = "changed".into();
+        col.update_note(&mut note)?;
+        assert_ne!(
+            col.storage.get_note(note.id)?.unwrap().mtime,
+            TimestampSecs(0)
+        );
+        assert_eq!(stored(&col)?, ("changed".into(), field_checksum("changed")));
+
+        col.remove_notes(&[note.id])?;
+        assert!(col.storage.get_note(note.id)?.is_none());
+        assert!(col.storage.all_cards_of_note(note.id)?.is_empty());
+        assert_eq!(col.storage.pending_graves(Usn(-1))?.notes, vec![note.id]);
+
+        Ok(())
+    }
 }
@@ -188,6 +188,34 @@ impl NoteType {
         self.fields.push(NoteField::new(name));
     }
 
+    /// Fields keep their original ordinal until saved, so that the
+    /// content of existing notes can be moved to match.
+    pub(crate) fn remove_field(&mut self, idx: usize) -> Result<()> {
+        if idx >= self.fields.len() {
+            return Err(AnkiError::invalid_input("field index out of range"));
+        }
+        self.fields.remove(idx);
+        Ok(())
+    }
+
+    pub(crate) fn reposition_field(&mut self, idx: usize, new_idx: usize) -> Result<()> {
+        if idx >= self.fields.len() || new_idx >= self.fields.len() {
+            return Err(AnkiError::invalid_input("field index out of range"));
+        }
+        let field = self.fields.remove(idx);
+        self.fields.insert(new_idx, field);
+        Ok(())
+    }
+
+    fn reset_ords(&mut self) {
+        for (idx, field) in self.fields.iter_mut().enumerate() {
+            field.ord = Some(idx as u32);
+        }
+        for (idx, template) in self.templates.iter_mut().enumerate() {
+            template.ord = Some(idx as u32);
+        }
+    }
+
     pub(crate) fn add_template<S1, S2, S3>(&mut self, name: S1, qfmt: S2, afmt: S3)
     where
         S1: Into<String>,
@@ -197,6 +225,25 @@ impl NoteType {
         self.templates.push(CardTemplate::new(name, qfmt, afmt));
     }
 
+    /// Like remove_field(); the cards of a removed template are deleted when
+    /// the note type is saved.
+    pub(crate) fn remove_template(&mut self, idx: usize) -> Result<()> {
+        if idx >= self.templates.len() {
+            return Err(AnkiError::invalid_input("template index out of range"));
+        }
+        self.templates.remove(idx);
+        Ok(())
+    }
+
+    pub(crate) fn reposition_template(&mut self, idx: usize, new_idx: usize) -> Result<()> {
+        if idx >= self.templates.len() || new_idx >= self.templates.len() {
+            return Err(AnkiError::invalid_input("template index out of range"));
+        }
+        let template = self.templates.remove(idx);
+        self.templates.insert(new_idx, template);
+        Ok(())
+    }
+
     pub(crate) fn prepare_for_adding(&mut self) -> Result<()> {
         // defaults to 0
         if self.config.target_deck_id == 0 {
@@ -361,7 +408,9 @@ impl Collection {
     pub(crate) fn add_notetype_inner(&mut self, nt: &mut NoteType, usn: Usn) -> Result<()> {
         nt.prepare_for_adding()?;
         self.ensure_notetype_name_unique(nt, usn)?;
-        self.storage.add_new_notetype(nt)
+        self.storage.add_new_notetype(nt)?;
+        nt.reset_ords();
+        Ok(())
     }
 
     fn ensure_notetype_name_unique(&self, notetype: &mut NoteType, usn: Usn) -> Result<()> {
@@ -383,6 +432,20 @@ impl Collection {
     /// Saves changes to a note type. This will force a full sync if templates
     /// or fields have been added/removed/reordered.
     pub fn update_notetype(&mut self, nt: &mut NoteType, preserve_usn: bool) -> Result<()> {
+        self.update_notetype_with_progress(nt, preserve_usn, |_, _| ())
+    }
+
+    /// Like update_notetype(). If fields were changed, progress_fn is called
+    /// with the number of notes rewritten so far and the total.
+    pub fn update_notetype_with_progress<F>(
+        &mut self,
+        nt: &mut NoteType,
+        preserve_usn: bool,
+        progress_fn: F,
+    ) -> Result<()>
+    where
+        F: FnMut(usize, usize),
+    {
         let existing = self.get_notetype(nt.id)?;
         let norm = self.normalize_note_text();
         nt.prepare_for_update(existing.as_ref().map(AsRef::as_ref))?;
@@ -396,6 +459,7 @@ impl Collection {
                     existing_notetype.fields.len(),
                     existing_notetype.config.sort_field_idx,
                     norm,
+                    progress_fn,
                 )?;
                 col.update_cards_for_changed_templates(nt, existing_notetype.templates.len())?;
             }
@@ -415,7 +479,10 @@ impl Collection {
             col.state.notetype_cache.remove(&nt.id);
 
             Ok(())
-        })
+        })?;
+        // further changes are relative to what was saved
+        nt.reset_ords();
+        Ok(())
     }
 
     pub fn get_notetype_by_name(&mut self, name: &str) -> Result<Option<Arc<NoteType>>> {
@@ -452,12 +519,14 @@ impl Collection {
             .collect()
     }
 
+    /// Remove a notetype, and its notes and cards. This will force a full
+    /// sync.
     pub fn remove_notetype(&mut self, ntid: NoteTypeID) -> Result<()> {
-        // fixme: currently the storage layer is taking care of removing the notes and cards,
-        // but we need to do it in this layer in the future for undo handling
         self.transact(None, |col| {
-            col.storage.set_schema_modified()?;
+            col.set_schema_modified()?;
             col.state.notetype_cache.remove(&ntid);
+            let nids = col.storage.note_ids_of_notetype(ntid)?;
+            col.remove_notes(&nids)?;
             col.storage.remove_notetype(ntid)?;
             let all = col.storage.get_all_notetype_names()?;
             if all.is_empty() {
@@ -470,3 +539,33 @@ impl Collection {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, decks::DeckID};
+
+    #[test]
+    fn adding_and_removing() -> Result<()> {
+        let mut col = open_test_collection();
+        let mut nt = all_stock_notetypes(&col.i18n).remove(0);
+        // names are made unique
+        col.add_notetype(&mut nt)?;
+        assert_eq!(nt.name, "Basic+");
+        let mut note = nt.new_note();
+        col.add_note(&mut note, DeckID(1))?;
+        let cid = col.storage.all_cards_of_note(note.id)?[0].id;
+
+        // notes and cards are removed with the notetype
+        col.remove_notetype(nt.id)?;
+        assert!(col.get_notetype(nt.id)?.is_none());
+        assert!(col.storage.get_note(note.id)?.is_none());
+        assert!(col.storage.get_card(cid)?.is_none());
+        let graves = col.storage.pending_graves(Usn(-1))?;
+        assert_eq!(graves.notes, vec![note.id]);
+        assert_eq!(graves.cards, vec![cid]);
+        assert!(col.get_notetype_by_name("Basic")?.is_some());
+
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@
 
 use super::{CardGenContext, NoteType};
 use crate::{collection::Collection, err::Result};
+use std::collections::HashMap;
 
 /// True if any ordinals added, removed or reordered.
 fn ords_changed(ords: &[Option<u32>], previous_len: usize) -> bool {
@@ -48,7 +49,8 @@ impl TemplateOrdChanges {
 }
 
 impl Collection {
-    /// Rewrite notes to match the updated field schema.
+    /// Rewrite notes to match the updated field schema, calling progress_fn
+    /// with the number of notes rewritten and the total.
     /// Caller must create transaction.
     pub(crate) fn update_notes_for_changed_fields(
         &mut self,
@@ -56,6 +58,7 @@ impl Collection {
         previous_field_count: usize,
         previous_sort_idx: u32,
         normalize_text: bool,
+        mut progress_fn: impl FnMut(usize, usize),
     ) -> Result<()> {
         let ords: Vec<_> = nt.fields.iter().map(|f| f.ord).collect();
         if !ords_changed(&ords, previous_field_count) {
@@ -67,17 +70,17 @@ impl Collection {
                     note.prepare_for_update(nt, normalize_text)?;
                     self.storage.update_note(&note)?;
                 }
-            } else {
-                // nothing to do
-                return Ok(());
             }
+            return Ok(());
         }
 
-        self.storage.set_schema_modified()?;
+        self.set_schema_modified()?;
 
         let nids = self.search_notes(&format!("mid:{}", nt.id))?;
         let usn = self.usn()?;
-        for nid in nids {
+        for (idx, nid) in nids.iter().enumerate() {
+            progress_fn(idx, nids.len());
+            let nid = *nid;
             let mut note = self.storage.get_note(nid)?.unwrap();
             note.fields = ords
                 .iter()
@@ -97,11 +100,13 @@ impl Collection {
             note.set_modified(usn);
             self.storage.update_note(&note)?;
         }
+        progress_fn(nids.len(), nids.len());
         Ok(())
     }
 
     /// Update cards after card templates added, removed or reordered.
     /// Does not remove cards where the template still exists but creates an empty card.
+    /// Notes that would be left without any cards are given a new one.
     /// Caller must create transaction.
     pub(crate) fn update_cards_for_changed_templates(
         &mut self,
@@ -114,19 +119,34 @@ impl Collection {
             return Ok(());
         }
 
-        self.storage.set_schema_modified()?;
+        self.set_schema_modified()?;
 
+        let usn = self.usn()?;
         let changes = TemplateOrdChanges::new(ords, previous_template_count as u32);
+        // note id -> deck of a removed card
+        let mut affected_notes = HashMap::new();
         if !changes.removed.is_empty() {
-            self.storage
-                .remove_cards_for_deleted_templates(nt.id, &changes.removed)?;
+            for card in self
+                .storage
+                .cards_of_notetype_templates(nt.id, &changes.removed)?
+            {
+                let did = if card.odid.0 > 0 { card.odid } else { card.did };
+                affected_notes.insert(card.nid, did);
+                self.remove_card_only(card, usn)?;
+            }
         }
         if !changes.moved.is_empty() {
             self.storage
                 .move_cards_for_repositioned_templates(nt.id, &changes.moved)?;
         }
 
-        let ctx = CardGenContext::new(nt, self.usn()?);
+        let ctx = CardGenContext::new(nt, usn);
+        for (nid, did) in affected_notes {
+            if self.storage.note_is_orphaned(nid)? {
+                let note = self.storage.get_note(nid)?.unwrap();
+                self.generate_cards_for_new_note(&ctx, &note, did)?;
+            }
+        }
         self.generate_cards_for_notetype(&ctx)?;
 
         Ok(())
@@ -136,7 +156,14 @@ impl Collection {
 #[cfg(test)]
 mod test {
     use super::{ords_changed, TemplateOrdChanges};
-    use crate::{collection::open_test_collection, decks::DeckID, err::Result, search::SortMode};
+    use crate::{
+        card::CardID,
+        collection::{open_test_collection, Collection},
+        decks::DeckID,
+        err::Result,
+        notes::NoteID,
+        search::SortMode,
+    };
 
     #[test]
     fn ord_changes() {
@@ -223,6 +250,39 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn field_repositioning() -> Result<()> {
+        let mut col = open_test_collection();
+        let mut nt = col
+            .storage
+            .get_notetype(col.get_current_notetype_id().unwrap())?
+            .unwrap();
+        let mut note = nt.new_note();
+        note.fields = vec!["one".into(), "two".into()];
+        col.add_note(&mut note, DeckID(1))?;
+
+        nt.add_field("three");
+        nt.reposition_field(2, 0)?;
+        nt.remove_field(2)?;
+        let mut progress = vec![];
+        col.update_notetype_with_progress(&mut nt, false, |current, total| {
+            progress.push((current, total))
+        })?;
+        assert_eq!(progress, vec![(0, 1), (1, 1)]);
+        let fields = |col: &Collection| -> Result<Vec<String>> {
+            Ok(col.storage.get_note(note.id)?.unwrap().fields)
+        };
+        assert_eq!(fields(&col)?, vec!["".to_string(), "one".into()]);
+
+        // the saved order is used for later changes
+        nt.reposition_field(1, 0)?;
+        col.update_notetype(&mut nt, false)?;
+        assert_eq!(fields(&col)?, vec!["one".to_string(), "".into()]);
+        assert!(nt.remove_field(2).is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn field_renaming_and_deleting() -> Result<()> {
         let mut col = open_test_collection();
@@ -276,4 +336,63 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn template_repositioning_and_removal() -> Result<()> {
+        let mut col = open_test_collection();
+        let mut nt = col
+            .storage
+            .get_notetype(col.get_current_notetype_id().unwrap())?
+            .unwrap();
+        let mut note = nt.new_note();
+        note.fields = vec!["one".into(), "two".into()];
+        col.add_note(&mut note, DeckID(1))?;
+        let cards = |col: &Collection, nid: NoteID| -> Result<Vec<(CardID, u16)>> {
+            let mut cards: Vec<_> = col
+                .storage
+                .all_cards_of_note(nid)?
+                .into_iter()
+                .map(|c| (c.id, c.ord))
+                .collect();
+            cards.sort_by_key(|c| c.1);
+            Ok(cards)
+        };
+
+        nt.add_template("card 2", "{{Front}}", "");
+        col.update_notetype(&mut nt, false)?;
+        let original = cards(&col, note.id)?;
+        assert_eq!(original.len(), 2);
+
+        // cards follow their templates
+        nt.reposition_template(1, 0)?;
+        col.update_notetype(&mut nt, false)?;
+        assert_eq!(
+            cards(&col, note.id)?,
+            vec![(original[1].0, 0), (original[0].0, 1)]
+        );
+
+        // and are removed with them
+        nt.remove_template(1)?;
+        col.update_notetype(&mut nt, false)?;
+        assert_eq!(cards(&col, note.id)?, vec![(original[1].0, 0)]);
+        assert!(col.storage.get_card(original[0].0)?.is_none());
+
+        // a note whose only card is removed is given a new one
+        nt.add_template("back", "{{Back}}", "");
+        col.update_notetype(&mut nt, false)?;
+        let mut note2 = nt.new_note();
+        note2.fields = vec!["".into(), "two".into()];
+        col.add_note(&mut note2, DeckID(1))?;
+        let removed = cards(&col, note2.id)?;
+        assert_eq!(removed.iter().map(|c| c.1).collect::<Vec<_>>(), vec![1]);
+        nt.remove_template(1)?;
+        col.update_notetype(&mut nt, false)?;
+        let regenerated = cards(&col, note2.id)?;
+        assert_eq!(regenerated.len(), 1);
+        assert_eq!(regenerated[0].1, 0);
+        assert_ne!(regenerated[0].0, removed[0].0);
+        assert!(nt.remove_template(1).is_err());
+
+        Ok(())
+    }
 }
@@ -44,6 +44,7 @@ impl Collection {
             time_limit_secs: self.get_answer_time_limit_secs(),
             new_timezone: self.get_creation_mins_west().is_some(),
             day_learn_first: self.get_day_learn_first(),
+            load_balance: self.get_load_balance(),
         })
     }
 
@@ -54,6 +55,7 @@ impl Collection {
         let s = settings;
 
         self.set_day_learn_first(s.day_learn_first)?;
+        self.set_load_balance(s.load_balance)?;
         self.set_answer_time_limit_secs(s.time_limit_secs)?;
         self.set_show_due_counts(s.show_remaining_due_counts)?;
         self.set_show_intervals_above_buttons(s.show_intervals_on_buttons)?;
@@ -42,6 +42,8 @@ pub enum RevlogReviewKind {
     Review = 1,
     Relearning = 2,
     EarlyReview = 3,
+    /// The card was rescheduled or reset by the user.
+    Manual = 4,
 }
 
 impl Default for RevlogReviewKind {
@@ -0,0 +1,148 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::answering::Rating;
+use crate::deckconf::{DeckConfigInner, SchedulingAlgorithm};
+
+/// A review card at the time it is answered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReviewState {
+    /// the current interval in days
+    pub interval: u32,
+    /// eg 2.5
+    pub ease_factor: f64,
+    /// days since the card became due, or 0 if it is not yet due
+    pub days_late: u32,
+    /// days since the card was last reviewed
+    pub elapsed_days: f64,
+}
+
+/// New intervals in days, before the interval modifier, fuzz and the
+/// config limits are applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReviewIntervals {
+    pub hard: f64,
+    pub good: f64,
+    pub easy: f64,
+}
+
+/// Computes the intervals and ease of review cards. Learning steps,
+/// fuzz and the interval limits of the deck config are handled by the
+/// caller, and apply to every algorithm.
+pub trait Scheduler: Sync {
+    /// Intervals for a card answered on or after its due date.
+    fn review_intervals(&self, state: &ReviewState, config: &DeckConfigInner) -> ReviewIntervals;
+
+    /// The interval for a card in a filtered deck answered before its
+    /// due date. Not called for Again.
+    fn early_review_interval(
+        &self,
+        state: &ReviewState,
+        config: &DeckConfigInner,
+        rating: Rating,
+    ) -> f64;
+
+    /// The interval a card is given once it has been relearnt.
+    fn lapse_interval(&self, state: &ReviewState, config: &DeckConfigInner) -> f64;
+
+    /// The ease factor in thousandths after a successful review.
+    fn ease_after_review(&self, ease_factor: u16, rating: Rating) -> u16;
+
+    /// The ease factor in thousandths after the card is forgotten.
+    fn ease_after_lapse(&self, ease_factor: u16) -> u16;
+}
+
+/// The algorithm selected by a deck config.
+pub(crate) fn scheduler_for_config(config: &DeckConfigInner) -> &'static dyn Scheduler {
+    match config.scheduling_algorithm() {
+        SchedulingAlgorithm::Sm2 => &Sm2,
+    }
+}
+
+/// The SM-2 derived algorithm used by the v2 scheduler.
+pub struct Sm2;
+
+/// The lowest ease factor a card can reach.
+const MINIMUM_EASE_FACTOR: u16 = 1300;
+
+impl Scheduler for Sm2 {
+    fn review_intervals(&self, state: &ReviewState, config: &DeckConfigInner) -> ReviewIntervals {
+        let ivl = state.interval as f64;
+        let days_late = state.days_late as f64;
+        ReviewIntervals {
+            hard: ivl * config.hard_multiplier(),
+            good: (ivl + (days_late / 2.0).floor()) * state.ease_factor,
+            easy: (ivl + days_late) * state.ease_factor * config.easy_multiplier(),
+        }
+    }
+
+    fn early_review_interval(
+        &self,
+        state: &ReviewState,
+        config: &DeckConfigInner,
+        rating: Rating,
+    ) -> f64 {
+        let (factor, min_new_ivl, easy_bonus) = match rating {
+            Rating::Hard => {
+                let hard_factor = config.hard_multiplier();
+                // hard shouldn't decrease the interval by more than half
+                // of the normal factor
+                (hard_factor, hard_factor / 2.0, 1.0)
+            }
+            Rating::Easy => {
+                let easy = config.easy_multiplier();
+                // eg 1.3 -> 1.15
+                (state.ease_factor, 1.0, easy - (easy - 1.0) / 2.0)
+            }
+            _ => (state.ease_factor, 1.0, 1.0),
+        };
+
+        let ivl = (state.elapsed_days * factor).max(1.0);
+        (state.interval as f64 * min_new_ivl).max(ivl) * easy_bonus
+    }
+
+    fn lapse_interval(&self, state: &ReviewState, config: &DeckConfigInner) -> f64 {
+        state.interval as f64 * config.lapse_multiplier()
+    }
+
+    fn ease_after_review(&self, ease_factor: u16, rating: Rating) -> u16 {
+        let factor = ease_factor as i32
+            + match rating {
+                Rating::Hard => -150,
+                Rating::Easy => 150,
+                _ => 0,
+            };
+        factor.max(MINIMUM_EASE_FACTOR as i32) as u16
+    }
+
+    fn ease_after_lapse(&self, ease_factor: u16) -> u16 {
+        ease_factor.saturating_sub(200).max(MINIMUM_EASE_FACTOR)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::deckconf::DeckConf;
+
+    #[test]
+    fn sm2() {
+        let config = DeckConf::default().inner;
+        let state = ReviewState {
+            interval: 100,
+            ease_factor: 2.5,
+            days_late: 10,
+            elapsed_days: 110.0,
+        };
+        let scheduler = scheduler_for_config(&config);
+        let intervals = scheduler.review_intervals(&state, &config);
+        // in tenths of a day
+        let tenths = |ivl: f64| (ivl * 10.0).round() as u32;
+        assert_eq!(tenths(intervals.hard), 1200);
+        assert_eq!(tenths(intervals.good), 2625);
+        assert_eq!(tenths(intervals.easy), 3575);
+        assert_eq!(scheduler.ease_after_review(2500, Rating::Hard), 2350);
+        assert_eq!(scheduler.ease_after_review(1400, Rating::Hard), 1300);
+        assert_eq!(scheduler.ease_after_lapse(2500), 2300);
+    }
+}
@@ -0,0 +1,1088 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    backend_proto as pb,
+    card::{CardQueue, CardType},
+    deckconf::{DeckConf, LeechAction},
+    decks::DeckKind,
+    prelude::*,
+    revlog::{RevlogEntry, RevlogReviewKind},
+    sched::{
+        algorithm::{scheduler_for_config, ReviewState, Scheduler},
+        states::{SchedulingState, SchedulingStates},
+        steps::step_secs,
+        timespan::answer_button_time,
+        undo_review::ReviewUndo,
+    },
+    undo::UNDO_LIMIT,
+};
+use num_enum::TryFromPrimitive;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{collections::HashMap, convert::TryFrom};
+
+/// The button the user pressed when answering a card.
+#[derive(Debug, PartialEq, Clone, Copy, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Rating {
+    Again = 1,
+    Hard = 2,
+    Good = 3,
+    Easy = 4,
+}
+
+impl Rating {
+    pub fn from_button(button: u32) -> Result<Self> {
+        Rating::try_from(button as u8).map_err(|_| AnkiError::invalid_input("invalid rating"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CardAnswer {
+    pub card_id: CardID,
+    pub rating: Rating,
+    pub answered_at: TimestampMillis,
+    pub milliseconds_taken: u32,
+    /// Seeds the random spread applied to the new interval. If not
+    /// provided, the seed is derived from the card's id and review count,
+    /// so siblings answered on the same day don't all land on the same
+    /// due date.
+    pub fuzz_seed: Option<u64>,
+    /// Replaces the state the rating would have produced, eg one taken
+    /// from scheduling_states() and adjusted by an add-on.
+    pub custom_state: Option<SchedulingState>,
+}
+
+/// Scheduling settings that apply to a card, and the time it was answered.
+struct AnswerContext {
+    /// the config of the card's home deck
+    config: DeckConf,
+    /// the algorithm selected by the config
+    scheduler: &'static dyn Scheduler,
+    /// if the card is in a filtered deck that doesn't reschedule cards,
+    /// the delay in seconds before a failed card is shown again
+    preview_delay: Option<u32>,
+    now: TimestampSecs,
+    today: u32,
+    next_day_at: i64,
+    /// if load balancing is enabled, the number of reviews due on each
+    /// day after today
+    review_load: Option<HashMap<i32, u32>>,
+}
+
+/// Tracks the changes made to a card while it is being answered.
+struct CardStateUpdater<'a> {
+    card: Card,
+    ctx: &'a AnswerContext,
+    /// the interval before the card was answered, for the review log
+    last_ivl: i32,
+    leeched: bool,
+    rng: StdRng,
+    /// True when predicting the outcome of an answer for display, in which
+    /// case no fuzz is applied and leeches are not handled.
+    simulating: bool,
+}
+
+impl Collection {
+    /// Answer a card using the v2 scheduler, updating its scheduling,
+    /// logging the review and updating the deck's daily counts.
+    /// Returns true if the card became a leech.
+    /// The answer can be reverted with undo_review().
+    pub fn answer_card(&mut self, answer: &CardAnswer) -> Result<bool> {
+        let (leeched, undo) = self.transact_review(|col| col.answer_card_inner(answer))?;
+        self.state.review_undo.push(undo);
+        if self.state.review_undo.len() > UNDO_LIMIT {
+            self.state.review_undo.remove(0);
+        }
+        Ok(leeched)
+    }
+
+    fn answer_card_inner(&mut self, answer: &CardAnswer) -> Result<(bool, ReviewUndo)> {
+        let original = self
+            .sched_storage()
+            .get_card(answer.card_id)?
+            .ok_or_else(|| AnkiError::invalid_input("no such card"))?;
+        if (original.queue as i8) < 0 {
+            return Err(AnkiError::invalid_input("card is suspended or buried"));
+        }
+        if answer.answered_at.0 <= 0 {
+            return Err(AnkiError::invalid_input("answer time not set"));
+        }
+        if let Some(state) = &answer.custom_state {
+            state.validate()?;
+        }
+        let mut ctx = self.answer_context(&original, answer.answered_at)?;
+        if ctx.preview_delay.is_none() && self.get_load_balance() {
+            ctx.review_load = Some(self.sched_storage().review_counts_by_day(ctx.today)?);
+        }
+        let mut updater = CardStateUpdater {
+            last_ivl: original.ivl as i32,
+            card: original.clone(),
+            ctx: &ctx,
+            leeched: false,
+            rng: StdRng::seed_from_u64(
+                answer
+                    .fuzz_seed
+                    .unwrap_or_else(|| (original.id.0 as u64).wrapping_add(original.reps as u64)),
+            ),
+            simulating: false,
+        };
+
+        let usn = self.usn()?;
+        let mut undo = ReviewUndo {
+            original: original.clone(),
+            answered: original.clone(),
+            revlog_id: None,
+            stats: None,
+            today: ctx.today,
+            leech_tag_added: false,
+            buried_siblings: vec![],
+        };
+        if let Some(delay) = ctx.preview_delay {
+            updater.answer_preview_card(answer.rating, delay);
+            if let Some(state) = &answer.custom_state {
+                updater.card.apply_scheduling_state(state);
+            }
+        } else {
+            let was_new = original.queue == CardQueue::New;
+            let mut entry = updater.answer(answer.rating)?;
+            if let Some(state) = &answer.custom_state {
+                updater.card.apply_scheduling_state(state);
+                entry.interval = match state.queue {
+                    CardQueue::Review | CardQueue::DayLearn => state.interval as i32,
+                    CardQueue::Learn => -(updater.seconds_until_due() as i32),
+                    _ => 0,
+                };
+                entry.ease_factor = state.ease_factor as u32;
+            }
+            let reviewed = matches!(
+                entry.review_kind,
+                RevlogReviewKind::Review | RevlogReviewKind::EarlyReview
+            );
+            let revlog_id = self.sched_storage().next_revlog_id(answer.answered_at)?;
+            self.sched_storage().add_revlog_entry(&RevlogEntry {
+                id: revlog_id,
+                cid: answer.card_id,
+                usn,
+                taken_millis: answer.milliseconds_taken,
+                ..entry
+            })?;
+            // charged to the deck the card was studied in, even if the
+            // answer moved it elsewhere
+            let stats = pb::UpdateStatsIn {
+                deck_id: original.did.0,
+                new_delta: if was_new { 1 } else { 0 },
+                review_delta: if reviewed { 1 } else { 0 },
+                millisecond_delta: answer.milliseconds_taken as i32,
+            };
+            self.update_deck_stats(ctx.today, usn, stats.clone())?;
+            undo.revlog_id = Some(revlog_id);
+            undo.stats = Some(stats);
+        }
+
+        let leeched = updater.leeched;
+        let mut card = updater.card;
+        if leeched {
+            undo.leech_tag_added = self.add_leech_tag(card.nid, usn)?;
+        }
+        self.update_card(&mut card, &original)?;
+        undo.buried_siblings = self.bury_siblings_of_inner(&card)?;
+        undo.answered = card;
+
+        Ok((leeched, undo))
+    }
+
+    /// The delay in seconds until the card would next be shown for each
+    /// of the four ratings, without any fuzz applied. 0 means the card
+    /// would leave a filtered deck that doesn't reschedule cards.
+    pub fn next_intervals(&mut self, cid: CardID) -> Result<[u32; 4]> {
+        let card = self
+            .sched_storage()
+            .get_card(cid)?
+            .ok_or_else(|| AnkiError::invalid_input("no such card"))?;
+        let ctx = self.answer_context(&card, TimestampMillis::now())?;
+        let mut intervals = [0; 4];
+        for (idx, rating) in [Rating::Again, Rating::Hard, Rating::Good, Rating::Easy]
+            .iter()
+            .enumerate()
+        {
+            intervals[idx] = match ctx.preview_delay {
+                Some(delay) if *rating == Rating::Again => delay,
+                Some(_) => 0,
+                None => {
+                    let mut updater = CardStateUpdater {
+                        last_ivl: card.ivl as i32,
+                        card: card.clone(),
+                        ctx: &ctx,
+                        leeched: false,
+                        rng: StdRng::seed_from_u64(0),
+                        simulating: true,
+                    };
+                    updater.answer(*rating)?;
+                    updater.seconds_until_due()
+                }
+            };
+        }
+        Ok(intervals)
+    }
+
+    /// The card's current scheduling, and the state each rating would
+    /// give it, without any fuzz applied. Front ends can adjust one of
+    /// the states and pass it back as CardAnswer::custom_state.
+    pub fn scheduling_states(&mut self, cid: CardID) -> Result<SchedulingStates> {
+        let card = self
+            .sched_storage()
+            .get_card(cid)?
+            .ok_or_else(|| AnkiError::invalid_input("no such card"))?;
+        let ctx = self.answer_context(&card, TimestampMillis::now())?;
+        let state_after = |rating| -> Result<SchedulingState> {
+            let mut updater = CardStateUpdater {
+                last_ivl: card.ivl as i32,
+                card: card.clone(),
+                ctx: &ctx,
+                leeched: false,
+                rng: StdRng::seed_from_u64(0),
+                simulating: true,
+            };
+            if let Some(delay) = ctx.preview_delay {
+                updater.answer_preview_card(rating, delay);
+            } else {
+                updater.answer(rating)?;
+            }
+            Ok(updater.card.scheduling_state())
+        };
+        Ok(SchedulingStates {
+            current: card.scheduling_state(),
+            again: state_after(Rating::Again)?,
+            hard: state_after(Rating::Hard)?,
+            good: state_after(Rating::Good)?,
+            easy: state_after(Rating::Easy)?,
+        })
+    }
+
+    /// The labels shown above the answer buttons, like "<10m" or "3.5mo".
+    pub fn answer_button_labels(&mut self, cid: CardID) -> Result<Vec<String>> {
+        let learn_ahead_secs = self.learn_ahead_secs();
+        Ok(self
+            .next_intervals(cid)?
+            .iter()
+            .map(|&secs| {
+                if secs == 0 {
+                    self.i18n.tr(TR::SchedulingEnd).into()
+                } else {
+                    let label = answer_button_time(secs as f32, &self.i18n);
+                    if secs < learn_ahead_secs {
+                        format!("<{}", label)
+                    } else {
+                        label
+                    }
+                }
+            })
+            .collect())
+    }
+
+    fn answer_context(
+        &mut self,
+        card: &Card,
+        answered_at: TimestampMillis,
+    ) -> Result<AnswerContext> {
+        let deck = self
+            .get_deck(card.did)?
+            .ok_or_else(|| AnkiError::invalid_input("missing deck"))?;
+        let preview_delay = match &deck.kind {
+            DeckKind::Filtered(filtered) if !filtered.reschedule => {
+                Some(filtered.preview_delay * 60)
+            }
+            _ => None,
+        };
+        let home_deck = if card.odid.0 != 0 {
+            self.get_deck(card.odid)?
+                .ok_or_else(|| AnkiError::invalid_input("missing home deck"))?
+        } else {
+            deck
+        };
+        let config = self
+            .get_deck_config(home_deck.config_id().unwrap_or(DeckConfID(1)), true)?
+            .unwrap();
+        let now = answered_at.as_secs();
+        let timing = self.timing_for_timestamp(now)?;
+
+        Ok(AnswerContext {
+            scheduler: scheduler_for_config(&config.inner),
+            config,
+            preview_delay,
+            now,
+            today: timing.days_elapsed,
+            next_day_at: timing.next_day_at,
+            review_load: None,
+        })
+    }
+
+    /// Returns false if the note was already tagged.
+    fn add_leech_tag(&mut self, nid: NoteID, usn: Usn) -> Result<bool> {
+        let mut note = self
+            .sched_storage()
+            .get_note(nid)?
+            .ok_or_else(|| AnkiError::invalid_input("missing note"))?;
+        if note
+            .tags
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case("leech"))
+        {
+            return Ok(false);
+        }
+        let nt = self
+            .get_notetype(note.ntid)?
+            .ok_or_else(|| AnkiError::invalid_input("missing note type"))?;
+        note.tags.push("leech".into());
+        let norm = self.normalize_note_text();
+        self.update_note_inner_without_cards(&mut note, &nt, usn, true, norm)?;
+        Ok(true)
+    }
+}
+
+impl CardStateUpdater<'_> {
+    /// Apply the answer to the card, returning a review log entry
+    /// without its id, card id, usn or time taken filled in.
+    fn answer(&mut self, rating: Rating) -> Result<RevlogEntry> {
+        self.card.reps += 1;
+
+        if self.card.queue == CardQueue::New {
+            // came from the new queue, move to learning
+            self.card.queue = CardQueue::Learn;
+            self.card.ctype = CardType::Learn;
+            self.card.left = self.starting_left();
+        }
+
+        let entry = match self.card.queue {
+            CardQueue::Learn | CardQueue::DayLearn => self.answer_learning_card(rating),
+            CardQueue::Review => self.answer_review_card(rating),
+            _ => return Err(AnkiError::invalid_input("card is not in a study queue")),
+        };
+
+        // once a card has been answered, the original due date no longer applies
+        self.card.odue = 0;
+
+        Ok(entry)
+    }
+
+    fn answer_learning_card(&mut self, rating: Rating) -> RevlogEntry {
+        let review_kind = if self.is_lapsed() {
+            RevlogReviewKind::Relearning
+        } else {
+            RevlogReviewKind::Learning
+        };
+        // graduating changes the card's type, and thus the steps that apply
+        let last_interval = -(delay_for_grade(self.learning_steps(), self.card.left) as i32);
+
+        let leaving = match rating {
+            Rating::Easy => {
+                self.reschedule_as_review(true);
+                true
+            }
+            Rating::Good => {
+                if (self.card.left % 1000) <= 1 {
+                    self.reschedule_as_review(false);
+                    true
+                } else {
+                    self.move_to_next_step();
+                    false
+                }
+            }
+            Rating::Hard => {
+                self.repeat_step();
+                false
+            }
+            Rating::Again => {
+                self.move_to_first_step();
+                false
+            }
+        };
+
+        let steps = self.learning_steps();
+        let interval = if leaving {
+            self.card.ivl as i32
+        } else if rating == Rating::Hard {
+            -(delay_for_repeating_grade(steps, self.card.left) as i32)
+        } else {
+            -(delay_for_grade(steps, self.card.left) as i32)
+        };
+
+        self.revlog_entry(rating, interval, last_interval, review_kind)
+    }
+
+    fn answer_review_card(&mut self, rating: Rating) -> RevlogEntry {
+        let early = self.card.odid.0 != 0 && self.card.odue > self.ctx.today as i32;
+        let review_kind = if early {
+            RevlogReviewKind::EarlyReview
+        } else {
+            RevlogReviewKind::Review
+        };
+
+        let delay = if rating == Rating::Again {
+            self.reschedule_lapse()
+        } else {
+            self.reschedule_review(rating, early);
+            0
+        };
+
+        let interval = if delay > 0 {
+            -(delay as i32)
+        } else {
+            self.card.ivl as i32
+        };
+        self.revlog_entry(rating, interval, self.last_ivl, review_kind)
+    }
+
+    fn answer_preview_card(&mut self, rating: Rating, delay: u32) {
+        if rating == Rating::Again {
+            self.card.queue = CardQueue::PreviewRepeat;
+            self.card.due = (self.ctx.now.0 + delay as i64) as i32;
+        } else {
+            // restore the original state and return to the home deck
+            self.card.due = self.card.odue;
+            self.card.queue = match self.card.ctype {
+                CardType::Learn | CardType::Relearn => {
+                    if self.card.odue > 1_000_000_000 {
+                        CardQueue::Learn
+                    } else {
+                        CardQueue::DayLearn
+                    }
+                }
+                CardType::New => CardQueue::New,
+                CardType::Review => CardQueue::Review,
+            };
+            self.card.remove_from_filtered_deck();
+        }
+    }
+
+    fn seconds_until_due(&self) -> u32 {
+        let secs = match self.card.queue {
+            CardQueue::Learn => self.card.due as i64 - self.ctx.now.0,
+            _ => (self.card.due as i64 - self.ctx.today as i64) * 86_400,
+        };
+        secs.max(0) as u32
+    }
+
+    fn revlog_entry(
+        &self,
+        rating: Rating,
+        interval: i32,
+        last_interval: i32,
+        review_kind: RevlogReviewKind,
+    ) -> RevlogEntry {
+        RevlogEntry {
+            button_chosen: rating as u8,
+            interval,
+            last_interval,
+            ease_factor: self.card.factor as u32,
+            review_kind,
+            ..Default::default()
+        }
+    }
+
+    // Learning
+    //----------------------------------------
+
+    fn is_lapsed(&self) -> bool {
+        matches!(self.card.ctype, CardType::Review | CardType::Relearn)
+    }
+
+    fn learning_steps(&self) -> &[f32] {
+        if self.is_lapsed() {
+            &self.ctx.config.inner.relearn_steps
+        } else {
+            &self.ctx.config.inner.learn_steps
+        }
+    }
+
+    /// The number of steps remaining, plus 1000 times the number of them
+    /// that can be completed today.
+    fn starting_left(&self) -> u32 {
+        let steps = self.learning_steps();
+        let total = steps.len() as u32;
+        let today = self.steps_left_today(steps, total);
+        total + today * 1000
+    }
+
+    /// The number of steps that can be completed before the day cutoff.
+    fn steps_left_today(&self, steps: &[f32], left: u32) -> u32 {
+        let remaining = &steps[steps.len().saturating_sub(left as usize)..];
+        let mut now = self.ctx.now.0;
+        let mut ok = 0;
+        for (idx, step) in remaining.iter().enumerate() {
+            now += step_secs(*step) as i64;
+            if now > self.ctx.next_day_at {
+                break;
+            }
+            ok = idx;
+        }
+        ok as u32 + 1
+    }
+
+    fn move_to_first_step(&mut self) -> u32 {
+        self.card.left = self.starting_left();
+        if self.card.ctype == CardType::Relearn {
+            self.update_review_interval_on_fail();
+        }
+        let delay = delay_for_grade(self.learning_steps(), self.card.left);
+        self.reschedule_learning_card(delay);
+        delay
+    }
+
+    fn move_to_next_step(&mut self) {
+        let left = (self.card.left % 1000) - 1;
+        self.card.left = self.steps_left_today(self.learning_steps(), left) * 1000 + left;
+        let delay = delay_for_grade(self.learning_steps(), self.card.left);
+        self.reschedule_learning_card(delay);
+    }
+
+    fn repeat_step(&mut self) {
+        let delay = delay_for_repeating_grade(self.learning_steps(), self.card.left);
+        self.reschedule_learning_card(delay);
+    }
+
+    fn reschedule_learning_card(&mut self, delay_secs: u32) {
+        let due = self.ctx.now.0 + delay_secs as i64;
+        if due < self.ctx.next_day_at {
+            // add some randomness, up to 5 minutes or 25%
+            let max_extra = (delay_secs / 4).min(300).max(1);
+            let fuzz = if self.simulating {
+                0
+            } else {
+                self.rng.gen_range(0, max_extra) as i64
+            };
+            self.card.due = (due + fuzz).min(self.ctx.next_day_at - 1) as i32;
+            self.card.queue = CardQueue::Learn;
+        } else {
+            // due in one or more days, so the day learn queue is used
+            let ahead = (due - self.ctx.next_day_at) / 86_400 + 1;
+            self.card.due = self.ctx.today as i32 + ahead as i32;
+            self.card.queue = CardQueue::DayLearn;
+        }
+    }
+
+    fn reschedule_as_review(&mut self, early: bool) {
+        if self.is_lapsed() {
+            if early {
+                self.card.ivl += 1;
+            }
+        } else {
+            // graduating for the first time
+            let ctx = self.ctx;
+            let config = &ctx.config.inner;
+            let ivl = if early {
+                config.graduating_interval_easy
+            } else {
+                config.graduating_interval_good
+            };
+            self.card.ivl = self.fuzzed_interval(ivl);
+            self.card.factor = (config.initial_ease() * 1000.0).round() as u16;
+        }
+        self.card.due = self.ctx.today as i32 + self.card.ivl as i32;
+        self.card.ctype = CardType::Review;
+        self.card.queue = CardQueue::Review;
+
+        self.card.remove_from_filtered_deck();
+    }
+
+    // Reviewing
+    //----------------------------------------
+
+    /// Returns the relearning delay in seconds, or 0 if the card has
+    /// no relearning steps.
+    fn reschedule_lapse(&mut self) -> u32 {
+        self.card.lapses += 1;
+        self.card.factor = self.ctx.scheduler.ease_after_lapse(self.card.factor);
+
+        let suspended = self.check_leech() && self.card.queue == CardQueue::Suspended;
+        if !self.ctx.config.inner.relearn_steps.is_empty() && !suspended {
+            self.card.ctype = CardType::Relearn;
+            self.move_to_first_step()
+        } else {
+            self.update_review_interval_on_fail();
+            self.reschedule_as_review(false);
+            if suspended {
+                self.card.queue = CardQueue::Suspended;
+            }
+            0
+        }
+    }
+
+    fn update_review_interval_on_fail(&mut self) {
+        let config = &self.ctx.config.inner;
+        let ivl = self
+            .ctx
+            .scheduler
+            .lapse_interval(&self.review_state(), config);
+        self.last_ivl = self.card.ivl as i32;
+        self.card.ivl = (ivl as u32).max(config.minimum_review_interval());
+    }
+
+    fn reschedule_review(&mut self, rating: Rating, early: bool) {
+        self.last_ivl = self.card.ivl as i32;
+        self.card.ivl = if early {
+            self.early_review_interval(rating)
+        } else {
+            self.next_review_interval(rating)
+        };
+
+        self.card.factor = self
+            .ctx
+            .scheduler
+            .ease_after_review(self.card.factor, rating);
+        self.card.due = self.ctx.today as i32 + self.card.ivl as i32;
+
+        self.card.remove_from_filtered_deck();
+    }
+
+    fn review_state(&self) -> ReviewState {
+        let due = if self.card.odid.0 != 0 {
+            self.card.odue
+        } else {
+            self.card.due
+        };
+        // relearning cards are no longer due on a day
+        let days_until_due = if self.card.queue == CardQueue::Review {
+            due - self.ctx.today as i32
+        } else {
+            0
+        };
+        ReviewState {
+            interval: self.card.ivl,
+            ease_factor: self.card.factor as f64 / 1000.0,
+            days_late: (-days_until_due).max(0) as u32,
+            elapsed_days: self.card.ivl as f64 - days_until_due as f64,
+        }
+    }
+
+    fn next_review_interval(&mut self, rating: Rating) -> u32 {
+        let ctx = self.ctx;
+        let config = &ctx.config.inner;
+        let intervals = ctx.scheduler.review_intervals(&self.review_state(), config);
+        let hard_min = if config.hard_multiplier() > 1.0 {
+            self.card.ivl
+        } else {
+            0
+        };
+
+        let hard = self.constrained_interval(intervals.hard, hard_min, true);
+        if rating == Rating::Hard {
+            return hard;
+        }
+        let good = self.constrained_interval(intervals.good, hard, true);
+        if rating == Rating::Good {
+            return good;
+        }
+        self.constrained_interval(intervals.easy, good, true)
+    }
+
+    /// The interval of a review card in a filtered deck that was answered
+    /// correctly before it was due.
+    fn early_review_interval(&mut self, rating: Rating) -> u32 {
+        let ctx = self.ctx;
+        let ivl =
+            ctx.scheduler
+                .early_review_interval(&self.review_state(), &ctx.config.inner, rating);
+        self.constrained_interval(ivl, 0, false)
+    }
+
+    /// Apply the interval modifier, optional fuzz and the maximum interval,
+    /// ensuring the interval is larger than prev.
+    fn constrained_interval(&mut self, ivl: f64, prev: u32, fuzz: bool) -> u32 {
+        let ctx = self.ctx;
+        let config = &ctx.config.inner;
+        let mut ivl = (ivl * config.interval_multiplier()) as u32;
+        if fuzz {
+            ivl = self.fuzzed_interval(ivl);
+        }
+        ivl.max(prev + 1)
+            .max(1)
+            .min(config.maximum_review_interval())
+    }
+
+    /// True if the card has lapsed enough times to be considered a leech.
+    /// Cards are suspended when the leech action requires it.
+    fn check_leech(&mut self) -> bool {
+        let threshold = self.ctx.config.inner.leech_threshold;
+        if threshold == 0 || self.simulating {
+            return false;
+        }
+        let lapses = self.card.lapses;
+        if lapses >= threshold && (lapses - threshold) % (threshold / 2).max(1) == 0 {
+            if self.ctx.config.inner.leech_action == LeechAction::Suspend as i32 {
+                self.card.queue = CardQueue::Suspended;
+            }
+            self.leeched = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn fuzzed_interval(&mut self, ivl: u32) -> u32 {
+        if self.simulating {
+            return ivl;
+        }
+        let (min, max) = fuzz_range(ivl);
+        let ctx = self.ctx;
+        if let Some(load) = &ctx.review_load {
+            return self.least_loaded_interval(load, min, max);
+        }
+        self.rng.gen_range(min, max + 1)
+    }
+
+    /// The interval in the range that lands on the day with the fewest
+    /// reviews due. Ties are broken randomly.
+    fn least_loaded_interval(&mut self, load: &HashMap<i32, u32>, min: u32, max: u32) -> u32 {
+        let today = self.ctx.today as i32;
+        let due_on = |ivl: u32| load.get(&(today + ivl as i32)).copied().unwrap_or(0);
+        let lowest = (min..=max).map(due_on).min().unwrap_or(0);
+        let candidates: Vec<_> = (min..=max).filter(|&ivl| due_on(ivl) == lowest).collect();
+        candidates[self.rng.gen_range(0, candidates.len())]
+    }
+}
+
+/// The range of days an interval may be randomly moved within, so that
+/// cards learnt together don't stay together.
+fn fuzz_range(ivl: u32) -> (u32, u32) {
+    if ivl < 2 {
+        return (1, 1);
+    } else if ivl == 2 {
+        return (2, 3);
+    }
+    let ivl_f = ivl as f64;
+    let fuzz = if ivl < 7 {
+        (ivl_f * 0.25) as u32
+    } else if ivl < 30 {
+        ((ivl_f * 0.15) as u32).max(2)
+    } else {
+        ((ivl_f * 0.05) as u32).max(4)
+    }
+    // fuzz at least a day
+    .max(1);
+    (ivl - fuzz, ivl + fuzz)
+}
+
+/// The delay in seconds of the current learning step.
+fn delay_for_grade(steps: &[f32], left: u32) -> u32 {
+    let left = (left % 1000) as usize;
+    let mins = steps
+        .len()
+        .checked_sub(left)
+        .and_then(|idx| steps.get(idx))
+        // the final step may have been removed from the config
+        .or_else(|| steps.first())
+        .copied()
+        .unwrap_or(1.0);
+    step_secs(mins)
+}
+
+/// Halfway between the current and next step.
+fn delay_for_repeating_grade(steps: &[f32], left: u32) -> u32 {
+    let current = delay_for_grade(steps, left);
+    let next = if steps.len() > 1 {
+        delay_for_grade(steps, left.saturating_sub(1))
+    } else {
+        current * 2
+    };
+    (current + current.max(next)) / 2
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn answering() -> Result<()> {
+        let mut col = open_test_collection();
+        let cid = col.add_test_note("Basic")?[0];
+
+        // answer in the middle of the day, so learning steps don't cross
+        // the day cutoff
+        let timing = col.timing_today()?;
+        let today = timing.days_elapsed as i32;
+        let mut now = (timing.next_day_at - 43_200) * 1000;
+        let mut answer = |col: &mut Collection, rating| -> Result<Card> {
+            now += 1000;
+            col.answer_card(&CardAnswer {
+                card_id: cid,
+                rating,
+                answered_at: TimestampMillis(now),
+                milliseconds_taken: 2000,
+                fuzz_seed: None,
+                custom_state: None,
+            })?;
+            Ok(col.storage.get_card(cid)?.unwrap())
+        };
+
+        // the default config has learning steps of 1 and 10 minutes
+        let card = answer(&mut col, Rating::Again)?;
+        assert_eq!(card.ctype, CardType::Learn);
+        assert_eq!(card.queue, CardQueue::Learn);
+        assert_eq!(card.left, 2002);
+        // learning cards are delayed by up to 25% extra
+        assert!((now / 1000 + 60..now / 1000 + 75).contains(&(card.due as i64)));
+
+        let card = answer(&mut col, Rating::Good)?;
+        assert_eq!(card.left, 1001);
+        assert!((now / 1000 + 600..now / 1000 + 750).contains(&(card.due as i64)));
+
+        let card = answer(&mut col, Rating::Good)?;
+        assert_eq!(card.ctype, CardType::Review);
+        assert_eq!(card.queue, CardQueue::Review);
+        assert_eq!(card.ivl, 1);
+        assert_eq!(card.due, today + 1);
+        assert_eq!(card.factor, 2500);
+
+        let card = answer(&mut col, Rating::Good)?;
+        assert_eq!(card.ivl, 3);
+        assert_eq!(card.due, today + 3);
+
+        // a lapse moves the card into relearning
+        let card = answer(&mut col, Rating::Again)?;
+        assert_eq!(card.ctype, CardType::Relearn);
+        assert_eq!(card.queue, CardQueue::Learn);
+        assert_eq!(card.lapses, 1);
+        assert_eq!(card.factor, 2300);
+        assert_eq!(card.ivl, 1);
+
+        let card = answer(&mut col, Rating::Easy)?;
+        assert_eq!(card.ctype, CardType::Review);
+        assert_eq!(card.ivl, 2);
+        assert_eq!(card.reps, 6);
+
+        let entries = col.storage.get_revlog_entries_for_card(cid)?;
+        assert_eq!(entries.len(), 6);
+        assert_eq!(entries[0].interval, -60);
+        assert_eq!(entries[3].review_kind, RevlogReviewKind::Review);
+        assert_eq!(entries[3].last_interval, 1);
+        assert_eq!(entries[4].interval, -600);
+        assert_eq!(entries[5].review_kind, RevlogReviewKind::Relearning);
+
+        let deck = col.storage.get_deck(DeckID(1))?.unwrap();
+        assert_eq!(deck.common.new_studied, 1);
+        assert_eq!(deck.common.review_studied, 2);
+        assert_eq!(deck.common.milliseconds_studied, 12_000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn revlog_entries() -> Result<()> {
+        let mut col = open_test_collection();
+        let cid = col.add_test_note("Basic")?[0];
+        let answer = |col: &mut Collection, rating, answered_at| {
+            col.answer_card(&CardAnswer {
+                card_id: cid,
+                rating,
+                answered_at: TimestampMillis(answered_at),
+                milliseconds_taken: 0,
+                fuzz_seed: None,
+                custom_state: None,
+            })
+        };
+
+        assert!(answer(&mut col, Rating::Again, 0).is_err());
+        // the first learning step was logged when graduating from it
+        let now = TimestampMillis::now().0;
+        answer(&mut col, Rating::Easy, now)?;
+        // and an answer logged in the same millisecond gets the next one
+        answer(&mut col, Rating::Again, now)?;
+        let entries = col.storage.get_revlog_entries_for_card(cid)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].last_interval, -60);
+        assert_eq!(entries[0].id.0, now);
+        assert_eq!(entries[1].id.0, now + 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn review_intervals() -> Result<()> {
+        let mut col = open_test_collection();
+        let cid = col.add_test_note("Basic")?[0];
+        let today = col.timing_today()?.days_elapsed as i32;
+
+        let answer = |col: &mut Collection, rating, fuzz_seed| -> Result<u32> {
+            col.get_and_update_card(cid, |card| {
+                card.ctype = CardType::Review;
+                card.queue = CardQueue::Review;
+                card.ivl = 100;
+                card.due = today - 10;
+                card.factor = 2500;
+                Ok(())
+            })?;
+            col.answer_card(&CardAnswer {
+                card_id: cid,
+                rating,
+                answered_at: TimestampMillis::now(),
+                milliseconds_taken: 0,
+                fuzz_seed,
+                custom_state: None,
+            })?;
+            Ok(col.storage.get_card(cid)?.unwrap().ivl)
+        };
+
+        let fuzzed = |ivl, expected| {
+            let (min, max) = fuzz_range(expected);
+            assert!((min..=max).contains(&ivl), "{} not near {}", ivl, expected);
+        };
+        fuzzed(answer(&mut col, Rating::Hard, None)?, 120);
+        fuzzed(answer(&mut col, Rating::Good, None)?, 262);
+        fuzzed(answer(&mut col, Rating::Easy, None)?, 357);
+
+        // the same seed always produces the same interval
+        let ivl = answer(&mut col, Rating::Good, Some(5))?;
+        assert_eq!(answer(&mut col, Rating::Good, Some(5))?, ivl);
+
+        // but different seeds spread the card over a range of days
+        let mut ivls = vec![];
+        for seed in 0..10 {
+            ivls.push(answer(&mut col, Rating::Good, Some(seed))?);
+        }
+        ivls.dedup();
+        assert!(ivls.len() > 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_balancing() -> Result<()> {
+        let mut col = open_test_collection();
+        col.set_load_balance(true)?;
+        let today = col.timing_today()?.days_elapsed as i32;
+
+        // every day in the fuzz range has a review due except one
+        let (min, max) = fuzz_range(262);
+        let quiet_day = min + 3;
+        // the last card added is the one answered
+        let mut cid = CardID(0);
+        for ivl in min..=max + 1 {
+            cid = col.add_test_note("Basic")?[0];
+            if ivl != quiet_day {
+                col.get_and_update_card(cid, |card| {
+                    card.ctype = CardType::Review;
+                    card.queue = CardQueue::Review;
+                    card.ivl = 100;
+                    card.due = today + ivl as i32;
+                    Ok(())
+                })?;
+            }
+        }
+
+        col.get_and_update_card(cid, |card| {
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Review;
+            card.ivl = 100;
+            card.due = today - 10;
+            card.factor = 2500;
+            Ok(())
+        })?;
+        col.answer_card(&CardAnswer {
+            card_id: cid,
+            rating: Rating::Good,
+            answered_at: TimestampMillis::now(),
+            milliseconds_taken: 0,
+            fuzz_seed: None,
+            custom_state: None,
+        })?;
+        assert_eq!(col.storage.get_card(cid)?.unwrap().ivl, quiet_day);
+
+        Ok(())
+    }
+
+    #[test]
+    fn leeches() -> Result<()> {
+        let mut col = open_test_collection();
+        let cid = col.add_test_note("Basic")?[0];
+        let nid = col.storage.get_card(cid)?.unwrap().nid;
+        let today = col.timing_today()?.days_elapsed as i32;
+        let mut conf = col.get_deck_config(DeckConfID(1), false)?.unwrap();
+        conf.inner.leech_threshold = 2;
+        conf.inner.leech_action = LeechAction::Suspend as i32;
+        col.add_or_update_deck_config(&mut conf, false)?;
+
+        let lapse = |col: &mut Collection, lapses| -> Result<bool> {
+            col.get_and_update_card(cid, |card| {
+                card.ctype = CardType::Review;
+                card.queue = CardQueue::Review;
+                card.ivl = 10;
+                card.due = today;
+                card.lapses = lapses;
+                Ok(())
+            })?;
+            col.answer_card(&CardAnswer {
+                card_id: cid,
+                rating: Rating::Again,
+                answered_at: TimestampMillis::now(),
+                milliseconds_taken: 0,
+                fuzz_seed: None,
+                custom_state: None,
+            })
+        };
+
+        assert!(!lapse(&mut col, 0)?);
+        assert_eq!(col.storage.get_note(nid)?.unwrap().tags.len(), 0);
+
+        // reaching the threshold tags the note and suspends the card
+        assert!(lapse(&mut col, 1)?);
+        assert_eq!(col.storage.get_note(nid)?.unwrap().tags, &["leech"]);
+        assert_eq!(
+            col.storage.get_card(cid)?.unwrap().queue,
+            CardQueue::Suspended
+        );
+
+        // the card may be left in the queue instead
+        conf.inner.leech_action = LeechAction::TagOnly as i32;
+        col.add_or_update_deck_config(&mut conf, false)?;
+        assert!(lapse(&mut col, 2)?);
+        assert_ne!(
+            col.storage.get_card(cid)?.unwrap().queue,
+            CardQueue::Suspended
+        );
+        assert_eq!(col.storage.get_note(nid)?.unwrap().tags, &["leech"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn next_intervals() -> Result<()> {
+        let mut col = open_test_collection();
+        let cid = col.add_test_note("Basic")?[0];
+
+        // learning steps are inside the learn ahead limit
+        let labels = col.answer_button_labels(cid)?;
+        assert_eq!(labels[0], "<1m");
+        assert_eq!(labels[3], "4d");
+
+        let today = col.timing_today()?.days_elapsed as i32;
+        col.get_and_update_card(cid, |card| {
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Review;
+            card.ivl = 100;
+            card.due = today - 10;
+            card.factor = 2500;
+            Ok(())
+        })?;
+        let day = 86_400;
+        assert_eq!(
+            col.next_intervals(cid)?[1..],
+            [120 * day, 262 * day, 357 * day]
+        );
+        // nothing is changed
+        assert_eq!(col.storage.get_card(cid)?.unwrap().ivl, 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fuzz_ranges() {
+        assert_eq!(fuzz_range(1), (1, 1));
+        assert_eq!(fuzz_range(2), (2, 3));
+        assert_eq!(fuzz_range(4), (3, 5));
+        assert_eq!(fuzz_range(10), (8, 12));
+        assert_eq!(fuzz_range(100), (95, 105));
+    }
+}
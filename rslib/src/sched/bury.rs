@@ -0,0 +1,253 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{card::CardQueue, collection::CollectionOp, config::ConfigKey, prelude::*};
+
+/// Cards are buried either manually by the user, or by the scheduler when
+/// a sibling is answered.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnburyMode {
+    All,
+    UserBuried,
+    SchedBuried,
+}
+
+impl UnburyMode {
+    pub(crate) fn queues(self) -> &'static [CardQueue] {
+        match self {
+            UnburyMode::All => &[CardQueue::UserBuried, CardQueue::SchedBuried],
+            UnburyMode::UserBuried => &[CardQueue::UserBuried],
+            UnburyMode::SchedBuried => &[CardQueue::SchedBuried],
+        }
+    }
+}
+
+impl Collection {
+    /// Hide cards until the next day. Cards buried by the user are tracked
+    /// separately to those buried automatically.
+    pub fn bury_cards(&mut self, cids: &[CardID], manual: bool) -> Result<()> {
+        let queue = if manual {
+            CardQueue::UserBuried
+        } else {
+            CardQueue::SchedBuried
+        };
+        self.transact(None, |col| col.move_cards_to_queue(cids, queue))
+    }
+
+    /// Bury the new and review siblings of a card that is being studied,
+    /// if its deck options ask for them to be buried.
+    pub fn bury_siblings_of(&mut self, card: &Card) -> Result<()> {
+        self.transact(None, |col| col.bury_siblings_of_inner(card).map(|_| ()))
+    }
+
+    /// Returns the buried siblings. Caller must create transaction.
+    pub(crate) fn bury_siblings_of_inner(&mut self, card: &Card) -> Result<Vec<CardID>> {
+        let home_did = if card.odid.0 != 0 {
+            card.odid
+        } else {
+            card.did
+        };
+        let dcid = self
+            .get_deck(home_did)?
+            .and_then(|deck| deck.config_id())
+            .unwrap_or(DeckConfID(1));
+        let config = self.get_deck_config(dcid, true)?.unwrap().inner;
+        if !config.bury_new && !config.bury_reviews {
+            return Ok(vec![]);
+        }
+        let today = self.timing_today()?.days_elapsed as i32;
+
+        let siblings: Vec<_> = self
+            .sched_storage()
+            .all_cards_of_note(card.nid)?
+            .into_iter()
+            .filter(|sibling| {
+                sibling.id != card.id
+                    && match sibling.queue {
+                        CardQueue::New => config.bury_new,
+                        CardQueue::Review => config.bury_reviews && sibling.due <= today,
+                        _ => false,
+                    }
+            })
+            .map(|sibling| sibling.id)
+            .collect();
+
+        self.move_cards_to_queue(&siblings, CardQueue::SchedBuried)?;
+        Ok(siblings)
+    }
+
+    /// Unbury cards in the current deck and its children.
+    pub fn unbury_cards_in_current_deck(&mut self, mode: UnburyMode) -> Result<()> {
+        let current = self.get_current_deck_id();
+        let mut dids = vec![current];
+        if let Some(deck) = self.sched_storage().get_deck(current)? {
+            dids.extend(
+                self.sched_storage()
+                    .child_decks(&deck)?
+                    .into_iter()
+                    .map(|d| d.id),
+            );
+        }
+        let cards = self.sched_storage().buried_cards(mode, Some(&dids))?;
+        self.transact(None, |col| col.unbury_cards(cards))
+    }
+
+    /// Unbury all cards if the day has rolled over since cards were last
+    /// unburied. Should be called before building the study queues.
+    pub fn unbury_on_day_rollover(&mut self) -> Result<()> {
+        let today = self.timing_today()?.days_elapsed;
+        let last_unburied: u32 = self.get_config_default(ConfigKey::LastUnburiedDay);
+        // the last day may be in the future if the clock was wrong
+        if last_unburied < today || today + 7 < last_unburied {
+            let cards = self.sched_storage().buried_cards(UnburyMode::All, None)?;
+            self.transact(None, |col| {
+                col.unbury_cards(cards)?;
+                col.set_config(ConfigKey::LastUnburiedDay, &today)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Remove cards from the study queues until they are unsuspended.
+    pub fn suspend_cards(&mut self, cids: &[CardID]) -> Result<()> {
+        self.transact(Some(CollectionOp::SuspendCards), |col| {
+            col.move_cards_to_queue(cids, CardQueue::Suspended)
+        })
+    }
+
+    /// Return suspended cards to the queue matching their type. Cards that
+    /// are not suspended are left alone.
+    pub fn unsuspend_cards(&mut self, cids: &[CardID]) -> Result<()> {
+        self.transact(Some(CollectionOp::UnsuspendCards), |col| {
+            for cid in cids {
+                if let Some(original) = col.sched_storage().get_card(*cid)? {
+                    if original.queue != CardQueue::Suspended {
+                        continue;
+                    }
+                    let mut card = original.clone();
+                    card.restore_queue_from_type();
+                    col.update_card(&mut card, &original)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn move_cards_to_queue(&mut self, cids: &[CardID], queue: CardQueue) -> Result<()> {
+        for cid in cids {
+            if let Some(original) = self.sched_storage().get_card(*cid)? {
+                if original.queue == queue {
+                    continue;
+                }
+                let mut card = original.clone();
+                card.queue = queue;
+                self.update_card(&mut card, &original)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unbury_cards(&mut self, cards: Vec<Card>) -> Result<()> {
+        for original in cards {
+            let mut card = original.clone();
+            card.restore_queue_from_type();
+            self.update_card(&mut card, &original)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{card::CardType, collection::open_test_collection};
+
+    #[test]
+    fn burying() -> Result<()> {
+        let mut col = open_test_collection();
+        let cids = col.add_test_note("Basic (and reversed card)")?;
+        let card = col.storage.get_card(cids[0])?.unwrap();
+        let queue = |col: &Collection, cid| -> Result<CardQueue> {
+            Ok(col.storage.get_card(cid)?.unwrap().queue)
+        };
+
+        // siblings are only buried if the options ask for it
+        col.bury_siblings_of(&card)?;
+        assert_eq!(queue(&col, cids[1])?, CardQueue::New);
+        let mut conf = col.get_deck_config(DeckConfID(1), false)?.unwrap();
+        conf.inner.bury_new = true;
+        col.add_or_update_deck_config(&mut conf, false)?;
+        col.bury_siblings_of(&card)?;
+        assert_eq!(queue(&col, cids[0])?, CardQueue::New);
+        assert_eq!(queue(&col, cids[1])?, CardQueue::SchedBuried);
+
+        col.bury_cards(&cids[..1], true)?;
+        assert_eq!(queue(&col, cids[0])?, CardQueue::UserBuried);
+
+        // the two kinds can be unburied separately
+        col.unbury_cards_in_current_deck(UnburyMode::SchedBuried)?;
+        assert_eq!(queue(&col, cids[0])?, CardQueue::UserBuried);
+        assert_eq!(queue(&col, cids[1])?, CardQueue::New);
+
+        // learning cards return to the queue matching their due date
+        col.get_and_update_card(cids[1], |card| {
+            card.ctype = CardType::Learn;
+            card.due = 1_500_000_000;
+            Ok(())
+        })?;
+        col.bury_cards(&cids[1..], false)?;
+
+        // cards are unburied once per day
+        col.set_config(ConfigKey::LastUnburiedDay, &0)?;
+        col.unbury_on_day_rollover()?;
+        assert_eq!(queue(&col, cids[0])?, CardQueue::New);
+        assert_eq!(queue(&col, cids[1])?, CardQueue::Learn);
+        col.bury_cards(&cids, true)?;
+        col.unbury_on_day_rollover()?;
+        assert_eq!(queue(&col, cids[0])?, CardQueue::UserBuried);
+
+        Ok(())
+    }
+
+    #[test]
+    fn suspending() -> Result<()> {
+        let mut col = open_test_collection();
+        let cids = col.add_test_note("Basic (and reversed card)")?;
+        let today = col.timing_today()?.days_elapsed as i32;
+        col.get_and_update_card(cids[1], |card| {
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Review;
+            card.due = today;
+            Ok(())
+        })?;
+
+        col.suspend_cards(&cids)?;
+        let card = col.storage.get_card(cids[1])?.unwrap();
+        assert_eq!(card.queue, CardQueue::Suspended);
+        assert_eq!(card.due, today);
+
+        // suspending can be undone
+        assert_eq!(col.can_undo(), Some(CollectionOp::SuspendCards));
+        col.undo()?;
+        assert_eq!(
+            col.storage.get_card(cids[1])?.unwrap().queue,
+            CardQueue::Review
+        );
+        col.redo()?;
+
+        // buried cards are not unsuspended
+        col.unsuspend_cards(&cids[..1])?;
+        col.bury_cards(&cids[..1], true)?;
+        col.unsuspend_cards(&cids)?;
+        assert_eq!(
+            col.storage.get_card(cids[0])?.unwrap().queue,
+            CardQueue::UserBuried
+        );
+        assert_eq!(
+            col.storage.get_card(cids[1])?.unwrap().queue,
+            CardQueue::Review
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    decks::{Deck, DeckKind, FilteredSearchOrder, FilteredSearchTerm},
+    prelude::*,
+};
+
+/// The largest number of cards a custom study session will gather.
+const MAX_CARDS: u32 = 99_999;
+
+/// The presets offered on the custom study screen.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CustomStudyRequest {
+    /// Change today's new card limit of the current deck.
+    NewLimitDelta(i32),
+    /// Change today's review limit of the current deck.
+    ReviewLimitDelta(i32),
+    /// Review cards answered Again in the last n days.
+    ForgotDays(u32),
+    /// Review cards that will become due in the next n days.
+    ReviewAheadDays(u32),
+    /// Preview new cards added in the last n days.
+    PreviewDays(u32),
+    Cram(CramRequest),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CramRequest {
+    pub kind: CramKind,
+    pub card_limit: u32,
+    pub tags_to_include: Vec<String>,
+    pub tags_to_exclude: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CramKind {
+    New,
+    Due,
+    Review,
+    All,
+}
+
+impl CramRequest {
+    fn search(&self) -> String {
+        let mut terms = vec![];
+        match self.kind {
+            CramKind::New => terms.push("is:new".to_string()),
+            CramKind::Due => terms.push("is:due".to_string()),
+            CramKind::Review => terms.push("-is:new".to_string()),
+            CramKind::All => (),
+        };
+        if !self.tags_to_include.is_empty() {
+            let tags: Vec<_> = self
+                .tags_to_include
+                .iter()
+                .map(|tag| format!("tag:\"{}\"", tag))
+                .collect();
+            terms.push(format!("({})", tags.join(" or ")));
+        }
+        for tag in &self.tags_to_exclude {
+            terms.push(format!("-tag:\"{}\"", tag));
+        }
+        terms.join(" ")
+    }
+
+    fn order(&self) -> FilteredSearchOrder {
+        match self.kind {
+            CramKind::New => FilteredSearchOrder::Added,
+            CramKind::Due => FilteredSearchOrder::Due,
+            CramKind::Review | CramKind::All => FilteredSearchOrder::Random,
+        }
+    }
+}
+
+impl Collection {
+    /// Apply a custom study preset to the current deck. The limit presets
+    /// adjust today's limits and return 0. The other presets (re)build the
+    /// custom study session filtered deck, select it, and return the
+    /// number of cards it gathered.
+    pub fn custom_study(&mut self, request: CustomStudyRequest) -> Result<u32> {
+        let did = self.get_current_deck_id();
+        let deck = self
+            .sched_storage()
+            .get_deck(did)?
+            .ok_or(AnkiError::NotFound)?;
+        match request {
+            CustomStudyRequest::NewLimitDelta(delta) => {
+                self.extend_limits_for_custom_study(deck, delta, 0)?;
+                Ok(0)
+            }
+            CustomStudyRequest::ReviewLimitDelta(delta) => {
+                self.extend_limits_for_custom_study(deck, 0, delta)?;
+                Ok(0)
+            }
+            _ => self.build_custom_study_deck(&deck, request),
+        }
+    }
+
+    fn extend_limits_for_custom_study(
+        &mut self,
+        mut deck: Deck,
+        new_delta: i32,
+        review_delta: i32,
+    ) -> Result<()> {
+        self.transact(None, |col| {
+            let usn = col.usn()?;
+            let today = col.current_due_day(0)?;
+            // remember the amount, so it can be offered again next time
+            if let DeckKind::Normal(normal) = &mut deck.kind {
+                if new_delta != 0 {
+                    normal.extend_new = new_delta.max(0) as u32;
+                }
+                if review_delta != 0 {
+                    normal.extend_review = review_delta.max(0) as u32;
+                }
+                deck.set_modified(usn);
+                col.add_or_update_single_deck(&mut deck, usn)?;
+            }
+            col.extend_limits(today, usn, deck.id, new_delta, review_delta)
+        })
+    }
+
+    fn build_custom_study_deck(&mut self, deck: &Deck, request: CustomStudyRequest) -> Result<u32> {
+        let (search, limit, order, reschedule) = match request {
+            CustomStudyRequest::ForgotDays(days) => (
+                format!("rated:{}:1", days),
+                MAX_CARDS,
+                FilteredSearchOrder::Random,
+                false,
+            ),
+            CustomStudyRequest::ReviewAheadDays(days) => (
+                format!("prop:due<={}", days),
+                MAX_CARDS,
+                FilteredSearchOrder::Due,
+                true,
+            ),
+            CustomStudyRequest::PreviewDays(days) => (
+                format!("is:new added:{}", days),
+                MAX_CARDS,
+                FilteredSearchOrder::OldestFirst,
+                false,
+            ),
+            CustomStudyRequest::Cram(cram) => (
+                cram.search(),
+                cram.card_limit,
+                cram.order(),
+                cram.kind != CramKind::All,
+            ),
+            CustomStudyRequest::NewLimitDelta(_) | CustomStudyRequest::ReviewLimitDelta(_) => {
+                unreachable!()
+            }
+        };
+        let search = format!("did:{} {}", deck.id, search).trim().to_string();
+
+        let name = self.i18n.tr(TR::SchedulingCustomStudySession).to_string();
+        let mut custom_deck = match self.sched_storage().get_deck_id(&name)? {
+            Some(did) => {
+                let existing = self
+                    .sched_storage()
+                    .get_deck(did)?
+                    .ok_or(AnkiError::NotFound)?;
+                if !existing.is_filtered() {
+                    return Err(AnkiError::invalid_input(
+                        self.i18n.tr(TR::SchedulingCustomStudyRenameExistingDeck),
+                    ));
+                }
+                existing
+            }
+            None => {
+                let mut custom_deck = Deck::new_filtered();
+                custom_deck.name = name;
+                custom_deck
+            }
+        };
+        if let DeckKind::Filtered(filtered) = &mut custom_deck.kind {
+            filtered.reschedule = reschedule;
+            filtered.search_terms = vec![FilteredSearchTerm {
+                search,
+                limit,
+                order: order as i32,
+            }];
+        }
+
+        let count = self.add_or_update_filtered_deck(&mut custom_deck)?;
+        if count > 0 {
+            self.set_current_deck(custom_deck.id)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, search::SortMode};
+
+    #[test]
+    fn custom_study() -> Result<()> {
+        let mut col = open_test_collection();
+        for tag in &["one", "two", "two"] {
+            let cid = col.add_test_note("Basic")?[0];
+            let nid = col.storage.get_card(cid)?.unwrap().nid;
+            col.add_tags_for_notes(&[nid], tag)?;
+        }
+
+        col.custom_study(CustomStudyRequest::NewLimitDelta(5))?;
+        let deck = col.storage.get_deck(DeckID(1))?.unwrap();
+        assert_eq!(deck.common.new_studied, -5);
+        match deck.kind {
+            DeckKind::Normal(normal) => assert_eq!(normal.extend_new, 5),
+            DeckKind::Filtered(_) => unreachable!(),
+        }
+
+        let cram = |tags: &[&str]| {
+            CustomStudyRequest::Cram(CramRequest {
+                kind: CramKind::New,
+                card_limit: 100,
+                tags_to_include: tags.iter().map(|tag| tag.to_string()).collect(),
+                tags_to_exclude: vec![],
+            })
+        };
+        assert_eq!(col.custom_study(cram(&["two"]))?, 2);
+        let custom_did = col.get_current_deck_id();
+        assert_ne!(custom_did, DeckID(1));
+        assert_eq!(
+            col.search_cards(format!("did:{}", custom_did).as_str(), SortMode::NoOrder)?
+                .len(),
+            2
+        );
+
+        // the existing session is reused
+        col.set_current_deck(DeckID(1))?;
+        assert_eq!(col.custom_study(cram(&["one", "two"]))?, 3);
+        assert_eq!(col.get_current_deck_id(), custom_did);
+
+        // nothing is selected when no cards match
+        col.set_current_deck(DeckID(1))?;
+        assert_eq!(col.custom_study(CustomStudyRequest::ForgotDays(1))?, 0);
+        assert_eq!(col.get_current_deck_id(), DeckID(1));
+
+        Ok(())
+    }
+}
@@ -66,6 +66,18 @@ fn days_elapsed(
     days.max(0) as u32
 }
 
+/// next_day_at is calculated with the offset in effect now. If the offset
+/// will be different at that time, eg because daylight savings starts or
+/// ends overnight, shift it so the rollover still happens at the same
+/// local hour.
+fn next_day_at_adjusted_for_offset_change(
+    next_day_at: i64,
+    now_mins_west: i32,
+    next_day_mins_west: i32,
+) -> i64 {
+    next_day_at + ((next_day_mins_west - now_mins_west) * 60) as i64
+}
+
 /// Build a FixedOffset struct, capping minutes_west if out of bounds.
 pub(crate) fn fixed_offset_from_minutes(minutes_west: i32) -> FixedOffset {
     let bounded_minutes = minutes_west.max(-23 * 60).min(23 * 60);
@@ -168,10 +180,10 @@ pub(crate) fn sched_timing_today(
     rollover_hour: Option<u8>,
 ) -> SchedTimingToday {
     let now_west = now_mins_west.unwrap_or_else(|| local_minutes_west_for_stamp(now_secs.0));
-    match (rollover_hour, created_mins_west) {
+    let mut timing = match (rollover_hour, created_mins_west) {
         (None, _) => {
             // if rollover unset, v1 scheduler
-            sched_timing_today_v1(created_secs.0, now_secs.0)
+            return sched_timing_today_v1(created_secs.0, now_secs.0);
         }
         (Some(roll), None) => {
             // if creationOffset unset, v2 scheduler with legacy cutoff handling
@@ -181,7 +193,16 @@ pub(crate) fn sched_timing_today(
             // v2 scheduler, new cutoff handling
             sched_timing_today_v2_new(created_secs.0, crt_west, now_secs.0, now_west, roll)
         }
+    };
+    // when a fixed offset was provided, there's no way to know if it will change
+    if now_mins_west.is_none() {
+        timing.next_day_at = next_day_at_adjusted_for_offset_change(
+            timing.next_day_at,
+            now_west,
+            local_minutes_west_for_stamp(timing.next_day_at),
+        );
     }
+    timing
 }
 
 #[cfg(test)]
@@ -345,6 +366,32 @@ mod test {
         assert_eq!(today.next_day_at, next_day_at.timestamp());
     }
 
+    #[test]
+    fn daylight_savings() {
+        // Sydney moves from UTC+11 to UTC+10 at 3am on April 5th 2020
+        let aedt = fixed_offset_from_minutes(-660);
+        let aest = fixed_offset_from_minutes(-600);
+        let crt = aedt.ymd(2020, 1, 1).and_hms(12, 0, 0);
+        let now = aedt.ymd(2020, 4, 4).and_hms(20, 0, 0);
+        let today = sched_timing_today_v2_new(crt.timestamp(), -660, now.timestamp(), -660, 4);
+        assert_eq!(
+            today.next_day_at,
+            aedt.ymd(2020, 4, 5).and_hms(4, 0, 0).timestamp()
+        );
+
+        // the rollover should still happen at 4am local time
+        assert_eq!(
+            next_day_at_adjusted_for_offset_change(today.next_day_at, -660, -600),
+            aest.ymd(2020, 4, 5).and_hms(4, 0, 0).timestamp()
+        );
+        // and the day count is unaffected
+        let later = aest.ymd(2020, 4, 5).and_hms(4, 30, 0);
+        assert_eq!(
+            elap(crt.timestamp(), later.timestamp(), -660, -600, 4),
+            today.days_elapsed + 1
+        );
+    }
+
     #[test]
     fn legacy_timing() {
         let now = 1584491078;
@@ -2,11 +2,25 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use crate::{
-    collection::Collection, config::SchedulerVersion, err::Result, timestamp::TimestampSecs,
+    collection::Collection, config::SchedulerVersion, err::Result, storage::Storage,
+    timestamp::TimestampSecs,
 };
 
+pub mod algorithm;
+pub mod answering;
+pub mod bury;
+pub mod custom_study;
 pub mod cutoff;
+pub mod new;
+pub mod queue;
+pub mod reconstruct;
+pub mod reschedule;
+pub mod simulate;
+pub mod states;
+pub mod steps;
 pub mod timespan;
+pub mod undo_review;
+pub mod upgrade;
 
 use chrono::FixedOffset;
 use cutoff::{
@@ -15,10 +29,42 @@ use cutoff::{
 };
 
 impl Collection {
+    /// The storage the scheduler's direct reads and writes go through.
+    /// Changes made via Collection helpers such as update_card(), and
+    /// searches, still go through SqliteStorage.
+    pub(crate) fn sched_storage(&self) -> &dyn Storage {
+        &self.storage
+    }
+
     pub fn timing_today(&self) -> Result<SchedTimingToday> {
         self.timing_for_timestamp(TimestampSecs::now())
     }
 
+    /// Intraday learning cards due before this time may be shown early
+    /// when there is nothing else to study. The window is set by the
+    /// collection's learn ahead limit, and does not extend into the next
+    /// day.
+    pub fn learn_ahead_cutoff(&self, now: TimestampSecs) -> Result<TimestampSecs> {
+        let next_day_at = self.timing_for_timestamp(now)?.next_day_at;
+        Ok(now
+            .adding_secs(self.learn_ahead_secs() as i64)
+            .min(TimestampSecs(next_day_at)))
+    }
+
+    /// True if the day has rolled over since the last call, so queues and
+    /// counts that were shown to the user are out of date. Long-running
+    /// clients can call this periodically. The first call after the
+    /// collection is opened returns false.
+    pub fn day_rolled_over(&mut self) -> Result<bool> {
+        self.day_rolled_over_at(TimestampSecs::now())
+    }
+
+    fn day_rolled_over_at(&mut self, now: TimestampSecs) -> Result<bool> {
+        let today = self.timing_for_timestamp(now)?.days_elapsed;
+        let previous = self.state.last_day_seen.replace(today);
+        Ok(matches!(previous, Some(day) if day != today))
+    }
+
     pub fn current_due_day(&mut self, delta: i32) -> Result<u32> {
         Ok(((self.timing_today()?.days_elapsed as i32) + delta).max(0) as u32)
     }
@@ -36,7 +82,7 @@ impl Collection {
         };
 
         Ok(sched_timing_today(
-            self.storage.creation_stamp()?,
+            self.sched_storage().creation_stamp()?,
             now,
             self.get_creation_mins_west(),
             local_offset,
@@ -60,7 +106,7 @@ impl Collection {
     pub fn rollover_for_current_scheduler(&self) -> Result<u8> {
         match self.sched_ver() {
             SchedulerVersion::V1 => Ok(v1_rollover_from_creation_stamp(
-                self.storage.creation_stamp()?.0,
+                self.sched_storage().creation_stamp()?.0,
             )),
             SchedulerVersion::V2 => Ok(self.get_v2_rollover().unwrap_or(4)),
         }
@@ -68,14 +114,46 @@ impl Collection {
 
     pub(crate) fn set_rollover_for_current_scheduler(&self, hour: u8) -> Result<()> {
         match self.sched_ver() {
-            SchedulerVersion::V1 => {
-                self.storage
-                    .set_creation_stamp(TimestampSecs(v1_creation_date_adjusted_to_hour(
-                        self.storage.creation_stamp()?.0,
-                        hour,
-                    )))
-            }
+            SchedulerVersion::V1 => self.sched_storage().set_creation_stamp(TimestampSecs(
+                v1_creation_date_adjusted_to_hour(self.sched_storage().creation_stamp()?.0, hour),
+            )),
             SchedulerVersion::V2 => self.set_v2_rollover(hour as u32),
         }
     }
 }
+
+#[cfg(test)]
+impl Collection {
+    /// Add a note of the named note type to the default deck, returning the
+    /// ids of its cards.
+    pub(crate) fn add_test_note(&mut self, notetype: &str) -> Result<Vec<crate::card::CardID>> {
+        let nt = self.get_notetype_by_name(notetype)?.unwrap();
+        let mut note = nt.new_note();
+        self.add_note(&mut note, crate::decks::DeckID(1))?;
+        Ok(self
+            .storage
+            .all_cards_of_note(note.id)?
+            .into_iter()
+            .map(|card| card.id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn day_rollover() -> Result<()> {
+        let mut col = open_test_collection();
+        let now = TimestampSecs::now();
+        assert!(!col.day_rolled_over_at(now)?);
+        assert!(!col.day_rolled_over_at(now)?);
+        let tomorrow = now.adding_secs(86_400);
+        assert!(col.day_rolled_over_at(tomorrow)?);
+        // reported only once
+        assert!(!col.day_rolled_over_at(tomorrow)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{card::CardType, prelude::*};
+use rand::seq::SliceRandom;
+use std::collections::{HashMap, HashSet};
+
+impl Collection {
+    /// Give new cards the positions start, start+step, and so on, in the
+    /// order they were provided, or in a random order. Cards of the same
+    /// note share a position. If shift is true, other new cards at or
+    /// after start are moved back to make room. Cards that are not new
+    /// are ignored. Returns the number of cards repositioned.
+    pub fn reposition_new_cards(
+        &mut self,
+        cids: &[CardID],
+        start: u32,
+        step: u32,
+        randomize: bool,
+        shift: bool,
+    ) -> Result<usize> {
+        self.transact(None, |col| {
+            col.reposition_new_cards_inner(cids, start, step, randomize, shift)
+        })
+    }
+
+    /// Caller must create transaction.
+    pub(crate) fn reposition_new_cards_inner(
+        &mut self,
+        cids: &[CardID],
+        start: u32,
+        step: u32,
+        randomize: bool,
+        shift: bool,
+    ) -> Result<usize> {
+        let mut cards = vec![];
+        for cid in cids {
+            if let Some(card) = self.sched_storage().get_card(*cid)? {
+                if card.ctype == CardType::New {
+                    cards.push(card);
+                }
+            }
+        }
+        if cards.is_empty() {
+            return Ok(0);
+        }
+
+        let mut seen = HashSet::new();
+        let mut nids: Vec<_> = cards
+            .iter()
+            .map(|card| card.nid)
+            .filter(|nid| seen.insert(*nid))
+            .collect();
+        if randomize {
+            nids.shuffle(&mut rand::thread_rng());
+        }
+        // positions are stored as an i32
+        let position = |idx: usize| start as i64 + idx as i64 * step as i64;
+        let last = position(nids.len() - 1);
+        if last > i32::MAX as i64 {
+            return Err(AnkiError::invalid_input("position out of range"));
+        }
+        let positions: HashMap<_, _> = nids
+            .iter()
+            .enumerate()
+            .map(|(idx, nid)| (*nid, position(idx) as i32))
+            .collect();
+
+        if shift {
+            self.shift_new_cards_from(start, last as i32, &cards)?;
+        }
+
+        for original in &cards {
+            let mut card = original.clone();
+            card.due = positions[&card.nid];
+            self.update_card(&mut card, original)?;
+        }
+
+        Ok(cards.len())
+    }
+
+    /// Move new cards positioned at or after start so that they come after
+    /// last, keeping their existing order.
+    fn shift_new_cards_from(&mut self, start: u32, last: i32, excluding: &[Card]) -> Result<()> {
+        let excluded: HashSet<_> = excluding.iter().map(|card| card.id).collect();
+        let others: Vec<_> = self
+            .sched_storage()
+            .new_cards_positioned_from(start)?
+            .into_iter()
+            .filter(|card| !excluded.contains(&card.id))
+            .collect();
+        if let Some(lowest) = others.iter().map(|card| card.due).min() {
+            let shift_by = last - lowest + 1;
+            for original in others {
+                let mut card = original.clone();
+                card.due = card
+                    .due
+                    .checked_add(shift_by)
+                    .ok_or_else(|| AnkiError::invalid_input("position out of range"))?;
+                self.update_card(&mut card, &original)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn repositioning() -> Result<()> {
+        let mut col = open_test_collection();
+        let mut cids = vec![];
+        for _ in 0..4 {
+            cids.push(col.add_test_note("Basic")?[0]);
+        }
+        let positions = |col: &Collection| -> Result<Vec<i32>> {
+            cids.iter()
+                .map(|cid| Ok(col.storage.get_card(*cid)?.unwrap().due))
+                .collect()
+        };
+        col.reposition_new_cards(&cids, 1, 1, false, false)?;
+        assert_eq!(positions(&col)?, &[1, 2, 3, 4]);
+
+        // the last two cards move to the front, and the others make room
+        assert_eq!(col.reposition_new_cards(&cids[2..], 1, 1, false, true)?, 2);
+        assert_eq!(positions(&col)?, &[3, 4, 1, 2]);
+
+        // a step leaves gaps between the cards
+        col.reposition_new_cards(&cids, 10, 5, false, false)?;
+        assert_eq!(positions(&col)?, &[10, 15, 20, 25]);
+
+        // review cards are not repositioned
+        col.set_due_date(&cids[..1], "1")?;
+        assert_eq!(col.reposition_new_cards(&cids, 0, 1, false, false)?, 3);
+
+        // positions that can't be stored are rejected
+        assert!(col
+            .reposition_new_cards(&cids, i32::MAX as u32, 1, false, false)
+            .is_err());
+        assert!(col
+            .reposition_new_cards(&cids, 0, u32::MAX, false, false)
+            .is_err());
+        assert_eq!(positions(&col)?[1..], [0, 1, 2]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    backend_proto::DeckTreeNode, card::CardQueue, config::NewReviewMix, decks::DeckKind, prelude::*,
+};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum QueueEntryKind {
+    New,
+    Learning,
+    Review,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct QueuedCard {
+    pub card: Card,
+    pub kind: QueueEntryKind,
+}
+
+/// The next cards to show in the current deck, and the number of cards of
+/// each kind that are due in it today.
+#[derive(Debug, PartialEq, Clone)]
+pub struct QueuedCards {
+    pub cards: Vec<QueuedCard>,
+    pub new_count: u32,
+    pub learning_count: u32,
+    pub review_count: u32,
+}
+
+/// Collects the cards that are due, skipping siblings of cards that have
+/// already been queued when the deck options ask for them to be buried.
+struct QueueBuilder {
+    learning: Vec<QueuedCard>,
+    review: Vec<QueuedCard>,
+    new: Vec<QueuedCard>,
+    seen_notes: HashSet<NoteID>,
+    /// (bury new, bury reviews) for each deck
+    bury_options: HashMap<DeckID, (bool, bool)>,
+}
+
+impl Collection {
+    /// Build the learning, review and new queues for the current deck and
+    /// its children, returning up to fetch_limit cards in the order they
+    /// should be shown: due learning cards, reviews and new cards in the
+    /// order the collection's new card setting asks for, and then learning
+    /// cards that can be shown early.
+    /// The returned counts cover all cards that were gathered, not just
+    /// the fetched ones.
+    pub fn get_queued_cards(&mut self, fetch_limit: u32) -> Result<QueuedCards> {
+        self.unbury_on_day_rollover()?;
+        let now = TimestampSecs::now();
+        let today = self.timing_for_timestamp(now)?.days_elapsed;
+        let learn_cutoff = self.learn_ahead_cutoff(now)?;
+        let current = self.get_current_deck_id();
+        let tree = self.deck_tree(Some(now), Some(current))?;
+        let node = match find_node(tree, current) {
+            Some(node) => node,
+            None => {
+                return Ok(QueuedCards {
+                    cards: vec![],
+                    new_count: 0,
+                    learning_count: 0,
+                    review_count: 0,
+                })
+            }
+        };
+        let mut dids = vec![];
+        collect_deck_ids(&node, &mut dids);
+
+        let mut builder = QueueBuilder {
+            learning: vec![],
+            review: vec![],
+            new: vec![],
+            seen_notes: HashSet::new(),
+            bury_options: HashMap::new(),
+        };
+
+        // learning cards are not limited, and previews are shown with them
+        let mut learning = vec![];
+        for queue in &[CardQueue::Learn, CardQueue::PreviewRepeat] {
+            learning.extend(self.sched_storage().cards_in_queue_for_decks(
+                &dids,
+                *queue,
+                learn_cutoff.0,
+                u32::MAX,
+            )?);
+        }
+        learning.sort_by_key(|card| card.due);
+        // cards due in the learn ahead window are only shown once the
+        // other queues are empty
+        let (learning, learn_ahead): (Vec<_>, Vec<_>) = learning
+            .into_iter()
+            .partition(|card| card.due as i64 <= now.0);
+        builder.add_all(learning, QueueEntryKind::Learning);
+        let day_learning = self.sched_storage().cards_in_queue_for_decks(
+            &dids,
+            CardQueue::DayLearn,
+            today as i64 + 1,
+            u32::MAX,
+        )?;
+        builder.add_all(day_learning, QueueEntryKind::Learning);
+
+        // reviews are limited by the selected deck only
+        let reviews = self.sched_storage().cards_in_queue_for_decks(
+            &dids,
+            CardQueue::Review,
+            today as i64 + 1,
+            node.review_count,
+        )?;
+        for card in reviews {
+            let bury = self.bury_options(&mut builder, card.did)?.1;
+            builder.add(card, QueueEntryKind::Review, bury);
+        }
+
+        // new cards are gathered from each deck in turn
+        self.add_new_cards(&mut builder, &node, node.new_count)?;
+
+        let learn_ahead: Vec<_> = learn_ahead
+            .into_iter()
+            .map(|card| QueuedCard {
+                card,
+                kind: QueueEntryKind::Learning,
+            })
+            .collect();
+
+        let new_count = builder.new.len() as u32;
+        let learning_count = (builder.learning.len() + learn_ahead.len()) as u32;
+        let review_count = builder.review.len() as u32;
+        let mut cards = builder.learning;
+        cards.extend(merge_new_and_reviews(
+            builder.review,
+            builder.new,
+            self.get_new_review_mix(),
+        ));
+        cards.extend(learn_ahead);
+        cards.truncate(fetch_limit as usize);
+
+        Ok(QueuedCards {
+            cards,
+            new_count,
+            learning_count,
+            review_count,
+        })
+    }
+
+    /// Add up to limit new cards from the deck and then its children.
+    /// The node's counts have already had the deck limits applied.
+    /// Returns the number of cards added.
+    fn add_new_cards(
+        &mut self,
+        builder: &mut QueueBuilder,
+        node: &DeckTreeNode,
+        limit: u32,
+    ) -> Result<u32> {
+        let limit = limit.min(node.new_count);
+        let mut added = 0;
+        let cards = self.sched_storage().cards_in_queue_for_decks(
+            &[DeckID(node.deck_id)],
+            CardQueue::New,
+            i64::MAX,
+            limit,
+        )?;
+        for card in cards {
+            let bury = self.bury_options(builder, card.did)?.0;
+            if builder.add(card, QueueEntryKind::New, bury) {
+                added += 1;
+            }
+        }
+        for child in &node.children {
+            if added >= limit {
+                break;
+            }
+            added += self.add_new_cards(builder, child, limit - added)?;
+        }
+
+        Ok(added)
+    }
+
+    fn bury_options(&mut self, builder: &mut QueueBuilder, did: DeckID) -> Result<(bool, bool)> {
+        if let Some(options) = builder.bury_options.get(&did) {
+            return Ok(*options);
+        }
+        let options = match self.get_deck(did)?.map(|deck| deck.kind.clone()) {
+            Some(DeckKind::Normal(normal)) => {
+                let conf = self
+                    .get_deck_config(DeckConfID(normal.config_id), true)?
+                    .unwrap();
+                (conf.inner.bury_new, conf.inner.bury_reviews)
+            }
+            // filtered decks don't bury siblings
+            _ => (false, false),
+        };
+        builder.bury_options.insert(did, options);
+        Ok(options)
+    }
+}
+
+impl QueueBuilder {
+    /// Returns false if the card was skipped because a sibling is queued.
+    fn add(&mut self, card: Card, kind: QueueEntryKind, bury_siblings: bool) -> bool {
+        let first_of_note = self.seen_notes.insert(card.nid);
+        if bury_siblings && !first_of_note {
+            return false;
+        }
+        let queue = match kind {
+            QueueEntryKind::New => &mut self.new,
+            QueueEntryKind::Learning => &mut self.learning,
+            QueueEntryKind::Review => &mut self.review,
+        };
+        queue.push(QueuedCard { card, kind });
+        true
+    }
+
+    fn add_all(&mut self, cards: Vec<Card>, kind: QueueEntryKind) {
+        for card in cards {
+            self.add(card, kind, false);
+        }
+    }
+}
+
+/// Combine the review and new queues. When mixing, new cards are spread
+/// evenly through the reviews, like the Python scheduler.
+fn merge_new_and_reviews(
+    review: Vec<QueuedCard>,
+    new: Vec<QueuedCard>,
+    mix: NewReviewMix,
+) -> Vec<QueuedCard> {
+    match mix {
+        NewReviewMix::ReviewsFirst => review.into_iter().chain(new).collect(),
+        NewReviewMix::NewFirst => new.into_iter().chain(review).collect(),
+        NewReviewMix::Mix => {
+            if new.is_empty() {
+                return review;
+            }
+            let total = review.len() + new.len();
+            let mut modulus = total / new.len();
+            if !review.is_empty() {
+                modulus = modulus.max(2);
+            }
+            let mut review = review.into_iter();
+            let mut new = new.into_iter();
+            (0..total)
+                .filter_map(|idx| {
+                    if idx != 0 && idx % modulus == 0 {
+                        new.next().or_else(|| review.next())
+                    } else {
+                        review.next().or_else(|| new.next())
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+fn find_node(node: DeckTreeNode, target: DeckID) -> Option<DeckTreeNode> {
+    if node.deck_id == target.0 {
+        return Some(node);
+    }
+    node.children
+        .into_iter()
+        .find_map(|child| find_node(child, target))
+}
+
+fn collect_deck_ids(node: &DeckTreeNode, dids: &mut Vec<DeckID>) {
+    dids.push(DeckID(node.deck_id));
+    for child in &node.children {
+        collect_deck_ids(child, dids);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{card::CardType, collection::open_test_collection};
+
+    #[test]
+    fn queues() -> Result<()> {
+        let mut col = open_test_collection();
+        for _ in 0..3 {
+            col.add_test_note("Basic (and reversed card)")?;
+        }
+        let review = col.add_test_note("Basic (and reversed card)")?;
+        let today = col.timing_today()?.days_elapsed as i32;
+        col.get_and_update_card(review[0], |card| {
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Review;
+            card.due = today;
+            card.ivl = 1;
+            Ok(())
+        })?;
+
+        let kinds = |queued: &QueuedCards| -> Vec<QueueEntryKind> {
+            queued.cards.iter().map(|queued| queued.kind).collect()
+        };
+        let queued = col.get_queued_cards(100)?;
+        assert_eq!(queued.new_count, 7);
+        assert_eq!(queued.review_count, 1);
+        assert_eq!(queued.learning_count, 0);
+        assert_eq!(queued.cards[0].card.id, review[0]);
+        assert_eq!(&kinds(&queued)[1..], &[QueueEntryKind::New; 7]);
+        assert_eq!(col.get_queued_cards(2)?.cards.len(), 2);
+
+        // new cards can be shown before reviews
+        col.set_new_review_mix(NewReviewMix::NewFirst)?;
+        let queued = col.get_queued_cards(100)?;
+        assert_eq!(kinds(&queued)[7], QueueEntryKind::Review);
+        col.set_new_review_mix(NewReviewMix::ReviewsFirst)?;
+        assert_eq!(col.get_queued_cards(100)?.cards[0].card.id, review[0]);
+
+        // burying siblings skips new cards whose note is already queued
+        let mut conf = col.get_deck_config(DeckConfID(1), false)?.unwrap();
+        conf.inner.bury_new = true;
+        conf.inner.new_per_day = 3;
+        col.add_or_update_deck_config(&mut conf, false)?;
+        let queued = col.get_queued_cards(100)?;
+        assert_eq!(queued.new_count, 2);
+        assert!(queued
+            .cards
+            .iter()
+            .all(|queued| queued.kind == QueueEntryKind::Review || queued.card.ord == 0));
+
+        // limits can be raised or lowered for today
+        col.extend_limits_for_today(DeckID(1), -2, -200)?;
+        let queued = col.get_queued_cards(100)?;
+        assert_eq!(queued.new_count, 1);
+        assert_eq!(queued.review_count, 0);
+        col.extend_limits_for_today(DeckID(1), 2, 200)?;
+        assert_eq!(col.get_queued_cards(100)?.new_count, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn learning_ahead() -> Result<()> {
+        let mut col = open_test_collection();
+        let mut cids = vec![];
+        for _ in 0..4 {
+            cids.push(col.add_test_note("Basic")?[0]);
+        }
+        let now = TimestampSecs::now().0 as i32;
+        for (cid, due) in cids.iter().zip(&[now - 60, now + 60, now + 3600]) {
+            col.get_and_update_card(*cid, |card| {
+                card.ctype = CardType::Learn;
+                card.queue = CardQueue::Learn;
+                card.due = *due;
+                Ok(())
+            })?;
+        }
+
+        // cards due soon are shown after the other queues
+        let queued = col.get_queued_cards(100)?;
+        let ids: Vec<_> = queued.cards.iter().map(|queued| queued.card.id).collect();
+        assert_eq!(ids, &[cids[0], cids[3], cids[1]]);
+        assert_eq!(queued.learning_count, 2);
+
+        col.set_learn_ahead_secs(0)?;
+        let queued = col.get_queued_cards(100)?;
+        assert_eq!(queued.cards.len(), 2);
+        assert_eq!(queued.learning_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn mixing() {
+        use QueueEntryKind::{New, Review};
+        let queued = |kind| QueuedCard {
+            card: Card::default(),
+            kind,
+        };
+        let kinds = |review: usize, new: usize, mix| -> Vec<QueueEntryKind> {
+            merge_new_and_reviews(
+                vec![queued(QueueEntryKind::Review); review],
+                vec![queued(QueueEntryKind::New); new],
+                mix,
+            )
+            .into_iter()
+            .map(|queued| queued.kind)
+            .collect()
+        };
+
+        assert_eq!(
+            kinds(4, 2, NewReviewMix::Mix),
+            &[Review, Review, Review, New, Review, New]
+        );
+        assert_eq!(kinds(1, 3, NewReviewMix::Mix), &[Review, New, New, New]);
+        assert_eq!(kinds(0, 2, NewReviewMix::Mix), &[New, New]);
+        assert_eq!(kinds(2, 1, NewReviewMix::NewFirst), &[New, Review, Review]);
+        assert_eq!(
+            kinds(2, 1, NewReviewMix::ReviewsFirst),
+            &[Review, Review, New]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::{
+    algorithm::{scheduler_for_config, ReviewState},
+    steps::step_secs,
+};
+use crate::{
+    card::{CardQueue, CardType},
+    config::SchedulerVersion,
+    deckconf::DeckConf,
+    prelude::*,
+    revlog::{RevlogEntry, RevlogReviewKind},
+};
+
+impl Collection {
+    /// Recompute the type, queue, due date, interval, ease, reps and
+    /// lapses of cards from their review history, eg after the database
+    /// was damaged or an add-on rescheduled cards incorrectly. Cards in
+    /// filtered decks are returned home, suspended and buried cards stay
+    /// that way, and cards without any history are left alone. Returns
+    /// the number of cards that were changed.
+    pub fn reconstruct_cards_from_revlog(&mut self, cids: &[CardID]) -> Result<usize> {
+        if self.sched_ver() == SchedulerVersion::V1 {
+            return Err(AnkiError::invalid_input("requires the v2 scheduler"));
+        }
+        self.transact(None, |col| {
+            let mut changed = 0;
+            for cid in cids {
+                if let Some(original) = col.sched_storage().get_card(*cid)? {
+                    let mut entries = col.sched_storage().get_revlog_entries_for_card(*cid)?;
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    entries.sort_by_key(|entry| entry.id);
+                    let mut card = original.clone();
+                    col.reconstruct_card(&mut card, &entries)?;
+                    if card != original {
+                        col.update_card(&mut card, &original)?;
+                        changed += 1;
+                    }
+                }
+            }
+            Ok(changed)
+        })
+    }
+
+    /// Entries must be sorted and not empty.
+    fn reconstruct_card(&mut self, card: &mut Card, entries: &[RevlogEntry]) -> Result<()> {
+        let answers = entries
+            .iter()
+            .filter(|entry| entry.review_kind != RevlogReviewKind::Manual);
+        card.reps = answers.clone().count() as u32;
+        card.lapses = answers
+            .filter(|entry| {
+                entry.button_chosen == 1 && entry.review_kind == RevlogReviewKind::Review
+            })
+            .count() as u32;
+
+        // only entries after the card was last reset to new matter
+        let start = entries
+            .iter()
+            .rposition(|entry| entry.review_kind == RevlogReviewKind::Manual && entry.interval == 0)
+            .map(|idx| idx + 1)
+            .unwrap_or(0);
+        let history = &entries[start..];
+        let last = match history.last() {
+            Some(last) => last,
+            None => {
+                if card.ctype != CardType::New {
+                    card.due = self.sched_storage().max_new_card_position()? as i32;
+                }
+                card.remove_from_filtered_deck();
+                card.ctype = CardType::New;
+                card.ivl = 0;
+                card.factor = 0;
+                card.left = 0;
+                restore_queue(card);
+                return Ok(());
+            }
+        };
+
+        card.remove_from_filtered_deck();
+        if let Some(entry) = history.iter().rev().find(|entry| entry.ease_factor > 0) {
+            card.factor = entry.ease_factor as u16;
+        }
+        let timing = self.timing_for_timestamp(last.id.as_secs())?;
+        if last.interval > 0 {
+            card.ctype = CardType::Review;
+            card.ivl = last.interval as u32;
+            card.due = (timing.days_elapsed + card.ivl) as i32;
+            restore_queue(card);
+            return Ok(());
+        }
+
+        // waiting for the next (re)learning step
+        let config = self.home_deck_config(card)?.inner;
+        let graduated_ivl = history
+            .iter()
+            .rev()
+            .find(|entry| entry.interval > 0)
+            .map(|entry| entry.interval as u32);
+        let steps = if let Some(ivl) = graduated_ivl {
+            card.ctype = CardType::Relearn;
+            let state = ReviewState {
+                interval: ivl,
+                ease_factor: card.factor as f64 / 1000.0,
+                days_late: 0,
+                elapsed_days: ivl as f64,
+            };
+            let ivl = scheduler_for_config(&config).lapse_interval(&state, &config);
+            card.ivl = (ivl as u32).max(config.minimum_review_interval());
+            &config.relearn_steps
+        } else {
+            card.ctype = CardType::Learn;
+            card.ivl = 0;
+            &config.learn_steps
+        };
+        let delay = -last.interval;
+        let remaining = steps
+            .iter()
+            .position(|step| step_secs(*step) as i32 == delay)
+            .map(|idx| steps.len() - idx)
+            .unwrap_or(1) as u32;
+        card.left = remaining + remaining * 1000;
+        let due = last.id.as_secs().0 + delay as i64;
+        if due < timing.next_day_at {
+            card.due = due as i32;
+        } else {
+            let days_ahead = (due - timing.next_day_at) / 86_400 + 1;
+            card.due = (timing.days_elapsed as i64 + days_ahead) as i32;
+        }
+        restore_queue(card);
+        Ok(())
+    }
+
+    fn home_deck_config(&mut self, card: &Card) -> Result<DeckConf> {
+        let dcid = self
+            .get_deck(card.did)?
+            .and_then(|deck| deck.config_id())
+            .unwrap_or(DeckConfID(1));
+        Ok(self.get_deck_config(dcid, true)?.unwrap())
+    }
+}
+
+/// Move the card into the queue of its type, unless it is suspended or
+/// buried.
+fn restore_queue(card: &mut Card) {
+    if !matches!(
+        card.queue,
+        CardQueue::Suspended | CardQueue::SchedBuried | CardQueue::UserBuried
+    ) {
+        card.restore_queue_from_type();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        collection::open_test_collection,
+        config::ConfigKey,
+        sched::answering::{CardAnswer, Rating},
+    };
+
+    #[test]
+    fn reconstructing() -> Result<()> {
+        let mut col = open_test_collection();
+        col.set_config(ConfigKey::SchedulerVersion, &SchedulerVersion::V2)?;
+        let mut cids = vec![];
+        for _ in 0..2 {
+            cids.push(col.add_test_note("Basic")?[0]);
+        }
+        let answer = |col: &mut Collection, cid: CardID, rating: Rating| -> Result<()> {
+            col.answer_card(&CardAnswer {
+                card_id: cid,
+                rating,
+                answered_at: TimestampMillis::now(),
+                milliseconds_taken: 0,
+                fuzz_seed: None,
+                custom_state: None,
+            })?;
+            Ok(())
+        };
+        // one card graduates, and the other is on its second step
+        answer(&mut col, cids[0], Rating::Easy)?;
+        answer(&mut col, cids[1], Rating::Good)?;
+        let expected: Vec<_> = cids
+            .iter()
+            .map(|cid| col.storage.get_card(*cid).unwrap().unwrap())
+            .collect();
+
+        // cards that match their history are not changed
+        assert_eq!(col.reconstruct_cards_from_revlog(&cids[..1])?, 0);
+
+        // damage the cards
+        for cid in &cids {
+            let mut card = col.storage.get_card(*cid)?.unwrap();
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Suspended;
+            card.ivl = 5000;
+            card.due = -3;
+            col.storage.update_card(&card)?;
+        }
+        assert_eq!(col.reconstruct_cards_from_revlog(&cids)?, 2);
+        for (cid, expected) in cids.iter().zip(expected) {
+            let card = col.storage.get_card(*cid)?.unwrap();
+            // the suspension is kept
+            assert_eq!(card.queue, CardQueue::Suspended);
+            assert_eq!(card.ctype, expected.ctype);
+            assert_eq!(card.ivl, expected.ivl);
+            // learning steps are fuzzed when answered
+            assert!(card.due <= expected.due && card.due >= expected.due - 300);
+            assert_eq!(card.factor, expected.factor);
+            assert_eq!(card.left, expected.left);
+            assert_eq!(card.reps, expected.reps);
+        }
+
+        // forgotten cards become new again
+        col.forget_cards(&cids[..1])?;
+        assert_eq!(col.reconstruct_cards_from_revlog(&cids[..1])?, 0);
+        let card = col.storage.get_card(cids[0])?.unwrap();
+        assert_eq!(card.ctype, CardType::New);
+        assert_eq!(card.queue, CardQueue::New);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    card::{CardQueue, CardType},
+    prelude::*,
+    revlog::{RevlogEntry, RevlogReviewKind},
+};
+use rand::Rng;
+use std::collections::HashMap;
+
+/// The ease factor given to cards that are rescheduled before they have
+/// been reviewed.
+const INITIAL_EASE_FACTOR: u16 = 2500;
+
+impl Card {
+    fn set_due_date(&mut self, due: i32, days: u32) {
+        self.remove_from_filtered_deck();
+        self.ctype = CardType::Review;
+        self.queue = CardQueue::Review;
+        self.due = due;
+        self.ivl = days.max(1);
+        if self.factor == 0 {
+            self.factor = INITIAL_EASE_FACTOR;
+        }
+    }
+
+    fn schedule_as_new(&mut self, position: u32) {
+        self.remove_from_filtered_deck();
+        self.ctype = CardType::New;
+        self.queue = CardQueue::New;
+        self.due = position as i32;
+        self.ivl = 0;
+        self.factor = 0;
+        self.left = 0;
+    }
+
+    /// Move the due date of a card that is due on a day. Cards can't be
+    /// moved before today. Returns false if the card is not due on a day.
+    fn shift_due_date(&mut self, today: u32, days: i32) -> bool {
+        let due_on_day = match self.queue {
+            CardQueue::Review | CardQueue::DayLearn => true,
+            CardQueue::Suspended | CardQueue::SchedBuried | CardQueue::UserBuried => {
+                self.ctype == CardType::Review
+            }
+            _ => false,
+        };
+        if !due_on_day {
+            return false;
+        }
+        let due = if self.odid.0 != 0 {
+            &mut self.odue
+        } else {
+            &mut self.due
+        };
+        *due = (*due + days).max(today as i32);
+        true
+    }
+}
+
+/// Parse a number of days like "5", or a range like "3-7".
+fn parse_due_date_range(days: &str) -> Result<(u32, u32)> {
+    let parse = |num: &str| {
+        num.trim()
+            .parse::<u32>()
+            .map_err(|_| AnkiError::invalid_input("invalid due date"))
+    };
+    let mut parts = days.splitn(2, '-');
+    let min = parse(parts.next().unwrap_or_default())?;
+    let max = match parts.next() {
+        Some(max) => parse(max)?,
+        None => min,
+    };
+    Ok((min.min(max), min.max(max)))
+}
+
+impl Collection {
+    /// Move cards into the review queue, due the provided number of days
+    /// from today. If a range like "3-7" is provided, each card is given a
+    /// random number of days from the range.
+    pub fn set_due_date(&mut self, cids: &[CardID], days: &str) -> Result<()> {
+        let (min, max) = parse_due_date_range(days)?;
+        let today = self.timing_today()?.days_elapsed;
+        // due dates are stored as an i32
+        if today
+            .checked_add(max)
+            .filter(|due| *due <= i32::MAX as u32)
+            .is_none()
+        {
+            return Err(AnkiError::invalid_input("due date out of range"));
+        }
+        let mut rng = rand::thread_rng();
+        self.transact(None, |col| {
+            let usn = col.usn()?;
+            let first_id = col.sched_storage().next_revlog_id(TimestampMillis::now())?;
+            for (idx, cid) in cids.iter().enumerate() {
+                if let Some(original) = col.sched_storage().get_card(*cid)? {
+                    let mut card = original.clone();
+                    let days = rng.gen_range(min, max + 1);
+                    card.set_due_date((today + days) as i32, days);
+                    col.log_manually_scheduled_card(
+                        &card,
+                        original.ivl,
+                        usn,
+                        TimestampMillis(first_id.0 + idx as i64),
+                    )?;
+                    col.update_card(&mut card, &original)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Reset cards to new, placing them at the end of the new queue. Cards
+    /// of the same note are given the same position.
+    pub fn forget_cards(&mut self, cids: &[CardID]) -> Result<()> {
+        self.transact(None, |col| col.forget_cards_inner(cids))
+    }
+
+    /// Caller must create transaction.
+    pub(crate) fn forget_cards_inner(&mut self, cids: &[CardID]) -> Result<()> {
+        let usn = self.usn()?;
+        let first_id = self
+            .sched_storage()
+            .next_revlog_id(TimestampMillis::now())?;
+        let mut next_position = self.sched_storage().max_new_card_position()?;
+        let mut positions = HashMap::new();
+        for (idx, cid) in cids.iter().enumerate() {
+            if let Some(original) = self.sched_storage().get_card(*cid)? {
+                let mut card = original.clone();
+                let position = *positions.entry(card.nid).or_insert_with(|| {
+                    next_position += 1;
+                    next_position - 1
+                });
+                card.schedule_as_new(position);
+                self.log_manually_scheduled_card(
+                    &card,
+                    original.ivl,
+                    usn,
+                    TimestampMillis(first_id.0 + idx as i64),
+                )?;
+                self.update_card(&mut card, &original)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Push the due dates of review cards back by the provided number of
+    /// days, or bring them forward if days is negative, keeping the
+    /// cards in the same order. Cards brought forward are due no earlier
+    /// than today. Cards that are new or in learning are left alone.
+    pub fn shift_due_dates(&mut self, cids: &[CardID], days: i32) -> Result<()> {
+        let today = self.timing_today()?.days_elapsed;
+        self.transact(None, |col| {
+            let usn = col.usn()?;
+            let first_id = col.sched_storage().next_revlog_id(TimestampMillis::now())?;
+            for (idx, cid) in cids.iter().enumerate() {
+                if let Some(original) = col.sched_storage().get_card(*cid)? {
+                    let mut card = original.clone();
+                    if !card.shift_due_date(today, days) {
+                        continue;
+                    }
+                    col.log_manually_scheduled_card(
+                        &card,
+                        original.ivl,
+                        usn,
+                        TimestampMillis(first_id.0 + idx as i64),
+                    )?;
+                    col.update_card(&mut card, &original)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Record a card being rescheduled by the user. Callers rescheduling
+    /// multiple cards must provide a different id for each entry.
+    fn log_manually_scheduled_card(
+        &mut self,
+        card: &Card,
+        original_interval: u32,
+        usn: Usn,
+        id: TimestampMillis,
+    ) -> Result<()> {
+        self.sched_storage().add_revlog_entry(&RevlogEntry {
+            id,
+            cid: card.id,
+            usn,
+            button_chosen: 0,
+            interval: card.ivl as i32,
+            last_interval: original_interval as i32,
+            ease_factor: card.factor as u32,
+            taken_millis: 0,
+            review_kind: RevlogReviewKind::Manual,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn due_date_ranges() -> Result<()> {
+        assert_eq!(parse_due_date_range("0")?, (0, 0));
+        assert_eq!(parse_due_date_range(" 3-7 ")?, (3, 7));
+        assert_eq!(parse_due_date_range("7-3")?, (3, 7));
+        assert!(parse_due_date_range("").is_err());
+        assert!(parse_due_date_range("-3").is_err());
+        assert!(parse_due_date_range("a").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rescheduling() -> Result<()> {
+        let mut col = open_test_collection();
+        let cids = col.add_test_note("Basic (and reversed card)")?;
+        let today = col.timing_today()?.days_elapsed as i32;
+
+        col.set_due_date(&cids, "5")?;
+        for cid in &cids {
+            let card = col.storage.get_card(*cid)?.unwrap();
+            assert_eq!(card.ctype, CardType::Review);
+            assert_eq!(card.queue, CardQueue::Review);
+            assert_eq!(card.due, today + 5);
+            assert_eq!(card.ivl, 5);
+            assert_eq!(card.factor, INITIAL_EASE_FACTOR);
+        }
+
+        col.set_due_date(&cids[..1], "0-2")?;
+        let card = col.storage.get_card(cids[0])?.unwrap();
+        assert!((today..=today + 2).contains(&card.due));
+        assert!((1..=2).contains(&card.ivl));
+
+        // due dates that can't be stored are rejected
+        assert!(col.set_due_date(&cids, &u32::MAX.to_string()).is_err());
+        assert!(col
+            .set_due_date(&cids, &(i32::MAX as u32).to_string())
+            .is_err());
+
+        col.forget_cards(&cids)?;
+        let cards: Vec<_> = cids
+            .iter()
+            .map(|cid| col.storage.get_card(*cid).map(Option::unwrap))
+            .collect::<Result<_>>()?;
+        for card in &cards {
+            assert_eq!(card.ctype, CardType::New);
+            assert_eq!(card.queue, CardQueue::New);
+            assert_eq!(card.ivl, 0);
+        }
+        // siblings share a position
+        assert_eq!(cards[0].due, cards[1].due);
+
+        let entries = col.storage.get_revlog_entries_for_card(cids[0])?;
+        assert_eq!(entries.len(), 3);
+        assert!(
+            entries
+                .iter()
+                .all(|entry| entry.review_kind == RevlogReviewKind::Manual
+                    && entry.button_chosen == 0)
+        );
+        assert_eq!(entries[0].interval, 5);
+        assert_eq!(entries[2].interval, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shifting_due_dates() -> Result<()> {
+        let mut col = open_test_collection();
+        let cids = col.add_test_note("Basic (and reversed card)")?;
+        let today = col.timing_today()?.days_elapsed as i32;
+        let due =
+            |col: &Collection, cid| -> Result<i32> { Ok(col.storage.get_card(cid)?.unwrap().due) };
+
+        // new cards are not affected
+        let new_due = due(&col, cids[1])?;
+        col.set_due_date(&cids[..1], "3")?;
+        col.shift_due_dates(&cids, 7)?;
+        assert_eq!(due(&col, cids[0])?, today + 10);
+        assert_eq!(due(&col, cids[1])?, new_due);
+
+        // cards are not brought forward past today
+        col.shift_due_dates(&cids, -5)?;
+        assert_eq!(due(&col, cids[0])?, today + 5);
+        col.shift_due_dates(&cids, -10)?;
+        assert_eq!(due(&col, cids[0])?, today);
+
+        // each change is logged
+        let entries = col.storage.get_revlog_entries_for_card(cids[0])?;
+        assert_eq!(entries.len(), 4);
+        assert!(col.storage.get_revlog_entries_for_card(cids[1])?.is_empty());
+
+        Ok(())
+    }
+}
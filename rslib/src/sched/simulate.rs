@@ -0,0 +1,273 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::algorithm::{scheduler_for_config, ReviewState};
+use crate::{
+    card::{CardQueue, CardType},
+    deckconf::{DeckConf, DeckConfigInner},
+    prelude::*,
+    revlog::RevlogReviewKind,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap};
+
+/// Used when the review log has no answers to estimate from.
+const DEFAULT_ANSWER_SECS: f64 = 8.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationRequest {
+    pub deck_id: DeckID,
+    pub days: u32,
+    /// Overrides the daily new card limit of the deck's options.
+    pub new_per_day: Option<u32>,
+    /// Overrides the daily review limit of the deck's options.
+    pub reviews_per_day: Option<u32>,
+    /// The chance of a review being answered correctly, eg 0.9. If not
+    /// set, every review is assumed to be passed.
+    pub retention: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimulatedDay {
+    /// reviews due, including ones left over from previous days
+    pub due: u32,
+    pub reviewed: u32,
+    pub lapsed: u32,
+    /// new cards studied for the first time
+    pub introduced: u32,
+    /// estimated from the time taken on past answers
+    pub seconds: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SimulatedCard {
+    due: i32,
+    /// 0 for cards still in learning
+    ivl: u32,
+    factor: u16,
+    did: DeckID,
+}
+
+impl Collection {
+    /// Project the workload of a deck and its children over the coming
+    /// days, assuming every card due is studied up to the deck's limits,
+    /// and that cards in learning graduate on the day they are due.
+    /// Answers are not fuzzed. Reviews are failed at random when a
+    /// retention is provided; the same request gives the same result.
+    pub fn simulate_reviews(&mut self, req: &SimulationRequest) -> Result<Vec<SimulatedDay>> {
+        let deck = self
+            .sched_storage()
+            .get_deck(req.deck_id)?
+            .ok_or(AnkiError::NotFound)?;
+        let top_config = match deck.config_id() {
+            Some(dcid) => self.get_deck_config(dcid, true)?.unwrap(),
+            None => return Err(AnkiError::invalid_input("deck is filtered")),
+        };
+        let today = self.timing_today()?.days_elapsed;
+
+        let mut configs: HashMap<DeckID, DeckConf> = HashMap::new();
+        for child in self.sched_storage().child_decks(&deck)? {
+            if let Some(dcid) = child.config_id() {
+                configs.insert(child.id, self.get_deck_config(dcid, true)?.unwrap());
+            }
+        }
+        configs.insert(deck.id, top_config.clone());
+        let dids: Vec<_> = configs.keys().copied().collect();
+
+        let mut due_by_day: BTreeMap<i32, Vec<SimulatedCard>> = BTreeMap::new();
+        let mut new_cards = vec![];
+        for card in self.sched_storage().all_cards_in_decks(&dids)? {
+            let due = match card.queue {
+                CardQueue::New => {
+                    new_cards.push(card);
+                    continue;
+                }
+                CardQueue::Review | CardQueue::DayLearn => card.due,
+                CardQueue::Learn => today as i32,
+                CardQueue::SchedBuried | CardQueue::UserBuried
+                    if card.ctype == CardType::Review =>
+                {
+                    card.due.max(today as i32 + 1)
+                }
+                _ => continue,
+            };
+            due_by_day.entry(due).or_default().push(SimulatedCard {
+                due,
+                ivl: if card.ctype == CardType::Learn {
+                    0
+                } else {
+                    card.ivl
+                },
+                factor: card.factor,
+                did: card.did,
+            });
+        }
+        new_cards.sort_by_key(|card| (card.due, card.ord));
+        let mut new_cards = new_cards.into_iter();
+
+        let new_per_day = req.new_per_day.unwrap_or(top_config.inner.new_per_day);
+        let reviews_per_day = req
+            .reviews_per_day
+            .unwrap_or(top_config.inner.reviews_per_day);
+        let (new_studied, review_studied) = deck.new_rev_counts(today);
+        let review_secs = self
+            .sched_storage()
+            .average_answer_secs(RevlogReviewKind::Review)?
+            .unwrap_or(DEFAULT_ANSWER_SECS);
+        let new_secs = self
+            .sched_storage()
+            .average_answer_secs(RevlogReviewKind::Learning)?
+            .unwrap_or(DEFAULT_ANSWER_SECS)
+            * top_config.inner.learn_steps.len().max(1) as f64;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut backlog = vec![];
+        let mut days = vec![];
+        for offset in 0..req.days {
+            let day = (today + offset) as i32;
+            let later = due_by_day.split_off(&(day + 1));
+            for (_, cards) in std::mem::replace(&mut due_by_day, later) {
+                backlog.extend(cards);
+            }
+            let (new_limit, review_limit) = if offset == 0 {
+                (
+                    (new_per_day as i32 - new_studied).max(0) as usize,
+                    (reviews_per_day as i32 - review_studied).max(0) as usize,
+                )
+            } else {
+                (new_per_day as usize, reviews_per_day as usize)
+            };
+
+            let mut sim = SimulatedDay {
+                due: backlog.len() as u32,
+                ..Default::default()
+            };
+            let count = review_limit.min(backlog.len());
+            for mut card in backlog.drain(..count) {
+                let config = &configs[&card.did].inner;
+                if card.ivl == 0 {
+                    graduate(&mut card, config);
+                } else if req.retention.map_or(true, |r| rng.gen::<f32>() < r) {
+                    card.ivl = passing_interval(&card, day, config);
+                } else {
+                    sim.lapsed += 1;
+                    lapse(&mut card, day, config);
+                }
+                card.due = day + card.ivl as i32;
+                due_by_day.entry(card.due).or_default().push(card);
+                sim.reviewed += 1;
+            }
+
+            for new in new_cards.by_ref().take(new_limit) {
+                let mut card = SimulatedCard {
+                    due: day,
+                    ivl: 0,
+                    factor: 0,
+                    did: new.did,
+                };
+                graduate(&mut card, &configs[&card.did].inner);
+                card.due = day + card.ivl as i32;
+                due_by_day.entry(card.due).or_default().push(card);
+                sim.introduced += 1;
+            }
+
+            sim.seconds = (sim.reviewed as f64 * review_secs + sim.introduced as f64 * new_secs)
+                .round() as u32;
+            days.push(sim);
+        }
+
+        Ok(days)
+    }
+}
+
+fn graduate(card: &mut SimulatedCard, config: &DeckConfigInner) {
+    card.ivl = config.graduating_interval_good.max(1);
+    card.factor = (config.initial_ease() * 1000.0).round() as u16;
+}
+
+/// Like answering Good, without fuzz.
+fn passing_interval(card: &SimulatedCard, day: i32, config: &DeckConfigInner) -> u32 {
+    let state = review_state(card, day);
+    let intervals = scheduler_for_config(config).review_intervals(&state, config);
+    let constrain = |ivl: f64, prev: u32| {
+        ((ivl * config.interval_multiplier()) as u32)
+            .max(prev + 1)
+            .max(1)
+            .min(config.maximum_review_interval())
+    };
+    let hard_min = if config.hard_multiplier() > 1.0 {
+        card.ivl
+    } else {
+        0
+    };
+    let hard = constrain(intervals.hard, hard_min);
+    constrain(intervals.good, hard)
+}
+
+/// Like answering Again, and then relearning the card the same day.
+fn lapse(card: &mut SimulatedCard, day: i32, config: &DeckConfigInner) {
+    let scheduler = scheduler_for_config(config);
+    let ivl = scheduler.lapse_interval(&review_state(card, day), config);
+    card.ivl = (ivl as u32).max(config.minimum_review_interval());
+    card.factor = scheduler.ease_after_lapse(card.factor);
+}
+
+fn review_state(card: &SimulatedCard, day: i32) -> ReviewState {
+    let days_late = (day - card.due).max(0) as u32;
+    ReviewState {
+        interval: card.ivl,
+        ease_factor: card.factor as f64 / 1000.0,
+        days_late,
+        elapsed_days: (card.ivl + days_late) as f64,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn simulating() -> Result<()> {
+        let mut col = open_test_collection();
+        let mut cids = vec![];
+        for _ in 0..9 {
+            cids.push(col.add_test_note("Basic")?[0]);
+        }
+        // four reviews due today with an interval of a day
+        col.set_due_date(&cids[..4], "0")?;
+
+        let mut req = SimulationRequest {
+            deck_id: DeckID(1),
+            days: 4,
+            new_per_day: Some(2),
+            reviews_per_day: Some(100),
+            retention: None,
+        };
+        let summary = |days: Vec<SimulatedDay>| -> Vec<(u32, u32, u32)> {
+            days.into_iter()
+                .map(|day| (day.due, day.reviewed, day.introduced))
+                .collect()
+        };
+        // the reviews come back in 3 days, and the new cards the next day
+        assert_eq!(
+            summary(col.simulate_reviews(&req)?),
+            &[(4, 4, 2), (2, 2, 2), (2, 2, 1), (5, 5, 0)]
+        );
+
+        // reviews over the limit are carried over
+        req.reviews_per_day = Some(3);
+        assert_eq!(
+            summary(col.simulate_reviews(&req)?),
+            &[(4, 3, 2), (3, 3, 2), (2, 2, 1), (4, 3, 0)]
+        );
+
+        // with no retention, every review lapses
+        req.retention = Some(0.0);
+        let days = col.simulate_reviews(&req)?;
+        assert_eq!(days[0].lapsed, 3);
+        assert!(days[0].seconds > 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    backend_proto as pb,
+    card::{CardQueue, CardType},
+    prelude::*,
+};
+use std::convert::TryFrom;
+
+/// The scheduling-related parts of a card.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulingState {
+    pub ctype: CardType,
+    pub queue: CardQueue,
+    pub due: i32,
+    pub interval: u32,
+    pub ease_factor: u16,
+    /// the learning steps left, as in Card::left
+    pub remaining_steps: u32,
+    pub lapses: u32,
+}
+
+/// A card's current state, and the state each rating would give it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulingStates {
+    pub current: SchedulingState,
+    pub again: SchedulingState,
+    pub hard: SchedulingState,
+    pub good: SchedulingState,
+    pub easy: SchedulingState,
+}
+
+impl SchedulingState {
+    /// Rejects states that answering a card could not produce, such as
+    /// one that suspends it, or a type that doesn't match the queue.
+    pub(crate) fn validate(&self) -> Result<()> {
+        let valid = self.due >= 0
+            && match self.queue {
+                CardQueue::New => self.ctype == CardType::New,
+                CardQueue::Learn | CardQueue::DayLearn => {
+                    matches!(self.ctype, CardType::Learn | CardType::Relearn)
+                }
+                CardQueue::Review => self.ctype == CardType::Review && self.interval > 0,
+                CardQueue::PreviewRepeat => true,
+                CardQueue::Suspended | CardQueue::SchedBuried | CardQueue::UserBuried => false,
+            };
+        if valid {
+            Ok(())
+        } else {
+            Err(AnkiError::invalid_input("invalid scheduling state"))
+        }
+    }
+}
+
+impl Card {
+    pub(crate) fn scheduling_state(&self) -> SchedulingState {
+        SchedulingState {
+            ctype: self.ctype,
+            queue: self.queue,
+            due: self.due,
+            interval: self.ivl,
+            ease_factor: self.factor,
+            remaining_steps: self.left,
+            lapses: self.lapses,
+        }
+    }
+
+    pub(crate) fn apply_scheduling_state(&mut self, state: &SchedulingState) {
+        // only cards in learning or being previewed stay in a filtered deck
+        // after they are answered, so other states return the card home
+        if matches!(state.queue, CardQueue::New | CardQueue::Review) {
+            self.remove_from_filtered_deck();
+        }
+        self.ctype = state.ctype;
+        self.queue = state.queue;
+        self.due = state.due;
+        self.ivl = state.interval;
+        self.factor = state.ease_factor;
+        self.left = state.remaining_steps;
+        self.lapses = state.lapses;
+    }
+}
+
+impl From<SchedulingState> for pb::SchedulingState {
+    fn from(s: SchedulingState) -> Self {
+        pb::SchedulingState {
+            card_type: s.ctype as u32,
+            queue: s.queue as i32,
+            due: s.due,
+            interval: s.interval,
+            ease_factor: s.ease_factor as u32,
+            remaining_steps: s.remaining_steps,
+            lapses: s.lapses,
+        }
+    }
+}
+
+impl TryFrom<pb::SchedulingState> for SchedulingState {
+    type Error = AnkiError;
+
+    fn try_from(s: pb::SchedulingState) -> Result<Self> {
+        Ok(SchedulingState {
+            ctype: u8::try_from(s.card_type)
+                .ok()
+                .and_then(|ctype| CardType::try_from(ctype).ok())
+                .ok_or_else(|| AnkiError::invalid_input("invalid card type"))?,
+            queue: i8::try_from(s.queue)
+                .ok()
+                .and_then(|queue| CardQueue::try_from(queue).ok())
+                .ok_or_else(|| AnkiError::invalid_input("invalid card queue"))?,
+            due: s.due,
+            interval: s.interval,
+            ease_factor: u16::try_from(s.ease_factor)
+                .map_err(|_| AnkiError::invalid_input("invalid ease factor"))?,
+            remaining_steps: s.remaining_steps,
+            lapses: s.lapses,
+        })
+    }
+}
+
+impl From<SchedulingStates> for pb::SchedulingStates {
+    fn from(s: SchedulingStates) -> Self {
+        pb::SchedulingStates {
+            current: Some(s.current.into()),
+            again: Some(s.again.into()),
+            hard: Some(s.hard.into()),
+            good: Some(s.good.into()),
+            easy: Some(s.easy.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        collection::open_test_collection,
+        decks::{Deck, DeckKind},
+        sched::answering::{CardAnswer, Rating},
+    };
+
+    #[test]
+    fn scheduling_states() -> Result<()> {
+        let mut col = open_test_collection();
+        let cid = col.add_test_note("Basic")?[0];
+
+        let states = col.scheduling_states(cid)?;
+        assert_eq!(states.current.queue, CardQueue::New);
+        assert_eq!(states.again.queue, CardQueue::Learn);
+        assert_eq!(states.easy.queue, CardQueue::Review);
+        assert_eq!(states.easy.interval, 4);
+        // nothing is changed
+        assert_eq!(col.storage.get_card(cid)?.unwrap().queue, CardQueue::New);
+
+        // the proposed state can be changed before answering
+        let mut custom = states.good;
+        custom.ctype = CardType::Review;
+        custom.queue = CardQueue::Review;
+        custom.interval = 7;
+        custom.ease_factor = 2500;
+        custom.due = states.easy.due + 3;
+        let mut answer = CardAnswer {
+            card_id: cid,
+            rating: Rating::Good,
+            answered_at: TimestampMillis::now(),
+            milliseconds_taken: 0,
+            fuzz_seed: None,
+            custom_state: Some(custom),
+        };
+        col.answer_card(&answer)?;
+        let card = col.storage.get_card(cid)?.unwrap();
+        assert_eq!(card.scheduling_state(), custom);
+        assert_eq!(card.reps, 1);
+        assert_eq!(col.storage.get_revlog_entries_for_card(cid)?[0].interval, 7);
+
+        // states that answering can't produce are rejected
+        custom.queue = CardQueue::Suspended;
+        answer.custom_state = Some(custom);
+        assert!(col.answer_card(&answer).is_err());
+
+        // a card given a review state in a filtered deck returns home
+        let cid = col.add_test_note("Basic")?[0];
+        let mut deck = Deck::new_filtered();
+        deck.name = "Filtered".into();
+        if let DeckKind::Filtered(filtered) = &mut deck.kind {
+            filtered.search_terms[0].search = "is:new".into();
+        }
+        assert_eq!(col.add_or_update_filtered_deck(&mut deck)?, 1);
+        custom.queue = CardQueue::Review;
+        answer.card_id = cid;
+        answer.custom_state = Some(custom);
+        col.answer_card(&answer)?;
+        let card = col.storage.get_card(cid)?.unwrap();
+        assert_eq!((card.did, card.odid, card.odue), (DeckID(1), DeckID(0), 0));
+        assert_eq!(card.scheduling_state(), custom);
+
+        // out of range values are rejected
+        let mut state: pb::SchedulingState = custom.into();
+        state.card_type = 256 + CardType::Review as u32;
+        assert!(SchedulingState::try_from(state).is_err());
+
+        Ok(())
+    }
+}
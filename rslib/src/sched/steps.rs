@@ -0,0 +1,96 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::err::{AnkiError, Result};
+
+/// Steps shorter than this can't be represented in a card's due time.
+const MINIMUM_STEP_SECS: u32 = 1;
+
+/// The delay of a learning step, which is stored in minutes, rounded to
+/// the nearest second.
+pub(crate) fn step_secs(step: f32) -> u32 {
+    (step as f64 * 60.0).round() as u32
+}
+
+/// Parse learning steps entered on the options screen, eg "30s 10m 1.5d".
+/// Steps without a unit are in minutes. Returns the steps in minutes.
+pub fn parse_learning_steps(text: &str) -> Result<Vec<f32>> {
+    text.split_whitespace()
+        .map(|step| {
+            let (number, secs_per_unit) = match step.chars().last() {
+                Some('s') => (&step[..step.len() - 1], 1.0),
+                Some('m') => (&step[..step.len() - 1], 60.0),
+                Some('h') => (&step[..step.len() - 1], 3_600.0),
+                Some('d') => (&step[..step.len() - 1], 86_400.0),
+                _ => (step, 60.0),
+            };
+            let secs = number
+                .parse::<f64>()
+                .ok()
+                .filter(|num| num.is_finite())
+                .map(|num| (num * secs_per_unit).round())
+                .filter(|secs| *secs >= MINIMUM_STEP_SECS as f64)
+                .ok_or_else(|| AnkiError::invalid_input(format!("invalid step: {}", step)))?;
+            Ok((secs / 60.0) as f32)
+        })
+        .collect()
+}
+
+/// The inverse of parse_learning_steps(), using the largest unit each step
+/// can be expressed in without a fraction.
+pub fn learning_steps_to_string(steps: &[f32]) -> String {
+    steps
+        .iter()
+        .map(|step| {
+            let secs = step_secs(*step);
+            if secs % 86_400 == 0 {
+                format!("{}d", secs / 86_400)
+            } else if secs % 3_600 == 0 {
+                format!("{}h", secs / 3_600)
+            } else if secs % 60 == 0 {
+                format!("{}m", secs / 60)
+            } else {
+                format!("{}s", secs)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fix up steps that did not come from parse_learning_steps(), eg from an
+/// older client or an add-on, by dropping steps that are not a number and
+/// lengthening steps that are too short.
+pub(crate) fn sanitize_learning_steps(steps: &mut Vec<f32>) {
+    let minimum = MINIMUM_STEP_SECS as f32 / 60.0;
+    steps.retain(|step| step.is_finite());
+    for step in steps.iter_mut() {
+        if step_secs(*step) < MINIMUM_STEP_SECS {
+            *step = minimum;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn steps() -> Result<()> {
+        let steps = parse_learning_steps("30s 1 10m 1h 1.5d")?;
+        let secs: Vec<_> = steps.iter().map(|step| step_secs(*step)).collect();
+        assert_eq!(secs, &[30, 60, 600, 3_600, 129_600]);
+        assert_eq!(learning_steps_to_string(&steps), "30s 1m 10m 1h 36h");
+        assert_eq!(learning_steps_to_string(&[1.0 / 6.0]), "10s");
+
+        assert!(parse_learning_steps("").unwrap().is_empty());
+        assert!(parse_learning_steps("1 x").is_err());
+        assert!(parse_learning_steps("0.1s").is_err());
+        assert!(parse_learning_steps("-5").is_err());
+
+        let mut steps = vec![0.5, 0.0, f32::NAN, -5.0, 1440.0];
+        sanitize_learning_steps(&mut steps);
+        assert_eq!(learning_steps_to_string(&steps), "30s 1s 1s 1d");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{backend_proto as pb, card::CardQueue, prelude::*};
+
+/// The changes made by answering a card, so they can be reverted.
+#[derive(Debug)]
+pub(crate) struct ReviewUndo {
+    /// the card before it was answered
+    pub original: Card,
+    /// the card after it was answered
+    pub answered: Card,
+    /// not set when previewing
+    pub revlog_id: Option<TimestampMillis>,
+    /// the amounts added to the deck's daily counts
+    pub stats: Option<pb::UpdateStatsIn>,
+    pub today: u32,
+    pub leech_tag_added: bool,
+    pub buried_siblings: Vec<CardID>,
+}
+
+impl Collection {
+    /// Revert the most recent answer_card() call, removing its review log
+    /// entry, restoring the card's previous scheduling and the deck's daily
+    /// counts, and unburying any siblings it buried. Returns the card, so
+    /// it can be shown again. Returns None if there is nothing to undo, or
+    /// if the card has been changed since it was answered.
+    pub fn undo_review(&mut self) -> Result<Option<Card>> {
+        let undo = match self.state.review_undo.pop() {
+            Some(undo) => undo,
+            None => return Ok(None),
+        };
+        let current = match self.sched_storage().get_card(undo.original.id)? {
+            Some(card) if card == undo.answered => card,
+            _ => {
+                // older reviews can't be safely reverted either
+                self.state.review_undo.clear();
+                return Ok(None);
+            }
+        };
+        let today = self.timing_today()?.days_elapsed;
+
+        self.transact_review(|col| {
+            let usn = col.usn()?;
+            if let Some(id) = undo.revlog_id {
+                col.sched_storage().remove_revlog_entry(id)?;
+            }
+            // the counts are discarded when the day rolls over
+            if let Some(stats) = undo.stats {
+                if undo.today == today {
+                    col.update_deck_stats(
+                        today,
+                        usn,
+                        pb::UpdateStatsIn {
+                            deck_id: stats.deck_id,
+                            new_delta: -stats.new_delta,
+                            review_delta: -stats.review_delta,
+                            millisecond_delta: -stats.millisecond_delta,
+                        },
+                    )?;
+                }
+            }
+            if undo.leech_tag_added {
+                col.remove_leech_tag(current.nid, usn)?;
+            }
+            for cid in &undo.buried_siblings {
+                if let Some(original) = col.sched_storage().get_card(*cid)? {
+                    if original.queue == CardQueue::SchedBuried {
+                        let mut sibling = original.clone();
+                        sibling.restore_queue_from_type();
+                        col.update_card(&mut sibling, &original)?;
+                    }
+                }
+            }
+            let mut card = undo.original;
+            col.update_card(&mut card, &current)?;
+            Ok(Some(card))
+        })
+    }
+
+    /// Like transact(None, ..), but answers that came before can still be
+    /// reverted afterwards.
+    pub(crate) fn transact_review<F, R>(&mut self, func: F) -> Result<R>
+    where
+        F: FnOnce(&mut Collection) -> Result<R>,
+    {
+        let review_undo = std::mem::take(&mut self.state.review_undo);
+        let res = self.transact(None, func);
+        self.state.review_undo = review_undo;
+        res
+    }
+
+    fn remove_leech_tag(&mut self, nid: NoteID, usn: Usn) -> Result<()> {
+        let mut note = self
+            .sched_storage()
+            .get_note(nid)?
+            .ok_or_else(|| AnkiError::invalid_input("missing note"))?;
+        let nt = self
+            .get_notetype(note.ntid)?
+            .ok_or_else(|| AnkiError::invalid_input("missing note type"))?;
+        note.tags.retain(|tag| !tag.eq_ignore_ascii_case("leech"));
+        let norm = self.normalize_note_text();
+        self.update_note_inner_without_cards(&mut note, &nt, usn, true, norm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        card::CardType,
+        collection::open_test_collection,
+        sched::answering::{CardAnswer, Rating},
+        undo::UNDO_LIMIT,
+    };
+
+    #[test]
+    fn undoing_reviews() -> Result<()> {
+        let mut col = open_test_collection();
+        let cids = col.add_test_note("Basic (and reversed card)")?;
+        let mut conf = col.get_deck_config(DeckConfID(1), false)?.unwrap();
+        conf.inner.bury_new = true;
+        col.add_or_update_deck_config(&mut conf, false)?;
+
+        assert!(col.undo_review()?.is_none());
+
+        let original = col.storage.get_card(cids[0])?.unwrap();
+        col.answer_card(&CardAnswer {
+            card_id: cids[0],
+            rating: Rating::Good,
+            answered_at: TimestampMillis::now(),
+            milliseconds_taken: 1000,
+            fuzz_seed: None,
+            custom_state: None,
+        })?;
+        assert_eq!(
+            col.storage.get_card(cids[1])?.unwrap().queue,
+            CardQueue::SchedBuried
+        );
+        assert_eq!(
+            col.storage.get_deck(DeckID(1))?.unwrap().common.new_studied,
+            1
+        );
+
+        let card = col.undo_review()?.unwrap();
+        assert_eq!(card.id, cids[0]);
+        assert_eq!(card.ctype, CardType::New);
+        assert_eq!(card.queue, CardQueue::New);
+        assert_eq!(card.due, original.due);
+        assert_eq!(
+            col.storage.get_card(cids[0])?.unwrap().queue,
+            CardQueue::New
+        );
+        assert_eq!(
+            col.storage.get_card(cids[1])?.unwrap().queue,
+            CardQueue::New
+        );
+        assert!(col.storage.get_revlog_entries_for_card(cids[0])?.is_empty());
+        let deck = col.storage.get_deck(DeckID(1))?.unwrap();
+        assert_eq!(deck.common.new_studied, 0);
+        assert_eq!(deck.common.milliseconds_studied, 0);
+
+        // nothing left to undo
+        assert!(col.undo_review()?.is_none());
+
+        // a card changed after it was answered can't be reverted
+        col.answer_card(&CardAnswer {
+            card_id: cids[0],
+            rating: Rating::Good,
+            answered_at: TimestampMillis::now(),
+            milliseconds_taken: 0,
+            fuzz_seed: None,
+            custom_state: None,
+        })?;
+        col.suspend_cards(&cids[..1])?;
+        assert!(col.undo_review()?.is_none());
+
+        // only the most recent answers are kept
+        col.unsuspend_cards(&cids[..1])?;
+        for _ in 0..UNDO_LIMIT + 5 {
+            col.answer_card(&CardAnswer {
+                card_id: cids[0],
+                rating: Rating::Good,
+                answered_at: TimestampMillis::now(),
+                milliseconds_taken: 0,
+                fuzz_seed: None,
+                custom_state: None,
+            })?;
+        }
+        assert_eq!(col.state.review_undo.len(), UNDO_LIMIT);
+
+        // and they are discarded by changes that can't be undone
+        col.set_due_date(&cids[1..], "1")?;
+        assert!(col.state.review_undo.is_empty());
+        assert!(col.undo_review()?.is_none());
+
+        Ok(())
+    }
+}
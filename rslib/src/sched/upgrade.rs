@@ -0,0 +1,183 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    card::{CardQueue, CardType},
+    config::{ConfigKey, SchedulerVersion},
+    prelude::*,
+};
+
+impl Collection {
+    /// Switch a collection using the v1 scheduler over to v2. Filtered
+    /// decks are emptied, cards in learning are returned to the review
+    /// queue or reset to new, learning answers in the review log are
+    /// remapped to the v2 buttons, and the decks' daily counts are reset.
+    /// This is a schema change, and can't be undone. Does nothing if the
+    /// collection already uses v2.
+    pub fn upgrade_to_v2_scheduler(&mut self) -> Result<()> {
+        if self.sched_ver() == SchedulerVersion::V2 {
+            return Ok(());
+        }
+        self.transact(None, |col| {
+            col.set_schema_modified()?;
+            col.return_all_filtered_cards_home_v1()?;
+            col.remove_all_from_learning_v1()?;
+            col.sched_storage().upgrade_revlog_to_v2()?;
+            col.reset_all_daily_counts()?;
+            col.set_config(ConfigKey::SchedulerVersion, &SchedulerVersion::V2)
+        })
+    }
+
+    fn return_all_filtered_cards_home_v1(&mut self) -> Result<()> {
+        for (cid, _) in self.sched_storage().all_filtered_cards_by_deck()? {
+            if let Some(original) = self.sched_storage().get_card(cid)? {
+                let mut card = original.clone();
+                card.return_home(SchedulerVersion::V1);
+                self.update_card(&mut card, &original)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Relearning cards go back to the review queue on their original due
+    /// date, as v2 would otherwise treat their due timestamp as a day.
+    /// Other learning cards are reset to new.
+    fn remove_all_from_learning_v1(&mut self) -> Result<()> {
+        let mut to_forget = vec![];
+        for original in self.sched_storage().all_learning_cards()? {
+            match original.ctype {
+                CardType::Review | CardType::Relearn => {
+                    let mut card = original.clone();
+                    card.due = card.odue;
+                    card.odue = 0;
+                    card.ctype = CardType::Review;
+                    card.queue = CardQueue::Review;
+                    self.update_card(&mut card, &original)?;
+                }
+                CardType::New | CardType::Learn => to_forget.push(original.id),
+            }
+        }
+        self.forget_cards_inner(&to_forget)
+    }
+
+    /// The day boundary and the way learning cards are counted differ
+    /// between the two versions, so today's counts are not carried over.
+    fn reset_all_daily_counts(&mut self) -> Result<()> {
+        let usn = self.usn()?;
+        for mut deck in self.sched_storage().get_all_decks()? {
+            let c = &mut deck.common;
+            if c.new_studied != 0
+                || c.review_studied != 0
+                || c.learning_studied != 0
+                || c.milliseconds_studied != 0
+            {
+                c.new_studied = 0;
+                c.review_studied = 0;
+                c.learning_studied = 0;
+                c.milliseconds_studied = 0;
+                deck.set_modified(usn);
+                self.add_or_update_single_deck(&mut deck, usn)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        collection::open_test_collection,
+        decks::Deck,
+        revlog::{RevlogEntry, RevlogID, RevlogReviewKind},
+    };
+
+    #[test]
+    fn upgrading() -> Result<()> {
+        let mut col = open_test_collection();
+        col.set_config(ConfigKey::SchedulerVersion, &SchedulerVersion::V1)?;
+        let mut cids = vec![];
+        for _ in 0..4 {
+            cids.push(col.add_test_note("Basic")?[0]);
+        }
+        let update = |col: &mut Collection, cid, func: &dyn Fn(&mut Card)| -> Result<()> {
+            let mut card = col.storage.get_card(cid)?.unwrap();
+            func(&mut card);
+            col.storage.update_card(&card)
+        };
+        // a new card in learning
+        update(&mut col, cids[0], &|card: &mut Card| {
+            card.ctype = CardType::Learn;
+            card.queue = CardQueue::Learn;
+            card.due = 1_600_000_000;
+            card.left = 1001;
+        })?;
+        // a review card in relearning
+        update(&mut col, cids[1], &|card: &mut Card| {
+            card.ctype = CardType::Review;
+            card.queue = CardQueue::Learn;
+            card.due = 1_600_000_000;
+            card.odue = 50;
+            card.ivl = 10;
+        })?;
+        // a learning card in a filtered deck
+        let mut filtered = Deck::new_filtered();
+        filtered.name = "filtered".into();
+        col.add_or_update_deck(&mut filtered)?;
+        update(&mut col, cids[2], &|card: &mut Card| {
+            card.ctype = CardType::Learn;
+            card.queue = CardQueue::Learn;
+            card.due = 1_600_000_000;
+            card.odue = 2;
+            card.odid = card.did;
+            card.did = filtered.id;
+        })?;
+        col.storage.add_revlog_entry(&RevlogEntry {
+            id: TimestampMillis(1),
+            cid: cids[0],
+            button_chosen: 2,
+            review_kind: RevlogReviewKind::Learning,
+            ..Default::default()
+        })?;
+        let mut deck = col.storage.get_deck(DeckID(1))?.unwrap();
+        deck.common.new_studied = 3;
+        col.add_or_update_deck(&mut deck)?;
+
+        col.upgrade_to_v2_scheduler()?;
+        assert_eq!(col.sched_ver(), SchedulerVersion::V2);
+
+        let card = col.storage.get_card(cids[0])?.unwrap();
+        assert_eq!(card.ctype, CardType::New);
+        assert_eq!(card.queue, CardQueue::New);
+
+        let card = col.storage.get_card(cids[1])?.unwrap();
+        assert_eq!(card.ctype, CardType::Review);
+        assert_eq!(card.queue, CardQueue::Review);
+        assert_eq!(card.due, 50);
+        assert_eq!(card.odue, 0);
+
+        let card = col.storage.get_card(cids[2])?.unwrap();
+        assert_eq!(card.did, DeckID(1));
+        assert_eq!(card.odid, DeckID(0));
+        assert_eq!(card.ctype, CardType::New);
+        assert_eq!(card.queue, CardQueue::New);
+        assert_eq!(card.due, 2);
+
+        let card = col.storage.get_card(cids[3])?.unwrap();
+        assert_eq!(card.queue, CardQueue::New);
+
+        assert_eq!(
+            col.storage
+                .get_revlog_entry(RevlogID(1))?
+                .unwrap()
+                .button_chosen,
+            3
+        );
+        assert_eq!(
+            col.storage.get_deck(DeckID(1))?.unwrap().common.new_studied,
+            0
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::{
+    parser::{parse, FieldSearchMode, Node, SearchNode},
+    writer::{group, write_nodes},
+};
+use crate::{card::CardID, decks::DeckID, err::Result, notes::NoteID};
+
+/// Something that can be converted into a parsed search.
+pub trait TryIntoSearch<'a> {
+    fn try_into_search(self) -> Result<Node<'a>>;
+}
+
+impl<'a> TryIntoSearch<'a> for &'a str {
+    fn try_into_search(self) -> Result<Node<'a>> {
+        parse(self).map(Node::Group)
+    }
+}
+
+impl<'a> TryIntoSearch<'a> for &'a String {
+    fn try_into_search(self) -> Result<Node<'a>> {
+        self.as_str().try_into_search()
+    }
+}
+
+impl<'a> TryIntoSearch<'a> for Node<'a> {
+    fn try_into_search(self) -> Result<Node<'a>> {
+        Ok(self)
+    }
+}
+
+impl TryIntoSearch<'static> for SearchBuilder {
+    fn try_into_search(self) -> Result<Node<'static>> {
+        Ok(Node::Group(self.0))
+    }
+}
+
+/// Constructs a search from code, so that names containing spaces, quotes
+/// or other special characters don't need to be escaped, eg
+/// `SearchBuilder::deck("My Deck").and(SearchBuilder::tag("marked"))`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchBuilder(Vec<Node<'static>>);
+
+impl SearchBuilder {
+    pub fn whole_collection() -> Self {
+        SearchNode::WholeCollection.into()
+    }
+
+    /// Matches the deck and its children.
+    pub fn deck(name: impl Into<String>) -> Self {
+        SearchNode::Deck(escaped(&name.into(), NAME_WILDCARDS).into()).into()
+    }
+
+    /// Matches the deck but not its children.
+    pub fn deck_exact(name: impl Into<String>) -> Self {
+        SearchNode::DeckExact(escaped(&name.into(), NAME_WILDCARDS).into()).into()
+    }
+
+    /// Matches the deck and its children.
+    pub fn deck_id(did: DeckID) -> Self {
+        SearchNode::DeckID(did).into()
+    }
+
+    /// A saved search, referred to by name.
+    pub fn preset(name: impl Into<String>) -> Self {
+        SearchNode::Preset(name.into().into()).into()
+    }
+
+    /// Matches the tag and its children.
+    pub fn tag(tag: impl Into<String>) -> Self {
+        SearchNode::Tag(escaped(&tag.into(), &['*']).into()).into()
+    }
+
+    pub fn note_type(name: impl Into<String>) -> Self {
+        SearchNode::NoteType(escaped(&name.into(), NAME_WILDCARDS).into()).into()
+    }
+
+    /// Text that may appear anywhere in a note's fields.
+    pub fn text(text: impl Into<String>) -> Self {
+        SearchNode::UnqualifiedText(escaped(&text.into(), TEXT_SPECIAL_CHARS).into()).into()
+    }
+
+    /// Matches notes where the field's content is the provided text.
+    pub fn field(field: impl Into<String>, text: impl Into<String>) -> Self {
+        SearchNode::SingleField {
+            field: field.into().into(),
+            text: escaped(&text.into(), TEXT_SPECIAL_CHARS).into(),
+            mode: FieldSearchMode::Normal,
+        }
+        .into()
+    }
+
+    pub fn note_ids(nids: &[NoteID]) -> Self {
+        if nids.is_empty() {
+            return Self::nothing();
+        }
+        SearchNode::NoteIDs(nids.iter().map(|nid| nid.0).collect()).into()
+    }
+
+    pub fn note_guid(guid: impl Into<String>) -> Self {
+        SearchNode::NoteGUID(guid.into().into()).into()
+    }
+
+    pub fn card_ids(cids: &[CardID]) -> Self {
+        if cids.is_empty() {
+            return Self::nothing();
+        }
+        SearchNode::CardIDs(cids.iter().map(|cid| cid.0).collect()).into()
+    }
+
+    /// Matches no cards or notes.
+    fn nothing() -> Self {
+        Node::Not(Box::new(Node::Search(SearchNode::WholeCollection))).into()
+    }
+
+    pub fn and(self, other: impl Into<SearchBuilder>) -> Self {
+        self.join(Node::And, other.into())
+    }
+
+    pub fn or(self, other: impl Into<SearchBuilder>) -> Self {
+        self.join(Node::Or, other.into())
+    }
+
+    pub fn negated(self) -> Self {
+        Node::Not(Box::new(group(self.0))).into()
+    }
+
+    /// The search as text, in the same format the user would type it.
+    pub fn write(&self) -> String {
+        write_nodes(&self.0)
+    }
+
+    fn join(self, sep: Node<'static>, other: SearchBuilder) -> Self {
+        let mut nodes = if only_joined_by(&self.0, &sep) {
+            self.0
+        } else {
+            vec![group(self.0)]
+        };
+        nodes.push(sep.clone());
+        if only_joined_by(&other.0, &sep) {
+            nodes.extend(other.0);
+        } else {
+            nodes.push(group(other.0));
+        }
+        SearchBuilder(nodes)
+    }
+}
+
+impl From<SearchNode<'static>> for SearchBuilder {
+    fn from(node: SearchNode<'static>) -> Self {
+        Node::Search(node).into()
+    }
+}
+
+impl From<Node<'static>> for SearchBuilder {
+    fn from(node: Node<'static>) -> Self {
+        SearchBuilder(vec![node])
+    }
+}
+
+/// Characters treated as wildcards in deck and note type names.
+const NAME_WILDCARDS: &[char] = &['*', '_', '%'];
+
+/// Characters with a special meaning in text and field searches. A colon
+/// would otherwise turn text into a qualified search.
+const TEXT_SPECIAL_CHARS: &[char] = &['\\', '*', '_', ':'];
+
+/// Escape the provided characters with a backslash, so they are matched
+/// literally. Quotes don't need escaping, as the writer escapes them.
+fn escaped(text: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// True if nodes don't contain any booleans other than sep, so they can be
+/// combined with sep without needing to be grouped first.
+fn only_joined_by(nodes: &[Node], sep: &Node) -> bool {
+    nodes
+        .iter()
+        .all(|node| !matches!(node, Node::And | Node::Or) || node == sep)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, decks::DeckID, search::SortMode};
+
+    #[test]
+    fn building() -> Result<()> {
+        use SearchBuilder as SB;
+        assert_eq!(
+            SB::deck("a \"b\"").and(SB::tag("c")).write(),
+            r#""deck:a \"b\"" AND "tag:c""#
+        );
+        assert_eq!(
+            SB::deck("a").or(SB::deck("b")).or(SB::deck("c")).write(),
+            r#""deck:a" OR "deck:b" OR "deck:c""#
+        );
+        assert_eq!(
+            SB::deck("a")
+                .or(SB::deck("b"))
+                .and(SB::tag("c").negated())
+                .write(),
+            r#"("deck:a" OR "deck:b") AND -"tag:c""#
+        );
+        assert_eq!(
+            SB::note_ids(&[NoteID(1), NoteID(2)]).write(),
+            "nid:1,2".to_string()
+        );
+        // special characters are matched literally
+        assert_eq!(SB::deck("a_b*").write(), r#""deck:a\_b\*""#);
+        assert_eq!(SB::text(r#"a:"b"\"#).write(), r#""a\:\"b\"\\""#);
+        // the written text parses back to the same nodes
+        let search = SB::field("front", "x y").and(SB::text("z").or(SB::tag("t")));
+        assert_eq!(
+            parse(&search.write())?,
+            search.clone().try_into_search().map(|node| match node {
+                Node::Group(nodes) => nodes,
+                _ => unreachable!(),
+            })?
+        );
+
+        // builders can be passed directly to the search functions
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields[0] = "one".into();
+        col.add_note(&mut note, DeckID(1))?;
+        assert_eq!(
+            col.search_notes(SB::field("front", "one").and(SB::deck("Default")))?,
+            vec![note.id]
+        );
+        assert_eq!(
+            col.search_cards(SB::note_ids(&[note.id]).negated(), SortMode::NoOrder)?,
+            vec![]
+        );
+        assert_eq!(col.search_notes(SB::note_ids(&[]))?, vec![]);
+
+        let mut note = nt.new_note();
+        note.fields[0] = "50%_off: *now*".into();
+        col.add_note(&mut note, DeckID(1))?;
+        assert_eq!(col.search_notes(SB::text("_off: *now"))?, vec![note.id]);
+        assert_eq!(
+            col.search_notes(SB::field("front", "50%_off: *now*"))?,
+            vec![note.id]
+        );
+        assert_eq!(col.search_notes(SB::text("off*now"))?, vec![]);
+
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use super::{
-    parser::Node,
+    builder::TryIntoSearch,
     sqlwriter::{RequiredTable, SqlWriter},
 };
 use crate::card::CardID;
@@ -10,8 +10,8 @@ use crate::card::CardType;
 use crate::collection::Collection;
 use crate::config::SortKind;
 use crate::err::Result;
-use crate::search::parser::parse;
 use rusqlite::NO_PARAMS;
+use std::fmt::Write;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum SortMode {
@@ -59,10 +59,48 @@ impl SortKind {
 }
 
 impl Collection {
-    pub fn search_cards(&mut self, search: &str, mut mode: SortMode) -> Result<Vec<CardID>> {
-        let top_node = Node::Group(parse(search)?);
+    pub fn search_cards<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+        mode: SortMode,
+    ) -> Result<Vec<CardID>> {
+        let (sql, args) = self.build_sorted_cards_query(search, mode)?;
+        self.query_card_ids(&sql, &args)
+    }
+
+    /// Like search_cards(), but only returns up to `limit` ids, starting at
+    /// `offset`, so that large results can be fetched a page at a time.
+    /// Pages are only consistent with each other if the sort order is.
+    pub fn search_cards_page<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+        mode: SortMode,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<CardID>> {
+        let (mut sql, args) = self.build_sorted_cards_query(search, mode)?;
+        write!(sql, " limit {} offset {}", limit, offset).unwrap();
+        self.query_card_ids(&sql, &args)
+    }
+
+    /// The SQL and arguments search_cards() would execute, for diagnosing
+    /// slow or unexpected searches.
+    pub fn search_cards_sql<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+        mode: SortMode,
+    ) -> Result<(String, Vec<String>)> {
+        self.build_sorted_cards_query(search, mode)
+    }
+
+    fn build_sorted_cards_query<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+        mut mode: SortMode,
+    ) -> Result<(String, Vec<String>)> {
+        let top_node = search.try_into_search()?;
         self.resolve_config_sort(&mut mode);
-        let writer = SqlWriter::new(self);
+        let writer = SqlWriter::new(self)?;
 
         let (mut sql, args) = writer.build_cards_query(&top_node, mode.required_table())?;
 
@@ -80,19 +118,39 @@ impl Collection {
             }
         }
 
-        let mut stmt = self.storage.db.prepare(&sql)?;
+        Ok((sql, args))
+    }
+
+    fn query_card_ids(&self, sql: &str, args: &[String]) -> Result<Vec<CardID>> {
+        let mut stmt = self.storage.db.prepare(sql)?;
         let ids: Vec<_> = stmt
-            .query_map(&args, |row| row.get(0))?
+            .query_map(args, |row| row.get(0))?
             .collect::<std::result::Result<_, _>>()?;
 
         Ok(ids)
     }
 
+    /// The number of cards matching the search, without fetching their ids.
+    pub fn count_cards<'a>(&mut self, search: impl TryIntoSearch<'a>) -> Result<u32> {
+        let top_node = search.try_into_search()?;
+        let writer = SqlWriter::new(self)?;
+        let (sql, args) = writer.build_cards_query(&top_node, RequiredTable::Cards)?;
+
+        self.storage
+            .db
+            .prepare(&format!("select count(*) from ({})", sql))?
+            .query_row(&args, |row| row.get(0))
+            .map_err(Into::into)
+    }
+
     /// Place the matched card ids into a temporary 'search_cids' table
     /// instead of returning them. Use clear_searched_cards() to remove it.
-    pub(crate) fn search_cards_into_table(&mut self, search: &str) -> Result<()> {
-        let top_node = Node::Group(parse(search)?);
-        let writer = SqlWriter::new(self);
+    pub(crate) fn search_cards_into_table<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+    ) -> Result<()> {
+        let top_node = search.try_into_search()?;
+        let writer = SqlWriter::new(self)?;
 
         let (sql, args) = writer.build_cards_query(&top_node, RequiredTable::Cards)?;
         self.storage.db.execute_batch(concat!(
@@ -190,3 +248,136 @@ fn prepare_sort(col: &mut Collection, kind: SortKind) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        collection::{open_test_collection, Collection},
+        decks::DeckID,
+    };
+
+    #[test]
+    fn paging() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        for _ in 0..5 {
+            let mut note = nt.new_note();
+            col.add_note(&mut note, DeckID(1))?;
+        }
+        let order = SortMode::Custom("c.id".into());
+        let all = col.search_cards("", order.clone())?;
+        assert_eq!(all.len(), 5);
+        assert_eq!(col.search_cards_page("", order.clone(), 0, 2)?, &all[..2]);
+        assert_eq!(col.search_cards_page("", order.clone(), 2, 2)?, &all[2..4]);
+        assert_eq!(col.search_cards_page("", order.clone(), 4, 2)?, &all[4..]);
+        assert_eq!(col.search_cards_page("", order, 5, 2)?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn debug_sql() -> Result<()> {
+        let mut col = open_test_collection();
+        assert_eq!(
+            col.search_cards_sql("dog", SortMode::Custom("c.id".into()))?,
+            (
+                concat!(
+                    "select c.id from cards c, notes n where c.nid=n.id and ",
+                    "((n.sfld like ?1 escape '\\' or n.flds like ?1 escape '\\'))",
+                    " order by c.id"
+                )
+                .into(),
+                vec!["%dog%".into()]
+            )
+        );
+        assert_eq!(
+            col.search_notes_sql("dog")?,
+            (
+                concat!(
+                    "select n.id from notes n where ",
+                    "((n.sfld like ?1 escape '\\' or n.flds like ?1 escape '\\'))"
+                )
+                .into(),
+                vec!["%dog%".into()]
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn counting() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col
+            .get_notetype_by_name("Basic (and reversed card)")?
+            .unwrap();
+        for text in &["one", "two"] {
+            let mut note = nt.new_note();
+            note.fields[0] = text.to_string();
+            col.add_note(&mut note, DeckID(1))?;
+        }
+        assert_eq!(col.count_cards("")?, 4);
+        assert_eq!(col.count_cards("one")?, 2);
+        assert_eq!(col.count_cards("card:2")?, 2);
+        assert_eq!(col.count_notes("")?, 2);
+        assert_eq!(col.count_notes("card:2")?, 2);
+        assert_eq!(col.count_notes("three")?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unicode_normalization() -> Result<()> {
+        let mut col = open_test_collection();
+        let ntid = col.get_notetype_by_name("Basic")?.unwrap().id;
+        let mut nt = col.storage.get_notetype(ntid)?.unwrap();
+        nt.fields[0].name = "Caf\u{e9}".into();
+        col.update_notetype(&mut nt, false)?;
+        let deck = col.get_or_create_normal_deck("Caf\u{e9}")?;
+        let mut note = nt.new_note();
+        // stored notes are composed
+        note.fields[0] = "cafe\u{301}".into();
+        col.add_note(&mut note, deck.id)?;
+        let cids = col.storage.all_cards_of_note(note.id)?;
+        let cid = cids[0].id;
+
+        // decomposed input should match the composed text
+        for search in &[
+            "cafe\u{301}",
+            "\"cafe\u{301}:cafe\u{301}\"",
+            "deck:cafe\u{301}",
+            "deck!:cafe\u{301}",
+            "w:cafe\u{301}",
+        ] {
+            assert_eq!(col.search_cards(*search, SortMode::NoOrder)?, vec![cid]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn custom_data() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut cids = vec![];
+        for data in &[r#"{"cd": {"n": 5, "s": "web"}}"#, r#"{"cd": {"n": 1}}"#, ""] {
+            let mut note = nt.new_note();
+            col.add_note(&mut note, DeckID(1))?;
+            let mut card = col.storage.all_cards_of_note(note.id)?.pop().unwrap();
+            card.data = data.to_string();
+            col.storage.update_card(&card)?;
+            cids.push(card.id);
+        }
+        let search = |col: &mut Collection, text: &str| {
+            col.search_cards(text, SortMode::Custom("c.id".into()))
+        };
+        assert_eq!(search(&mut col, "prop:cd:n>2")?, vec![cids[0]]);
+        assert_eq!(search(&mut col, "prop:cd:n<=5")?, &cids[..2]);
+        assert_eq!(search(&mut col, "prop:cd:s=web")?, vec![cids[0]]);
+        // cards without the key match the negated search
+        assert_eq!(search(&mut col, "-prop:cd:s=web")?, &cids[1..]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::{
+    builder::TryIntoSearch,
+    parser::{Node, SearchNode},
+};
+use crate::{
+    collection::Collection,
+    err::Result,
+    notes::NoteID,
+    text::{matches_wildcard, normalize_to_nfc, strip_html_preserving_image_filenames},
+};
+use std::collections::HashMap;
+
+/// Notes that have the same text in a field.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DupeGroup {
+    /// the shared text, with HTML formatting removed
+    pub text: String,
+    pub note_ids: Vec<NoteID>,
+}
+
+impl Collection {
+    /// Find notes matching the search that have the same text in the named
+    /// field, ignoring HTML formatting. Empty fields are not considered
+    /// duplicates. Groups are sorted by their text.
+    pub fn find_dupes<'a>(
+        &mut self,
+        field_name: &str,
+        search: impl TryIntoSearch<'a>,
+    ) -> Result<Vec<DupeGroup>> {
+        let field_name = normalize_to_nfc(field_name);
+        let field_name = field_name.as_ref();
+        let search = Node::Group(vec![
+            search.try_into_search()?,
+            Node::And,
+            Node::Search(SearchNode::HasField(field_name.into())),
+        ]);
+        let mut groups: HashMap<String, Vec<NoteID>> = HashMap::new();
+        for nid in self.search_notes(search)? {
+            let note = match self.storage.get_note(nid)? {
+                Some(note) => note,
+                None => continue,
+            };
+            let ord = match self.get_notetype(note.ntid)?.and_then(|nt| {
+                nt.fields
+                    .iter()
+                    .position(|field| matches_wildcard(&field.name, field_name))
+            }) {
+                Some(ord) => ord,
+                None => continue,
+            };
+            let text = strip_html_preserving_image_filenames(&note.fields[ord])
+                .trim()
+                .to_string();
+            if !text.is_empty() {
+                groups.entry(text).or_default().push(nid);
+            }
+        }
+
+        let mut dupes: Vec<_> = groups
+            .into_iter()
+            .filter(|(_, nids)| nids.len() > 1)
+            .map(|(text, mut note_ids)| {
+                note_ids.sort();
+                DupeGroup { text, note_ids }
+            })
+            .collect();
+        dupes.sort_by(|a, b| a.text.cmp(&b.text));
+
+        Ok(dupes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, decks::DeckID};
+
+    #[test]
+    fn dupes() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut add_note = |front: &str, back: &str| -> Result<NoteID> {
+            let mut note = nt.new_note();
+            note.fields[0] = front.into();
+            note.fields[1] = back.into();
+            col.add_note(&mut note, DeckID(1))?;
+            Ok(note.id)
+        };
+        let one = add_note("one", "same")?;
+        let two = add_note("<b>one</b>", "")?;
+        let three = add_note("three", "same")?;
+        add_note("four", "")?;
+
+        assert_eq!(
+            col.find_dupes("front", "")?,
+            vec![DupeGroup {
+                text: "one".into(),
+                note_ids: vec![one, two]
+            }]
+        );
+        assert_eq!(
+            col.find_dupes("back", "")?,
+            vec![DupeGroup {
+                text: "same".into(),
+                note_ids: vec![one, three]
+            }]
+        );
+        assert_eq!(col.find_dupes("back", "-three")?, vec![]);
+        assert_eq!(col.find_dupes("missing", "")?, vec![]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{collection::Collection, config::ConfigKey, err::Result};
+
+/// The number of recent searches that are remembered.
+const SEARCH_HISTORY_LIMIT: usize = 30;
+
+impl Collection {
+    /// Recently executed searches, most recent first.
+    pub fn get_search_history(&self) -> Vec<String> {
+        self.get_config_default(ConfigKey::SearchHistory)
+    }
+
+    /// Add a search to the start of the history. If it was already present,
+    /// the earlier entry is removed.
+    pub fn add_to_search_history(&self, search: &str) -> Result<()> {
+        let search = search.trim();
+        if search.is_empty() {
+            return Ok(());
+        }
+        let mut history = self.get_search_history();
+        history.retain(|existing| existing != search);
+        history.insert(0, search.into());
+        history.truncate(SEARCH_HISTORY_LIMIT);
+        self.set_config(ConfigKey::SearchHistory, &history)
+    }
+
+    pub fn clear_search_history(&self) -> Result<()> {
+        self.remove_config(ConfigKey::SearchHistory)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn history() -> Result<()> {
+        let col = open_test_collection();
+        assert!(col.get_search_history().is_empty());
+
+        col.add_to_search_history("dog")?;
+        col.add_to_search_history(" ")?;
+        col.add_to_search_history("cat")?;
+        col.add_to_search_history("dog ")?;
+        assert_eq!(col.get_search_history(), vec!["dog", "cat"]);
+
+        for i in 0..SEARCH_HISTORY_LIMIT {
+            col.add_to_search_history(&i.to_string())?;
+        }
+        let history = col.get_search_history();
+        assert_eq!(history.len(), SEARCH_HISTORY_LIMIT);
+        assert_eq!(history[0], (SEARCH_HISTORY_LIMIT - 1).to_string());
+
+        col.clear_search_history()?;
+        assert!(col.get_search_history().is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::{
+    builder::TryIntoSearch,
+    parser::{FieldSearchMode, Node, SearchNode},
+    sqlwriter::{text_to_re, to_re},
+};
+use crate::{
+    collection::Collection,
+    err::Result,
+    notes::{Note, NoteID},
+    notetype::NoteType,
+    text::{matches_wildcard, normalize_to_nfc},
+};
+use regex::Regex;
+use std::borrow::Cow;
+
+/// A part of a note's field that matched a search.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldMatch {
+    pub field_ord: usize,
+    /// byte offsets into the field's text
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A note returned by a search, and where the search matched its fields.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NoteMatches {
+    pub note_id: NoteID,
+    pub matches: Vec<FieldMatch>,
+}
+
+/// A text search, converted into a regex that can be run on each field.
+struct TextMatcher {
+    /// if set, only fields with a name matching this are searched
+    field: Option<String>,
+    re: Regex,
+}
+
+impl Collection {
+    /// Like search_notes(), but also reports where text searches matched
+    /// each note's fields, so the browser can highlight them. Negated
+    /// searches and saved searches don't contribute any matches.
+    pub fn search_notes_with_matches<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+    ) -> Result<Vec<NoteMatches>> {
+        let node = search.try_into_search()?;
+        let mut matchers = vec![];
+        collect_matchers(&node, self.normalize_note_text(), &mut matchers);
+
+        let nids = self.search_notes(node)?;
+        let mut notes = Vec::with_capacity(nids.len());
+        for nid in nids {
+            let mut matches = vec![];
+            if !matchers.is_empty() {
+                if let Some(note) = self.storage.get_note(nid)? {
+                    if let Some(nt) = self.get_notetype(note.ntid)? {
+                        matches = field_matches(&note, &nt, &matchers);
+                    }
+                }
+            }
+            notes.push(NoteMatches {
+                note_id: nid,
+                matches,
+            });
+        }
+
+        Ok(notes)
+    }
+}
+
+fn collect_matchers(node: &Node, normalize: bool, matchers: &mut Vec<TextMatcher>) {
+    match node {
+        Node::Group(nodes) => {
+            for node in nodes {
+                collect_matchers(node, normalize, matchers);
+            }
+        }
+        Node::Search(search) => {
+            if let Some(matcher) = matcher_for_search(search, normalize) {
+                matchers.push(matcher);
+            }
+        }
+        Node::Not(_) | Node::And | Node::Or => (),
+    }
+}
+
+/// Mirrors the regexes and wildcards the SQL writer uses for each kind of
+/// text search.
+fn matcher_for_search(search: &SearchNode, normalize: bool) -> Option<TextMatcher> {
+    let norm = |text: &str| -> String {
+        if normalize {
+            normalize_to_nfc(text).into()
+        } else {
+            text.into()
+        }
+    };
+    let (field, re): (Option<Cow<str>>, String) = match search {
+        SearchNode::UnqualifiedText(text) => (None, format!("(?i){}", to_re(&norm(text)))),
+        SearchNode::SingleField {
+            field,
+            text,
+            mode: FieldSearchMode::Normal,
+        } if !text.is_empty() => (
+            Some(normalize_to_nfc(field)),
+            format!("(?is)^{}$", to_re(&norm(text))),
+        ),
+        SearchNode::SingleField {
+            field,
+            text,
+            mode: FieldSearchMode::Regex,
+        } => (Some(normalize_to_nfc(field)), format!("(?i){}", norm(text))),
+        SearchNode::Regex(re) => (None, format!("(?i){}", norm(re))),
+        SearchNode::WordBoundary(text) => (None, format!(r"(?i)\b{}\b", text_to_re(&norm(text)))),
+        _ => return None,
+    };
+
+    Regex::new(&re).ok().map(|re| TextMatcher {
+        field: field.map(Into::into),
+        re,
+    })
+}
+
+fn field_matches(note: &Note, nt: &NoteType, matchers: &[TextMatcher]) -> Vec<FieldMatch> {
+    let mut matches = vec![];
+    for (ord, (text, field)) in note.fields.iter().zip(&nt.fields).enumerate() {
+        for matcher in matchers {
+            if let Some(name) = &matcher.field {
+                if !matches_wildcard(&field.name, name) {
+                    continue;
+                }
+            }
+            matches.extend(
+                matcher
+                    .re
+                    .find_iter(text)
+                    .filter(|m| m.start() != m.end())
+                    .map(|m| FieldMatch {
+                        field_ord: ord,
+                        start: m.start(),
+                        end: m.end(),
+                    }),
+            );
+        }
+    }
+    matches.sort_by_key(|m| (m.field_ord, m.start, m.end));
+    matches.dedup();
+
+    matches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, decks::DeckID};
+
+    #[test]
+    fn matches() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields[0] = "the dog".into();
+        note.fields[1] = "dog food".into();
+        col.add_note(&mut note, DeckID(1))?;
+
+        let matches = |col: &mut Collection, search: &str| -> Result<Vec<FieldMatch>> {
+            Ok(col
+                .search_notes_with_matches(search)?
+                .pop()
+                .map(|note| note.matches)
+                .unwrap_or_default())
+        };
+        let field_match = |field_ord, start, end| FieldMatch {
+            field_ord,
+            start,
+            end,
+        };
+
+        assert_eq!(
+            matches(&mut col, "dog")?,
+            vec![field_match(0, 4, 7), field_match(1, 0, 3)]
+        );
+        assert_eq!(matches(&mut col, "front:*dog")?, vec![field_match(0, 0, 7)]);
+        assert_eq!(
+            matches(&mut col, "w:food or re:^t")?,
+            vec![field_match(0, 0, 1), field_match(1, 4, 8)]
+        );
+        // negated searches are not highlighted
+        assert_eq!(matches(&mut col, "food -cat")?, vec![field_match(1, 4, 8)]);
+        assert_eq!(
+            col.search_notes_with_matches("-food")?,
+            Vec::<NoteMatches>::new()
+        );
+
+        Ok(())
+    }
+}
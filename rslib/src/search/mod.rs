@@ -1,6 +1,107 @@
+mod builder;
 mod cards;
+mod dupes;
+mod history;
+mod matches;
 mod notes;
 mod parser;
+mod saved;
+mod simplify;
 mod sqlwriter;
+mod writer;
 
+pub use builder::{SearchBuilder, TryIntoSearch};
 pub use cards::SortMode;
+pub use dupes::DupeGroup;
+pub use matches::{FieldMatch, NoteMatches};
+pub use parser::{FieldSearchMode, Node, PropertyKind, SearchNode, StateKind, TemplateKind};
+pub use writer::{concatenate_searches, replace_search_node, BoolSeparator};
+
+use crate::{collection::Collection, err::Result};
+use parser::parse;
+use writer::write_nodes;
+
+/// Parse the provided search and write it back out in canonical form.
+pub fn normalize_search(input: &str) -> Result<String> {
+    Ok(write_nodes(&parse(input)?))
+}
+
+impl Collection {
+    /// Enable or disable the full text index of note fields. When enabled,
+    /// plain text searches match against the start of words instead of
+    /// anywhere in the text, but are much faster on large collections.
+    pub fn set_full_text_search(&mut self, enabled: bool) -> Result<()> {
+        self.state.full_text_index = None;
+        if enabled {
+            self.storage.create_full_text_index()?;
+        } else {
+            self.storage.drop_full_text_index()?;
+        }
+        self.state.full_text_index = Some(enabled);
+        Ok(())
+    }
+
+    /// True if the full text index exists. The database is only checked
+    /// on first use, as the answer doesn't change between searches.
+    pub(crate) fn full_text_index_exists(&mut self) -> Result<bool> {
+        if let Some(exists) = self.state.full_text_index {
+            return Ok(exists);
+        }
+        let exists = self.storage.full_text_index_exists()?;
+        self.state.full_text_index = Some(exists);
+        Ok(exists)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SortMode;
+    use crate::{collection::open_test_collection, decks::DeckID, err::AnkiError};
+
+    #[test]
+    fn cancelling() {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic").unwrap().unwrap();
+        let mut note = nt.new_note();
+        col.add_note(&mut note, DeckID(1)).unwrap();
+
+        // a slow sort, to ensure the handler gets called
+        let order = SortMode::Custom(
+            concat!(
+                "(with recursive r(x) as (select 1 union all select x + 1 from r",
+                " where x < 100000) select count(*) from r)"
+            )
+            .into(),
+        );
+        assert_eq!(
+            col.cancellable(|| false, |col| col.search_cards("", order.clone())),
+            Err(AnkiError::Interrupted)
+        );
+        assert_eq!(
+            col.cancellable(|| true, |col| col.search_cards("", order))
+                .unwrap()
+                .len(),
+            1
+        );
+        // the handler is removed afterwards
+        assert_eq!(col.search_cards("", SortMode::NoOrder).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn case_folding() {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic").unwrap().unwrap();
+        let mut note = nt.new_note();
+        note.set_field(0, "STRASSE").unwrap();
+        col.add_note(&mut note, DeckID(1)).unwrap();
+
+        for search in &["straße", "nc:straße", "front:straße", "front:nc:straße"] {
+            assert_eq!(
+                col.search_cards(search, SortMode::NoOrder).unwrap().len(),
+                1,
+                "{}",
+                search
+            );
+        }
+    }
+}
@@ -1,16 +1,15 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
-use super::{parser::Node, sqlwriter::SqlWriter};
+use super::{builder::TryIntoSearch, sqlwriter::SqlWriter};
 use crate::collection::Collection;
 use crate::err::Result;
 use crate::notes::NoteID;
-use crate::search::parser::parse;
 
 impl Collection {
-    pub fn search_notes(&mut self, search: &str) -> Result<Vec<NoteID>> {
-        let top_node = Node::Group(parse(search)?);
-        let writer = SqlWriter::new(self);
+    pub fn search_notes<'a>(&mut self, search: impl TryIntoSearch<'a>) -> Result<Vec<NoteID>> {
+        let top_node = search.try_into_search()?;
+        let writer = SqlWriter::new(self)?;
         let (sql, args) = writer.build_notes_query(&top_node)?;
 
         let mut stmt = self.storage.db.prepare(&sql)?;
@@ -20,4 +19,27 @@ impl Collection {
 
         Ok(ids)
     }
+
+    /// The SQL and arguments search_notes() would execute, for diagnosing
+    /// slow or unexpected searches.
+    pub fn search_notes_sql<'a>(
+        &mut self,
+        search: impl TryIntoSearch<'a>,
+    ) -> Result<(String, Vec<String>)> {
+        let top_node = search.try_into_search()?;
+        SqlWriter::new(self)?.build_notes_query(&top_node)
+    }
+
+    /// The number of notes matching the search, without fetching their ids.
+    pub fn count_notes<'a>(&mut self, search: impl TryIntoSearch<'a>) -> Result<u32> {
+        let top_node = search.try_into_search()?;
+        let writer = SqlWriter::new(self)?;
+        let (sql, args) = writer.build_notes_query(&top_node)?;
+
+        self.storage
+            .db
+            .prepare(&format!("select count(*) from ({})", sql))?
+            .query_row(&args, |row| row.get(0))
+            .map_err(Into::into)
+    }
 }
@@ -2,19 +2,21 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use crate::{
-    err::{AnkiError, Result},
+    decks::DeckID,
+    err::{AnkiError, Result, SearchErrorKind},
     notetype::NoteTypeID,
 };
+use chrono::NaiveDate;
 use nom::{
     branch::alt,
     bytes::complete::{escaped, is_not, tag, take_while1},
     character::complete::{anychar, char, one_of},
     character::is_digit,
-    combinator::{all_consuming, map, map_res},
+    combinator::{all_consuming, map, map_res, recognize},
     sequence::{delimited, preceded, tuple},
     {multi::many0, IResult},
 };
-use std::{borrow::Cow, num};
+use std::{borrow::Cow, num, ops::Range};
 
 // fixme: need to preserve \ when used twice in string
 
@@ -40,8 +42,8 @@ impl<I> From<nom::Err<(I, nom::error::ErrorKind)>> for ParseError {
 
 type ParseResult<T> = std::result::Result<T, ParseError>;
 
-#[derive(Debug, PartialEq)]
-pub(super) enum Node<'a> {
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node<'a> {
     And,
     Or,
     Not(Box<Node<'a>>),
@@ -49,35 +51,66 @@ pub(super) enum Node<'a> {
     Search(SearchNode<'a>),
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) enum SearchNode<'a> {
+#[derive(Debug, PartialEq, Clone)]
+pub enum SearchNode<'a> {
     // text without a colon
     UnqualifiedText(Cow<'a, str>),
     // foo:bar, where foo doesn't match a term below
     SingleField {
         field: Cow<'a, str>,
         text: Cow<'a, str>,
-        is_re: bool,
+        mode: FieldSearchMode,
     },
+    /// note type contains a field with the given name
+    HasField(Cow<'a, str>),
     AddedInDays(u32),
+    AddedBetween(DateRange),
     EditedInDays(u32),
+    EditedBetween(DateRange),
+    IntroducedInDays(u32),
+    RescheduledInDays(u32),
+    FirstReviewInDays(u32),
+    FirstReviewBetween(DateRange),
+    LastReviewInDays(u32),
+    LastReviewBetween(DateRange),
     CardTemplate(TemplateKind),
     Deck(Cow<'a, str>),
-    NoteTypeID(NoteTypeID),
+    /// deck!:, which does not include child decks
+    DeckExact(Cow<'a, str>),
+    DeckID(DeckID),
+    NoteTypeIDs(Vec<i64>),
     NoteType(Cow<'a, str>),
     Rated {
         days: u32,
         ease: Option<u8>,
     },
+    /// eg added:>7 for cards added more than a week ago
+    AddedDaysAgo {
+        operator: String,
+        days: u32,
+    },
+    /// eg rated:<=3:1
+    RatedDaysAgo {
+        operator: String,
+        days: u32,
+        ease: Option<u8>,
+    },
     Tag(Cow<'a, str>),
     Duplicates {
         note_type_id: NoteTypeID,
+        /// a field name or 1-based ordinal; the first field if not provided
+        field: Option<String>,
         text: String,
     },
     State(StateKind),
     Flag(u8),
-    NoteIDs(Cow<'a, str>),
-    CardIDs(Cow<'a, str>),
+    /// cards with any of the provided flags
+    Flags(Vec<u8>),
+    /// cards with a flag of any color
+    AnyFlag,
+    NoteIDs(Vec<i64>),
+    NoteGUID(Cow<'a, str>),
+    CardIDs(Vec<i64>),
     Property {
         operator: String,
         kind: PropertyKind,
@@ -86,46 +119,165 @@ pub(super) enum SearchNode<'a> {
     Regex(Cow<'a, str>),
     NoCombining(Cow<'a, str>),
     WordBoundary(Cow<'a, str>),
+    /// a saved search, referred to by name
+    Preset(Cow<'a, str>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldSearchMode {
+    Normal,
+    Regex,
+    NoCombining,
+}
+
+/// Calendar dates, inclusive at both ends. At least one end is provided.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DateRange {
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) enum PropertyKind {
+#[derive(Debug, PartialEq, Clone)]
+pub enum PropertyKind {
     Due(i32),
     Interval(u32),
     Reps(u32),
     Lapses(u32),
     Ease(f32),
+    Position(u32),
+    /// a value an add-on has stored in the card's custom data
+    CustomDataNumber {
+        key: String,
+        value: f32,
+    },
+    CustomDataString {
+        key: String,
+        value: String,
+    },
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) enum StateKind {
+#[derive(Debug, PartialEq, Clone)]
+pub enum StateKind {
     New,
     Review,
     Learning,
     Due,
     Buried,
+    BuriedSibling,
+    BuriedManually,
     Suspended,
+    Preview,
+    DayLearning,
 }
 
-#[derive(Debug, PartialEq)]
-pub(super) enum TemplateKind {
+#[derive(Debug, PartialEq, Clone)]
+pub enum TemplateKind {
     Ordinal(u16),
     Name(String),
 }
 
 /// Parse the input string into a list of nodes.
 pub(super) fn parse(input: &str) -> Result<Vec<Node>> {
-    let input = input.trim();
-    if input.is_empty() {
+    let trimmed = input.trim();
+    if trimmed.is_empty() {
         return Ok(vec![Node::Search(SearchNode::WholeCollection)]);
     }
 
-    let (_, nodes) =
-        all_consuming(group_inner)(input).map_err(|_e| AnkiError::SearchError(None))?;
+    let (_, nodes) = all_consuming(group_inner)(trimmed).map_err(|err| {
+        let remaining = match err {
+            nom::Err::Error((remaining, _)) | nom::Err::Failure((remaining, _)) => remaining,
+            nom::Err::Incomplete(_) => "",
+        };
+        let leading_whitespace = input.len() - input.trim_start().len();
+        search_error(input, leading_whitespace + trimmed.len() - remaining.len())
+    })?;
 
     Ok(nodes)
 }
 
+/// Build an error describing why the search failed to parse. Unbalanced
+/// quotes and brackets are reported first, as they cause later terms to be
+/// misparsed. Otherwise, the term at the offset the parser stopped at is
+/// reported.
+fn search_error(input: &str, offset: usize) -> AnkiError {
+    let (kind, span) = unbalanced_syntax(input).unwrap_or_else(|| {
+        let start = input.len() - input[offset..].trim_start().len();
+        let span = start..start + term_len(&input[start..]);
+        let term = input[span.clone()]
+            .trim_start_matches('-')
+            .trim_matches('"');
+        let kind = if term.eq_ignore_ascii_case("and") || term.eq_ignore_ascii_case("or") {
+            SearchErrorKind::MisplacedBoolean
+        } else if has_invalid_prop_operator(term) {
+            SearchErrorKind::InvalidPropertyOperator
+        } else if term.contains(':') {
+            SearchErrorKind::InvalidArgument
+        } else {
+            SearchErrorKind::Other
+        };
+        (kind, span)
+    });
+
+    AnkiError::SearchError {
+        info: input[span.clone()].into(),
+        kind,
+        span: Some(span),
+    }
+}
+
+/// Locate the first unclosed quote or bracket, or closing bracket without
+/// a matching opening bracket.
+fn unbalanced_syntax(input: &str) -> Option<(SearchErrorKind, Range<usize>)> {
+    let mut open_groups = vec![];
+    let mut quote_start = None;
+    let mut escaped = false;
+    for (idx, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quote_start = match quote_start {
+                Some(_) => None,
+                None => Some(idx),
+            };
+        } else if quote_start.is_none() {
+            if c == '(' {
+                open_groups.push(idx);
+            } else if c == ')' && open_groups.pop().is_none() {
+                return Some((SearchErrorKind::UnopenedGroup, idx..idx + 1));
+            }
+        }
+    }
+
+    if let Some(start) = quote_start {
+        Some((SearchErrorKind::UnclosedQuote, start..input.len()))
+    } else if let Some(start) = open_groups.pop() {
+        Some((SearchErrorKind::UnclosedGroup, start..input.len()))
+    } else {
+        None
+    }
+}
+
+/// The length of the term at the start of input, which ends at the first
+/// space or ) outside of quotes. If input starts with a ), it is the term.
+fn term_len(input: &str) -> usize {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (idx, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes && (c == ' ' || c == '\u{3000}' || c == ')') {
+            return if idx == 0 { c.len_utf8() } else { idx };
+        }
+    }
+    input.len()
+}
+
 /// One or more nodes surrounded by brackets, eg (one OR two)
 fn group(s: &str) -> IResult<&str, Node> {
     map(delimited(char('('), group_inner, char(')')), |nodes| {
@@ -136,18 +288,20 @@ fn group(s: &str) -> IResult<&str, Node> {
 /// One or more nodes inside brackets, er 'one OR two -three'
 fn group_inner(input: &str) -> IResult<&str, Vec<Node>> {
     let mut remaining = input;
+    let mut previous = input;
     let mut nodes = vec![];
 
     loop {
         match node(remaining) {
             Ok((rem, node)) => {
-                remaining = rem;
-
                 if nodes.len() % 2 == 0 {
                     // before adding the node, if the length is even then the node
                     // must not be a boolean
                     if matches!(node, Node::And | Node::Or) {
-                        return Err(nom::Err::Failure(("", nom::error::ErrorKind::NoneOf)));
+                        return Err(nom::Err::Failure((
+                            remaining,
+                            nom::error::ErrorKind::NoneOf,
+                        )));
                     }
                 } else {
                     // if the length is odd, the next item must be a boolean. if it's
@@ -157,6 +311,8 @@ fn group_inner(input: &str) -> IResult<&str, Vec<Node>> {
                     }
                 }
                 nodes.push(node);
+                previous = remaining;
+                remaining = rem;
             }
             Err(e) => match e {
                 nom::Err::Error(_) => break,
@@ -167,6 +323,9 @@ fn group_inner(input: &str) -> IResult<&str, Vec<Node>> {
 
     if nodes.is_empty() {
         Err(nom::Err::Error((remaining, nom::error::ErrorKind::Many1)))
+    } else if matches!(nodes.last(), Some(Node::And) | Some(Node::Or)) {
+        // a trailing boolean
+        Err(nom::Err::Failure((previous, nom::error::ErrorKind::NoneOf)))
     } else {
         // chomp any trailing whitespace
         let (remaining, _) = whitespace0(remaining)?;
@@ -195,19 +354,32 @@ fn text(s: &str) -> IResult<&str, Node> {
     alt((quoted_term, partially_quoted_term, unquoted_term))(s)
 }
 
-/// Determine if text is a qualified search, and handle escaped chars.
+/// Determine if text is a qualified search, and handle escaped chars. A
+/// colon escaped with a backslash does not end the qualifier.
 fn search_node_for_text(s: &str) -> ParseResult<SearchNode> {
-    let mut it = s.splitn(2, ':');
-    let (head, tail) = (
-        unescape_quotes(it.next().unwrap()),
-        it.next().map(unescape_quotes),
-    );
-
-    if let Some(tail) = tail {
-        search_node_for_text_with_argument(head, tail)
+    if let Some(idx) = unescaped_colon(s) {
+        search_node_for_text_with_argument(
+            unescape_quotes(&s[..idx]),
+            unescape_quotes(&s[idx + 1..]),
+        )
     } else {
-        Ok(SearchNode::UnqualifiedText(head))
+        Ok(SearchNode::UnqualifiedText(unescape_quotes(s)))
+    }
+}
+
+/// The position of the first colon that is not escaped with a backslash.
+fn unescaped_colon(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (idx, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == ':' {
+            return Some(idx);
+        }
     }
+    None
 }
 
 /// \" -> "
@@ -252,14 +424,14 @@ fn quoted_term_inner(s: &str) -> IResult<&str, &str> {
 }
 
 /// eg deck:"foo bar" - quotes must come after the :
-fn partially_quoted_term(s: &str) -> IResult<&str, Node> {
+fn partially_quoted_term(input: &str) -> IResult<&str, Node> {
     let term = take_while1(|c| c != ' ' && c != ')' && c != ':');
-    let (s, (term, _, quoted_val)) = tuple((term, char(':'), quoted_term_str))(s)?;
+    let (s, (term, _, quoted_val)) = tuple((term, char(':'), quoted_term_str))(input)?;
     let quoted_val = unescape_quotes(quoted_val);
 
     match search_node_for_text_with_argument(term.into(), quoted_val) {
         Ok(search) => Ok((s, Node::Search(search))),
-        Err(_) => Err(nom::Err::Failure((s, nom::error::ErrorKind::NoneOf))),
+        Err(_) => Err(nom::Err::Failure((input, nom::error::ErrorKind::NoneOf))),
     }
 }
 
@@ -269,14 +441,43 @@ fn search_node_for_text_with_argument<'a>(
     val: Cow<'a, str>,
 ) -> ParseResult<SearchNode<'a>> {
     Ok(match key.to_ascii_lowercase().as_str() {
-        "added" => SearchNode::AddedInDays(val.parse()?),
-        "edited" => SearchNode::EditedInDays(val.parse()?),
+        "has-field" => SearchNode::HasField(val),
+        "added" => {
+            if let Some((operator, days)) = prop_operator(val.as_ref()) {
+                SearchNode::AddedDaysAgo {
+                    operator: operator.into(),
+                    days: days.parse()?,
+                }
+            } else {
+                match val.parse() {
+                    Ok(days) => SearchNode::AddedInDays(days),
+                    Err(_) => SearchNode::AddedBetween(parse_date_range(val.as_ref())?),
+                }
+            }
+        }
+        "edited" => match val.parse() {
+            Ok(days) => SearchNode::EditedInDays(days),
+            Err(_) => SearchNode::EditedBetween(parse_date_range(val.as_ref())?),
+        },
+        "introduced" => SearchNode::IntroducedInDays(val.parse()?),
+        "resched" => SearchNode::RescheduledInDays(val.parse()?),
+        "firstreview" => match val.parse() {
+            Ok(days) => SearchNode::FirstReviewInDays(days),
+            Err(_) => SearchNode::FirstReviewBetween(parse_date_range(val.as_ref())?),
+        },
+        "lastreview" => match val.parse() {
+            Ok(days) => SearchNode::LastReviewInDays(days),
+            Err(_) => SearchNode::LastReviewBetween(parse_date_range(val.as_ref())?),
+        },
         "deck" => SearchNode::Deck(val),
+        "deck!" => SearchNode::DeckExact(val),
+        "did" => SearchNode::DeckID(val.parse()?),
         "note" => SearchNode::NoteType(val),
         "tag" => SearchNode::Tag(val),
-        "mid" => SearchNode::NoteTypeID(val.parse()?),
-        "nid" => SearchNode::NoteIDs(check_id_list(val)?),
-        "cid" => SearchNode::CardIDs(check_id_list(val)?),
+        "mid" => SearchNode::NoteTypeIDs(check_id_list(&val)?),
+        "nid" => SearchNode::NoteIDs(check_id_list(&val)?),
+        "guid" => SearchNode::NoteGUID(val),
+        "cid" => SearchNode::CardIDs(check_id_list(&val)?),
         "card" => parse_template(val.as_ref()),
         "is" => parse_state(val.as_ref())?,
         "flag" => parse_flag(val.as_ref())?,
@@ -286,19 +487,25 @@ fn search_node_for_text_with_argument<'a>(
         "re" => SearchNode::Regex(val),
         "nc" => SearchNode::NoCombining(val),
         "w" => SearchNode::WordBoundary(val),
+        "preset" => SearchNode::Preset(val),
         // anything else is a field search
         _ => parse_single_field(key.as_ref(), val.as_ref()),
     })
 }
 
-/// ensure a list of ids contains only numbers and commas, returning unchanged if true
-/// used by nid: and cid:
-fn check_id_list(s: Cow<str>) -> ParseResult<Cow<str>> {
-    if s.is_empty() || s.as_bytes().iter().any(|&c| !is_digit(c) && c != b',') {
-        Err(ParseError {})
-    } else {
-        Ok(s)
-    }
+/// ensure a list of ids is a comma-separated list of valid 64 bit integers,
+/// returning them parsed, so only checked ids can reach the SQL.
+/// used by mid:, nid: and cid:
+fn check_id_list(s: &str) -> ParseResult<Vec<i64>> {
+    s.split(',')
+        .map(|id| {
+            if !id.is_empty() && id.as_bytes().iter().all(|&c| is_digit(c)) {
+                Ok(id.parse()?)
+            } else {
+                Err(ParseError {})
+            }
+        })
+        .collect()
 }
 
 /// eg is:due
@@ -310,18 +517,56 @@ fn parse_state(s: &str) -> ParseResult<SearchNode<'static>> {
         "learn" => Learning,
         "due" => Due,
         "buried" => Buried,
+        "buried-sibling" => BuriedSibling,
+        "buried-manually" => BuriedManually,
         "suspended" => Suspended,
+        "preview" => Preview,
+        "day-learn" => DayLearning,
         _ => return Err(ParseError {}),
     }))
 }
 
-/// flag:0-4
+/// eg 2020-01-01, 2020-01-01..2020-02-01, 2020-01-01.. or ..2020-02-01
+fn parse_date_range(val: &str) -> ParseResult<DateRange> {
+    let parse_date = |s: &str| -> ParseResult<Option<NaiveDate>> {
+        if s.is_empty() {
+            Ok(None)
+        } else {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| ParseError {})
+        }
+    };
+    let (start, end) = match val.find("..") {
+        Some(idx) => (parse_date(&val[..idx])?, parse_date(&val[idx + 2..])?),
+        None => {
+            let date = parse_date(val)?;
+            (date, date)
+        }
+    };
+    match (start, end) {
+        (None, None) => Err(ParseError {}),
+        (Some(start), Some(end)) if start > end => Err(ParseError {}),
+        _ => Ok(DateRange { start, end }),
+    }
+}
+
+/// flag:0-4, flag:any, or a comma-separated list like flag:1,3
 fn parse_flag(s: &str) -> ParseResult<SearchNode<'static>> {
-    let n: u8 = s.parse()?;
-    if n > 4 {
-        Err(ParseError {})
+    if s == "any" {
+        return Ok(SearchNode::AnyFlag);
+    }
+    let mut flags = s
+        .split(',')
+        .map(|flag| match flag.parse::<u8>()? {
+            n @ 0..=4 => Ok(n),
+            _ => Err(ParseError {}),
+        })
+        .collect::<ParseResult<Vec<u8>>>()?;
+    if flags.len() == 1 {
+        Ok(SearchNode::Flag(flags.pop().unwrap()))
     } else {
-        Ok(SearchNode::Flag(n))
+        Ok(SearchNode::Flags(flags))
     }
 }
 
@@ -329,7 +574,9 @@ fn parse_flag(s: &str) -> ParseResult<SearchNode<'static>> {
 /// second arg must be between 0-4
 fn parse_rated(val: &str) -> ParseResult<SearchNode<'static>> {
     let mut it = val.splitn(2, ':');
-    let days = it.next().unwrap().parse()?;
+    let days = it.next().unwrap();
+    let (operator, days) = prop_operator(days).unwrap_or(("", days));
+    let days = days.parse()?;
     let ease = match it.next() {
         Some(v) => {
             let n: u8 = v.parse()?;
@@ -342,40 +589,92 @@ fn parse_rated(val: &str) -> ParseResult<SearchNode<'static>> {
         None => None,
     };
 
-    Ok(SearchNode::Rated { days, ease })
+    Ok(if operator.is_empty() {
+        SearchNode::Rated { days, ease }
+    } else {
+        SearchNode::RatedDaysAgo {
+            operator: operator.into(),
+            days,
+            ease,
+        }
+    })
 }
 
-/// eg dupes:1231,hello
+/// eg dupes:1231,hello, dupes:1231:back,hello or dupes:1231:2,hello
 fn parse_dupes(val: &str) -> ParseResult<SearchNode<'static>> {
     let mut it = val.splitn(2, ',');
-    let mid: NoteTypeID = it.next().unwrap().parse()?;
+    let mut note_type = it.next().unwrap().splitn(2, ':');
+    let mid: NoteTypeID = note_type.next().unwrap().parse()?;
+    let field = match note_type.next() {
+        Some("") => return Err(ParseError {}),
+        field => field.map(Into::into),
+    };
     let text = it.next().ok_or(ParseError {})?;
     Ok(SearchNode::Duplicates {
         note_type_id: mid,
+        field,
         text: text.into(),
     })
 }
 
-/// eg prop:ivl>3, prop:ease!=2.5
-fn parse_prop(val: &str) -> ParseResult<SearchNode<'static>> {
-    let (val, key) = alt((
+/// The comparisons prop: searches support.
+pub(super) const PROP_OPERATORS: &[&str] = &["<", "<=", "=", "!=", ">", ">="];
+
+fn prop_key(s: &str) -> IResult<&str, &str> {
+    alt((
+        recognize(preceded(
+            tag("cd:"),
+            take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        )),
         tag("ivl"),
         tag("due"),
         tag("reps"),
         tag("lapses"),
         tag("ease"),
-    ))(val)?;
-
-    let (val, operator) = alt((
-        tag("<="),
-        tag(">="),
-        tag("!="),
-        tag("="),
-        tag("<"),
-        tag(">"),
-    ))(val)?;
-
-    let kind = if key == "ease" {
+        tag("pos"),
+    ))(s)
+}
+
+/// Split the comparison from the start of the input, returning None if it
+/// is missing or not supported.
+fn prop_operator(s: &str) -> Option<(&str, &str)> {
+    let len = s.find(|c| !"<>=!".contains(c)).unwrap_or_else(|| s.len());
+    let (operator, rest) = s.split_at(len);
+    if PROP_OPERATORS.contains(&operator) {
+        Some((operator, rest))
+    } else {
+        None
+    }
+}
+
+/// True for prop: searches of a known property with a missing or
+/// unsupported comparison.
+fn has_invalid_prop_operator(term: &str) -> bool {
+    let mut it = term.splitn(2, ':');
+    if !it.next().unwrap().eq_ignore_ascii_case("prop") {
+        return false;
+    }
+    match it.next().map(prop_key) {
+        Some(Ok((rest, _))) => prop_operator(rest).is_none(),
+        _ => false,
+    }
+}
+
+/// eg prop:ivl>3, prop:ease!=2.5, prop:cd:key>=3
+fn parse_prop(val: &str) -> ParseResult<SearchNode<'static>> {
+    let (val, key) = prop_key(val)?;
+    let (operator, val) = prop_operator(val).ok_or(ParseError {})?;
+
+    let kind = if key.starts_with("cd:") {
+        let key = key[3..].to_string();
+        match val.parse::<f32>() {
+            Ok(value) if value.is_finite() => PropertyKind::CustomDataNumber { key, value },
+            _ => PropertyKind::CustomDataString {
+                key,
+                value: val.into(),
+            },
+        }
+    } else if key == "ease" {
         let num: f32 = val.parse()?;
         PropertyKind::Ease(num)
     } else if key == "due" {
@@ -387,6 +686,7 @@ fn parse_prop(val: &str) -> ParseResult<SearchNode<'static>> {
             "ivl" => PropertyKind::Interval(num),
             "reps" => PropertyKind::Reps(num),
             "lapses" => PropertyKind::Lapses(num),
+            "pos" => PropertyKind::Position(num),
             _ => unreachable!(),
         }
     };
@@ -404,17 +704,19 @@ fn parse_template(val: &str) -> SearchNode<'static> {
     })
 }
 
-fn parse_single_field(key: &str, mut val: &str) -> SearchNode<'static> {
-    let is_re = if val.starts_with("re:") {
-        val = val.trim_start_matches("re:");
-        true
+/// eg front:text, front:re:regex or front:nc:text
+fn parse_single_field(key: &str, val: &str) -> SearchNode<'static> {
+    let (mode, text) = if val.starts_with("re:") {
+        (FieldSearchMode::Regex, &val[3..])
+    } else if val.starts_with("nc:") {
+        (FieldSearchMode::NoCombining, &val[3..])
     } else {
-        false
+        (FieldSearchMode::Normal, val)
     };
     SearchNode::SingleField {
         field: key.to_string().into(),
-        text: val.to_string().into(),
-        is_re,
+        text: text.to_string().into(),
+        mode,
     }
 }
 
@@ -461,7 +763,7 @@ mod test {
                     Search(SingleField {
                         field: "foo".into(),
                         text: "bar baz".into(),
-                        is_re: false,
+                        mode: FieldSearchMode::Normal,
                     })
                 ]))),
                 Or,
@@ -474,7 +776,15 @@ mod test {
             vec![Search(SingleField {
                 field: "foo".into(),
                 text: "bar".into(),
-                is_re: true
+                mode: FieldSearchMode::Regex
+            })]
+        );
+        assert_eq!(
+            parse("foo:nc:bar")?,
+            vec![Search(SingleField {
+                field: "foo".into(),
+                text: "bar".into(),
+                mode: FieldSearchMode::NoCombining
             })]
         );
 
@@ -484,11 +794,33 @@ mod test {
             vec![Search(SingleField {
                 field: "field".into(),
                 text: "va\"lue".into(),
-                is_re: false
+                mode: FieldSearchMode::Normal
             })]
         );
         assert_eq!(parse(r#""field:va\"lue""#)?, parse(r#"field:"va\"lue""#)?,);
 
+        // an escaped colon is part of the text
+        assert_eq!(
+            parse(r"a\:b")?,
+            vec![Search(UnqualifiedText(r"a\:b".into()))]
+        );
+        assert_eq!(
+            parse(r"a\:b:c")?,
+            vec![Search(SingleField {
+                field: r"a\:b".into(),
+                text: "c".into(),
+                mode: FieldSearchMode::Normal
+            })]
+        );
+        assert_eq!(
+            parse(r"a\\:b")?,
+            vec![Search(SingleField {
+                field: r"a\\".into(),
+                text: "b".into(),
+                mode: FieldSearchMode::Normal
+            })]
+        );
+
         // any character should be escapable in quotes
         assert_eq!(
             parse(r#""re:\btest""#)?,
@@ -496,6 +828,60 @@ mod test {
         );
 
         assert_eq!(parse("added:3")?, vec![Search(AddedInDays(3))]);
+        assert_eq!(
+            parse("added:>=7")?,
+            vec![Search(AddedDaysAgo {
+                operator: ">=".into(),
+                days: 7
+            })]
+        );
+        assert_eq!(
+            parse("rated:>7:3")?,
+            vec![Search(RatedDaysAgo {
+                operator: ">".into(),
+                days: 7,
+                ease: Some(3)
+            })]
+        );
+        assert!(parse("added:=>7").is_err());
+        assert!(parse("rated:<").is_err());
+        assert_eq!(parse("edited:3")?, vec![Search(EditedInDays(3))]);
+        assert!(parse("edited:-1").is_err());
+        let date = |y, m, d| Some(NaiveDate::from_ymd(y, m, d));
+        assert_eq!(
+            parse("added:2020-01-01..2020-02-01")?,
+            vec![Search(AddedBetween(DateRange {
+                start: date(2020, 1, 1),
+                end: date(2020, 2, 1)
+            }))]
+        );
+        assert_eq!(
+            parse("edited:..2020-02-01")?,
+            vec![Search(EditedBetween(DateRange {
+                start: None,
+                end: date(2020, 2, 1)
+            }))]
+        );
+        assert_eq!(
+            parse("added:2020-01-01")?,
+            vec![Search(AddedBetween(DateRange {
+                start: date(2020, 1, 1),
+                end: date(2020, 1, 1)
+            }))]
+        );
+        assert_eq!(parse("lastreview:90")?, vec![Search(LastReviewInDays(90))]);
+        assert_eq!(parse("resched:7")?, vec![Search(RescheduledInDays(7))]);
+        assert_eq!(
+            parse("firstreview:2020-01-01..")?,
+            vec![Search(FirstReviewBetween(DateRange {
+                start: date(2020, 1, 1),
+                end: None
+            }))]
+        );
+        assert!(parse("added:..").is_err());
+        assert!(parse("added:2020-02-01..2020-01-01").is_err());
+        assert!(parse("added:2020-13-01").is_err());
+        assert_eq!(parse("introduced:3")?, vec![Search(IntroducedInDays(3))]);
         assert_eq!(
             parse("card:front")?,
             vec![Search(CardTemplate(TemplateKind::Name("front".into())))]
@@ -514,18 +900,82 @@ mod test {
             parse("deck:\"default one\"")?,
             vec![Search(Deck("default one".into()))]
         );
+        assert_eq!(
+            parse("deck!:\"a b\"")?,
+            vec![Search(DeckExact("a b".into()))]
+        );
 
         assert_eq!(parse("note:basic")?, vec![Search(NoteType("basic".into()))]);
         assert_eq!(parse("tag:hard")?, vec![Search(Tag("hard".into()))]);
         assert_eq!(
             parse("nid:1237123712,2,3")?,
-            vec![Search(NoteIDs("1237123712,2,3".into()))]
+            vec![Search(NoteIDs(vec![1237123712, 2, 3]))]
         );
         assert!(parse("nid:1237123712_2,3").is_err());
+        assert_eq!(
+            parse("guid:AbC+d%1")?,
+            vec![Search(NoteGUID("AbC+d%1".into()))]
+        );
+        assert_eq!(parse("mid:3,4")?, vec![Search(NoteTypeIDs(vec![3, 4]))]);
+        assert_eq!(
+            parse("did:1")?,
+            vec![Search(SearchNode::DeckID(crate::decks::DeckID(1)))]
+        );
+        assert!(parse("did:x").is_err());
+        assert_eq!(
+            parse("front: has-field:back")?,
+            vec![
+                Search(SingleField {
+                    field: "front".into(),
+                    text: "".into(),
+                    mode: FieldSearchMode::Normal
+                }),
+                And,
+                Search(HasField("back".into()))
+            ]
+        );
+        assert_eq!(
+            parse(r#"preset:"a b""#)?,
+            vec![Search(Preset("a b".into()))]
+        );
+        assert!(parse("nid:1,,2").is_err());
+        assert!(parse("nid:,1").is_err());
+        assert!(parse("cid:1,").is_err());
+        assert!(parse("cid:99999999999999999999").is_err());
         assert_eq!(parse("is:due")?, vec![Search(State(StateKind::Due))]);
+        assert_eq!(
+            parse("is:buried-sibling")?,
+            vec![Search(State(StateKind::BuriedSibling))]
+        );
+        assert_eq!(
+            parse("is:day-learn")?,
+            vec![Search(State(StateKind::DayLearning))]
+        );
         assert_eq!(parse("flag:3")?, vec![Search(Flag(3))]);
         assert!(parse("flag:-1").is_err());
         assert!(parse("flag:5").is_err());
+        assert_eq!(parse("flag:any")?, vec![Search(AnyFlag)]);
+        assert_eq!(parse("flag:1,3")?, vec![Search(Flags(vec![1, 3]))]);
+        assert!(parse("flag:1,5").is_err());
+        assert!(parse("flag:1,").is_err());
+
+        assert_eq!(
+            parse("dupe:123,a,b")?,
+            vec![Search(Duplicates {
+                note_type_id: NoteTypeID(123),
+                field: None,
+                text: "a,b".into()
+            })]
+        );
+        assert_eq!(
+            parse("dupe:123:back,a")?,
+            vec![Search(Duplicates {
+                note_type_id: NoteTypeID(123),
+                field: Some("back".into()),
+                text: "a".into()
+            })]
+        );
+        assert!(parse("dupe:123:,a").is_err());
 
         assert_eq!(
             parse("prop:ivl>3")?,
@@ -535,6 +985,30 @@ mod test {
             })]
         );
         assert!(parse("prop:ivl>3.3").is_err());
+        assert!(parse("prop:ivl==3").is_err());
+        assert!(parse("prop:ivl3").is_err());
+        assert_eq!(
+            parse("prop:cd:last_seen>=1.5")?,
+            vec![Search(Property {
+                operator: ">=".into(),
+                kind: PropertyKind::CustomDataNumber {
+                    key: "last_seen".into(),
+                    value: 1.5
+                }
+            })]
+        );
+        assert_eq!(
+            parse("prop:cd:source=web")?,
+            vec![Search(Property {
+                operator: "=".into(),
+                kind: PropertyKind::CustomDataString {
+                    key: "source".into(),
+                    value: "web".into()
+                }
+            })]
+        );
+        assert!(parse("prop:cd:>1").is_err());
+        assert!(parse("prop:cd:a-b=1").is_err());
         assert_eq!(
             parse("prop:ease<=3.3")?,
             vec![Search(Property {
@@ -542,7 +1016,52 @@ mod test {
                 kind: PropertyKind::Ease(3.3)
             })]
         );
+        assert_eq!(
+            parse("prop:pos<100")?,
+            vec![Search(Property {
+                operator: "<".into(),
+                kind: PropertyKind::Position(100)
+            })]
+        );
 
         Ok(())
     }
+
+    #[test]
+    fn errors() {
+        use SearchErrorKind::*;
+        let error = |search| match parse(search) {
+            Err(AnkiError::SearchError { info, kind, span }) => (kind, span.unwrap(), info),
+            other => panic!("unexpected result: {:?}", other),
+        };
+
+        assert_eq!(error("dog and"), (MisplacedBoolean, 4..7, "and".into()));
+        assert_eq!(error("or dog"), (MisplacedBoolean, 0..2, "or".into()));
+        assert_eq!(error("(dog or)"), (MisplacedBoolean, 5..7, "or".into()));
+        assert_eq!(error("dog)"), (UnopenedGroup, 3..4, ")".into()));
+        assert_eq!(
+            error(r#"deck:"foo"#),
+            (UnclosedQuote, 5..9, r#""foo"#.into())
+        );
+        assert_eq!(
+            error(" added:x cat"),
+            (InvalidArgument, 1..8, "added:x".into())
+        );
+        assert_eq!(
+            error(r#"dog is:"a b""#),
+            (InvalidArgument, 4..12, r#"is:"a b""#.into())
+        );
+        assert_eq!(
+            error("prop:ivl>>3"),
+            (InvalidPropertyOperator, 0..11, "prop:ivl>>3".into())
+        );
+        assert_eq!(
+            error("prop:ease2"),
+            (InvalidPropertyOperator, 0..10, "prop:ease2".into())
+        );
+        assert_eq!(
+            error("prop:ivl>x"),
+            (InvalidArgument, 0..10, "prop:ivl>x".into())
+        );
+    }
 }
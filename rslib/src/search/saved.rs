@@ -0,0 +1,130 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::parser::parse;
+use crate::{
+    collection::Collection,
+    config::ConfigKey,
+    err::{AnkiError, Result},
+    text::normalize_to_nfc,
+};
+use std::collections::HashMap;
+
+impl Collection {
+    /// A map of saved search names to their search text.
+    pub fn saved_searches(&self) -> HashMap<String, String> {
+        self.get_config_default(ConfigKey::SavedSearches)
+    }
+
+    /// The search text for the provided name. If there is no exact match, a
+    /// case-insensitive match ignoring differences in Unicode normalization
+    /// is tried.
+    pub(crate) fn get_saved_search(&self, name: &str) -> Option<String> {
+        let mut searches = self.saved_searches();
+        searches.remove(name).or_else(|| {
+            searches
+                .into_iter()
+                .find(|(key, _)| {
+                    unicase::eq(
+                        normalize_to_nfc(key).as_ref(),
+                        normalize_to_nfc(name).as_ref(),
+                    )
+                })
+                .map(|(_, search)| search)
+        })
+    }
+
+    /// Add a saved search, or replace an existing one with the same name.
+    pub fn set_saved_search(&self, name: &str, search: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(AnkiError::invalid_input("empty saved search name"));
+        }
+        // ensure we don't store a search that can't be used later
+        parse(search)?;
+        let mut searches = self.saved_searches();
+        searches.insert(name.into(), search.into());
+        self.set_config(ConfigKey::SavedSearches, &searches)
+    }
+
+    pub fn rename_saved_search(&self, old_name: &str, new_name: &str) -> Result<()> {
+        let new_name = new_name.trim();
+        if new_name.is_empty() {
+            return Err(AnkiError::invalid_input("empty saved search name"));
+        }
+        let mut searches = self.saved_searches();
+        if old_name != new_name && searches.contains_key(new_name) {
+            return Err(AnkiError::Existing);
+        }
+        let search = searches.remove(old_name).ok_or(AnkiError::NotFound)?;
+        searches.insert(new_name.into(), search);
+        self.set_config(ConfigKey::SavedSearches, &searches)
+    }
+
+    pub fn remove_saved_search(&self, name: &str) -> Result<()> {
+        let mut searches = self.saved_searches();
+        if searches.remove(name).is_some() {
+            self.set_config(ConfigKey::SavedSearches, &searches)
+        } else {
+            Err(AnkiError::NotFound)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        collection::open_test_collection,
+        decks::DeckID,
+        err::{AnkiError, Result},
+        search::SortMode,
+    };
+
+    #[test]
+    fn saved_searches() -> Result<()> {
+        let mut col = open_test_collection();
+        assert!(col.saved_searches().is_empty());
+
+        col.set_saved_search("one", "dog")?;
+        col.set_saved_search("two", "cat")?;
+        assert!(col.set_saved_search("three", "(").is_err());
+        assert!(col.set_saved_search(" ", "cat").is_err());
+        assert_eq!(col.saved_searches().len(), 2);
+
+        assert_eq!(
+            col.rename_saved_search("one", "two"),
+            Err(AnkiError::Existing)
+        );
+        assert_eq!(
+            col.rename_saved_search("missing", "four"),
+            Err(AnkiError::NotFound)
+        );
+        col.rename_saved_search("one", "One Dog")?;
+        assert_eq!(col.get_saved_search("one dog"), Some("dog".into()));
+
+        col.remove_saved_search("two")?;
+        assert_eq!(col.remove_saved_search("two"), Err(AnkiError::NotFound));
+        assert_eq!(
+            col.saved_searches().into_iter().collect::<Vec<_>>(),
+            vec![("One Dog".to_string(), "dog".to_string())]
+        );
+
+        // saved searches can be referred to with preset:
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields[0] = "dog".into();
+        col.add_note(&mut note, DeckID(1))?;
+        assert_eq!(col.search_notes(r#"preset:"one dog""#)?, vec![note.id]);
+        assert_eq!(col.search_notes("-preset:\"One Dog\"")?, vec![]);
+        assert!(matches!(
+            col.search_cards("preset:missing", SortMode::NoOrder),
+            Err(AnkiError::SearchError { .. })
+        ));
+
+        // presets can't refer to themselves
+        col.set_saved_search("loop", "dog or preset:loop")?;
+        assert!(col.search_notes("preset:loop").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::parser::Node;
+
+/// How the nodes of a group are joined together.
+#[derive(PartialEq, Clone, Copy)]
+enum Joiner {
+    /// a single node, or none
+    Nothing,
+    And,
+    Or,
+    Mixed,
+}
+
+/// Rewrite a parsed search into an equivalent one that is cheaper to turn
+/// into SQL: redundant groups are flattened, and double negations removed.
+pub(super) fn simplify(node: Node) -> Node {
+    match node {
+        Node::Not(inner) => match unwrap_single(simplify(*inner)) {
+            Node::Not(inner) => *inner,
+            inner => Node::Not(Box::new(inner)),
+        },
+        Node::Group(nodes) => Node::Group(simplify_group(nodes)),
+        node => node,
+    }
+}
+
+fn simplify_group(nodes: Vec<Node>) -> Vec<Node> {
+    let outer = joiner(&nodes);
+    let mut out = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match simplify(node) {
+            Node::Group(children) if can_splice(&children, outer) => out.extend(children),
+            node => out.push(node),
+        }
+    }
+    out
+}
+
+/// A group's contents can be moved into its parent if that doesn't change
+/// how its nodes are combined.
+fn can_splice(children: &[Node], outer: Joiner) -> bool {
+    match joiner(children) {
+        Joiner::Nothing => !children.is_empty(),
+        Joiner::Mixed => outer == Joiner::Nothing,
+        inner => outer == Joiner::Nothing || outer == inner,
+    }
+}
+
+fn joiner(nodes: &[Node]) -> Joiner {
+    nodes.iter().fold(Joiner::Nothing, |acc, node| {
+        let joiner = match node {
+            Node::And => Joiner::And,
+            Node::Or => Joiner::Or,
+            _ => return acc,
+        };
+        if acc == Joiner::Nothing || acc == joiner {
+            joiner
+        } else {
+            Joiner::Mixed
+        }
+    })
+}
+
+/// A group containing a single search or negation is replaced by its
+/// contents.
+fn unwrap_single(node: Node) -> Node {
+    match node {
+        Node::Group(mut nodes) if nodes.len() == 1 && joiner(&nodes) == Joiner::Nothing => {
+            unwrap_single(nodes.pop().unwrap())
+        }
+        node => node,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{err::Result, search::parser::parse};
+
+    fn simplified(search: &str) -> Result<Node> {
+        Ok(simplify(Node::Group(parse(search)?)))
+    }
+
+    #[test]
+    fn simplifying() -> Result<()> {
+        let same = |a: &str, b: &str| -> Result<()> {
+            assert_eq!(simplified(a)?, Node::Group(parse(b)?));
+            Ok(())
+        };
+        same("a (b c)", "a b c")?;
+        same("a or (b or (c))", "a or b or c")?;
+        same("((a or b))", "a or b")?;
+        same("-(-a)", "a")?;
+        same("-(-(-a)) b", "-a b")?;
+        same("-(a)", "-a")?;
+
+        // groups that change precedence are kept
+        assert_eq!(simplified("a (b or c)")?, Node::Group(parse("a (b or c)")?));
+        assert_eq!(simplified("-(a b)")?, Node::Group(parse("-(a b)")?));
+
+        Ok(())
+    }
+}
@@ -1,17 +1,27 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
-use super::parser::{Node, PropertyKind, SearchNode, StateKind, TemplateKind};
+use super::{
+    parser::{
+        parse, DateRange, FieldSearchMode, Node, PropertyKind, SearchNode, StateKind, TemplateKind,
+        PROP_OPERATORS,
+    },
+    simplify::simplify,
+};
 use crate::{
     card::{CardQueue, CardType},
     collection::Collection,
-    decks::human_deck_name_to_native,
-    err::Result,
+    decks::{human_deck_name_to_native, DeckID},
+    err::{AnkiError, Result, SearchErrorKind},
     notes::field_checksum,
     notetype::NoteTypeID,
+    storage::ids_to_string,
     text::matches_wildcard,
-    text::{normalize_to_nfc, strip_html_preserving_image_filenames, without_combining},
+    text::{fold_case, normalize_to_nfc, strip_html_preserving_image_filenames, without_combining},
+    timestamp::TimestampSecs,
 };
+use chrono::{NaiveDate, TimeZone};
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 use std::{borrow::Cow, fmt::Write};
@@ -21,21 +31,34 @@ pub(crate) struct SqlWriter<'a> {
     sql: String,
     args: Vec<String>,
     normalize_note_text: bool,
+    full_text_index: bool,
     table: RequiredTable,
+    /// names of the saved searches currently being written, to catch loops
+    presets: Vec<String>,
+    /// (notetype id, ordinal, name) of each field, loaded on first use and
+    /// reused for the rest of the search
+    fields: Option<Vec<(NoteTypeID, u32, String)>>,
+    /// (id, native name) of each deck, likewise
+    decks: Option<Vec<(DeckID, String)>>,
 }
 
 impl SqlWriter<'_> {
-    pub(crate) fn new(col: &mut Collection) -> SqlWriter<'_> {
+    pub(crate) fn new(col: &mut Collection) -> Result<SqlWriter<'_>> {
         let normalize_note_text = col.normalize_note_text();
+        let full_text_index = col.full_text_index_exists()?;
         let sql = String::new();
         let args = vec![];
-        SqlWriter {
+        Ok(SqlWriter {
             col,
             sql,
             args,
             normalize_note_text,
+            full_text_index,
             table: RequiredTable::CardsOrNotes,
-        }
+            presets: vec![],
+            fields: None,
+            decks: None,
+        })
     }
 
     pub(super) fn build_cards_query(
@@ -43,6 +66,7 @@ impl SqlWriter<'_> {
         node: &Node,
         table: RequiredTable,
     ) -> Result<(String, Vec<String>)> {
+        let node = simplify(node.clone());
         self.table = table.combine(node.required_table());
         self.write_cards_table_sql();
         self.write_node_to_sql(&node)?;
@@ -50,6 +74,7 @@ impl SqlWriter<'_> {
     }
 
     pub(super) fn build_notes_query(mut self, node: &Node) -> Result<(String, Vec<String>)> {
+        let node = simplify(node.clone());
         self.table = RequiredTable::Notes.combine(node.required_table());
         self.write_notes_table_sql();
         self.write_node_to_sql(&node)?;
@@ -88,13 +113,25 @@ impl SqlWriter<'_> {
             Node::And => write!(self.sql, " and ").unwrap(),
             Node::Or => write!(self.sql, " or ").unwrap(),
             Node::Not(node) => {
+                if let Node::Search(search) = node.as_ref() {
+                    if self.write_negated_search_node(search)? {
+                        return Ok(());
+                    }
+                }
                 write!(self.sql, "not ").unwrap();
                 self.write_node_to_sql(node)?;
             }
             Node::Group(nodes) => {
                 write!(self.sql, "(").unwrap();
-                for node in nodes {
-                    self.write_node_to_sql(node)?;
+                if let Some(others) = self.write_merged_decks(nodes)? {
+                    for node in others {
+                        write!(self.sql, " or ").unwrap();
+                        self.write_node_to_sql(node)?;
+                    }
+                } else {
+                    for node in nodes {
+                        self.write_node_to_sql(node)?;
+                    }
                 }
                 write!(self.sql, ")").unwrap();
             }
@@ -117,19 +154,34 @@ impl SqlWriter<'_> {
         match node {
             // note fields related
             SearchNode::UnqualifiedText(text) => self.write_unqualified(&self.norm_note(text)),
-            SearchNode::SingleField { field, text, is_re } => {
-                self.write_single_field(field.as_ref(), &self.norm_note(text), *is_re)?
-            }
-            SearchNode::Duplicates { note_type_id, text } => {
-                self.write_dupes(*note_type_id, &self.norm_note(text))
+            SearchNode::SingleField { field, text, mode } => {
+                self.write_single_field(&norm(field), &self.norm_note(text), *mode)?
             }
+            SearchNode::Duplicates {
+                note_type_id,
+                field,
+                text,
+            } => self.write_dupes(
+                *note_type_id,
+                field.as_deref().map(norm).as_deref(),
+                &self.norm_note(text),
+            )?,
+            SearchNode::HasField(field) => self.write_has_field(&norm(field))?,
             SearchNode::Regex(re) => self.write_regex(&self.norm_note(re)),
             SearchNode::NoCombining(text) => self.write_no_combining(&self.norm_note(text)),
             SearchNode::WordBoundary(text) => self.write_word_boundary(&self.norm_note(text)),
 
             // other
             SearchNode::AddedInDays(days) => self.write_added(*days)?,
+            SearchNode::AddedBetween(range) => self.write_date_range("c.id", range, 1_000)?,
             SearchNode::EditedInDays(days) => self.write_edited(*days)?,
+            SearchNode::EditedBetween(range) => self.write_date_range("n.mod", range, 1)?,
+            SearchNode::IntroducedInDays(days) => self.write_introduced(*days)?,
+            SearchNode::RescheduledInDays(days) => self.write_rescheduled(*days)?,
+            SearchNode::FirstReviewInDays(days) => self.write_review_in_days("min", *days)?,
+            SearchNode::FirstReviewBetween(range) => self.write_review_between("min", range)?,
+            SearchNode::LastReviewInDays(days) => self.write_review_in_days("max", *days)?,
+            SearchNode::LastReviewBetween(range) => self.write_review_between("max", range)?,
             SearchNode::CardTemplate(template) => match template {
                 TemplateKind::Ordinal(_) => {
                     self.write_template(template)?;
@@ -139,31 +191,97 @@ impl SqlWriter<'_> {
                 }
             },
             SearchNode::Deck(deck) => self.write_deck(&norm(deck))?,
-            SearchNode::NoteTypeID(ntid) => {
-                write!(self.sql, "n.mid = {}", ntid).unwrap();
-            }
+            SearchNode::DeckExact(deck) => self.write_deck_exact(&norm(deck))?,
+            SearchNode::DeckID(did) => self.write_deck_id(*did)?,
+            SearchNode::NoteTypeIDs(ntids) => self.write_id_list("n.mid", ntids, false),
             SearchNode::NoteType(notetype) => self.write_note_type(&norm(notetype))?,
             SearchNode::Rated { days, ease } => self.write_rated(*days, *ease)?,
+            SearchNode::AddedDaysAgo { operator, days } => {
+                let cond = self.days_ago_sql("c.id", operator, *days, 1_000)?;
+                self.sql.push_str(&cond);
+            }
+            SearchNode::RatedDaysAgo {
+                operator,
+                days,
+                ease,
+            } => self.write_rated_days_ago(operator, *days, *ease)?,
             SearchNode::Tag(tag) => self.write_tag(&norm(tag))?,
             SearchNode::State(state) => self.write_state(state)?,
             SearchNode::Flag(flag) => {
                 write!(self.sql, "(c.flags & 7) == {}", flag).unwrap();
             }
-            SearchNode::NoteIDs(nids) => {
-                write!(self.sql, "{} in ({})", self.note_id_column(), nids).unwrap();
+            SearchNode::Flags(flags) => {
+                write!(self.sql, "(c.flags & 7) in ({})", flags.iter().join(",")).unwrap();
             }
-            SearchNode::CardIDs(cids) => {
-                write!(self.sql, "c.id in ({})", cids).unwrap();
+            SearchNode::AnyFlag => write!(self.sql, "(c.flags & 7) != 0").unwrap(),
+            SearchNode::NoteIDs(nids) => self.write_id_list(self.note_id_column(), nids, false),
+            SearchNode::CardIDs(cids) => self.write_id_list("c.id", cids, false),
+            SearchNode::NoteGUID(guid) => {
+                self.args.push(guid.to_string());
+                write!(self.sql, "n.guid = ?{}", self.args.len()).unwrap();
             }
             SearchNode::Property { operator, kind } => self.write_prop(operator, kind)?,
             SearchNode::WholeCollection => write!(self.sql, "true").unwrap(),
+            SearchNode::Preset(name) => self.write_preset(&norm(name))?,
         };
         Ok(())
     }
 
+    /// Some searches have a cheaper negated form than wrapping them in
+    /// `not`. Returns false if the search was not written.
+    fn write_negated_search_node(&mut self, node: &SearchNode) -> Result<bool> {
+        match node {
+            SearchNode::State(state) => match state_column_values(state) {
+                Some((column, values)) => self.write_column_values(column, &values, true),
+                None => return Ok(false),
+            },
+            SearchNode::Flag(flag) => write!(self.sql, "(c.flags & 7) != {}", flag).unwrap(),
+            SearchNode::Flags(flags) => write!(
+                self.sql,
+                "(c.flags & 7) not in ({})",
+                flags.iter().join(",")
+            )
+            .unwrap(),
+            SearchNode::AnyFlag => write!(self.sql, "(c.flags & 7) = 0").unwrap(),
+            SearchNode::NoteIDs(nids) => self.write_id_list(self.note_id_column(), nids, true),
+            SearchNode::CardIDs(cids) => self.write_id_list("c.id", cids, true),
+            SearchNode::NoteTypeIDs(ntids) => self.write_id_list("n.mid", ntids, true),
+            _ => return Ok(false),
+        };
+        Ok(true)
+    }
+
     fn write_unqualified(&mut self, text: &str) {
+        if self.full_text_index && is_plain_text(text) {
+            // match any word starting with the text
+            self.args.push(format!("\"{}\"*", text));
+            write!(
+                self.sql,
+                "(n.id in (select rowid from notes_fts where notes_fts match ?{}))",
+                self.args.len()
+            )
+            .unwrap();
+            return;
+        }
+
+        if !text.is_ascii() {
+            // SQLite's like only folds the case of ASCII characters
+            self.args
+                .push(format!("%{}%", to_sql_like(&fold_case(text))));
+            write!(
+                self.sql,
+                concat!(
+                    "(fold_case(cast(n.sfld as text)) like ?{n} escape '\\' ",
+                    "or fold_case(n.flds) like ?{n} escape '\\')"
+                ),
+                n = self.args.len(),
+            )
+            .unwrap();
+            return;
+        }
+
         // implicitly wrap in %
-        let text = format!("%{}%", text);
+        let text = format!("%{}%", to_sql_like(text));
         self.args.push(text);
         write!(
             self.sql,
@@ -174,14 +292,21 @@ impl SqlWriter<'_> {
     }
 
     fn write_no_combining(&mut self, text: &str) {
-        let text = format!("%{}%", without_combining(text));
-        self.args.push(text);
+        let text = without_combining(text);
+        let fold = !text.is_ascii();
+        let text = if fold {
+            fold_case(&text).into_owned()
+        } else {
+            text.into_owned()
+        };
+        self.args.push(format!("%{}%", to_sql_like(&text)));
+        let sfld = no_combining_sql("cast(n.sfld as text)", fold);
+        let flds = no_combining_sql("n.flds", fold);
         write!(
             self.sql,
-            concat!(
-                "(coalesce(without_combining(cast(n.sfld as text)), n.sfld) like ?{n} escape '\\' ",
-                "or coalesce(without_combining(n.flds), n.flds) like ?{n} escape '\\')"
-            ),
+            "({} like ?{n} escape '\\' or {} like ?{n} escape '\\')",
+            sfld,
+            flds,
             n = self.args.len(),
         )
         .unwrap();
@@ -196,14 +321,27 @@ impl SqlWriter<'_> {
                 write!(self.sql, "true").unwrap();
             }
             text => {
-                if let Some(re_glob) = glob_to_re(text) {
+                // tags are matched along with any of their children, so
+                // tag:parent finds parent::child as well
+                if text.contains('*') {
                     // text contains a wildcard
-                    let re_glob = format!("(?i).* {} .*", re_glob);
+                    let re_glob = format!("(?i).* {}(::| ).*", to_re(text));
                     write!(self.sql, "n.tags regexp ?").unwrap();
                     self.args.push(re_glob);
                 } else if let Some(tag) = self.col.storage.preferred_tag_case(&text)? {
-                    write!(self.sql, "n.tags like ?").unwrap();
+                    let tag = to_sql_like(&tag);
+                    write!(
+                        self.sql,
+                        "(n.tags like ? escape '\\' or n.tags like ? escape '\\')"
+                    )
+                    .unwrap();
                     self.args.push(format!("% {} %", tag));
+                    self.args.push(format!("% {}::%", tag));
+                } else if self.col.storage.tag_has_children(text)? {
+                    // the tag only exists as the parent of other tags
+                    let re = format!("(?i).* {}::.*", to_re(text));
+                    write!(self.sql, "n.tags regexp ?").unwrap();
+                    self.args.push(re);
                 } else {
                     write!(self.sql, "false").unwrap();
                 }
@@ -213,9 +351,9 @@ impl SqlWriter<'_> {
     }
 
     fn write_rated(&mut self, days: u32, ease: Option<u8>) -> Result<()> {
-        let today_cutoff = self.col.timing_today()?.next_day_at;
-        let days = days.min(365) as i64;
-        let target_cutoff_ms = (today_cutoff - 86_400 * days) * 1_000;
+        // revlog ids are timestamps and the table's primary key, so
+        // arbitrarily long ranges can be searched without a table scan
+        let target_cutoff_ms = self.days_ago_cutoff(days)?.as_millis();
         write!(
             self.sql,
             "c.id in (select cid from revlog where id>{}",
@@ -231,7 +369,62 @@ impl SqlWriter<'_> {
         Ok(())
     }
 
+    fn write_rated_days_ago(&mut self, op: &str, days: u32, ease: Option<u8>) -> Result<()> {
+        let cond = self.days_ago_sql("id", op, days, 1_000)?;
+        write!(self.sql, "c.id in (select cid from revlog where {}", cond).unwrap();
+        if let Some(ease) = ease {
+            write!(self.sql, " and ease={})", ease).unwrap();
+        } else {
+            write!(self.sql, ")").unwrap();
+        }
+        Ok(())
+    }
+
+    /// Compare a timestamp column against the number of days ago it falls
+    /// on, where today is 1 day ago, as added:N does. `scale` converts
+    /// seconds into the units of the column.
+    fn days_ago_sql(&self, column: &str, op: &str, days: u32, scale: i64) -> Result<String> {
+        let next_day_at = self.col.timing_today()?.next_day_at;
+        // timestamps after the cutoff are within `days` days
+        let cutoff = |days: i64| (next_day_at - 86_400 * days) * scale;
+        let days = days as i64;
+        Ok(match op {
+            "<=" => format!("{} > {}", column, cutoff(days)),
+            "<" => format!("{} > {}", column, cutoff(days - 1)),
+            ">" => format!("{} <= {}", column, cutoff(days)),
+            ">=" => format!("{} <= {}", column, cutoff(days - 1)),
+            "=" => format!(
+                "({c} > {} and {c} <= {})",
+                cutoff(days),
+                cutoff(days - 1),
+                c = column
+            ),
+            "!=" => format!(
+                "({c} <= {} or {c} > {})",
+                cutoff(days),
+                cutoff(days - 1),
+                c = column
+            ),
+            _ => {
+                return Err(AnkiError::SearchError {
+                    info: op.into(),
+                    kind: SearchErrorKind::InvalidPropertyOperator,
+                    span: None,
+                })
+            }
+        })
+    }
+
     fn write_prop(&mut self, op: &str, kind: &PropertyKind) -> Result<()> {
+        // nodes may have been constructed in code instead of parsed, and
+        // the operator is written into the SQL as-is
+        if !PROP_OPERATORS.contains(&op) {
+            return Err(AnkiError::SearchError {
+                info: op.into(),
+                kind: SearchErrorKind::InvalidPropertyOperator,
+                span: None,
+            });
+        }
         let timing = self.col.timing_today()?;
         match kind {
             PropertyKind::Due(days) => {
@@ -249,84 +442,275 @@ impl SqlWriter<'_> {
             PropertyKind::Reps(reps) => write!(self.sql, "reps {} {}", op, reps),
             PropertyKind::Lapses(days) => write!(self.sql, "lapses {} {}", op, days),
             PropertyKind::Ease(ease) => {
-                write!(self.sql, "factor {} {}", op, (ease * 1000.0) as u32)
+                // round, so that eg 2.55 is not truncated to 2549
+                write!(self.sql, "factor {} {}", op, (ease * 1000.0).round() as u32)
+            }
+            PropertyKind::CustomDataNumber { key, value } => {
+                let data = self.custom_data(key);
+                write!(self.sql, "coalesce({} {} {}, 0)", data, op, value)
             }
+            PropertyKind::CustomDataString { key, value } => {
+                let data = self.custom_data(key);
+                self.args.push(value.into());
+                write!(
+                    self.sql,
+                    "coalesce({} {} ?{}, 0)",
+                    data,
+                    op,
+                    self.args.len()
+                )
+            }
+            // new cards store their position in the due column
+            PropertyKind::Position(pos) => write!(
+                self.sql,
+                "(c.type = {} and due {} {})",
+                CardType::New as u8,
+                op,
+                pos
+            ),
         }
         .unwrap();
         Ok(())
     }
 
+    /// Add-ons can store their own data on a card as a JSON object in the
+    /// data column, like {"cd": {"key": value}}. Returns an expression that
+    /// is null if the card has no such key, so the caller should make sure
+    /// the comparison is false in that case.
+    fn custom_data(&mut self, key: &str) -> String {
+        self.args.push(format!("$.cd.{}", key));
+        format!(
+            "(case when json_valid(c.data) then json_extract(c.data, ?{}) end)",
+            self.args.len()
+        )
+    }
+
     fn write_state(&mut self, state: &StateKind) -> Result<()> {
+        if let Some((column, values)) = state_column_values(state) {
+            self.write_column_values(column, &values, false);
+            return Ok(());
+        }
+        // only is:due remains
         let timing = self.col.timing_today()?;
-        match state {
-            StateKind::New => write!(self.sql, "c.type = {}", CardType::New as i8),
-            StateKind::Review => write!(
-                self.sql,
-                "c.type in ({}, {})",
-                CardType::Review as i8,
-                CardType::Relearn as i8,
-            ),
-            StateKind::Learning => write!(
-                self.sql,
-                "c.queue in ({},{})",
-                CardQueue::Learn as i8,
-                CardQueue::DayLearn as i8
-            ),
-            StateKind::Buried => write!(
-                self.sql,
-                "c.queue in ({},{})",
-                CardQueue::SchedBuried as i8,
-                CardQueue::UserBuried as i8
-            ),
-            StateKind::Suspended => write!(self.sql, "c.queue = {}", CardQueue::Suspended as i8),
-            StateKind::Due => write!(
-                self.sql,
-                "(
+        write!(
+            self.sql,
+            "(
     (c.queue in ({rev},{daylrn}) and c.due <= {today}) or
     (c.queue = {lrn} and c.due <= {daycutoff})
     )",
-                rev = CardQueue::Review as i8,
-                daylrn = CardQueue::DayLearn as i8,
-                today = timing.days_elapsed,
-                lrn = CardQueue::Learn as i8,
-                daycutoff = timing.next_day_at,
-            ),
-        }
+            rev = CardQueue::Review as i8,
+            daylrn = CardQueue::DayLearn as i8,
+            today = timing.days_elapsed,
+            lrn = CardQueue::Learn as i8,
+            daycutoff = timing.next_day_at,
+        )
         .unwrap();
         Ok(())
     }
 
+    fn write_column_values(&mut self, column: &str, values: &[i8], negated: bool) {
+        match (values, negated) {
+            ([value], false) => write!(self.sql, "{} = {}", column, value),
+            ([value], true) => write!(self.sql, "{} != {}", column, value),
+            (_, false) => write!(self.sql, "{} in ({})", column, values.iter().join(",")),
+            (_, true) => write!(self.sql, "{} not in ({})", column, values.iter().join(",")),
+        }
+        .unwrap();
+    }
+
+    fn write_id_list(&mut self, column: &str, ids: &[i64], negated: bool) {
+        let mut list = String::new();
+        ids_to_string(&mut list, ids);
+        let operator = if negated { "not in" } else { "in" };
+        write!(self.sql, "{} {} {}", column, operator, list).unwrap();
+    }
+
+    fn fields(&mut self) -> Result<&[(NoteTypeID, u32, String)]> {
+        if self.fields.is_none() {
+            self.fields = Some(self.col.storage.get_all_field_names()?);
+        }
+        Ok(self.fields.as_ref().unwrap())
+    }
+
+    fn decks(&mut self) -> Result<&[(DeckID, String)]> {
+        if self.decks.is_none() {
+            self.decks = Some(self.col.storage.get_all_native_deck_names()?);
+        }
+        Ok(self.decks.as_ref().unwrap())
+    }
+
     fn write_deck(&mut self, deck: &str) -> Result<()> {
         match deck {
             "*" => write!(self.sql, "true").unwrap(),
             "filtered" => write!(self.sql, "c.odid != 0").unwrap(),
             deck => {
-                // rewrite "current" to the current deck name
-                let native_deck = if deck == "current" {
-                    let current_did = self.col.get_current_deck_id();
-                    self.col
-                        .storage
-                        .get_deck(current_did)?
-                        .map(|d| d.name)
-                        .unwrap_or_else(|| "Default".into())
-                } else {
-                    human_deck_name_to_native(deck)
-                };
-
                 // convert to a regex that includes child decks
-                let re = text_to_re(&native_deck);
-                self.args.push(format!("(?i)^{}($|\x1f)", re));
-                let arg_idx = self.args.len();
-                self.sql.push_str(&format!(concat!(
-                    "(c.did in (select id from decks where name regexp ?{n})",
-                    " or (c.odid != 0 and c.odid in (select id from decks where name regexp ?{n})))"),
-                    n=arg_idx
-                ));
+                let re = self.deck_regex(deck, true)?;
+                self.write_deck_regex(re);
             }
         };
         Ok(())
     }
 
+    /// Like write_deck(), but child decks are not included.
+    fn write_deck_exact(&mut self, deck: &str) -> Result<()> {
+        let re = self.deck_regex(deck, false)?;
+        self.write_deck_regex(re);
+        Ok(())
+    }
+
+    fn deck_regex(&mut self, deck: &str, include_children: bool) -> Result<String> {
+        let re = text_to_re(&self.native_deck_name(deck)?);
+        Ok(if include_children {
+            format!("(?i)^{}($|\x1f)", re)
+        } else {
+            format!("(?i)^{}$", re)
+        })
+    }
+
+    /// The native form of a deck name, with "current" rewritten to the
+    /// current deck's name.
+    fn native_deck_name(&mut self, deck: &str) -> Result<String> {
+        Ok(if deck == "current" {
+            let current_did = self.col.get_current_deck_id();
+            self.decks()?
+                .iter()
+                .find(|(did, _)| *did == current_did)
+                .map(|(_, name)| name.clone())
+                .unwrap_or_else(|| "Default".into())
+        } else {
+            human_deck_name_to_native(deck)
+        })
+    }
+
+    fn write_deck_regex(&mut self, re: String) {
+        self.args.push(re);
+        let arg_idx = self.args.len();
+        self.sql.push_str(&format!(
+            concat!(
+                "(c.did in (select id from decks where name regexp ?{n})",
+                " or (c.odid != 0 and c.odid in (select id from decks where name regexp ?{n})))"
+            ),
+            n = arg_idx
+        ));
+    }
+
+    fn write_deck_id(&mut self, did: DeckID) -> Result<()> {
+        let dids = self.deck_and_child_ids(did)?;
+        self.write_deck_ids(&dids);
+        Ok(())
+    }
+
+    /// like deck:, include child decks
+    fn deck_and_child_ids(&mut self, did: DeckID) -> Result<Vec<DeckID>> {
+        let decks = self.decks()?;
+        let mut dids = vec![did];
+        if let Some((_, name)) = decks.iter().find(|(id, _)| *id == did) {
+            let prefix = format!("{}\x1f", name);
+            dids.extend(
+                decks
+                    .iter()
+                    .filter(|(_, name)| name.starts_with(&prefix))
+                    .map(|(id, _)| *id),
+            );
+        }
+        Ok(dids)
+    }
+
+    fn write_deck_ids(&mut self, dids: &[DeckID]) {
+        let mut ids = String::new();
+        ids_to_string(&mut ids, dids);
+        write!(
+            self.sql,
+            "(c.did in {ids} or (c.odid != 0 and c.odid in {ids}))",
+            ids = ids
+        )
+        .unwrap();
+    }
+
+    /// Sidebar searches often OR together many decks. When a group's
+    /// nodes are only joined by OR and more than one of them is a deck
+    /// search, the decks are resolved up front and written as a single
+    /// clause. The remaining nodes are returned for the caller to write.
+    fn write_merged_decks<'n, 'b>(
+        &mut self,
+        nodes: &'n [Node<'b>],
+    ) -> Result<Option<Vec<&'n Node<'b>>>> {
+        if nodes.iter().any(|node| matches!(node, Node::And)) {
+            return Ok(None);
+        }
+        let (decks, others): (Vec<_>, Vec<_>) = nodes
+            .iter()
+            .filter(|node| !matches!(node, Node::Or))
+            .partition(|node| match node {
+                Node::Search(SearchNode::Deck(deck)) => deck != "*" && deck != "filtered",
+                Node::Search(SearchNode::DeckExact(_)) | Node::Search(SearchNode::DeckID(_)) => {
+                    true
+                }
+                _ => false,
+            });
+        if decks.len() < 2 {
+            return Ok(None);
+        }
+
+        let mut dids = vec![];
+        for node in decks {
+            match node {
+                Node::Search(SearchNode::Deck(deck)) => {
+                    dids.extend(self.deck_ids_matching(&normalize_to_nfc(deck), true)?)
+                }
+                Node::Search(SearchNode::DeckExact(deck)) => {
+                    dids.extend(self.deck_ids_matching(&normalize_to_nfc(deck), false)?)
+                }
+                Node::Search(SearchNode::DeckID(did)) => {
+                    dids.extend(self.deck_and_child_ids(*did)?)
+                }
+                _ => unreachable!(),
+            }
+        }
+        dids.sort();
+        dids.dedup();
+
+        self.write_deck_ids(&dids);
+
+        Ok(Some(others))
+    }
+
+    fn deck_ids_matching(&mut self, deck: &str, include_children: bool) -> Result<Vec<DeckID>> {
+        let re = Regex::new(&self.deck_regex(deck, include_children)?)
+            .map_err(|err| AnkiError::invalid_input(err.to_string()))?;
+        Ok(self
+            .decks()?
+            .iter()
+            .filter(|(_, name)| re.is_match(name))
+            .map(|(did, _)| *did)
+            .collect())
+    }
+
+    /// Write the contents of a saved search in place of its name.
+    fn write_preset(&mut self, name: &str) -> Result<()> {
+        let invalid = || AnkiError::SearchError {
+            info: format!("preset:{}", name),
+            kind: SearchErrorKind::InvalidArgument,
+            span: None,
+        };
+        if self
+            .presets
+            .iter()
+            .any(|preset| unicase::eq(preset.as_str(), name))
+        {
+            // the saved search refers to itself
+            return Err(invalid());
+        }
+        let search = self.col.get_saved_search(name).ok_or_else(invalid)?;
+
+        self.presets.push(name.into());
+        self.write_node_to_sql(&Node::Group(parse(&search)?))?;
+        self.presets.pop();
+
+        Ok(())
+    }
+
     fn write_template(&mut self, template: &TemplateKind) -> Result<()> {
         match template {
             TemplateKind::Ordinal(n) => {
@@ -364,46 +748,60 @@ impl SqlWriter<'_> {
         Ok(())
     }
 
-    fn write_single_field(&mut self, field_name: &str, val: &str, is_re: bool) -> Result<()> {
-        let note_types = self.col.get_all_notetypes()?;
-
-        let mut field_map = vec![];
-        for nt in note_types.values() {
-            for field in &nt.fields {
-                if matches_wildcard(&field.name, field_name) {
-                    field_map.push((nt.id, field.ord));
-                }
-            }
-        }
-
-        // for now, sort the map for the benefit of unit tests
-        field_map.sort();
+    fn write_single_field(
+        &mut self,
+        field_name: &str,
+        val: &str,
+        mode: FieldSearchMode,
+    ) -> Result<()> {
+        let field_map: Vec<_> = self
+            .fields()?
+            .iter()
+            .filter(|(_, _, name)| matches_wildcard(name, field_name))
+            .map(|(ntid, ord, _)| (*ntid, *ord))
+            .collect();
 
         if field_map.is_empty() {
             write!(self.sql, "false").unwrap();
             return Ok(());
         }
 
-        let cmp;
-        if is_re {
-            cmp = "regexp";
-            self.args.push(format!("(?i){}", val));
-        } else {
-            cmp = "like";
-            self.args.push(val.replace('*', "%"));
+        let match_empty = val.is_empty() && mode == FieldSearchMode::Normal;
+        let val = match mode {
+            FieldSearchMode::NoCombining => without_combining(val),
+            _ => val.into(),
+        };
+        // SQLite's like only folds the case of ASCII characters
+        let fold = mode != FieldSearchMode::Regex && !val.is_ascii();
+        if !match_empty {
+            self.args.push(match mode {
+                FieldSearchMode::Regex => format!("(?i){}", val),
+                _ if fold => to_sql_like(&fold_case(&val)),
+                _ => to_sql_like(&val),
+            });
         }
 
         let arg_idx = self.args.len();
         let searches: Vec<_> = field_map
             .iter()
             .map(|(ntid, ord)| {
-                format!(
-                    "(n.mid = {mid} and field_at_index(n.flds, {ord}) {cmp} ?{n})",
-                    mid = ntid,
-                    ord = ord.unwrap_or_default(),
-                    cmp = cmp,
-                    n = arg_idx
-                )
+                let field = format!("field_at_index(n.flds, {})", ord);
+                let cmp = match mode {
+                    FieldSearchMode::Normal if match_empty => format!("{} = ''", field),
+                    FieldSearchMode::Normal if fold => {
+                        format!("fold_case({}) like ?{} escape '\\'", field, arg_idx)
+                    }
+                    FieldSearchMode::Normal => {
+                        format!("{} like ?{} escape '\\'", field, arg_idx)
+                    }
+                    FieldSearchMode::Regex => format!("{} regexp ?{}", field, arg_idx),
+                    FieldSearchMode::NoCombining => format!(
+                        "{} like ?{} escape '\\'",
+                        no_combining_sql(&field, fold),
+                        arg_idx
+                    ),
+                };
+                format!("(n.mid = {} and {})", ntid, cmp)
             })
             .collect();
         write!(self.sql, "({})", searches.join(" or ")).unwrap();
@@ -411,34 +809,187 @@ impl SqlWriter<'_> {
         Ok(())
     }
 
-    fn write_dupes(&mut self, ntid: NoteTypeID, text: &str) {
-        let text_nohtml = strip_html_preserving_image_filenames(text);
-        let csum = field_checksum(text_nohtml.as_ref());
-        write!(
-            self.sql,
-            "(n.mid = {} and n.csum = {} and n.sfld = ?)",
-            ntid, csum
-        )
-        .unwrap();
+    /// Match notes whose note type has a field with the provided name.
+    fn write_has_field(&mut self, field_name: &str) -> Result<()> {
+        let mut ntids: Vec<_> = self
+            .fields()?
+            .iter()
+            .filter(|(_, _, name)| matches_wildcard(name, field_name))
+            .map(|(ntid, _, _)| *ntid)
+            .collect();
+        ntids.dedup();
+
+        let mut ids = String::new();
+        ids_to_string(&mut ids, &ntids);
+        write!(self.sql, "n.mid in {}", ids).unwrap();
+
+        Ok(())
+    }
+
+    fn write_dupes(&mut self, ntid: NoteTypeID, field: Option<&str>, text: &str) -> Result<()> {
+        let ord = match field {
+            Some(field) => match self.dupe_field_ord(ntid, field)? {
+                Some(ord) => ord,
+                None => {
+                    write!(self.sql, "false").unwrap();
+                    return Ok(());
+                }
+            },
+            None => 0,
+        };
+
+        if ord == 0 {
+            // the first field has a checksum we can use
+            let text_nohtml = strip_html_preserving_image_filenames(text);
+            let csum = field_checksum(text_nohtml.as_ref());
+            write!(
+                self.sql,
+                "(n.mid = {} and n.csum = {} and n.sfld = ?)",
+                ntid, csum
+            )
+            .unwrap();
+        } else {
+            write!(
+                self.sql,
+                "(n.mid = {} and field_at_index(n.flds, {}) = ?)",
+                ntid, ord
+            )
+            .unwrap();
+        }
         self.args.push(text.to_string());
+
+        Ok(())
+    }
+
+    /// The ordinal of a field provided by name or 1-based number, if the
+    /// note type has such a field.
+    fn dupe_field_ord(&mut self, ntid: NoteTypeID, field: &str) -> Result<Option<u32>> {
+        let nt = match self.col.get_notetype(ntid)? {
+            Some(nt) => nt,
+            None => return Ok(None),
+        };
+        if let Some(ord) = nt.get_field_ord(field) {
+            return Ok(Some(ord as u32));
+        }
+        Ok(match field.parse::<u32>() {
+            Ok(n) if n > 0 && (n as usize) <= nt.fields.len() => Some(n - 1),
+            _ => None,
+        })
     }
 
     fn write_added(&mut self, days: u32) -> Result<()> {
-        let timing = self.col.timing_today()?;
-        let cutoff = (timing.next_day_at - (86_400 * (days as i64))) * 1_000;
+        let cutoff = self.days_ago_cutoff(days)?.as_millis();
         write!(self.sql, "c.id > {}", cutoff).unwrap();
         Ok(())
     }
 
     fn write_edited(&mut self, days: u32) -> Result<()> {
-        let timing = self.col.timing_today()?;
-        let cutoff = timing.next_day_at - (86_400 * (days as i64));
+        let cutoff = self.days_ago_cutoff(days)?;
         write!(self.sql, "n.mod > {}", cutoff).unwrap();
         Ok(())
     }
 
+    /// The start of the day `days` days ago, where today is day 1.
+    fn days_ago_cutoff(&self, days: u32) -> Result<TimestampSecs> {
+        let timing = self.col.timing_today()?;
+        Ok(TimestampSecs(timing.next_day_at).adding_secs(-86_400 * days as i64))
+    }
+
+    /// Compare a timestamp column against the start and end of the dates,
+    /// using the collection's day rollover. `scale` converts seconds into
+    /// the units of the column.
+    fn write_date_range(&mut self, column: &str, range: &DateRange, scale: i64) -> Result<()> {
+        let sql = self.date_range_sql(column, range, scale)?;
+        self.sql.push_str(&sql);
+        Ok(())
+    }
+
+    fn date_range_sql(&self, column: &str, range: &DateRange, scale: i64) -> Result<String> {
+        let mut clauses = vec![];
+        if let Some(start) = range.start {
+            // days are not always 24 hours long
+            let start = self.end_of_day(start.pred())?;
+            clauses.push(format!("{} >= {}", column, start * scale));
+        }
+        if let Some(end) = range.end {
+            let end = self.end_of_day(end)?;
+            clauses.push(format!("{} < {}", column, end * scale));
+        }
+        Ok(clauses.join(" and "))
+    }
+
+    /// Cards whose first or last review, depending on whether `aggregate`
+    /// is min or max, happened in the last `days` days.
+    fn write_review_in_days(&mut self, aggregate: &str, days: u32) -> Result<()> {
+        let cutoff = self.days_ago_cutoff(days)?.as_millis();
+        write!(
+            self.sql,
+            "c.id in (select cid from revlog group by cid having {}(id) > {})",
+            aggregate, cutoff
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    fn write_review_between(&mut self, aggregate: &str, range: &DateRange) -> Result<()> {
+        let cond = self.date_range_sql(&format!("{}(id)", aggregate), range, 1_000)?;
+        write!(
+            self.sql,
+            "c.id in (select cid from revlog group by cid having {})",
+            cond
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    /// The timestamp of the rollover that ends the provided day.
+    fn end_of_day(&self, date: NaiveDate) -> Result<i64> {
+        // the last moment of the calendar day always falls within the
+        // scheduling day of the same date, regardless of the rollover hour
+        let stamp = self
+            .col
+            .local_offset()
+            .from_local_datetime(&date.and_hms(23, 59, 59))
+            .unwrap()
+            .timestamp();
+        Ok(self
+            .col
+            .timing_for_timestamp(TimestampSecs(stamp))?
+            .next_day_at)
+    }
+
+    /// Cards whose first review (ignoring manual rescheduling) happened
+    /// in the last `days` days.
+    fn write_introduced(&mut self, days: u32) -> Result<()> {
+        let cutoff = self.days_ago_cutoff(days)?.as_millis();
+        write!(
+            self.sql,
+            concat!(
+                "((select min(id) > {cutoff} from revlog where cid = c.id and ease != 0)",
+                // redundant, but allows the revlog id index to narrow the search
+                " and c.id in (select cid from revlog where id > {cutoff}))"
+            ),
+            cutoff = cutoff,
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    /// Cards with a manual rescheduling entry in the review log, which is
+    /// recorded with an ease of 0.
+    fn write_rescheduled(&mut self, days: u32) -> Result<()> {
+        let cutoff = self.days_ago_cutoff(days)?.as_millis();
+        write!(
+            self.sql,
+            "c.id in (select cid from revlog where id > {} and ease = 0)",
+            cutoff
+        )
+        .unwrap();
+        Ok(())
+    }
+
     fn write_regex(&mut self, word: &str) {
-        self.sql.push_str("n.flds regexp ?");
+        self.sql.push_str("regexp_fields(?, n.flds)");
         self.args.push(format!(r"(?i){}", word));
     }
 
@@ -449,6 +1000,104 @@ impl SqlWriter<'_> {
     }
 }
 
+/// True if text contains only words that can be passed to the full
+/// text index without escaping.
+fn is_plain_text(text: &str) -> bool {
+    text.chars().any(char::is_alphanumeric) && text.chars().all(|c| c.is_alphanumeric() || c == ' ')
+}
+
+/// An SQL expression for the provided column without combining characters,
+/// and with its case folded if fold is true.
+fn no_combining_sql(column: &str, fold: bool) -> String {
+    let sql = format!("coalesce(without_combining({c}), {c})", c = column);
+    if fold {
+        format!("fold_case({})", sql)
+    } else {
+        sql
+    }
+}
+
+/// Convert user text into a pattern for use with `like ? escape '\'`.
+/// * matches any sequence of characters, and % and _ match themselves.
+/// A backslash before *, %, _ or : matches the character literally.
+fn to_sql_like(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ '*') | Some(c @ ':') => out.push(c),
+                Some(c @ '%') | Some(c @ '_') => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                Some('\\') | None => out.push_str(r"\\"),
+                Some(c) => {
+                    out.push_str(r"\\");
+                    out.push(c);
+                }
+            },
+            '*' => out.push('%'),
+            '%' | '_' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// For states that only depend on a single column, the column and the
+/// values it may have.
+fn state_column_values(state: &StateKind) -> Option<(&'static str, Vec<i8>)> {
+    Some(match state {
+        StateKind::New => ("c.type", vec![CardType::New as i8]),
+        StateKind::Review => (
+            "c.type",
+            vec![CardType::Review as i8, CardType::Relearn as i8],
+        ),
+        StateKind::Learning => (
+            "c.queue",
+            vec![CardQueue::Learn as i8, CardQueue::DayLearn as i8],
+        ),
+        StateKind::Buried => (
+            "c.queue",
+            vec![CardQueue::SchedBuried as i8, CardQueue::UserBuried as i8],
+        ),
+        StateKind::BuriedSibling => ("c.queue", vec![CardQueue::SchedBuried as i8]),
+        StateKind::BuriedManually => ("c.queue", vec![CardQueue::UserBuried as i8]),
+        StateKind::Suspended => ("c.queue", vec![CardQueue::Suspended as i8]),
+        StateKind::Preview => ("c.queue", vec![CardQueue::PreviewRepeat as i8]),
+        StateKind::DayLearning => ("c.queue", vec![CardQueue::DayLearn as i8]),
+        StateKind::Due => return None,
+    })
+}
+
+/// Convert user text into a regex, with the same wildcard and escaping
+/// rules as to_sql_like().
+pub(super) fn to_re(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ '*') | Some(c @ '%') | Some(c @ '_') | Some(c @ ':') => {
+                    out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])))
+                }
+                Some('\\') | None => out.push_str(r"\\"),
+                Some(c) => {
+                    out.push_str(r"\\");
+                    out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+                }
+            },
+            '*' => out.push_str(".*"),
+            c => out.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    out
+}
+
 /// Convert a string with _, % or * characters into a regex.
 /// If string contains no globbing characters, return None.
 fn glob_to_re(glob: &str) -> Option<String> {
@@ -459,7 +1108,7 @@ fn glob_to_re(glob: &str) -> Option<String> {
 }
 
 /// Escape text, converting glob characters to regex syntax, then return.
-fn text_to_re(glob: &str) -> String {
+pub(super) fn text_to_re(glob: &str) -> String {
     lazy_static! {
         static ref ESCAPED: Regex = Regex::new(r"(\\\\)?\\\*").unwrap();
         static ref GLOB: Regex = Regex::new(r"(\\\\)?[_%]").unwrap();
@@ -534,26 +1183,44 @@ impl SearchNode<'_> {
     fn required_table(&self) -> RequiredTable {
         match self {
             SearchNode::AddedInDays(_) => RequiredTable::Cards,
+            SearchNode::AddedBetween(_) => RequiredTable::Cards,
+            SearchNode::IntroducedInDays(_) => RequiredTable::Cards,
+            SearchNode::RescheduledInDays(_) => RequiredTable::Cards,
+            SearchNode::FirstReviewInDays(_) => RequiredTable::Cards,
+            SearchNode::FirstReviewBetween(_) => RequiredTable::Cards,
+            SearchNode::LastReviewInDays(_) => RequiredTable::Cards,
+            SearchNode::LastReviewBetween(_) => RequiredTable::Cards,
             SearchNode::Deck(_) => RequiredTable::Cards,
+            SearchNode::DeckExact(_) => RequiredTable::Cards,
+            SearchNode::DeckID(_) => RequiredTable::Cards,
             SearchNode::Rated { .. } => RequiredTable::Cards,
+            SearchNode::AddedDaysAgo { .. } => RequiredTable::Cards,
+            SearchNode::RatedDaysAgo { .. } => RequiredTable::Cards,
             SearchNode::State(_) => RequiredTable::Cards,
             SearchNode::Flag(_) => RequiredTable::Cards,
+            SearchNode::Flags(_) => RequiredTable::Cards,
+            SearchNode::AnyFlag => RequiredTable::Cards,
             SearchNode::CardIDs(_) => RequiredTable::Cards,
             SearchNode::Property { .. } => RequiredTable::Cards,
 
             SearchNode::UnqualifiedText(_) => RequiredTable::Notes,
             SearchNode::SingleField { .. } => RequiredTable::Notes,
+            SearchNode::HasField(_) => RequiredTable::Notes,
             SearchNode::Tag(_) => RequiredTable::Notes,
             SearchNode::Duplicates { .. } => RequiredTable::Notes,
             SearchNode::Regex(_) => RequiredTable::Notes,
             SearchNode::NoCombining(_) => RequiredTable::Notes,
             SearchNode::WordBoundary(_) => RequiredTable::Notes,
-            SearchNode::NoteTypeID(_) => RequiredTable::Notes,
+            SearchNode::NoteTypeIDs(_) => RequiredTable::Notes,
             SearchNode::NoteType(_) => RequiredTable::Notes,
             SearchNode::EditedInDays(_) => RequiredTable::Notes,
+            SearchNode::EditedBetween(_) => RequiredTable::Notes,
 
             SearchNode::NoteIDs(_) => RequiredTable::CardsOrNotes,
+            SearchNode::NoteGUID(_) => RequiredTable::Notes,
             SearchNode::WholeCollection => RequiredTable::CardsOrNotes,
+            // the contents aren't known until the search is written
+            SearchNode::Preset(_) => RequiredTable::CardsAndNotes,
 
             SearchNode::CardTemplate(_) => RequiredTable::CardsAndNotes,
         }
@@ -564,11 +1231,13 @@ impl SearchNode<'_> {
 mod test {
     use super::*;
     use crate::{
-        collection::{open_collection, Collection},
+        collection::{open_collection, open_test_collection, Collection},
+        decks::DeckID,
         i18n::I18n,
         log,
         types::Usn,
     };
+    use rusqlite::NO_PARAMS;
     use std::{fs, path::PathBuf};
     use tempfile::tempdir;
 
@@ -577,7 +1246,7 @@ mod test {
     // shortcut
     fn s(req: &mut Collection, search: &str) -> (String, Vec<String>) {
         let node = Node::Group(parse(search).unwrap());
-        let mut writer = SqlWriter::new(req);
+        let mut writer = SqlWriter::new(req).unwrap();
         writer.table = RequiredTable::Notes.combine(node.required_table());
         writer.write_node_to_sql(&node).unwrap();
         (writer.sql, writer.args)
@@ -612,25 +1281,83 @@ mod test {
                 vec!["%test%".into()]
             )
         );
-        assert_eq!(s(ctx, "te%st").1, vec!["%te%st%".to_string()]);
-        // user should be able to escape sql wildcards
-        assert_eq!(s(ctx, r#"te\%s\_t"#).1, vec!["%te\\%s\\_t%".to_string()]);
+        // * is a wildcard, and sql wildcards are matched literally
+        assert_eq!(s(ctx, "te*st").1, vec!["%te%st%".to_string()]);
+        assert_eq!(s(ctx, "te%s_t").1, vec![r"%te\%s\_t%".to_string()]);
+        assert_eq!(s(ctx, r"te\*s\%t\\").1, vec![r"%te*s\%t\\%".to_string()]);
+        assert_eq!(s(ctx, r"a\:b").1, vec!["%a:b%".to_string()]);
 
         // qualified search
         assert_eq!(
             s(ctx, "front:te*st"),
             (
                 concat!(
-                    "(((n.mid = 1581236385344 and field_at_index(n.flds, 0) like ?1) or ",
-                    "(n.mid = 1581236385345 and field_at_index(n.flds, 0) like ?1) or ",
-                    "(n.mid = 1581236385346 and field_at_index(n.flds, 0) like ?1) or ",
-                    "(n.mid = 1581236385347 and field_at_index(n.flds, 0) like ?1)))"
+                    "(((n.mid = 1581236385344 and field_at_index(n.flds, 0) like ?1 escape '\\') or ",
+                    "(n.mid = 1581236385345 and field_at_index(n.flds, 0) like ?1 escape '\\') or ",
+                    "(n.mid = 1581236385346 and field_at_index(n.flds, 0) like ?1 escape '\\') or ",
+                    "(n.mid = 1581236385347 and field_at_index(n.flds, 0) like ?1 escape '\\')))"
                 )
                 .into(),
                 vec!["te%st".into()]
             )
         );
 
+        // empty fields
+        assert_eq!(
+            s(ctx, "front:"),
+            (
+                concat!(
+                    "(((n.mid = 1581236385344 and field_at_index(n.flds, 0) = '') or ",
+                    "(n.mid = 1581236385345 and field_at_index(n.flds, 0) = '') or ",
+                    "(n.mid = 1581236385346 and field_at_index(n.flds, 0) = '') or ",
+                    "(n.mid = 1581236385347 and field_at_index(n.flds, 0) = '')))"
+                )
+                .into(),
+                vec![]
+            )
+        );
+
+        // note types with a given field
+        assert_eq!(
+            s(ctx, "has-field:back*"),
+            (
+                "(n.mid in (1581236385345,1581236385346,1581236385347,1581236385344))".into(),
+                vec![]
+            )
+        );
+        assert_eq!(s(ctx, "has-field:missing").0, "(n.mid in ())");
+
+        // the case of non-ASCII text is folded by a function, as like
+        // only folds ASCII
+        assert_eq!(
+            s(ctx, "Über*"),
+            (
+                concat!(
+                    "((fold_case(cast(n.sfld as text)) like ?1 escape '\\' ",
+                    "or fold_case(n.flds) like ?1 escape '\\'))"
+                )
+                .into(),
+                vec!["%über%%".into()]
+            )
+        );
+        assert_eq!(s(ctx, "front:Über*").1, vec!["über%".to_string()]);
+        assert!(s(ctx, "front:Über*")
+            .0
+            .contains("fold_case(field_at_index(n.flds, 0)) like ?1"));
+
+        // field names can contain wildcards
+        assert_eq!(s(ctx, "fr*:te*st"), s(ctx, "front:te*st"));
+        assert_eq!(s(ctx, "*ONT:te*st"), s(ctx, "front:te*st"));
+        assert_eq!(s(ctx, "missing*:test"), ("(false)".into(), vec![]));
+
+        // field search ignoring combining characters
+        assert_eq!(s(ctx, "front:nc:über").1, vec!["uber".to_string()]);
+        assert!(s(ctx, "front:nc:über")
+            .0
+            .contains("coalesce(without_combining(field_at_index(n.flds, 0)), "));
+        assert_eq!(s(ctx, "front:nc:STRAẞE").1, vec!["strasse".to_string()]);
+        assert!(s(ctx, "nc:straße").0.contains("fold_case(coalesce("));
+
         // added
         let timing = ctx.timing_today().unwrap();
         assert_eq!(
@@ -638,6 +1365,67 @@ mod test {
             format!("(c.id > {})", (timing.next_day_at - (86_400 * 3)) * 1_000)
         );
 
+        // edited
+        assert_eq!(
+            s(ctx, "edited:3").0,
+            format!("(n.mod > {})", timing.next_day_at - (86_400 * 3))
+        );
+
+        // date ranges
+        let numbers = |sql: String| -> Vec<i64> {
+            sql.split(|c: char| !c.is_ascii_digit())
+                .filter_map(|n| n.parse().ok())
+                .collect()
+        };
+        let added = numbers(s(ctx, "added:2020-01-01..2020-01-02").0);
+        assert_eq!(added[1] - added[0], 86_400 * 2 * 1_000);
+        let edited = numbers(s(ctx, "edited:2020-01-01").0);
+        assert_eq!(edited[1] - edited[0], 86_400);
+        assert_eq!(added[0], edited[0] * 1_000);
+        assert_eq!(
+            s(ctx, "edited:..2020-01-01").0,
+            format!("(n.mod < {})", edited[1])
+        );
+
+        // manual rescheduling
+        assert_eq!(
+            s(ctx, "resched:7").0,
+            format!(
+                "(c.id in (select cid from revlog where id > {} and ease = 0))",
+                (timing.next_day_at - (86_400 * 7)) * 1_000
+            )
+        );
+
+        // first and last review
+        assert_eq!(
+            s(ctx, "lastreview:3").0,
+            format!(
+                "(c.id in (select cid from revlog group by cid having max(id) > {}))",
+                (timing.next_day_at - (86_400 * 3)) * 1_000
+            )
+        );
+        assert_eq!(
+            s(ctx, "firstreview:2020-01-01").0,
+            format!(
+                "(c.id in (select cid from revlog group by cid having min(id) >= {} and min(id) < {}))",
+                added[0],
+                added[0] + 86_400 * 1_000
+            )
+        );
+
+        // introduced
+        let cutoff = (timing.next_day_at - (86_400 * 3)) * 1_000;
+        assert_eq!(
+            s(ctx, "introduced:3").0,
+            format!(
+                concat!(
+                    "(((select min(id) > {cutoff} from revlog where cid = c.id and ease != 0)",
+                    " and c.id in (select cid from revlog where id > {cutoff})))"
+                ),
+                cutoff = cutoff
+            )
+        );
+
         // deck
         assert_eq!(
             s(ctx, "deck:default"),
@@ -654,6 +1442,23 @@ mod test {
         );
         assert_eq!(s(ctx, "deck:d*").1, vec!["(?i)^d.*($|\u{1f})".to_string()]);
         assert_eq!(s(ctx, "deck:filtered"), ("(c.odid != 0)".into(), vec![],));
+        assert_eq!(s(ctx, "deck!:a::b*").1, vec!["(?i)^a\x1fb.*$".to_string()]);
+        assert_eq!(s(ctx, "deck!:current").1, vec!["(?i)^Default$".to_string()]);
+        assert_eq!(
+            s(ctx, "did:1").0,
+            "((c.did in (1) or (c.odid != 0 and c.odid in (1))))"
+        );
+        // deck searches joined by OR are merged
+        assert_eq!(
+            s(ctx, "deck:default OR did:1 OR deck!:missing OR nid:5").0,
+            "((c.did in (1) or (c.odid != 0 and c.odid in (1))) or n.id in (5))"
+        );
+        assert_eq!(
+            s(ctx, "did:1 deck:default").0,
+            "((c.did in (1) or (c.odid != 0 and c.odid in (1))) and \
+             (c.did in (select id from decks where name regexp ?1) or (c.odid != 0 and \
+             c.odid in (select id from decks where name regexp ?1))))"
+        );
 
         // card
         assert_eq!(
@@ -665,14 +1470,24 @@ mod test {
         );
 
         // IDs
-        assert_eq!(s(ctx, "mid:3"), ("(n.mid = 3)".into(), vec![]));
+        assert_eq!(s(ctx, "mid:3"), ("(n.mid in (3))".into(), vec![]));
+        assert_eq!(s(ctx, "mid:3,4"), ("(n.mid in (4,3))".into(), vec![]));
         assert_eq!(s(ctx, "nid:3"), ("(n.id in (3))".into(), vec![]));
-        assert_eq!(s(ctx, "nid:3,4"), ("(n.id in (3,4))".into(), vec![]));
-        assert_eq!(s(ctx, "cid:3,4"), ("(c.id in (3,4))".into(), vec![]));
+        assert_eq!(s(ctx, "nid:3,4"), ("(n.id in (4,3))".into(), vec![]));
+        assert_eq!(
+            s(ctx, "guid:a*b"),
+            ("(n.guid = ?1)".into(), vec!["a*b".into()])
+        );
+        assert_eq!(s(ctx, "cid:3,4"), ("(c.id in (4,3))".into(), vec![]));
 
         // flags
         assert_eq!(s(ctx, "flag:2"), ("((c.flags & 7) == 2)".into(), vec![]));
         assert_eq!(s(ctx, "flag:0"), ("((c.flags & 7) == 0)".into(), vec![]));
+        assert_eq!(s(ctx, "flag:any"), ("((c.flags & 7) != 0)".into(), vec![]));
+        assert_eq!(
+            s(ctx, "flag:1,3"),
+            ("((c.flags & 7) in (1,3))".into(), vec![]),
+        );
 
         // dupes
         assert_eq!(
@@ -682,6 +1497,18 @@ mod test {
                 vec!["test".into()]
             )
         );
+        assert_eq!(
+            s(ctx, "dupe:1581236385344:back,test"),
+            (
+                "((n.mid = 1581236385344 and field_at_index(n.flds, 1) = ?))".into(),
+                vec!["test".into()]
+            )
+        );
+        assert_eq!(
+            s(ctx, "dupe:1581236385344:1,test"),
+            s(ctx, "dupe:1581236385344,test")
+        );
+        assert_eq!(s(ctx, "dupe:123:back,test"), ("(false)".into(), vec![]));
 
         // unregistered tag short circuits
         assert_eq!(s(ctx, r"tag:one"), ("(false)".into(), vec![]));
@@ -691,7 +1518,18 @@ mod test {
             .unwrap();
         assert_eq!(
             s(ctx, r"tag:one"),
-            ("(n.tags like ?)".into(), vec![r"% One %".into()])
+            (
+                "((n.tags like ? escape '\\' or n.tags like ? escape '\\'))".into(),
+                vec![r"% One %".into(), r"% One::%".into()]
+            )
+        );
+
+        // a parent that only exists through its children is matched too
+        ctx.transact(None, |col| col.register_tag("Two::Three", Usn(-1)))
+            .unwrap();
+        assert_eq!(
+            s(ctx, r"tag:two"),
+            ("(n.tags regexp ?)".into(), vec![r"(?i).* two::.*".into()])
         );
 
         // wildcards force a regexp search
@@ -699,7 +1537,14 @@ mod test {
             s(ctx, r"tag:o*n\*et%w\%oth_re\_e"),
             (
                 "(n.tags regexp ?)".into(),
-                vec![r"(?i).* o.*n\*et.*w%oth.re_e .*".into()]
+                vec![r"(?i).* o.*n\*et%w%oth_re_e(::| ).*".into()]
+            )
+        );
+        assert_eq!(
+            s(ctx, r"tag:two::*"),
+            (
+                "(n.tags regexp ?)".into(),
+                vec![r"(?i).* two::.*(::| ).*".into()]
             )
         );
         assert_eq!(s(ctx, "tag:none"), ("(n.tags = '')".into(), vec![]));
@@ -714,6 +1559,18 @@ mod test {
             s(ctx, "is:new").0,
             format!("(c.type = {})", CardType::New as i8)
         );
+        assert_eq!(
+            s(ctx, "is:buried-sibling").0,
+            format!("(c.queue = {})", CardQueue::SchedBuried as i8)
+        );
+        assert_eq!(
+            s(ctx, "is:buried-manually").0,
+            format!("(c.queue = {})", CardQueue::UserBuried as i8)
+        );
+        assert_eq!(
+            s(ctx, "is:day-learn").0,
+            format!("(c.queue = {})", CardQueue::DayLearn as i8)
+        );
 
         // rated
         assert_eq!(
@@ -727,13 +1584,85 @@ mod test {
             s(ctx, "rated:400:1").0,
             format!(
                 "(c.id in (select cid from revlog where id>{} and ease=1))",
-                (timing.next_day_at - (86_400 * 365)) * 1_000
+                (timing.next_day_at - (86_400 * 400)) * 1_000
+            )
+        );
+
+        let cutoff = |days: i64| (timing.next_day_at - 86_400 * days) * 1_000;
+        assert_eq!(
+            s(ctx, "rated:>7:3").0,
+            format!(
+                "(c.id in (select cid from revlog where id <= {} and ease=3))",
+                cutoff(7)
             )
         );
+        assert_eq!(s(ctx, "added:>=7").0, format!("(c.id <= {})", cutoff(6)));
+        assert_eq!(
+            s(ctx, "added:=2").0,
+            format!("((c.id > {} and c.id <= {}))", cutoff(2), cutoff(1))
+        );
+        assert_eq!(s(ctx, "added:<=2").0, format!("(c.id > {})", cutoff(2)));
+
+        // negated searches
+        assert_eq!(
+            s(ctx, "-is:suspended").0,
+            format!("(c.queue != {})", CardQueue::Suspended as i8)
+        );
+        assert_eq!(
+            s(ctx, "-is:buried").0,
+            format!(
+                "(c.queue not in ({},{}))",
+                CardQueue::SchedBuried as i8,
+                CardQueue::UserBuried as i8
+            )
+        );
+        assert_eq!(s(ctx, "-flag:any").0, "((c.flags & 7) = 0)");
+        assert_eq!(s(ctx, "-nid:3,4").0, "(n.id not in (4,3))");
+        assert!(s(ctx, "-is:due").0.starts_with("(not ("));
 
         // props
         assert_eq!(s(ctx, "prop:lapses=3").0, "(lapses = 3)".to_string());
         assert_eq!(s(ctx, "prop:ease>=2.5").0, "(factor >= 2500)".to_string());
+        assert_eq!(s(ctx, "prop:ease=2.55").0, "(factor = 2550)".to_string());
+        assert_eq!(
+            s(ctx, "prop:cd:n>1"),
+            (
+                concat!(
+                    "(coalesce((case when json_valid(c.data) then json_extract(c.data, ?1) end)",
+                    " > 1, 0))"
+                )
+                .into(),
+                vec!["$.cd.n".into()]
+            )
+        );
+        assert_eq!(
+            s(ctx, "prop:cd:s!=x"),
+            (
+                concat!(
+                    "(coalesce((case when json_valid(c.data) then json_extract(c.data, ?1) end)",
+                    " != ?2, 0))"
+                )
+                .into(),
+                vec!["$.cd.s".into(), "x".into()]
+            )
+        );
+        let node = Node::Search(SearchNode::Property {
+            operator: "= 0 or 1 =".into(),
+            kind: PropertyKind::Interval(1),
+        });
+        assert!(matches!(
+            SqlWriter::new(ctx)
+                .unwrap()
+                .build_cards_query(&node, RequiredTable::Cards),
+            Err(AnkiError::SearchError {
+                kind: SearchErrorKind::InvalidPropertyOperator,
+                ..
+            })
+        ));
+        assert_eq!(
+            s(ctx, "prop:pos<100").0,
+            "((c.type = 0 and due < 100))".to_string()
+        );
         assert_eq!(
             s(ctx, "prop:due!=-1").0,
             format!(
@@ -761,27 +1690,128 @@ mod test {
         // regex
         assert_eq!(
             s(ctx, r"re:\bone"),
-            ("(n.flds regexp ?)".into(), vec![r"(?i)\bone".into()])
+            (
+                "(regexp_fields(?, n.flds))".into(),
+                vec![r"(?i)\bone".into()]
+            )
         );
 
         // word boundary
         assert_eq!(
             s(ctx, r"w:foo"),
-            ("(n.flds regexp ?)".into(), vec![r"(?i)\bfoo\b".into()])
+            (
+                "(regexp_fields(?, n.flds))".into(),
+                vec![r"(?i)\bfoo\b".into()]
+            )
         );
         assert_eq!(
             s(ctx, r"w:*foo"),
-            ("(n.flds regexp ?)".into(), vec![r"(?i)\b.*foo\b".into()])
+            (
+                "(regexp_fields(?, n.flds))".into(),
+                vec![r"(?i)\b.*foo\b".into()]
+            )
         );
 
         assert_eq!(
             s(ctx, r"w:*fo_o*"),
-            ("(n.flds regexp ?)".into(), vec![r"(?i)\b.*fo.o.*\b".into()])
+            (
+                "(regexp_fields(?, n.flds))".into(),
+                vec![r"(?i)\b.*fo.o.*\b".into()]
+            )
         );
 
         Ok(())
     }
 
+    #[test]
+    fn full_text_search() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields[0] = "hello world".into();
+        col.add_note(&mut note, DeckID(1))?;
+        assert_eq!(col.search_notes("orld")?, vec![note.id]);
+
+        // the cached state is updated when the index is created
+        col.set_full_text_search(true)?;
+        assert_eq!(
+            s(&mut col, "hel"),
+            (
+                "((n.id in (select rowid from notes_fts where notes_fts match ?1)))".into(),
+                vec!["\"hel\"*".into()]
+            )
+        );
+        assert_eq!(col.search_notes("wor")?, vec![note.id]);
+        // only the start of words is matched
+        assert_eq!(col.search_notes("orld")?, vec![]);
+        // wildcards fall back on a normal search
+        assert_eq!(s(&mut col, "w*rld").1, vec!["%w%rld%".to_string()]);
+
+        // edits are reflected in the index
+        note.fields[0] = "goodbye".into();
+        col.update_note(&mut note)?;
+        assert_eq!(col.search_notes("hello")?, vec![]);
+        assert_eq!(col.search_notes("good")?, vec![note.id]);
+
+        col.set_full_text_search(false)?;
+        assert_eq!(col.search_notes("oodby")?, vec![note.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn field_searches_use_indexes() -> Result<()> {
+        let mut col = open_test_collection();
+        let ntid = col.get_notetype_by_name("Basic")?.unwrap().id;
+        let mut plan = |search: &str| -> Result<String> {
+            let sql = format!(
+                "explain query plan select c.id from cards c, notes n where c.nid = n.id and {}",
+                s(&mut col, search).0
+            );
+            let mut stmt = col.storage.db.prepare(&sql)?;
+            let rows: Vec<String> = stmt
+                .query_map(NO_PARAMS, |row| row.get(3))?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(rows.join("\n"))
+        };
+
+        assert!(plan("front:test")?.contains("idx_notes_mid"));
+        assert!(plan(&format!("dupe:{},test", ntid))?.contains("ix_notes_csum"));
+        assert!(plan(&format!("dupe:{}:back,test", ntid))?.contains("idx_notes_mid"));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_vendor = "apple")]
+    /// On Linux, TZ needs to be set prior to the process being started to take effect,
+    /// so we limit this test to Macs.
+    fn date_ranges_across_dst() -> Result<()> {
+        use crate::config::{ConfigKey, SchedulerVersion};
+        // clocks go forward on 2021-03-28 and back on 2021-10-31 at 2/3am
+        std::env::set_var("TZ", "Europe/Berlin");
+        let mut col = open_test_collection();
+        col.set_config(ConfigKey::SchedulerVersion, &SchedulerVersion::V2)?;
+        col.set_v2_rollover(4)?;
+        let mut range = |search: &str| -> (i64, i64) {
+            let sql = s(&mut col, search).0;
+            let numbers: Vec<i64> = sql
+                .split(|c: char| !c.is_ascii_digit())
+                .filter_map(|n| n.parse().ok())
+                .collect();
+            (numbers[0], numbers[1])
+        };
+
+        let (start, end) = range("edited:2021-03-27");
+        assert_eq!(end - start, 23 * 3_600);
+        assert_eq!(range("edited:2021-03-28").0, end);
+        let (start, end) = range("edited:2021-10-30");
+        assert_eq!(end - start, 25 * 3_600);
+        assert_eq!(range("edited:2021-10-31").0, end);
+
+        Ok(())
+    }
+
     #[test]
     fn required_table() {
         assert_eq!(
@@ -0,0 +1,370 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::parser::{
+    parse, DateRange, FieldSearchMode, Node, PropertyKind, SearchNode, StateKind, TemplateKind,
+};
+use crate::{
+    err::{AnkiError, Result},
+    notetype::NoteTypeID,
+};
+use chrono::NaiveDate;
+use itertools::Itertools;
+use std::{fmt::Write, mem, slice};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BoolSeparator {
+    And,
+    Or,
+}
+
+/// Join an additional search onto an existing one, eg to narrow the current
+/// search down to a deck clicked on in the sidebar.
+pub fn concatenate_searches(
+    sep: BoolSeparator,
+    existing: &str,
+    additional: &str,
+) -> Result<String> {
+    let sep = match sep {
+        BoolSeparator::And => Node::And,
+        BoolSeparator::Or => Node::Or,
+    };
+    Ok(write_nodes(&concatenate_nodes(
+        sep,
+        parse(existing)?,
+        parse(additional)?,
+    )))
+}
+
+/// Replace any terms in an existing search that are of the same kind as
+/// the replacement, eg swapping one deck: term for another. If the existing
+/// search has no such terms, the replacement is added to it.
+pub fn replace_search_node(existing: &str, replacement: &str) -> Result<String> {
+    let mut existing = parse(existing)?;
+    let mut nodes = parse(replacement)?;
+    let replacement = match (nodes.pop(), nodes.is_empty()) {
+        (Some(Node::Search(node)), true) => node,
+        _ => {
+            return Err(AnkiError::invalid_input(
+                "replacement must be a single search term",
+            ))
+        }
+    };
+    if !replace_matching(&mut existing, &replacement) {
+        existing = concatenate_nodes(Node::And, existing, vec![Node::Search(replacement)]);
+    }
+    Ok(write_nodes(&existing))
+}
+
+fn concatenate_nodes<'a>(
+    sep: Node<'a>,
+    existing: Vec<Node<'a>>,
+    additional: Vec<Node<'a>>,
+) -> Vec<Node<'a>> {
+    if existing == [Node::Search(SearchNode::WholeCollection)] {
+        additional
+    } else {
+        vec![group(existing), sep, group(additional)]
+    }
+}
+
+/// Replace nodes of the same kind as replacement, returning true if any
+/// were found.
+fn replace_matching<'a>(nodes: &mut [Node<'a>], replacement: &SearchNode<'a>) -> bool {
+    let mut found = false;
+    for node in nodes {
+        found |= match node {
+            Node::Not(node) => replace_matching(slice::from_mut(node.as_mut()), replacement),
+            Node::Group(nodes) => replace_matching(nodes, replacement),
+            Node::Search(search) if mem::discriminant(search) == mem::discriminant(replacement) => {
+                *search = replacement.clone();
+                true
+            }
+            _ => false,
+        };
+    }
+    found
+}
+
+/// Wrap multiple nodes in a group, so they can be combined with other nodes.
+pub(super) fn group(mut nodes: Vec<Node>) -> Node {
+    if nodes.len() == 1 {
+        nodes.pop().unwrap()
+    } else {
+        Node::Group(nodes)
+    }
+}
+
+/// Convert the provided nodes back into a search string. The output
+/// parses to the same nodes, but is not necessarily identical to the
+/// original input, as terms are quoted and booleans are made explicit.
+pub(super) fn write_nodes(nodes: &[Node]) -> String {
+    nodes.iter().map(write_node).collect()
+}
+
+fn write_node(node: &Node) -> String {
+    match node {
+        Node::And => " AND ".into(),
+        Node::Or => " OR ".into(),
+        Node::Not(node) => format!("-{}", write_node(node)),
+        Node::Group(nodes) => format!("({})", write_nodes(nodes)),
+        Node::Search(node) => write_search_node(node),
+    }
+}
+
+fn write_search_node(node: &SearchNode) -> String {
+    use SearchNode::*;
+    match node {
+        UnqualifiedText(text) => quote(text),
+        SingleField { field, text, mode } => write_single_field(field, text, *mode),
+        HasField(field) => quote(&format!("has-field:{}", field)),
+        AddedInDays(days) => format!("added:{}", days),
+        AddedBetween(range) => format!("added:{}", write_date_range(range)),
+        EditedInDays(days) => format!("edited:{}", days),
+        EditedBetween(range) => format!("edited:{}", write_date_range(range)),
+        IntroducedInDays(days) => format!("introduced:{}", days),
+        RescheduledInDays(days) => format!("resched:{}", days),
+        FirstReviewInDays(days) => format!("firstreview:{}", days),
+        FirstReviewBetween(range) => format!("firstreview:{}", write_date_range(range)),
+        LastReviewInDays(days) => format!("lastreview:{}", days),
+        LastReviewBetween(range) => format!("lastreview:{}", write_date_range(range)),
+        CardTemplate(template) => write_template(template),
+        Deck(deck) => quote(&format!("deck:{}", deck)),
+        DeckExact(deck) => quote(&format!("deck!:{}", deck)),
+        DeckID(did) => format!("did:{}", did),
+        NoteTypeIDs(ntids) => format!("mid:{}", ntids.iter().join(",")),
+        NoteType(notetype) => quote(&format!("note:{}", notetype)),
+        Rated { days, ease } => write_rated("", *days, *ease),
+        AddedDaysAgo { operator, days } => format!("added:{}{}", operator, days),
+        RatedDaysAgo {
+            operator,
+            days,
+            ease,
+        } => write_rated(operator, *days, *ease),
+        Tag(tag) => quote(&format!("tag:{}", tag)),
+        Duplicates {
+            note_type_id,
+            field,
+            text,
+        } => write_dupes(*note_type_id, field.as_deref(), text),
+        State(state) => write_state(state),
+        Flag(flag) => format!("flag:{}", flag),
+        Flags(flags) => format!("flag:{}", flags.iter().join(",")),
+        AnyFlag => "flag:any".into(),
+        NoteIDs(nids) => format!("nid:{}", nids.iter().join(",")),
+        NoteGUID(guid) => quote(&format!("guid:{}", guid)),
+        CardIDs(cids) => format!("cid:{}", cids.iter().join(",")),
+        Property { operator, kind } => write_property(operator, kind),
+        WholeCollection => "deck:*".into(),
+        Regex(re) => quote(&format!("re:{}", re)),
+        NoCombining(text) => quote(&format!("nc:{}", text)),
+        WordBoundary(text) => quote(&format!("w:{}", text)),
+        Preset(name) => quote(&format!("preset:{}", name)),
+    }
+}
+
+/// Wrap text in double quotes, escaping any quotes it contains. Escapes
+/// like \* are kept, but a backslash that would otherwise escape a quote is
+/// doubled, which searches treat the same as a single literal backslash.
+fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    let mut backslashes = 0;
+    for c in text.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                if backslashes % 2 == 1 {
+                    out.push('\\');
+                }
+                out.push('\\');
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+        out.push(c);
+    }
+    if backslashes % 2 == 1 {
+        out.push('\\');
+    }
+    out.push('"');
+    out
+}
+
+fn write_single_field(field: &str, text: &str, mode: FieldSearchMode) -> String {
+    let prefix = match mode {
+        FieldSearchMode::Normal => "",
+        FieldSearchMode::Regex => "re:",
+        FieldSearchMode::NoCombining => "nc:",
+    };
+    quote(&format!("{}:{}{}", field, prefix, text))
+}
+
+fn write_dupes(ntid: NoteTypeID, field: Option<&str>, text: &str) -> String {
+    match field {
+        Some(field) => quote(&format!("dupe:{}:{},{}", ntid, field, text)),
+        None => quote(&format!("dupe:{},{}", ntid, text)),
+    }
+}
+
+fn write_template(template: &TemplateKind) -> String {
+    match template {
+        TemplateKind::Ordinal(ord) => format!("card:{}", ord + 1),
+        TemplateKind::Name(name) => quote(&format!("card:{}", name)),
+    }
+}
+
+fn write_date_range(range: &DateRange) -> String {
+    let date = |date: Option<NaiveDate>| {
+        date.map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    };
+    if range.start == range.end {
+        date(range.start)
+    } else {
+        format!("{}..{}", date(range.start), date(range.end))
+    }
+}
+
+fn write_rated(operator: &str, days: u32, ease: Option<u8>) -> String {
+    let mut text = format!("rated:{}{}", operator, days);
+    if let Some(ease) = ease {
+        write!(text, ":{}", ease).unwrap();
+    }
+    text
+}
+
+fn write_state(state: &StateKind) -> String {
+    use StateKind::*;
+    format!(
+        "is:{}",
+        match state {
+            New => "new",
+            Review => "review",
+            Learning => "learn",
+            Due => "due",
+            Buried => "buried",
+            BuriedSibling => "buried-sibling",
+            BuriedManually => "buried-manually",
+            Suspended => "suspended",
+            Preview => "preview",
+            DayLearning => "day-learn",
+        }
+    )
+}
+
+fn write_property(operator: &str, kind: &PropertyKind) -> String {
+    use PropertyKind::*;
+    match kind {
+        Due(days) => format!("prop:due{}{}", operator, days),
+        Interval(days) => format!("prop:ivl{}{}", operator, days),
+        Reps(reps) => format!("prop:reps{}{}", operator, reps),
+        Lapses(lapses) => format!("prop:lapses{}{}", operator, lapses),
+        Ease(ease) => format!("prop:ease{}{}", operator, ease),
+        Position(pos) => format!("prop:pos{}{}", operator, pos),
+        CustomDataNumber { key, value } => format!("prop:cd:{}{}{}", key, operator, value),
+        CustomDataString { key, value } => quote(&format!("prop:cd:{}{}{}", key, operator, value)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parser::parse;
+    use super::*;
+    use crate::err::Result;
+
+    /// Parse the input, write it back out, and check that it parses to the
+    /// same nodes.
+    fn normalize(input: &str) -> Result<String> {
+        let nodes = parse(input)?;
+        let output = write_nodes(&nodes);
+        assert_eq!(parse(&output)?, nodes);
+        Ok(output)
+    }
+
+    #[test]
+    fn writing() -> Result<()> {
+        assert_eq!(normalize("")?, "deck:*");
+        assert_eq!(normalize("dog cat")?, r#""dog" AND "cat""#);
+        assert_eq!(
+            normalize("(dog or cat) -tag:foo")?,
+            r#"("dog" OR "cat") AND -"tag:foo""#
+        );
+        assert_eq!(normalize(r#"deck:"a b""#)?, r#""deck:a b""#);
+        assert_eq!(
+            normalize(r#""a \"quoted\" word""#)?,
+            r#""a \"quoted\" word""#
+        );
+        // a trailing backslash can't escape the closing quote
+        assert_eq!(normalize(r"a\")?, r#""a\\""#);
+        assert_eq!(normalize(r#""a\\""#)?, r#""a\\""#);
+        assert_eq!(normalize(r"a\* b\_")?, r#""a\*" AND "b\_""#);
+        assert_eq!(normalize("front:re:^a")?, r#""front:re:^a""#);
+        assert_eq!(normalize("front:nc:b")?, r#""front:nc:b""#);
+        assert_eq!(normalize("front:")?, r#""front:""#);
+        assert_eq!(normalize("has-field:front")?, r#""has-field:front""#);
+        assert_eq!(normalize("card:2")?, "card:2");
+        assert_eq!(normalize("card:Reverse")?, r#""card:Reverse""#);
+        assert_eq!(normalize("rated:3:1 rated:2")?, "rated:3:1 AND rated:2");
+        assert_eq!(
+            normalize("rated:>3:1 added:<=2")?,
+            "rated:>3:1 AND added:<=2"
+        );
+        assert_eq!(normalize("is:learn")?, "is:learn");
+        assert_eq!(normalize("is:buried-manually")?, "is:buried-manually");
+        assert_eq!(normalize("prop:ease>=2.5")?, "prop:ease>=2.5");
+        assert_eq!(normalize("prop:cd:n<3")?, "prop:cd:n<3");
+        assert_eq!(normalize("prop:cd:s=a b")?, r#""prop:cd:s=a b""#);
+        assert_eq!(normalize("nid:1,2 cid:3")?, "nid:1,2 AND cid:3");
+        assert_eq!(normalize("did:1")?, "did:1");
+        assert_eq!(normalize("added:2020-01-01")?, "added:2020-01-01");
+        assert_eq!(
+            normalize("firstreview:3 lastreview:..2020-01-01")?,
+            "firstreview:3 AND lastreview:..2020-01-01"
+        );
+        assert_eq!(normalize("resched:1")?, "resched:1");
+        assert_eq!(
+            normalize("edited:2020-01-01..2020-02-01 added:..2020-02-01")?,
+            "edited:2020-01-01..2020-02-01 AND added:..2020-02-01"
+        );
+        assert_eq!(normalize("flag:any flag:1,2")?, "flag:any AND flag:1,2");
+        assert_eq!(normalize("dupe:123,text")?, r#""dupe:123,text""#);
+        assert_eq!(normalize("dupe:123:2,text")?, r#""dupe:123:2,text""#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concatenating() -> Result<()> {
+        use BoolSeparator::*;
+        assert_eq!(concatenate_searches(And, "", "deck:foo")?, r#""deck:foo""#);
+        assert_eq!(
+            concatenate_searches(And, "dog", "deck:foo")?,
+            r#""dog" AND "deck:foo""#
+        );
+        assert_eq!(
+            concatenate_searches(Or, "dog or cat", "-deck:foo")?,
+            r#"("dog" OR "cat") OR -"deck:foo""#
+        );
+        assert!(concatenate_searches(And, "dog", "(").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn replacing() -> Result<()> {
+        assert_eq!(
+            replace_search_node("deck:foo (dog or -deck:bar)", "deck:baz")?,
+            r#""deck:baz" AND ("dog" OR -"deck:baz")"#
+        );
+        assert_eq!(
+            replace_search_node("dog or cat", "deck:baz")?,
+            r#"("dog" OR "cat") AND "deck:baz""#
+        );
+        assert_eq!(replace_search_node("", "tag:x")?, r#""tag:x""#);
+        assert!(replace_search_node("dog", "deck:a deck:b").is_err());
+        assert!(replace_search_node("dog", "-deck:a").is_err());
+
+        Ok(())
+    }
+}
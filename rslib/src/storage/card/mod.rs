@@ -1,11 +1,14 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use super::{ids_to_string, FromRow};
 use crate::{
     card::{Card, CardID, CardQueue, CardType},
     decks::DeckID,
     err::Result,
     notes::NoteID,
+    notetype::NoteTypeID,
+    sched::bury::UnburyMode,
     timestamp::{TimestampMillis, TimestampSecs},
     types::Usn,
 };
@@ -14,7 +17,10 @@ use rusqlite::{
     types::{FromSql, FromSqlError, ValueRef},
     OptionalExtension, Row, NO_PARAMS,
 };
-use std::{collections::HashSet, convert::TryFrom, result};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+};
 
 impl FromSql for CardType {
     fn column_result(value: ValueRef<'_>) -> std::result::Result<Self, FromSqlError> {
@@ -36,87 +42,105 @@ impl FromSql for CardQueue {
     }
 }
 
-fn row_to_card(row: &Row) -> result::Result<Card, rusqlite::Error> {
-    Ok(Card {
-        id: row.get(0)?,
-        nid: row.get(1)?,
-        did: row.get(2)?,
-        ord: row.get(3)?,
-        mtime: row.get(4)?,
-        usn: row.get(5)?,
-        ctype: row.get(6)?,
-        queue: row.get(7)?,
-        due: row.get(8).ok().unwrap_or_default(),
-        ivl: row.get(9)?,
-        factor: row.get(10)?,
-        reps: row.get(11)?,
-        lapses: row.get(12)?,
-        left: row.get(13)?,
-        odue: row.get(14).ok().unwrap_or_default(),
-        odid: row.get(15)?,
-        flags: row.get(16)?,
-        data: row.get(17)?,
-    })
+impl FromRow for Card {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Card {
+            id: row.get(0)?,
+            nid: row.get(1)?,
+            did: row.get(2)?,
+            ord: row.get(3)?,
+            mtime: row.get(4)?,
+            usn: row.get(5)?,
+            ctype: row.get(6)?,
+            queue: row.get(7)?,
+            due: row.get(8).ok().unwrap_or_default(),
+            ivl: row.get(9)?,
+            factor: row.get(10)?,
+            reps: row.get(11)?,
+            lapses: row.get(12)?,
+            left: row.get(13)?,
+            odue: row.get(14).ok().unwrap_or_default(),
+            odid: row.get(15)?,
+            flags: row.get(16)?,
+            data: row.get(17)?,
+        })
+    }
 }
 
 impl super::SqliteStorage {
     pub fn get_card(&self, cid: CardID) -> Result<Option<Card>> {
         self.db
             .prepare_cached(concat!(include_str!("get_card.sql"), " where id = ?"))?
-            .query_row(params![cid], row_to_card)
-            .optional()
-            .map_err(Into::into)
+            .query_and_then(params![cid], Card::from_row)?
+            .next()
+            .transpose()
     }
 
     pub(crate) fn update_card(&self, card: &Card) -> Result<()> {
+        self.update_cards(std::slice::from_ref(card))
+    }
+
+    /// Update many cards with a single statement. Caller should create
+    /// transaction.
+    pub(crate) fn update_cards(&self, cards: &[Card]) -> Result<()> {
         let mut stmt = self.db.prepare_cached(include_str!("update_card.sql"))?;
-        stmt.execute(params![
-            card.nid,
-            card.did,
-            card.ord,
-            card.mtime,
-            card.usn,
-            card.ctype as u8,
-            card.queue as i8,
-            card.due,
-            card.ivl,
-            card.factor,
-            card.reps,
-            card.lapses,
-            card.left,
-            card.odue,
-            card.odid,
-            card.flags,
-            card.data,
-            card.id,
-        ])?;
+        for card in cards {
+            stmt.execute(params![
+                card.nid,
+                card.did,
+                card.ord,
+                card.mtime,
+                card.usn,
+                card.ctype as u8,
+                card.queue as i8,
+                card.due,
+                card.ivl,
+                card.factor,
+                card.reps,
+                card.lapses,
+                card.left,
+                card.odue,
+                card.odid,
+                card.flags,
+                card.data,
+                card.id,
+            ])?;
+        }
         Ok(())
     }
 
     pub(crate) fn add_card(&self, card: &mut Card) -> Result<()> {
+        self.add_cards(std::slice::from_mut(card))
+    }
+
+    /// Add many cards with a single statement, setting their IDs. Caller
+    /// should create transaction.
+    pub(crate) fn add_cards(&self, cards: &mut [Card]) -> Result<()> {
         let now = TimestampMillis::now().0;
         let mut stmt = self.db.prepare_cached(include_str!("add_card.sql"))?;
-        stmt.execute(params![
-            now,
-            card.nid,
-            card.did,
-            card.ord,
-            card.mtime,
-            card.usn,
-            card.ctype as u8,
-            card.queue as i8,
-            card.due,
-            card.ivl,
-            card.factor,
-            card.reps,
-            card.lapses,
-            card.left,
-            card.odue,
-            card.odid,
-            card.flags,
-            card.data,
-        ])?;
-        card.id = CardID(self.db.last_insert_rowid());
+        for card in cards {
+            stmt.execute(params![
+                now,
+                card.nid,
+                card.did,
+                card.ord,
+                card.mtime,
+                card.usn,
+                card.ctype as u8,
+                card.queue as i8,
+                card.due,
+                card.ivl,
+                card.factor,
+                card.reps,
+                card.lapses,
+                card.left,
+                card.odue,
+                card.odid,
+                card.flags,
+                card.data,
+            ])?;
+            card.id = CardID(self.db.last_insert_rowid());
+        }
         Ok(())
     }
 
@@ -194,22 +218,87 @@ impl super::SqliteStorage {
             .collect()
     }
 
+    /// Cards in the (day) learning queue, in any deck.
+    pub(crate) fn all_learning_cards(&self) -> Result<Vec<Card>> {
+        self.db
+            .prepare(concat!(
+                include_str!("get_card.sql"),
+                " where queue in (?, ?)"
+            ))?
+            .query_and_then(
+                params![CardQueue::Learn as i8, CardQueue::DayLearn as i8],
+                Card::from_row,
+            )?
+            .collect()
+    }
+
+    pub(crate) fn all_cards_in_decks(&self, dids: &[DeckID]) -> Result<Vec<Card>> {
+        let mut sql = concat!(include_str!("get_card.sql"), " where did in ").to_string();
+        ids_to_string(&mut sql, dids);
+        self.db
+            .prepare(&sql)?
+            .query_and_then(NO_PARAMS, Card::from_row)?
+            .collect()
+    }
+
     pub(crate) fn max_new_card_position(&self) -> Result<u32> {
         self.db
-            .prepare("select max(due)+1 from cards where type=0")?
+            .prepare("select coalesce(max(due) + 1, 0) from cards where type = 0")?
             .query_row(NO_PARAMS, |r| r.get(0))
             .map_err(Into::into)
     }
 
+    /// The number of review cards due on each day after today.
+    pub(crate) fn review_counts_by_day(&self, today: u32) -> Result<HashMap<i32, u32>> {
+        self.db
+            .prepare_cached(
+                "select due, count() from cards where queue in (?, ?) and due > ? group by due",
+            )?
+            .query_and_then(
+                params![CardQueue::Review as i8, CardQueue::DayLearn as i8, today],
+                |r| -> Result<_> { Ok((r.get(0)?, r.get(1)?)) },
+            )?
+            .collect()
+    }
+
+    /// New cards with a position at or after the provided one.
+    pub(crate) fn new_cards_positioned_from(&self, position: u32) -> Result<Vec<Card>> {
+        self.db
+            .prepare_cached(concat!(
+                include_str!("get_card.sql"),
+                " where type = ? and due >= ?"
+            ))?
+            .query_and_then(params![CardType::New as i8, position], Card::from_row)?
+            .collect()
+    }
+
+    /// Cards of the given note type that use one of the provided templates.
+    pub(crate) fn cards_of_notetype_templates(
+        &self,
+        ntid: NoteTypeID,
+        ords: &[u32],
+    ) -> Result<Vec<Card>> {
+        let mut sql = concat!(
+            include_str!("get_card.sql"),
+            " where nid in (select id from notes where mid = ?) and ord in "
+        )
+        .to_string();
+        ids_to_string(&mut sql, ords);
+        self.db
+            .prepare(&sql)?
+            .query_and_then(&[ntid], Card::from_row)?
+            .collect()
+    }
+
     pub(crate) fn get_card_by_ordinal(&self, nid: NoteID, ord: u16) -> Result<Option<Card>> {
         self.db
             .prepare_cached(concat!(
                 include_str!("get_card.sql"),
                 " where nid = ? and ord = ?"
             ))?
-            .query_row(params![nid, ord], row_to_card)
-            .optional()
-            .map_err(Into::into)
+            .query_and_then(params![nid, ord], Card::from_row)?
+            .next()
+            .transpose()
     }
 
     pub(crate) fn clear_pending_card_usns(&self) -> Result<()> {
@@ -231,7 +320,7 @@ impl super::SqliteStorage {
     pub(crate) fn all_cards_of_note(&self, nid: NoteID) -> Result<Vec<Card>> {
         self.db
             .prepare_cached(concat!(include_str!("get_card.sql"), " where nid = ?"))?
-            .query_and_then(&[nid], |r| row_to_card(r).map_err(Into::into))?
+            .query_and_then(&[nid], Card::from_row)?
             .collect()
     }
 
@@ -251,30 +340,121 @@ impl super::SqliteStorage {
         Ok(nids)
     }
 
+    /// Up to limit cards from the provided decks and queue, with a due
+    /// number less than due_before, in due order.
+    pub(crate) fn cards_in_queue_for_decks(
+        &self,
+        dids: &[DeckID],
+        queue: CardQueue,
+        due_before: i64,
+        limit: u32,
+    ) -> Result<Vec<Card>> {
+        let mut sql = concat!(include_str!("get_card.sql"), " where did in ").to_string();
+        ids_to_string(&mut sql, dids);
+        sql.push_str(" and queue = ? and due < ? order by due, ord limit ?");
+        self.db
+            .prepare_cached(&sql)?
+            .query_and_then(params![queue as i8, due_before, limit], Card::from_row)?
+            .collect()
+    }
+
+    /// Buried cards of the given kind, optionally limited to the provided
+    /// decks.
+    pub(crate) fn buried_cards(
+        &self,
+        mode: UnburyMode,
+        dids: Option<&[DeckID]>,
+    ) -> Result<Vec<Card>> {
+        let mut sql = concat!(include_str!("get_card.sql"), " where queue in ").to_string();
+        let queues: Vec<_> = mode.queues().iter().map(|queue| *queue as i8).collect();
+        ids_to_string(&mut sql, &queues);
+        if let Some(dids) = dids {
+            sql.push_str(" and did in ");
+            ids_to_string(&mut sql, dids);
+        }
+        self.db
+            .prepare(&sql)?
+            .query_and_then(NO_PARAMS, Card::from_row)?
+            .collect()
+    }
+
     pub(crate) fn all_searched_cards(&self) -> Result<Vec<Card>> {
         self.db
             .prepare_cached(concat!(
                 include_str!("get_card.sql"),
                 " where id in (select id from search_cids)"
             ))?
-            .query_and_then(NO_PARAMS, |r| row_to_card(r).map_err(Into::into))?
+            .query_and_then(NO_PARAMS, Card::from_row)?
             .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{card::Card, i18n::I18n, log, storage::SqliteStorage};
+    use crate::{
+        card::Card,
+        i18n::I18n,
+        log,
+        storage::{SqliteStorage, StorageOptions},
+    };
     use std::path::Path;
 
     #[test]
     fn add_card() {
         let i18n = I18n::new(&[""], "", log::terminal());
-        let storage = SqliteStorage::open_or_create(Path::new(":memory:"), &i18n, false).unwrap();
+        let storage = SqliteStorage::open_or_create(
+            Path::new(":memory:"),
+            &i18n,
+            false,
+            &StorageOptions::default(),
+        )
+        .unwrap();
         let mut card = Card::default();
         storage.add_card(&mut card).unwrap();
         let id1 = card.id;
         storage.add_card(&mut card).unwrap();
         assert_ne!(id1, card.id);
     }
+
+    #[test]
+    fn new_card_position() {
+        let i18n = I18n::new(&[""], "", log::terminal());
+        let storage = SqliteStorage::open_or_create(
+            Path::new(":memory:"),
+            &i18n,
+            false,
+            &StorageOptions::default(),
+        )
+        .unwrap();
+        // a collection without new cards starts from 0
+        assert_eq!(storage.max_new_card_position().unwrap(), 0);
+        let mut card = Card::default();
+        card.due = 5;
+        storage.add_card(&mut card).unwrap();
+        assert_eq!(storage.max_new_card_position().unwrap(), 6);
+    }
+
+    #[test]
+    fn batches() {
+        let i18n = I18n::new(&[""], "", log::terminal());
+        let storage = SqliteStorage::open_or_create(
+            Path::new(":memory:"),
+            &i18n,
+            false,
+            &StorageOptions::default(),
+        )
+        .unwrap();
+        let mut cards = vec![Card::default(); 3];
+        storage.add_cards(&mut cards).unwrap();
+        assert_ne!(cards[0].id, cards[1].id);
+        assert_ne!(cards[1].id, cards[2].id);
+
+        for (idx, card) in cards.iter_mut().enumerate() {
+            card.due = idx as i32 + 10;
+        }
+        storage.update_cards(&cards).unwrap();
+        for card in &cards {
+            assert_eq!(&storage.get_card(card.id).unwrap().unwrap(), card);
+        }
+    }
 }
@@ -3,12 +3,14 @@
 
 use super::SqliteStorage;
 use crate::{err::Result, timestamp::TimestampSecs, types::Usn};
-use rusqlite::{params, NO_PARAMS};
+use rusqlite::{params, OptionalExtension, NO_PARAMS};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 
 impl SqliteStorage {
+    /// Does nothing if the key already holds the same value, so that
+    /// unchanged settings are not marked as needing a sync.
     pub(crate) fn set_config_value<T: Serialize>(
         &self,
         key: &str,
@@ -17,6 +19,14 @@ impl SqliteStorage {
         mtime: TimestampSecs,
     ) -> Result<()> {
         let json = serde_json::to_vec(val)?;
+        let existing: Option<Vec<u8>> = self
+            .db
+            .prepare_cached(include_str!("get.sql"))?
+            .query_row(&[key], |row| row.get(0))
+            .optional()?;
+        if existing.as_ref() == Some(&json) {
+            return Ok(());
+        }
         self.db
             .prepare_cached(include_str!("add.sql"))?
             .execute(params![key, usn, mtime, &json])?;
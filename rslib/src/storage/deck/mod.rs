@@ -1,7 +1,7 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
-use super::SqliteStorage;
+use super::{FromRow, SqliteStorage};
 use crate::{
     card::CardID,
     card::CardQueue,
@@ -17,21 +17,23 @@ use rusqlite::{params, Row, NO_PARAMS};
 use std::collections::{HashMap, HashSet};
 use unicase::UniCase;
 
-fn row_to_deck(row: &Row) -> Result<Deck> {
-    let common = DeckCommon::decode(row.get_raw(4).as_blob()?)?;
-    let kind = DeckKindProto::decode(row.get_raw(5).as_blob()?)?;
-    let id = row.get(0)?;
-    Ok(Deck {
-        id,
-        name: row.get(1)?,
-        mtime_secs: row.get(2)?,
-        usn: row.get(3)?,
-        common,
-        kind: kind.kind.ok_or_else(|| AnkiError::DBError {
-            kind: DBErrorKind::MissingEntity,
-            info: format!("invalid deck kind: {}", id),
-        })?,
-    })
+impl FromRow for Deck {
+    fn from_row(row: &Row) -> Result<Self> {
+        let common = DeckCommon::decode(row.get_raw(4).as_blob()?)?;
+        let kind = DeckKindProto::decode(row.get_raw(5).as_blob()?)?;
+        let id = row.get(0)?;
+        Ok(Deck {
+            id,
+            name: row.get(1)?,
+            mtime_secs: row.get(2)?,
+            usn: row.get(3)?,
+            common,
+            kind: kind.kind.ok_or_else(|| AnkiError::DBError {
+                kind: DBErrorKind::MissingEntity,
+                info: format!("invalid deck kind: {}", id),
+            })?,
+        })
+    }
 }
 
 fn row_to_due_counts(row: &Row) -> Result<(DeckID, DueCounts)> {
@@ -54,7 +56,7 @@ impl SqliteStorage {
     pub(crate) fn get_deck(&self, did: DeckID) -> Result<Option<Deck>> {
         self.db
             .prepare_cached(concat!(include_str!("get_deck.sql"), " where id = ?"))?
-            .query_and_then(&[did], row_to_deck)?
+            .query_and_then(&[did], Deck::from_row)?
             .next()
             .transpose()
     }
@@ -62,7 +64,7 @@ impl SqliteStorage {
     pub(crate) fn get_all_decks(&self) -> Result<Vec<Deck>> {
         self.db
             .prepare(include_str!("get_deck.sql"))?
-            .query_and_then(NO_PARAMS, row_to_deck)?
+            .query_and_then(NO_PARAMS, Deck::from_row)?
             .collect()
     }
 
@@ -76,9 +78,18 @@ impl SqliteStorage {
             .collect()
     }
 
+    /// Get all deck names in their native (\x1f-separated) form, without
+    /// decoding the rest of each deck.
+    pub(crate) fn get_all_native_deck_names(&self) -> Result<Vec<(DeckID, String)>> {
+        self.db
+            .prepare_cached("select id, name from decks")?
+            .query_and_then(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect()
+    }
+
     pub(crate) fn get_deck_id(&self, machine_name: &str) -> Result<Option<DeckID>> {
         self.db
-            .prepare("select id from decks where name = ?")?
+            .prepare_cached("select id from decks where name = ?")?
             .query_and_then(&[machine_name], |row| row.get(0))?
             .next()
             .transpose()
@@ -150,7 +161,7 @@ impl SqliteStorage {
                 include_str!("get_deck.sql"),
                 " where name >= ? and name < ?"
             ))?
-            .query_and_then(&[prefix_start, prefix_end], row_to_deck)?
+            .query_and_then(&[prefix_start, prefix_end], Deck::from_row)?
             .collect()
     }
 
@@ -1,7 +1,7 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
-use super::SqliteStorage;
+use super::{FromRow, SqliteStorage};
 use crate::{
     deckconf::{DeckConf, DeckConfID, DeckConfSchema11, DeckConfigInner},
     err::Result,
@@ -11,29 +11,31 @@ use prost::Message;
 use rusqlite::{params, Row, NO_PARAMS};
 use std::collections::HashMap;
 
-fn row_to_deckconf(row: &Row) -> Result<DeckConf> {
-    let config = DeckConfigInner::decode(row.get_raw(4).as_blob()?)?;
-    Ok(DeckConf {
-        id: row.get(0)?,
-        name: row.get(1)?,
-        mtime_secs: row.get(2)?,
-        usn: row.get(3)?,
-        inner: config,
-    })
+impl FromRow for DeckConf {
+    fn from_row(row: &Row) -> Result<Self> {
+        let config = DeckConfigInner::decode(row.get_raw(4).as_blob()?)?;
+        Ok(DeckConf {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            mtime_secs: row.get(2)?,
+            usn: row.get(3)?,
+            inner: config,
+        })
+    }
 }
 
 impl SqliteStorage {
     pub(crate) fn all_deck_config(&self) -> Result<Vec<DeckConf>> {
         self.db
             .prepare_cached(include_str!("get.sql"))?
-            .query_and_then(NO_PARAMS, row_to_deckconf)?
+            .query_and_then(NO_PARAMS, DeckConf::from_row)?
             .collect()
     }
 
     pub(crate) fn get_deck_config(&self, dcid: DeckConfID) -> Result<Option<DeckConf>> {
         self.db
             .prepare_cached(concat!(include_str!("get.sql"), " where id = ?"))?
-            .query_and_then(params![dcid], row_to_deckconf)?
+            .query_and_then(params![dcid], DeckConf::from_row)?
             .next()
             .transpose()
     }
@@ -166,13 +168,25 @@ impl SqliteStorage {
         Ok(())
     }
 
-    // schema 15->11
+    // schema 15->14
 
     pub(super) fn downgrade_deck_conf_from_schema15(&self) -> Result<()> {
-        let allconf = self.all_deck_config()?;
-        let confmap: HashMap<DeckConfID, DeckConfSchema11> = allconf
+        let mut stmt = self
+            .db
+            .prepare_cached("update deck_config set config = ? where id = ?")?;
+        for conf in self.all_deck_config()? {
+            let conf: DeckConfSchema11 = conf.into();
+            stmt.execute(params![serde_json::to_vec(&conf)?, conf.id])?;
+        }
+        Ok(())
+    }
+
+    // schema 14->11
+
+    pub(super) fn downgrade_deck_conf_from_schema14(&self) -> Result<()> {
+        let confmap: HashMap<DeckConfID, DeckConfSchema11> = self
+            .all_deck_config_schema14()?
             .into_iter()
-            .map(|c| -> DeckConfSchema11 { c.into() })
             .map(|c| (c.id, c))
             .collect();
         self.db.execute(
@@ -0,0 +1,48 @@
+create virtual table notes_fts using fts5 (
+  flds,
+  content = 'notes',
+  content_rowid = 'id',
+  tokenize = 'unicode61 remove_diacritics 0'
+);
+create trigger notes_fts_insert
+after
+insert
+  on notes begin
+insert into notes_fts (rowid, flds)
+values
+  (new.id, new.flds);
+end;
+-- 'insert or replace' does not fire delete triggers
+create trigger notes_fts_replace before
+insert
+  on notes begin
+insert into notes_fts (notes_fts, rowid, flds)
+select
+  'delete',
+  id,
+  flds
+from notes
+where
+  id = new.id;
+end;
+create trigger notes_fts_delete
+after
+  delete on notes begin
+insert into notes_fts (notes_fts, rowid, flds)
+values
+  ('delete', old.id, old.flds);
+end;
+create trigger notes_fts_update
+after
+update
+  on notes begin
+insert into notes_fts (notes_fts, rowid, flds)
+values
+  ('delete', old.id, old.flds);
+insert into notes_fts (rowid, flds)
+values
+  (new.id, new.flds);
+end;
+insert into notes_fts (notes_fts)
+values
+  ('rebuild');
@@ -0,0 +1,5 @@
+drop trigger if exists notes_fts_insert;
+drop trigger if exists notes_fts_replace;
+drop trigger if exists notes_fts_delete;
+drop trigger if exists notes_fts_update;
+drop table if exists notes_fts;
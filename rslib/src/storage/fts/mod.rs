@@ -0,0 +1,33 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::SqliteStorage;
+use crate::err::Result;
+use rusqlite::NO_PARAMS;
+
+// An optional full text index of note fields. It is kept up to date by
+// triggers on the notes table, so notes modified by legacy code are
+// indexed as well.
+impl SqliteStorage {
+    pub(crate) fn full_text_index_exists(&self) -> Result<bool> {
+        self.db
+            .prepare_cached(
+                "select null from sqlite_master where type = 'table' and name = 'notes_fts'",
+            )?
+            .exists(NO_PARAMS)
+            .map_err(Into::into)
+    }
+
+    /// Create the index and populate it with existing notes.
+    pub(crate) fn create_full_text_index(&self) -> Result<()> {
+        if !self.full_text_index_exists()? {
+            self.db.execute_batch(include_str!("create.sql"))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn drop_full_text_index(&self) -> Result<()> {
+        self.db.execute_batch(include_str!("drop.sql"))?;
+        Ok(())
+    }
+}
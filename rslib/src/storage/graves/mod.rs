@@ -75,3 +75,38 @@ impl SqliteStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{collection::open_test_collection, decks::Deck};
+
+    #[test]
+    fn graves() -> Result<()> {
+        let mut col = open_test_collection();
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        let mut deck = Deck::new_normal();
+        deck.name = "deck".into();
+        col.add_or_update_deck(&mut deck)?;
+        col.add_note(&mut note, deck.id)?;
+        let cid = col.storage.all_cards_of_note(note.id)?[0].id;
+
+        // removing the deck removes its cards, and their orphaned note
+        col.remove_deck_and_child_decks(deck.id)?;
+        let graves = col.storage.pending_graves(Usn(-1))?;
+        assert_eq!(graves.cards, vec![cid]);
+        assert_eq!(graves.notes, vec![note.id]);
+        assert_eq!(graves.decks, vec![deck.id]);
+
+        // once sent, they're no longer pending
+        col.storage.update_pending_grave_usns(Usn(5))?;
+        assert!(col.storage.pending_graves(Usn(-1))?.cards.is_empty());
+        assert_eq!(col.storage.pending_graves(Usn(5))?.cards, vec![cid]);
+
+        col.storage.clear_all_graves()?;
+        assert!(col.storage.pending_graves(Usn(0))?.decks.is_empty());
+
+        Ok(())
+    }
+}
@@ -5,20 +5,34 @@ mod card;
 mod config;
 mod deck;
 mod deckconf;
+mod fts;
 mod graves;
 mod note;
 mod notetype;
+mod recover;
 mod revlog;
 mod sqlite;
 mod sync;
 mod sync_check;
 mod tag;
+mod traits;
 mod upgrades;
 
+pub use recover::{recover_collection, RecoveryOutput};
 pub(crate) use sqlite::SqliteStorage;
+pub use sqlite::{StorageOptions, Synchronous};
+pub use traits::Storage;
 
+use crate::err::Result;
+use rusqlite::Row;
 use std::fmt::Write;
 
+/// Conversion from a row of the columns that a type's get.sql selects, so
+/// that each type's column order is defined in one place.
+pub(crate) trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
 // Write a list of IDs as '(x,y,...)' into the provided string.
 pub(crate) fn ids_to_string<T>(buf: &mut String, ids: &[T])
 where
@@ -37,6 +51,70 @@ where
 #[cfg(test)]
 mod test {
     use super::ids_to_string;
+    use crate::{
+        card::{Card, CardID, CardQueue, CardType},
+        collection::open_test_collection,
+        deckconf::DeckConf,
+        decks::{Deck, DeckID},
+        err::Result,
+        revlog::{RevlogEntry, RevlogReviewKind},
+        timestamp::TimestampMillis,
+    };
+
+    #[test]
+    fn rows_round_trip() -> Result<()> {
+        let mut col = open_test_collection();
+
+        let mut card = Card {
+            ctype: CardType::Review,
+            queue: CardQueue::Review,
+            due: 5,
+            ivl: 10,
+            factor: 2500,
+            data: "data".into(),
+            ..Default::default()
+        };
+        col.storage.add_card(&mut card)?;
+        assert_eq!(col.storage.get_card(card.id)?.unwrap(), card);
+
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields[0] = "front".into();
+        note.tags = vec!["one".into(), "two".into()];
+        col.add_note(&mut note, DeckID(1))?;
+        // derived columns are not read back
+        note.sort_field = None;
+        note.checksum = None;
+        assert_eq!(col.storage.get_note(note.id)?.unwrap(), note);
+
+        let mut deck = Deck::new_normal();
+        deck.name = "deck".into();
+        col.storage.add_deck(&mut deck)?;
+        assert_eq!(col.storage.get_deck(deck.id)?.unwrap(), deck);
+
+        let mut conf = DeckConf::default();
+        col.storage.add_deck_conf(&mut conf)?;
+        assert_eq!(col.storage.get_deck_config(conf.id)?.unwrap(), conf);
+
+        let entry = RevlogEntry {
+            id: TimestampMillis(5),
+            cid: CardID(1),
+            button_chosen: 3,
+            interval: -600,
+            last_interval: 1,
+            ease_factor: 2500,
+            taken_millis: 1000,
+            review_kind: RevlogReviewKind::Relearning,
+            ..Default::default()
+        };
+        col.storage.add_revlog_entry(&entry)?;
+        assert_eq!(
+            col.storage.get_revlog_entries_for_card(CardID(1))?,
+            vec![entry]
+        );
+
+        Ok(())
+    }
 
     #[test]
     fn ids_string() {
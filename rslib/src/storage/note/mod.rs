@@ -1,6 +1,7 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use super::FromRow;
 use crate::{
     err::Result,
     notes::{Note, NoteID},
@@ -18,64 +19,84 @@ pub(crate) fn join_fields(fields: &[String]) -> String {
     fields.join("\x1f")
 }
 
-fn row_to_note(row: &Row) -> Result<Note> {
-    Ok(Note {
-        id: row.get(0)?,
-        guid: row.get(1)?,
-        ntid: row.get(2)?,
-        mtime: row.get(3)?,
-        usn: row.get(4)?,
-        tags: split_tags(row.get_raw(5).as_str()?)
-            .map(Into::into)
-            .collect(),
-        fields: split_fields(row.get_raw(6).as_str()?),
-        sort_field: None,
-        checksum: None,
-    })
+impl FromRow for Note {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Note {
+            id: row.get(0)?,
+            guid: row.get(1)?,
+            ntid: row.get(2)?,
+            mtime: row.get(3)?,
+            usn: row.get(4)?,
+            tags: split_tags(row.get_raw(5).as_str()?)
+                .map(Into::into)
+                .collect(),
+            fields: split_fields(row.get_raw(6).as_str()?),
+            sort_field: None,
+            checksum: None,
+        })
+    }
 }
 
 impl super::SqliteStorage {
     pub fn get_note(&self, nid: NoteID) -> Result<Option<Note>> {
         self.db
             .prepare_cached(concat!(include_str!("get.sql"), " where id = ?"))?
-            .query_and_then(params![nid], row_to_note)?
+            .query_and_then(params![nid], Note::from_row)?
             .next()
             .transpose()
     }
 
     /// Caller must call note.prepare_for_update() prior to calling this.
     pub(crate) fn update_note(&self, note: &Note) -> Result<()> {
-        assert!(note.id.0 != 0);
+        self.update_notes(std::slice::from_ref(note))
+    }
+
+    /// Update many notes with a single statement. Caller must call
+    /// note.prepare_for_update() on each note, and should create
+    /// transaction.
+    pub(crate) fn update_notes(&self, notes: &[Note]) -> Result<()> {
         let mut stmt = self.db.prepare_cached(include_str!("update.sql"))?;
-        stmt.execute(params![
-            note.guid,
-            note.ntid,
-            note.mtime,
-            note.usn,
-            join_tags(&note.tags),
-            join_fields(&note.fields()),
-            note.sort_field.as_ref().unwrap(),
-            note.checksum.unwrap(),
-            note.id
-        ])?;
+        for note in notes {
+            assert!(note.id.0 != 0);
+            stmt.execute(params![
+                note.guid,
+                note.ntid,
+                note.mtime,
+                note.usn,
+                join_tags(&note.tags),
+                join_fields(&note.fields()),
+                note.sort_field.as_ref().unwrap(),
+                note.checksum.unwrap(),
+                note.id
+            ])?;
+        }
         Ok(())
     }
 
     pub(crate) fn add_note(&self, note: &mut Note) -> Result<()> {
-        assert!(note.id.0 == 0);
+        self.add_notes(std::slice::from_mut(note))
+    }
+
+    /// Add many notes with a single statement, setting their IDs. Caller
+    /// must call note.prepare_for_update() on each note, and should create
+    /// transaction.
+    pub(crate) fn add_notes(&self, notes: &mut [Note]) -> Result<()> {
         let mut stmt = self.db.prepare_cached(include_str!("add.sql"))?;
-        stmt.execute(params![
-            TimestampMillis::now(),
-            note.guid,
-            note.ntid,
-            note.mtime,
-            note.usn,
-            join_tags(&note.tags),
-            join_fields(&note.fields()),
-            note.sort_field.as_ref().unwrap(),
-            note.checksum.unwrap(),
-        ])?;
-        note.id.0 = self.db.last_insert_rowid();
+        for note in notes {
+            assert!(note.id.0 == 0);
+            stmt.execute(params![
+                TimestampMillis::now(),
+                note.guid,
+                note.ntid,
+                note.mtime,
+                note.usn,
+                join_tags(&note.tags),
+                join_fields(&note.fields()),
+                note.sort_field.as_ref().unwrap(),
+                note.checksum.unwrap(),
+            ])?;
+            note.id.0 = self.db.last_insert_rowid();
+        }
         Ok(())
     }
 
@@ -126,7 +147,9 @@ impl super::SqliteStorage {
         csum: u32,
     ) -> Result<Vec<String>> {
         self.db
-            .prepare("select field_at_index(flds, 0) from notes where csum=? and mid=? and id !=?")?
+            .prepare_cached(
+                "select field_at_index(flds, 0) from notes where csum=? and mid=? and id !=?",
+            )?
             .query_and_then(params![csum, ntid, nid], |r| r.get(0).map_err(Into::into))?
             .collect()
     }
@@ -1,10 +0,0 @@
-delete from cards
-where
-  nid in (
-    select
-      id
-    from notes
-    where
-      mid = ?
-  )
-  and ord = ?;
\ No newline at end of file
@@ -105,6 +105,15 @@ impl SqliteStorage {
             .collect()
     }
 
+    /// Returns list of (notetype id, ordinal, name) for the fields of all
+    /// note types, without loading the note types themselves.
+    pub(crate) fn get_all_field_names(&self) -> Result<Vec<(NoteTypeID, u32, String)>> {
+        self.db
+            .prepare_cached("select ntid, ord, name from fields order by ntid, ord")?
+            .query_and_then(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect()
+    }
+
     /// Returns list of (id, name, use_count)
     pub fn get_notetype_use_counts(&self) -> Result<Vec<(NoteTypeID, String, u32)>> {
         self.db
@@ -157,6 +166,13 @@ impl SqliteStorage {
             .collect()
     }
 
+    pub(crate) fn note_ids_of_notetype(&self, ntid: NoteTypeID) -> Result<Vec<NoteID>> {
+        self.db
+            .prepare_cached("select id from notes where mid = ?")?
+            .query_and_then(&[ntid], |r| r.get(0).map_err(Into::into))?
+            .collect()
+    }
+
     pub(crate) fn all_note_ids_by_notetype(&self) -> Result<Vec<(NoteTypeID, NoteID)>> {
         let sql = String::from("select mid, id from notes order by mid, id");
         self.db
@@ -238,27 +254,8 @@ impl SqliteStorage {
         Ok(())
     }
 
-    pub(crate) fn remove_cards_for_deleted_templates(
-        &self,
-        ntid: NoteTypeID,
-        ords: &[u32],
-    ) -> Result<()> {
-        let mut stmt = self
-            .db
-            .prepare(include_str!("delete_cards_for_template.sql"))?;
-        for ord in ords {
-            stmt.execute(params![ntid, ord])?;
-        }
-        Ok(())
-    }
-
+    /// Notes and cards must be removed first.
     pub(crate) fn remove_notetype(&self, ntid: NoteTypeID) -> Result<()> {
-        self.db
-            .prepare_cached("delete from cards where nid in (select id from notes where mid=?)")?
-            .execute(&[ntid])?;
-        self.db
-            .prepare_cached("delete from notes where mid=?")?
-            .execute(&[ntid])?;
         self.db
             .prepare_cached("delete from templates where ntid=?")?
             .execute(&[ntid])?;
@@ -0,0 +1,302 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use crate::{
+    err::{AnkiError, DBErrorKind, Result},
+    i18n::{tr_args, I18n, TR},
+    timestamp::TimestampSecs,
+};
+use rusqlite::{params, types::Value, Connection, Statement, ToSql, NO_PARAMS};
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+
+/// What could be saved from a damaged collection.
+#[derive(Debug, Default, PartialEq)]
+pub struct RecoveryOutput {
+    pub rows_recovered: usize,
+    /// tables that could not be read in full, so may have lost rows
+    pub damaged_tables: Vec<String>,
+    /// where the damaged collection was moved to
+    pub damaged_path: PathBuf,
+}
+
+impl RecoveryOutput {
+    pub fn to_i18n_strings(&self, i18n: &I18n) -> Vec<String> {
+        let mut out = vec![i18n.trn(
+            TR::DatabaseCheckRecovered,
+            tr_args!["count"=>self.rows_recovered],
+        )];
+        if !self.damaged_tables.is_empty() {
+            out.push(i18n.trn(
+                TR::DatabaseCheckRecoveryIncomplete,
+                tr_args!["tables"=>self.damaged_tables.join(", ")],
+            ));
+        }
+        out.push(i18n.trn(
+            TR::DatabaseCheckDamagedFileKept,
+            tr_args!["path"=>self.damaged_path.to_string_lossy().to_string()],
+        ));
+        out
+    }
+}
+
+struct SchemaItem {
+    kind: String,
+    name: String,
+    sql: String,
+}
+
+impl SchemaItem {
+    fn is_virtual_table(&self) -> bool {
+        self.kind == "table" && self.sql.to_ascii_lowercase().starts_with("create virtual")
+    }
+}
+
+/// Copy every readable row of a damaged collection into a new file, and
+/// replace the collection with it. The damaged file is kept alongside it,
+/// as it may allow a better repair later. The collection must be closed.
+pub fn recover_collection(col_path: &Path) -> Result<RecoveryOutput> {
+    let folder = col_path
+        .parent()
+        .ok_or_else(|| AnkiError::invalid_input("invalid collection path"))?;
+    let old = Connection::open(col_path)?;
+    let schema = read_schema(&old)?;
+    let virtual_tables: Vec<_> = schema
+        .iter()
+        .filter(|item| item.is_virtual_table())
+        .map(|item| item.name.as_str())
+        .collect();
+    // virtual tables and their shadow tables are rebuilt instead of copied
+    let is_virtual = |item: &SchemaItem| {
+        virtual_tables.iter().any(|name| {
+            item.name == *name
+                || item.name.starts_with(&format!("{}_", name))
+                || (item.kind == "trigger" && item.sql.contains(name))
+        })
+    };
+
+    let out_file = NamedTempFile::new_in(folder)?;
+    let new = Connection::open(out_file.path())?;
+    // match a newly created collection
+    new.pragma_update(None, "page_size", &4096)?;
+    new.pragma_update(None, "legacy_file_format", &false)?;
+    new.execute_batch("begin")?;
+
+    let mut output = RecoveryOutput::default();
+    for item in schema
+        .iter()
+        .filter(|item| item.kind == "table" && !is_virtual(item))
+    {
+        new.execute_batch(&item.sql)?;
+        let has_rowid = !item.sql.to_ascii_lowercase().contains("without rowid");
+        let (copied, complete) = copy_table(&old, &new, &item.name, has_rowid)?;
+        output.rows_recovered += copied;
+        if !complete {
+            output.damaged_tables.push(item.name.clone());
+        }
+    }
+    for item in schema
+        .iter()
+        .filter(|item| matches!(item.kind.as_str(), "index" | "trigger") && !is_virtual(item))
+    {
+        new.execute_batch(&item.sql)?;
+    }
+    if virtual_tables.contains(&"notes_fts") {
+        new.execute_batch(include_str!("fts/create.sql"))?;
+    }
+    new.execute_batch("commit")?;
+    drop(old);
+
+    let check_result: String = new.pragma_query_value(None, "integrity_check", |r| r.get(0))?;
+    drop(new);
+    if check_result != "ok" {
+        return Err(AnkiError::DBError {
+            info: "recovered collection is corrupt".into(),
+            kind: DBErrorKind::Corrupt,
+        });
+    }
+
+    // the log is moved too, as it would otherwise be applied to the new file
+    let damaged_path = with_suffix(col_path, &format!(".corrupt-{}", TimestampSecs::now()));
+    for suffix in &["-wal", "-shm"] {
+        let path = with_suffix(col_path, suffix);
+        if path.exists() {
+            fs::rename(&path, with_suffix(&damaged_path, suffix))?;
+        }
+    }
+    fs::rename(col_path, &damaged_path)?;
+    out_file.persist(col_path).map_err(|e| AnkiError::IOError {
+        info: format!("recovery save failed: {}", e),
+    })?;
+    output.damaged_path = damaged_path;
+
+    Ok(output)
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path: OsString = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Tables first, so that indexes and triggers can be created after the
+/// rows are copied.
+fn read_schema(db: &Connection) -> Result<Vec<SchemaItem>> {
+    db.prepare(concat!(
+        "select type, name, sql from sqlite_master where sql not null ",
+        "and name not like 'sqlite_%' order by type != 'table'"
+    ))?
+    .query_map(NO_PARAMS, |row| {
+        Ok(SchemaItem {
+            kind: row.get(0)?,
+            name: row.get(1)?,
+            sql: row.get(2)?,
+        })
+    })?
+    .collect::<rusqlite::Result<_>>()
+    .map_err(Into::into)
+}
+
+/// Copy the readable rows of a table. Returns the number of rows copied,
+/// and whether every row could be read.
+fn copy_table(
+    old: &Connection,
+    new: &Connection,
+    table: &str,
+    has_rowid: bool,
+) -> Result<(usize, bool)> {
+    let columns = match old.prepare(&format!("select * from \"{}\"", table)) {
+        Ok(stmt) => stmt.column_count(),
+        Err(_) => return Ok((0, false)),
+    };
+    let mut insert = new.prepare(&format!(
+        "insert or ignore into \"{}\" values ({})",
+        table,
+        vec!["?"; columns].join(",")
+    ))?;
+    let mut copied = 0;
+
+    if !has_rowid {
+        let (complete, _) = copy_rows(
+            old,
+            &mut insert,
+            &format!("select null, * from \"{}\"", table),
+            NO_PARAMS,
+            &mut copied,
+        )?;
+        return Ok((copied, complete));
+    }
+
+    let (complete, last_rowid) = copy_rows(
+        old,
+        &mut insert,
+        &format!("select rowid, * from \"{}\" order by rowid", table),
+        NO_PARAMS,
+        &mut copied,
+    )?;
+    if !complete {
+        // rows after the damage may still be reachable from the other end
+        copy_rows(
+            old,
+            &mut insert,
+            &format!(
+                "select rowid, * from \"{}\" where rowid > ? order by rowid desc",
+                table
+            ),
+            params![last_rowid.unwrap_or(i64::MIN)],
+            &mut copied,
+        )?;
+    }
+
+    Ok((copied, complete))
+}
+
+/// Insert the rows returned by sql, whose first column is the rowid,
+/// stopping at the first row that can't be read. Returns false if that
+/// happened, and the last rowid that was copied.
+fn copy_rows(
+    old: &Connection,
+    insert: &mut Statement,
+    sql: &str,
+    params: &[&dyn ToSql],
+    copied: &mut usize,
+) -> Result<(bool, Option<i64>)> {
+    let mut last_rowid = None;
+    let mut stmt = match old.prepare(sql) {
+        Ok(stmt) => stmt,
+        Err(_) => return Ok((false, last_rowid)),
+    };
+    let columns = stmt.column_count();
+    let mut rows = match stmt.query(params) {
+        Ok(rows) => rows,
+        Err(_) => return Ok((false, last_rowid)),
+    };
+    loop {
+        let row = match rows.next() {
+            Ok(Some(row)) => row,
+            Ok(None) => return Ok((true, last_rowid)),
+            Err(_) => return Ok((false, last_rowid)),
+        };
+        let values = match (1..columns)
+            .map(|idx| row.get(idx))
+            .collect::<rusqlite::Result<Vec<Value>>>()
+        {
+            Ok(values) => values,
+            Err(_) => return Ok((false, last_rowid)),
+        };
+        *copied += insert.execute(values)?;
+        last_rowid = row.get(0).ok();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        collection::{open_collection, Collection},
+        decks::DeckID,
+        log,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn recovering() -> Result<()> {
+        let dir = tempdir()?;
+        let col_path = dir.path().join("collection.anki2");
+        let open = || -> Result<Collection> {
+            let i18n = I18n::new(&[""], "", log::terminal());
+            open_collection(
+                col_path.clone(),
+                dir.path().join("media"),
+                dir.path().join("media.db2"),
+                false,
+                i18n,
+                log::terminal(),
+            )
+        };
+        let mut col = open()?;
+        col.set_full_text_search(true)?;
+        let nt = col.get_notetype_by_name("Basic")?.unwrap();
+        let mut note = nt.new_note();
+        note.fields[0] = "front".into();
+        col.add_note(&mut note, DeckID(1))?;
+        col.close(false)?;
+
+        // an intact collection is copied in full
+        let out = recover_collection(&col_path)?;
+        assert!(out.rows_recovered > 0);
+        assert!(out.damaged_tables.is_empty());
+        assert!(out.damaged_path.exists());
+
+        let mut col = open()?;
+        assert_eq!(col.storage.get_note(note.id)?.unwrap().fields, note.fields);
+        assert!(col.storage.full_text_index_exists()?);
+        assert_eq!(col.search_notes("front")?, vec![note.id]);
+
+        Ok(())
+    }
+}
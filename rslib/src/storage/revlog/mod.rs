@@ -1,7 +1,7 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
-use super::SqliteStorage;
+use super::{FromRow, SqliteStorage};
 use crate::err::Result;
 use crate::{
     backend_proto as pb,
@@ -25,18 +25,20 @@ impl FromSql for RevlogReviewKind {
     }
 }
 
-fn row_to_revlog_entry(row: &Row) -> Result<RevlogEntry> {
-    Ok(RevlogEntry {
-        id: row.get(0)?,
-        cid: row.get(1)?,
-        usn: row.get(2)?,
-        button_chosen: row.get(3)?,
-        interval: row.get(4)?,
-        last_interval: row.get(5)?,
-        ease_factor: row.get(6)?,
-        taken_millis: row.get(7).unwrap_or_default(),
-        review_kind: row.get(8).unwrap_or_default(),
-    })
+impl FromRow for RevlogEntry {
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(RevlogEntry {
+            id: row.get(0)?,
+            cid: row.get(1)?,
+            usn: row.get(2)?,
+            button_chosen: row.get(3)?,
+            interval: row.get(4)?,
+            last_interval: row.get(5)?,
+            ease_factor: row.get(6)?,
+            taken_millis: row.get(7).unwrap_or_default(),
+            review_kind: row.get(8).unwrap_or_default(),
+        })
+    }
 }
 
 impl SqliteStorage {
@@ -71,10 +73,49 @@ impl SqliteStorage {
         Ok(())
     }
 
+    pub(crate) fn remove_revlog_entry(&self, id: TimestampMillis) -> Result<()> {
+        self.db
+            .prepare_cached("delete from revlog where id = ?")?
+            .execute(&[id])?;
+        Ok(())
+    }
+
+    /// The v1 scheduler had one less button in (re)learning, so answers
+    /// other than Again are shifted up one to their v2 equivalent.
+    pub(crate) fn upgrade_revlog_to_v2(&self) -> Result<()> {
+        self.db
+            .prepare("update revlog set ease = ease + 1 where ease in (2, 3) and type in (?, ?)")?
+            .execute(params![
+                RevlogReviewKind::Learning as u8,
+                RevlogReviewKind::Relearning as u8
+            ])?;
+        Ok(())
+    }
+
+    /// The average time taken on answers of the given kind, in seconds.
+    pub(crate) fn average_answer_secs(&self, kind: RevlogReviewKind) -> Result<Option<f64>> {
+        self.db
+            .prepare_cached("select avg(time) / 1000.0 from revlog where type = ? and time > 0")?
+            .query_row(&[kind as u8], |r| r.get(0))
+            .map_err(Into::into)
+    }
+
+    /// The provided time, or if entries have been logged at or after it,
+    /// the first free id after them.
+    pub(crate) fn next_revlog_id(&self, now: TimestampMillis) -> Result<TimestampMillis> {
+        let max: Option<i64> = self
+            .db
+            .prepare_cached("select max(id) from revlog")?
+            .query_row(NO_PARAMS, |r| r.get(0))?;
+        Ok(TimestampMillis(
+            max.map(|max| now.0.max(max + 1)).unwrap_or(now.0),
+        ))
+    }
+
     pub(crate) fn get_revlog_entry(&self, id: RevlogID) -> Result<Option<RevlogEntry>> {
         self.db
             .prepare_cached(concat!(include_str!("get.sql"), " where id=?"))?
-            .query_and_then(&[id], row_to_revlog_entry)?
+            .query_and_then(&[id], RevlogEntry::from_row)?
             .next()
             .transpose()
     }
@@ -82,7 +123,7 @@ impl SqliteStorage {
     pub(crate) fn get_revlog_entries_for_card(&self, cid: CardID) -> Result<Vec<RevlogEntry>> {
         self.db
             .prepare_cached(concat!(include_str!("get.sql"), " where cid=?"))?
-            .query_and_then(&[cid], row_to_revlog_entry)?
+            .query_and_then(&[cid], RevlogEntry::from_row)?
             .collect()
     }
 
@@ -95,8 +136,8 @@ impl SqliteStorage {
                 include_str!("get.sql"),
                 " where cid in (select id from search_cids) and id >= ?"
             ))?
-            .query_and_then(&[after.0 * 1000], |r| {
-                row_to_revlog_entry(r).map(Into::into)
+            .query_and_then(&[after.as_millis()], |r| {
+                RevlogEntry::from_row(r).map(Into::into)
             })?
             .collect()
     }
@@ -108,8 +149,8 @@ impl SqliteStorage {
     ) -> Result<Vec<pb::RevlogEntry>> {
         self.db
             .prepare_cached(concat!(include_str!("get.sql"), " where id >= ?"))?
-            .query_and_then(&[after.0 * 1000], |r| {
-                row_to_revlog_entry(r).map(Into::into)
+            .query_and_then(&[after.as_millis()], |r| {
+                RevlogEntry::from_row(r).map(Into::into)
             })?
             .collect()
     }
@@ -1,21 +1,27 @@
 // Copyright: Ankitects Pty Ltd and contributors
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
+use super::upgrades::{check_schema_version, SCHEMA_MAX_VERSION, SCHEMA_MIN_VERSION};
 use crate::config::schema11_config_as_string;
-use crate::err::Result;
-use crate::err::{AnkiError, DBErrorKind};
+use crate::err::{AnkiError, Result};
 use crate::timestamp::{TimestampMillis, TimestampSecs};
-use crate::{i18n::I18n, sched::cutoff::v1_creation_date, text::without_combining};
+use crate::{
+    i18n::I18n,
+    sched::cutoff::v1_creation_date,
+    text::{fold_case, without_combining},
+};
 use regex::Regex;
 use rusqlite::{functions::FunctionFlags, params, Connection, NO_PARAMS};
 use std::cmp::Ordering;
-use std::{borrow::Cow, path::Path, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 use unicase::UniCase;
 
-const SCHEMA_MIN_VERSION: u8 = 11;
-const SCHEMA_STARTING_VERSION: u8 = 11;
-const SCHEMA_MAX_VERSION: u8 = 15;
-
 fn unicase_compare(s1: &str, s2: &str) -> Ordering {
     UniCase::new(s1).cmp(&UniCase::new(s2))
 }
@@ -30,32 +36,102 @@ pub struct SqliteStorage {
     pub(crate) db: Connection,
 }
 
-fn open_or_create_collection_db(path: &Path) -> Result<Connection> {
+/// How often SQLite waits for data to reach the disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Synchronous {
+    /// Safe against power loss. The SQLite default.
+    Full,
+    /// In WAL mode, the last transactions may be lost on power loss, but
+    /// the database will not be corrupted. Considerably faster on devices
+    /// with slow storage.
+    Normal,
+}
+
+impl Synchronous {
+    fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Full => "full",
+            Synchronous::Normal => "normal",
+        }
+    }
+}
+
+/// Settings applied to the collection's database connection when it is
+/// opened. The database always uses WAL journaling.
+#[derive(Debug, Clone)]
+pub struct StorageOptions {
+    /// How long to wait for a lock held by another connection before
+    /// failing with "database is locked". Zero fails immediately.
+    pub busy_timeout: Duration,
+    pub synchronous: Synchronous,
+    /// If set, the database is encrypted with SQLCipher using this key.
+    /// Opening an existing file with the wrong key fails with a DBError.
+    /// The backend sets this from OpenCollectionIn.encryption_key.
+    #[cfg(feature = "sqlcipher")]
+    pub encryption_key: Option<String>,
+}
+
+impl Default for StorageOptions {
+    fn default() -> Self {
+        StorageOptions {
+            busy_timeout: Duration::from_secs(5),
+            synchronous: if cfg!(any(target_os = "android", target_os = "ios")) {
+                Synchronous::Normal
+            } else {
+                Synchronous::Full
+            },
+            #[cfg(feature = "sqlcipher")]
+            encryption_key: None,
+        }
+    }
+}
+
+fn open_or_create_collection_db(path: &Path, options: &StorageOptions) -> Result<Connection> {
     let mut db = Connection::open(path)?;
 
+    #[cfg(feature = "sqlcipher")]
+    set_encryption_key(&db, options)?;
+
     if std::env::var("TRACESQL").is_ok() {
         db.trace(Some(trace));
     }
 
-    db.busy_timeout(std::time::Duration::from_secs(0))?;
+    db.busy_timeout(options.busy_timeout)?;
 
     db.pragma_update(None, "locking_mode", &"exclusive")?;
     db.pragma_update(None, "page_size", &4096)?;
     db.pragma_update(None, "cache_size", &(-40 * 1024))?;
     db.pragma_update(None, "legacy_file_format", &false)?;
     db.pragma_update(None, "journal_mode", &"wal")?;
+    db.pragma_update(None, "synchronous", &options.synchronous.as_str())?;
 
-    db.set_prepared_statement_cache_capacity(50);
+    // large enough to hold every statement prepared with prepare_cached(),
+    // so that statements used when reviewing are not evicted by others
+    db.set_prepared_statement_cache_capacity(150);
 
     add_field_index_function(&db)?;
-    add_regexp_function(&db)?;
+    let regex_cache = Arc::new(RegexCache::default());
+    add_regexp_function(&db, regex_cache.clone())?;
+    add_regexp_fields_function(&db, regex_cache)?;
     add_without_combining_function(&db)?;
+    add_fold_case_function(&db)?;
 
     db.create_collation("unicase", unicase_compare)?;
 
     Ok(db)
 }
 
+/// Must be done before anything else reads the database.
+#[cfg(feature = "sqlcipher")]
+fn set_encryption_key(db: &Connection, options: &StorageOptions) -> Result<()> {
+    if let Some(key) = &options.encryption_key {
+        db.pragma_update(None, "key", key)?;
+        // the key is not checked until the file is first read
+        db.query_row("select count() from sqlite_master", NO_PARAMS, |_| Ok(()))?;
+    }
+    Ok(())
+}
+
 /// Adds sql function field_at_index(flds, index)
 /// to split provided fields and return field at zero-based index.
 /// If out of range, returns empty string.
@@ -87,10 +163,42 @@ fn add_without_combining_function(db: &Connection) -> rusqlite::Result<()> {
     )
 }
 
+fn add_fold_case_function(db: &Connection) -> rusqlite::Result<()> {
+    db.create_scalar_function("fold_case", 1, FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+        let text = ctx.get_raw(0).as_str()?;
+        Ok(fold_case(text).into_owned())
+    })
+}
+
+/// The number of compiled regexes kept by RegexCache.
+const REGEX_CACHE_SIZE: usize = 32;
+
+/// Compiled regexes shared by the regexp functions. SQLite only keeps a
+/// compiled regex for the duration of a statement, and not at all when the
+/// pattern comes from a column, so this avoids recompiling a pattern for
+/// each statement or row.
+#[derive(Default)]
+struct RegexCache(Mutex<HashMap<String, Regex>>);
+
+impl RegexCache {
+    fn get(&self, pattern: &str) -> std::result::Result<Regex, regex::Error> {
+        let mut cache = self.0.lock().unwrap();
+        if let Some(re) = cache.get(pattern) {
+            return Ok(re.clone());
+        }
+        let re = Regex::new(pattern)?;
+        if cache.len() >= REGEX_CACHE_SIZE {
+            cache.clear();
+        }
+        cache.insert(pattern.to_string(), re.clone());
+        Ok(re)
+    }
+}
+
 /// Adds sql function regexp(regex, string) -> is_match
 /// Taken from the rusqlite docs
 type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
-fn add_regexp_function(db: &Connection) -> rusqlite::Result<()> {
+fn add_regexp_function(db: &Connection, cache: Arc<RegexCache>) -> rusqlite::Result<()> {
     db.create_scalar_function(
         "regexp",
         2,
@@ -100,7 +208,7 @@ fn add_regexp_function(db: &Connection) -> rusqlite::Result<()> {
 
             let re: Arc<Regex> = ctx
                 .get_or_create_aux(0, |vr| -> std::result::Result<_, BoxError> {
-                    Ok(Regex::new(vr.as_str()?)?)
+                    Ok(cache.get(vr.as_str()?)?)
                 })?;
 
             let is_match = {
@@ -117,6 +225,32 @@ fn add_regexp_function(db: &Connection) -> rusqlite::Result<()> {
     )
 }
 
+/// Adds sql function regexp_fields(regex, flds) -> is_match
+/// Unlike regexp(), the regex is tested against each field separately,
+/// so ^ and $ match the start and end of a field instead of the whole note.
+fn add_regexp_fields_function(db: &Connection, cache: Arc<RegexCache>) -> rusqlite::Result<()> {
+    db.create_scalar_function(
+        "regexp_fields",
+        2,
+        FunctionFlags::SQLITE_DETERMINISTIC,
+        move |ctx| {
+            assert_eq!(ctx.len(), 2, "called with unexpected number of arguments");
+
+            let re: Arc<Regex> = ctx
+                .get_or_create_aux(0, |vr| -> std::result::Result<_, BoxError> {
+                    Ok(cache.get(vr.as_str()?)?)
+                })?;
+
+            let fields = ctx
+                .get_raw(1)
+                .as_str()
+                .map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?;
+
+            Ok(fields.split('\x1f').any(|field| re.is_match(field)))
+        },
+    )
+}
+
 /// Fetch schema version from database.
 /// Return (must_create, version)
 fn schema_version(db: &Connection) -> Result<(bool, u8)> {
@@ -124,7 +258,7 @@ fn schema_version(db: &Connection) -> Result<(bool, u8)> {
         .prepare("select null from sqlite_master where type = 'table' and name = 'col'")?
         .exists(NO_PARAMS)?
     {
-        return Ok((true, SCHEMA_STARTING_VERSION));
+        return Ok((true, SCHEMA_MIN_VERSION));
     }
 
     Ok((
@@ -138,26 +272,16 @@ fn trace(s: &str) {
 }
 
 impl SqliteStorage {
-    pub(crate) fn open_or_create(path: &Path, i18n: &I18n, server: bool) -> Result<Self> {
-        let db = open_or_create_collection_db(path)?;
+    pub(crate) fn open_or_create(
+        path: &Path,
+        i18n: &I18n,
+        server: bool,
+        options: &StorageOptions,
+    ) -> Result<Self> {
+        let db = open_or_create_collection_db(path, options)?;
         let (create, ver) = schema_version(&db)?;
 
-        let err = match ver {
-            v if v < SCHEMA_MIN_VERSION => Some(DBErrorKind::FileTooOld),
-            v if v > SCHEMA_MAX_VERSION => Some(DBErrorKind::FileTooNew),
-            12 | 13 => {
-                // as schema definition changed, user must perform clean
-                // shutdown to return to schema 11 prior to running this version
-                Some(DBErrorKind::FileTooNew)
-            }
-            _ => None,
-        };
-        if let Some(kind) = err {
-            return Err(AnkiError::DBError {
-                info: "".to_string(),
-                kind,
-            });
-        }
+        check_schema_version(ver)?;
 
         let upgrade = ver != SCHEMA_MAX_VERSION;
         if create || upgrade {
@@ -173,7 +297,7 @@ impl SqliteStorage {
                 params![
                     crt,
                     crt * 1000,
-                    SCHEMA_STARTING_VERSION,
+                    SCHEMA_MIN_VERSION,
                     &schema11_config_as_string()
                 ],
             )?;
@@ -200,7 +324,7 @@ impl SqliteStorage {
 
     pub(crate) fn close(self, downgrade: bool) -> Result<()> {
         if downgrade {
-            self.downgrade_to_schema_11()?;
+            self.downgrade_to_schema(SCHEMA_MIN_VERSION)?;
             self.db.pragma_update(None, "journal_mode", &"delete")?;
         }
         Ok(())
@@ -230,6 +354,42 @@ impl SqliteStorage {
         Ok(())
     }
 
+    // Attached databases
+    //////////////////////////////////////////
+
+    /// Make the tables of another database file available as alias.table,
+    /// so rows can be copied between it and the collection in a single
+    /// statement, eg when importing a package.
+    ///
+    /// SQLite can't attach inside a transaction, so an open transaction is
+    /// committed first, and a new one begun. Must not be called inside
+    /// a Rust transaction.
+    pub(crate) fn attach_database(&self, path: &str, alias: &str) -> Result<()> {
+        self.outside_trx(|db| {
+            db.execute("attach database ? as ?", params![path, alias])?;
+            Ok(())
+        })
+    }
+
+    pub(crate) fn detach_database(&self, alias: &str) -> Result<()> {
+        self.outside_trx(|db| {
+            db.execute("detach database ?", params![alias])?;
+            Ok(())
+        })
+    }
+
+    fn outside_trx(&self, func: impl FnOnce(&Connection) -> Result<()>) -> Result<()> {
+        let in_trx = !self.db.is_autocommit();
+        if in_trx {
+            self.commit_trx()?;
+        }
+        let res = func(&self.db);
+        if in_trx {
+            self.begin_trx()?;
+        }
+        res
+    }
+
     // Savepoints
     //////////////////////////////////////////
     //
@@ -237,6 +397,9 @@ impl SqliteStorage {
     // long-running transactions as an undo mechanism. Once a proper undo
     // mechanism has been added to all existing functionality, we could
     // transition these to standard commits.
+    //
+    // Savepoints may be nested; each rollback or release applies to the
+    // innermost open one.
 
     pub(crate) fn begin_rust_trx(&self) -> Result<()> {
         self.db
@@ -250,11 +413,12 @@ impl SqliteStorage {
         Ok(())
     }
 
+    /// Undo changes made since the savepoint was opened, and close it.
     pub(crate) fn rollback_rust_trx(&self) -> Result<()> {
         self.db
             .prepare_cached("rollback to rust")?
             .execute(NO_PARAMS)?;
-        Ok(())
+        self.commit_rust_trx()
     }
 
     //////////////////////////////////////////
@@ -327,19 +491,18 @@ impl SqliteStorage {
 
     //////////////////////////////////////////
 
-    /// true if corrupt/can't access
-    pub(crate) fn quick_check_corrupt(&self) -> bool {
-        match self.db.pragma_query_value(None, "quick_check", |row| {
-            row.get(0).map(|v: String| v != "ok")
-        }) {
-            Ok(corrupt) => corrupt,
-            Err(e) => {
-                println!("error: {:?}", e);
-                true
-            }
-        }
+    /// true if corrupt. An error is returned if the database couldn't be
+    /// read, or the check was interrupted.
+    pub(crate) fn quick_check_corrupt(&self) -> Result<bool> {
+        self.db
+            .pragma_query_value(None, "quick_check", |row| {
+                row.get(0).map(|v: String| v != "ok")
+            })
+            .map_err(Into::into)
     }
 
+    /// Reclaim unused space and refresh the query planner's statistics.
+    /// Must not be called inside a transaction.
     pub(crate) fn optimize(&self) -> Result<()> {
         self.db.execute_batch("vacuum; analyze")?;
         Ok(())
@@ -352,3 +515,102 @@ impl SqliteStorage {
             .map_err(Into::into)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn options() -> Result<()> {
+        let i18n = I18n::new(&[""], "", log::terminal());
+        let options = StorageOptions {
+            busy_timeout: Duration::from_millis(1500),
+            synchronous: Synchronous::Normal,
+            ..Default::default()
+        };
+        let storage = SqliteStorage::open_or_create(Path::new(":memory:"), &i18n, false, &options)?;
+        assert_eq!(storage.db_scalar::<u32>("pragma busy_timeout")?, 1500);
+        // 1 = normal
+        assert_eq!(storage.db_scalar::<u32>("pragma synchronous")?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn regexp() -> Result<()> {
+        let db = open_or_create_collection_db(Path::new(":memory:"), &StorageOptions::default())?;
+        let matches = |sql: &str| -> Result<bool> {
+            db.query_row(sql, NO_PARAMS, |r| r.get(0))
+                .map_err(Into::into)
+        };
+        assert!(matches("select 'abc' regexp 'b'")?);
+        assert!(!matches("select 'abc' regexp '^b'")?);
+        assert!(matches("select regexp_fields('^b', 'a\x1fbc')")?);
+        // patterns may differ between rows
+        let found: u32 = db.query_row(
+            "select count() from (select 'a' p union all select '^c') where 'abc' regexp p",
+            NO_PARAMS,
+            |r| r.get(0),
+        )?;
+        assert_eq!(found, 1);
+        // invalid patterns are an error
+        assert!(matches("select 'abc' regexp '('").is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[test]
+    fn encryption() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("collection.anki2");
+        let i18n = I18n::new(&[""], "", log::terminal());
+        let options = |key: &str| StorageOptions {
+            encryption_key: Some(key.into()),
+            ..Default::default()
+        };
+        SqliteStorage::open_or_create(&path, &i18n, false, &options("secret"))?.close(false)?;
+
+        assert!(SqliteStorage::open_or_create(&path, &i18n, false, &options("wrong")).is_err());
+        assert!(
+            SqliteStorage::open_or_create(&path, &i18n, false, &StorageOptions::default()).is_err()
+        );
+        SqliteStorage::open_or_create(&path, &i18n, false, &options("secret"))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn attaching() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let other_path = dir.path().join("other.anki2");
+        Connection::open(&other_path)?.execute_batch(
+            "create table items (id integer primary key); insert into items values (1), (2)",
+        )?;
+
+        let i18n = I18n::new(&[""], "", log::terminal());
+        let storage = SqliteStorage::open_or_create(
+            Path::new(":memory:"),
+            &i18n,
+            false,
+            &StorageOptions::default(),
+        )?;
+        storage.begin_trx()?;
+        storage.attach_database(other_path.to_str().unwrap(), "other")?;
+        // the open transaction is preserved
+        assert!(!storage.db.is_autocommit());
+        assert_eq!(
+            storage.db_scalar::<u32>("select count() from other.items")?,
+            2
+        );
+
+        storage.detach_database("other")?;
+        assert!(!storage.db.is_autocommit());
+        assert!(storage
+            .db_scalar::<u32>("select count() from other.items")
+            .is_err());
+
+        Ok(())
+    }
+}
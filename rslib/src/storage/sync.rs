@@ -59,3 +59,75 @@ impl SqliteStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        card::CardID,
+        collection::open_test_collection,
+        deckconf::DeckConfID,
+        decks::{Deck, DeckID},
+        err::Result,
+        notes::NoteID,
+        notetype::NoteTypeID,
+        types::Usn,
+    };
+
+    #[test]
+    fn changes_are_pending() -> Result<()> {
+        let mut col = open_test_collection();
+        let ntid = col.get_notetype_by_name("Basic")?.unwrap().id;
+        let mut nt = col.storage.get_notetype(ntid)?.unwrap();
+        let mut note = nt.new_note();
+        col.add_note(&mut note, DeckID(1))?;
+        let mut deck = Deck::new_normal();
+        deck.name = "deck".into();
+        col.add_or_update_deck(&mut deck)?;
+        // as if the collection had just been synced
+        col.before_upload()?;
+        let pending = Usn(-1);
+        assert!(col
+            .storage
+            .objects_pending_sync::<CardID>("cards", pending)?
+            .is_empty());
+
+        note.fields[0] = "changed".into();
+        col.update_note(&mut note)?;
+        let card = col.storage.all_cards_of_note(note.id)?.pop().unwrap();
+        col.get_and_update_card(card.id, |card| {
+            card.due = 5;
+            Ok(())
+        })?;
+        deck.name = "renamed".into();
+        col.add_or_update_deck(&mut deck)?;
+        let mut conf = col.get_deck_config(DeckConfID(1), false)?.unwrap();
+        conf.inner.new_per_day += 1;
+        col.add_or_update_deck_config(&mut conf, false)?;
+        nt.config.css += " ";
+        col.update_notetype(&mut nt, false)?;
+
+        let storage = &col.storage;
+        assert_eq!(
+            storage.objects_pending_sync::<NoteID>("notes", pending)?,
+            vec![note.id]
+        );
+        assert_eq!(
+            storage.objects_pending_sync::<CardID>("cards", pending)?,
+            vec![card.id]
+        );
+        assert_eq!(
+            storage.objects_pending_sync::<DeckID>("decks", pending)?,
+            vec![deck.id]
+        );
+        assert_eq!(
+            storage.objects_pending_sync::<DeckConfID>("deck_config", pending)?,
+            vec![DeckConfID(1)]
+        );
+        assert_eq!(
+            storage.objects_pending_sync::<NoteTypeID>("notetypes", pending)?,
+            vec![nt.id]
+        );
+
+        Ok(())
+    }
+}
@@ -32,6 +32,33 @@ impl SqliteStorage {
             .map_err(Into::into)
     }
 
+    /// True if any registered tag is a child of the provided tag.
+    pub(crate) fn tag_has_children(&self, tag: &str) -> Result<bool> {
+        let prefix = tag
+            .replace('\\', r"\\")
+            .replace('%', r"\%")
+            .replace('_', r"\_");
+        self.db
+            .prepare_cached("select exists(select 1 from tags where tag like ? escape '\\')")?
+            .query_row(params![format!("{}::%", prefix)], |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    pub(crate) fn remove_tag(&self, tag: &str) -> Result<()> {
+        self.db
+            .prepare_cached("delete from tags where tag = ?")?
+            .execute(&[tag])?;
+        Ok(())
+    }
+
+    /// The distinct tag strings of all notes, eg " one two ".
+    pub(crate) fn all_note_tag_strings(&self) -> Result<Vec<String>> {
+        self.db
+            .prepare("select distinct tags from notes")?
+            .query_and_then(NO_PARAMS, |row| row.get(0).map_err(Into::into))?
+            .collect()
+    }
+
     pub(crate) fn clear_tags(&self) -> Result<()> {
         self.db.execute("delete from tags", NO_PARAMS)?;
         Ok(())
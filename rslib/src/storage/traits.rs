@@ -0,0 +1,255 @@
+// Copyright: Ankitects Pty Ltd and contributors
+// License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
+
+use super::SqliteStorage;
+use crate::{
+    card::{Card, CardID, CardQueue},
+    decks::{Deck, DeckID},
+    err::Result,
+    notes::{Note, NoteID},
+    notetype::{NoteType, NoteTypeID},
+    revlog::{RevlogEntry, RevlogReviewKind},
+    sched::bury::UnburyMode,
+    timestamp::{TimestampMillis, TimestampSecs},
+    types::Usn,
+};
+use std::collections::HashMap;
+
+/// The record-level reads and writes that the scheduler makes directly.
+///
+/// Only these calls are abstracted. Collection still owns a SqliteStorage,
+/// and searching (which is compiled to SQL), Collection::transact(), the
+/// card and note helpers on Collection, and filtered decks all use it
+/// directly, so another backend would need more than an implementation of
+/// this trait.
+pub trait Storage {
+    // transactions
+    fn begin_rust_trx(&self) -> Result<()>;
+    fn commit_rust_trx(&self) -> Result<()>;
+    fn rollback_rust_trx(&self) -> Result<()>;
+
+    // collection metadata
+    fn usn(&self, server: bool) -> Result<Usn>;
+    fn set_modified_time(&self, stamp: TimestampMillis) -> Result<()>;
+    fn get_modified_time(&self) -> Result<TimestampMillis>;
+    fn creation_stamp(&self) -> Result<TimestampSecs>;
+    fn set_creation_stamp(&self, stamp: TimestampSecs) -> Result<()>;
+
+    // cards
+    fn get_card(&self, cid: CardID) -> Result<Option<Card>>;
+    fn add_cards(&self, cards: &mut [Card]) -> Result<()>;
+    fn update_cards(&self, cards: &[Card]) -> Result<()>;
+    fn remove_card(&self, cid: CardID) -> Result<()>;
+    fn all_cards_of_note(&self, nid: NoteID) -> Result<Vec<Card>>;
+    fn all_cards_in_decks(&self, dids: &[DeckID]) -> Result<Vec<Card>>;
+    fn all_learning_cards(&self) -> Result<Vec<Card>>;
+    fn all_filtered_cards_by_deck(&self) -> Result<Vec<(CardID, DeckID)>>;
+    fn cards_in_queue_for_decks(
+        &self,
+        dids: &[DeckID],
+        queue: CardQueue,
+        due_before: i64,
+        limit: u32,
+    ) -> Result<Vec<Card>>;
+    fn buried_cards(&self, mode: UnburyMode, dids: Option<&[DeckID]>) -> Result<Vec<Card>>;
+    fn max_new_card_position(&self) -> Result<u32>;
+    fn new_cards_positioned_from(&self, position: u32) -> Result<Vec<Card>>;
+    fn review_counts_by_day(&self, today: u32) -> Result<HashMap<i32, u32>>;
+
+    // notes; callers must call note.prepare_for_update() before saving
+    fn get_note(&self, nid: NoteID) -> Result<Option<Note>>;
+    fn add_notes(&self, notes: &mut [Note]) -> Result<()>;
+    fn update_notes(&self, notes: &[Note]) -> Result<()>;
+    fn remove_note(&self, nid: NoteID) -> Result<()>;
+
+    // review history
+    fn add_revlog_entry(&self, entry: &RevlogEntry) -> Result<()>;
+    fn get_revlog_entries_for_card(&self, cid: CardID) -> Result<Vec<RevlogEntry>>;
+    fn remove_revlog_entry(&self, id: TimestampMillis) -> Result<()>;
+    fn next_revlog_id(&self, now: TimestampMillis) -> Result<TimestampMillis>;
+    fn upgrade_revlog_to_v2(&self) -> Result<()>;
+    fn average_answer_secs(&self, kind: RevlogReviewKind) -> Result<Option<f64>>;
+
+    // decks and notetypes
+    fn get_deck(&self, did: DeckID) -> Result<Option<Deck>>;
+    fn get_deck_id(&self, machine_name: &str) -> Result<Option<DeckID>>;
+    fn get_all_decks(&self) -> Result<Vec<Deck>>;
+    fn child_decks(&self, parent: &Deck) -> Result<Vec<Deck>>;
+    fn get_notetype(&self, ntid: NoteTypeID) -> Result<Option<NoteType>>;
+}
+
+impl Storage for SqliteStorage {
+    fn begin_rust_trx(&self) -> Result<()> {
+        SqliteStorage::begin_rust_trx(self)
+    }
+
+    fn commit_rust_trx(&self) -> Result<()> {
+        SqliteStorage::commit_rust_trx(self)
+    }
+
+    fn rollback_rust_trx(&self) -> Result<()> {
+        SqliteStorage::rollback_rust_trx(self)
+    }
+
+    fn usn(&self, server: bool) -> Result<Usn> {
+        SqliteStorage::usn(self, server)
+    }
+
+    fn set_modified_time(&self, stamp: TimestampMillis) -> Result<()> {
+        SqliteStorage::set_modified_time(self, stamp)
+    }
+
+    fn get_modified_time(&self) -> Result<TimestampMillis> {
+        SqliteStorage::get_modified_time(self)
+    }
+
+    fn creation_stamp(&self) -> Result<TimestampSecs> {
+        SqliteStorage::creation_stamp(self)
+    }
+
+    fn set_creation_stamp(&self, stamp: TimestampSecs) -> Result<()> {
+        SqliteStorage::set_creation_stamp(self, stamp)
+    }
+
+    fn get_card(&self, cid: CardID) -> Result<Option<Card>> {
+        SqliteStorage::get_card(self, cid)
+    }
+
+    fn add_cards(&self, cards: &mut [Card]) -> Result<()> {
+        SqliteStorage::add_cards(self, cards)
+    }
+
+    fn update_cards(&self, cards: &[Card]) -> Result<()> {
+        SqliteStorage::update_cards(self, cards)
+    }
+
+    fn remove_card(&self, cid: CardID) -> Result<()> {
+        SqliteStorage::remove_card(self, cid)
+    }
+
+    fn all_cards_of_note(&self, nid: NoteID) -> Result<Vec<Card>> {
+        SqliteStorage::all_cards_of_note(self, nid)
+    }
+
+    fn all_cards_in_decks(&self, dids: &[DeckID]) -> Result<Vec<Card>> {
+        SqliteStorage::all_cards_in_decks(self, dids)
+    }
+
+    fn all_learning_cards(&self) -> Result<Vec<Card>> {
+        SqliteStorage::all_learning_cards(self)
+    }
+
+    fn all_filtered_cards_by_deck(&self) -> Result<Vec<(CardID, DeckID)>> {
+        SqliteStorage::all_filtered_cards_by_deck(self)
+    }
+
+    fn cards_in_queue_for_decks(
+        &self,
+        dids: &[DeckID],
+        queue: CardQueue,
+        due_before: i64,
+        limit: u32,
+    ) -> Result<Vec<Card>> {
+        SqliteStorage::cards_in_queue_for_decks(self, dids, queue, due_before, limit)
+    }
+
+    fn buried_cards(&self, mode: UnburyMode, dids: Option<&[DeckID]>) -> Result<Vec<Card>> {
+        SqliteStorage::buried_cards(self, mode, dids)
+    }
+
+    fn max_new_card_position(&self) -> Result<u32> {
+        SqliteStorage::max_new_card_position(self)
+    }
+
+    fn new_cards_positioned_from(&self, position: u32) -> Result<Vec<Card>> {
+        SqliteStorage::new_cards_positioned_from(self, position)
+    }
+
+    fn review_counts_by_day(&self, today: u32) -> Result<HashMap<i32, u32>> {
+        SqliteStorage::review_counts_by_day(self, today)
+    }
+
+    fn get_note(&self, nid: NoteID) -> Result<Option<Note>> {
+        SqliteStorage::get_note(self, nid)
+    }
+
+    fn add_notes(&self, notes: &mut [Note]) -> Result<()> {
+        SqliteStorage::add_notes(self, notes)
+    }
+
+    fn update_notes(&self, notes: &[Note]) -> Result<()> {
+        SqliteStorage::update_notes(self, notes)
+    }
+
+    fn remove_note(&self, nid: NoteID) -> Result<()> {
+        SqliteStorage::remove_note(self, nid)
+    }
+
+    fn add_revlog_entry(&self, entry: &RevlogEntry) -> Result<()> {
+        SqliteStorage::add_revlog_entry(self, entry)
+    }
+
+    fn get_revlog_entries_for_card(&self, cid: CardID) -> Result<Vec<RevlogEntry>> {
+        SqliteStorage::get_revlog_entries_for_card(self, cid)
+    }
+
+    fn remove_revlog_entry(&self, id: TimestampMillis) -> Result<()> {
+        SqliteStorage::remove_revlog_entry(self, id)
+    }
+
+    fn next_revlog_id(&self, now: TimestampMillis) -> Result<TimestampMillis> {
+        SqliteStorage::next_revlog_id(self, now)
+    }
+
+    fn upgrade_revlog_to_v2(&self) -> Result<()> {
+        SqliteStorage::upgrade_revlog_to_v2(self)
+    }
+
+    fn average_answer_secs(&self, kind: RevlogReviewKind) -> Result<Option<f64>> {
+        SqliteStorage::average_answer_secs(self, kind)
+    }
+
+    fn get_deck(&self, did: DeckID) -> Result<Option<Deck>> {
+        SqliteStorage::get_deck(self, did)
+    }
+
+    fn get_deck_id(&self, machine_name: &str) -> Result<Option<DeckID>> {
+        SqliteStorage::get_deck_id(self, machine_name)
+    }
+
+    fn get_all_decks(&self) -> Result<Vec<Deck>> {
+        SqliteStorage::get_all_decks(self)
+    }
+
+    fn child_decks(&self, parent: &Deck) -> Result<Vec<Deck>> {
+        SqliteStorage::child_decks(self, parent)
+    }
+
+    fn get_notetype(&self, ntid: NoteTypeID) -> Result<Option<NoteType>> {
+        SqliteStorage::get_notetype(self, ntid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn sqlite_storage() -> Result<()> {
+        let col = open_test_collection();
+        let storage: &dyn Storage = &col.storage;
+        assert!(storage.get_deck(DeckID(1))?.is_some());
+
+        storage.begin_rust_trx()?;
+        let mut cards = vec![Card::default()];
+        storage.add_cards(&mut cards)?;
+        cards[0].due = 5;
+        storage.update_cards(&cards)?;
+        assert_eq!(storage.get_card(cards[0].id)?.unwrap().due, 5);
+        storage.rollback_rust_trx()?;
+        assert!(storage.get_card(cards[0].id)?.is_none());
+
+        Ok(())
+    }
+}
@@ -2,40 +2,179 @@
 // License: GNU AGPL, version 3 or later; http://www.gnu.org/licenses/agpl.html
 
 use super::SqliteStorage;
-use crate::err::Result;
+use crate::err::{AnkiError, DBErrorKind, Result};
+use rusqlite::params;
+
+/// The oldest schema that can be opened, and the one collections are
+/// created in before being upgraded.
+pub(super) const SCHEMA_MIN_VERSION: u8 = 11;
+pub(super) const SCHEMA_MAX_VERSION: u8 = 15;
+
+/// A change to the database schema.
+struct SchemaUpgrade {
+    /// the schema version after the change has been applied
+    version: u8,
+    /// the second argument is true if running on a sync server
+    upgrade: fn(&SqliteStorage, bool) -> Result<()>,
+    /// returns the schema to the previous version
+    downgrade: fn(&SqliteStorage) -> Result<()>,
+}
+
+/// Applied in order; the last one must match SCHEMA_MAX_VERSION.
+const UPGRADES: &[SchemaUpgrade] = &[
+    SchemaUpgrade {
+        version: 14,
+        upgrade: SqliteStorage::upgrade_to_schema14,
+        downgrade: SqliteStorage::downgrade_from_schema14,
+    },
+    SchemaUpgrade {
+        version: 15,
+        upgrade: SqliteStorage::upgrade_to_schema15,
+        downgrade: SqliteStorage::downgrade_from_schema15,
+    },
+];
+
+/// Returns an error if a collection with the provided schema version can't
+/// be opened.
+pub(super) fn check_schema_version(ver: u8) -> Result<()> {
+    let kind = match ver {
+        v if v < SCHEMA_MIN_VERSION => DBErrorKind::FileTooOld,
+        v if v > SCHEMA_MAX_VERSION => DBErrorKind::FileTooNew,
+        12 | 13 => {
+            // as schema definition changed, user must perform clean
+            // shutdown to return to schema 11 prior to running this version
+            DBErrorKind::FileTooNew
+        }
+        _ => return Ok(()),
+    };
+    Err(AnkiError::DBError {
+        info: "".to_string(),
+        kind,
+    })
+}
 
 impl SqliteStorage {
+    /// Apply each upgrade newer than `ver`, recording the new version after
+    /// each one. Caller must create transaction.
     pub(super) fn upgrade_to_latest_schema(&self, ver: u8, server: bool) -> Result<()> {
-        if ver < 14 {
-            self.db
-                .execute_batch(include_str!("schema14_upgrade.sql"))?;
-            self.upgrade_deck_conf_to_schema14()?;
-            self.upgrade_tags_to_schema14()?;
-            self.upgrade_config_to_schema14()?;
+        for step in UPGRADES.iter().filter(|step| step.version > ver) {
+            (step.upgrade)(self, server)?;
+            self.set_schema_version(step.version)?;
+        }
+        Ok(())
+    }
+
+    /// Revert upgrades newer than `target`, so that older clients can open
+    /// the collection.
+    pub(super) fn downgrade_to_schema(&self, target: u8) -> Result<()> {
+        self.begin_trx()?;
+
+        let ver = self.schema_version()?;
+        for step in UPGRADES
+            .iter()
+            .rev()
+            .filter(|step| step.version > target && step.version <= ver)
+        {
+            (step.downgrade)(self)?;
         }
-        if ver < 15 {
-            self.db
-                .execute_batch(include_str!("schema15_upgrade.sql"))?;
-            self.upgrade_notetypes_to_schema15()?;
-            self.upgrade_decks_to_schema15(server)?;
-            self.upgrade_deck_conf_to_schema15()?;
+        if ver > target {
+            self.set_schema_version(target)?;
         }
 
+        self.commit_trx()?;
+
         Ok(())
     }
 
-    pub(super) fn downgrade_to_schema_11(&self) -> Result<()> {
-        self.begin_trx()?;
+    fn schema_version(&self) -> Result<u8> {
+        self.db
+            .query_row("select ver from col", params![], |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    fn set_schema_version(&self, ver: u8) -> Result<()> {
+        self.db.execute("update col set ver = ?", params![ver])?;
+        Ok(())
+    }
+
+    fn upgrade_to_schema14(&self, _server: bool) -> Result<()> {
+        self.db
+            .execute_batch(include_str!("schema14_upgrade.sql"))?;
+        self.upgrade_deck_conf_to_schema14()?;
+        self.upgrade_tags_to_schema14()?;
+        self.upgrade_config_to_schema14()
+    }
+
+    fn downgrade_from_schema14(&self) -> Result<()> {
+        self.downgrade_deck_conf_from_schema14()?;
+        self.downgrade_config_from_schema14()?;
+        self.downgrade_tags_from_schema14()?;
+        self.db
+            .execute_batch(include_str!("schema14_downgrade.sql"))?;
+        Ok(())
+    }
+
+    fn upgrade_to_schema15(&self, server: bool) -> Result<()> {
+        self.db
+            .execute_batch(include_str!("schema15_upgrade.sql"))?;
+        self.upgrade_notetypes_to_schema15()?;
+        self.upgrade_decks_to_schema15(server)?;
+        self.upgrade_deck_conf_to_schema15()
+    }
 
+    fn downgrade_from_schema15(&self) -> Result<()> {
         self.downgrade_deck_conf_from_schema15()?;
         self.downgrade_decks_from_schema15()?;
         self.downgrade_notetypes_from_schema15()?;
-        self.downgrade_config_from_schema14()?;
-        self.downgrade_tags_from_schema14()?;
+        self.drop_full_text_index()?;
         self.db
-            .execute_batch(include_str!("schema11_downgrade.sql"))?;
+            .execute_batch(include_str!("schema15_downgrade.sql"))?;
+        Ok(())
+    }
+}
 
-        self.commit_trx()?;
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::collection::open_test_collection;
+
+    #[test]
+    fn upgrades_are_ordered() {
+        let versions: Vec<_> = UPGRADES.iter().map(|step| step.version).collect();
+        let mut sorted = versions.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(versions, sorted);
+        assert_eq!(*versions.last().unwrap(), SCHEMA_MAX_VERSION);
+    }
+
+    #[test]
+    fn downgrade_and_upgrade() -> Result<()> {
+        let col = open_test_collection();
+        let storage = &col.storage;
+        let notetypes = storage.get_all_notetype_names()?;
+        let config_names = |storage: &SqliteStorage| -> Result<Vec<String>> {
+            Ok(storage
+                .all_deck_config()?
+                .into_iter()
+                .map(|conf| conf.name)
+                .collect())
+        };
+        let config = config_names(storage)?;
+
+        storage.downgrade_to_schema(14)?;
+        assert_eq!(storage.schema_version()?, 14);
+        storage.downgrade_to_schema(SCHEMA_MIN_VERSION)?;
+        assert_eq!(storage.schema_version()?, SCHEMA_MIN_VERSION);
+        assert!(check_schema_version(SCHEMA_MIN_VERSION).is_ok());
+        assert!(check_schema_version(13).is_err());
+
+        storage.begin_trx()?;
+        storage.upgrade_to_latest_schema(SCHEMA_MIN_VERSION, false)?;
+        storage.commit_trx()?;
+        assert_eq!(storage.schema_version()?, SCHEMA_MAX_VERSION);
+        assert_eq!(storage.get_all_notetype_names()?, notetypes);
+        assert_eq!(config_names(storage)?, config);
 
         Ok(())
     }
@@ -0,0 +1,3 @@
+drop table config;
+drop table deck_config;
+drop table tags;
@@ -39,7 +39,4 @@ create index idx_notes_mid on notes (mid);
 create index idx_cards_odid on cards (odid)
 where
   odid != 0;
-update col
-set
-  ver = 15;
 analyze;
\ No newline at end of file
@@ -87,6 +87,28 @@ impl Collection {
         Ok(changed)
     }
 
+    /// Remove tags no note uses from the tag list, and add any tags notes
+    /// use that are missing from it. Returns the number of tags removed.
+    pub fn clear_unused_tags(&mut self) -> Result<usize> {
+        self.transact(None, |col| {
+            let usn = col.usn()?;
+            let mut in_use = HashSet::new();
+            for tags in col.storage.all_note_tag_strings()? {
+                for tag in split_tags(&tags) {
+                    in_use.insert(UniCase::new(col.register_tag(tag, usn)?.into_owned()));
+                }
+            }
+            let mut removed = 0;
+            for (tag, _) in col.storage.all_tags()? {
+                if !in_use.contains(&UniCase::new(tag.clone())) {
+                    col.storage.remove_tag(&tag)?;
+                    removed += 1;
+                }
+            }
+            Ok(removed)
+        })
+    }
+
     fn replace_tags_for_notes_inner<R: Replacer>(
         &mut self,
         nids: &[NoteID],
@@ -215,6 +237,18 @@ mod test {
         col.update_note(&mut note)?;
         assert_eq!(&note.tags, &["one", "two"]);
 
+        // tags no longer on any note can be cleared
+        col.storage.register_tag("Unused", Usn(0))?;
+        assert_eq!(col.clear_unused_tags()?, 3);
+        let mut tags: Vec<_> = col
+            .storage
+            .all_tags()?
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect();
+        tags.sort();
+        assert_eq!(tags, &["one", "two"]);
+
         Ok(())
     }
 
@@ -243,6 +243,29 @@ pub(crate) fn matches_wildcard(text: &str, search: &str) -> bool {
     }
 }
 
+/// Fold the case of the provided string, so it can be compared regardless
+/// of case. Unlike to_lowercase(), characters like ß that fold to more than
+/// one character are handled, so "straße" and "STRASSE" fold to the same
+/// text.
+pub(crate) fn fold_case(s: &str) -> Cow<str> {
+    if s.is_ascii() {
+        if s.bytes().any(|b| b.is_ascii_uppercase()) {
+            s.to_ascii_lowercase().into()
+        } else {
+            s.into()
+        }
+    } else {
+        // uppercasing expands characters like ß and ﬁ, which is done after
+        // lowercasing so that ẞ is expanded too. The final sigma is folded
+        // to the regular one, as to_lowercase() depends on its position.
+        s.to_lowercase()
+            .to_uppercase()
+            .to_lowercase()
+            .replace('ς', "σ")
+            .into()
+    }
+}
+
 /// Convert provided string to NFKD form and strip combining characters.
 pub(crate) fn without_combining(s: &str) -> Cow<str> {
     // if the string is already normalized
@@ -264,10 +287,10 @@ pub(crate) fn without_combining(s: &str) -> Cow<str> {
 #[cfg(test)]
 mod test {
     use super::matches_wildcard;
-    use crate::text::without_combining;
     use crate::text::{
         extract_av_tags, strip_av_tags, strip_html, strip_html_preserving_image_filenames, AVTag,
     };
+    use crate::text::{fold_case, without_combining};
     use std::borrow::Cow;
 
     #[test]
@@ -325,4 +348,13 @@ mod test {
         assert!(matches!(without_combining("test"), Cow::Borrowed(_)));
         assert!(matches!(without_combining("Über"), Cow::Owned(_)));
     }
+
+    #[test]
+    fn case_folding() {
+        assert!(matches!(fold_case("test"), Cow::Borrowed(_)));
+        assert_eq!(fold_case("TeSt"), "test");
+        assert_eq!(fold_case("straße"), fold_case("STRASSE"));
+        assert_eq!(fold_case("STRAẞE"), "strasse");
+        assert_eq!(fold_case("ΣΟΦΟΣ"), fold_case("σοφος"));
+    }
 }
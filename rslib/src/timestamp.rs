@@ -19,6 +19,16 @@ impl TimestampSecs {
         (Self::now().0 - self.0).max(0) as u64
     }
 
+    /// For comparing with millisecond timestamps, such as card and revlog
+    /// ids.
+    pub fn as_millis(self) -> TimestampMillis {
+        TimestampMillis(self.0 * 1000)
+    }
+
+    pub fn adding_secs(self, secs: i64) -> Self {
+        TimestampSecs(self.0 + secs)
+    }
+
     /// YYYY-mm-dd
     pub(crate) fn date_string(self, offset: FixedOffset) -> String {
         offset.timestamp(self.0, 0).format("%Y-%m-%d").to_string()
@@ -57,3 +67,18 @@ fn elapsed() -> time::Duration {
             .unwrap()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let secs = TimestampSecs(1_600_000_000);
+        assert_eq!(secs.as_millis(), TimestampMillis(1_600_000_000_000));
+        assert_eq!(secs.as_millis().as_secs(), secs);
+        // partial seconds are dropped
+        assert_eq!(TimestampMillis(1_600_000_000_999).as_secs(), secs);
+        assert_eq!(secs.adding_secs(-86_400), TimestampSecs(1_599_913_600));
+    }
+}
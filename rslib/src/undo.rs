@@ -7,6 +7,10 @@ use crate::{
 };
 use std::fmt;
 
+/// The number of steps kept for undoing, and of answers kept for
+/// undo_review().
+pub(crate) const UNDO_LIMIT: usize = 30;
+
 pub(crate) trait Undoable: fmt::Debug + Send {
     fn apply(&self, ctx: &mut Collection) -> Result<()>;
 }
@@ -62,10 +66,14 @@ impl UndoManager {
 
     pub(crate) fn end_step(&mut self) {
         if let Some(step) = self.current_step.take() {
-            if self.mode == UndoMode::Undoing {
-                self.redo_steps.push(step);
+            let steps = if self.mode == UndoMode::Undoing {
+                &mut self.redo_steps
             } else {
-                self.undo_steps.push(step);
+                &mut self.undo_steps
+            };
+            steps.push(step);
+            if steps.len() > UNDO_LIMIT {
+                steps.remove(0);
             }
         }
     }
@@ -74,6 +82,22 @@ impl UndoManager {
         self.begin_step(None)
     }
 
+    /// The number of changes recorded in the current step.
+    pub(crate) fn current_step_len(&self) -> usize {
+        self.current_step
+            .as_ref()
+            .map(|step| step.changes.len())
+            .unwrap_or_default()
+    }
+
+    /// Drop the changes recorded after the step had the given length, as
+    /// they have been rolled back.
+    pub(crate) fn truncate_current_step(&mut self, len: usize) {
+        if let Some(step) = self.current_step.as_mut() {
+            step.changes.truncate(len);
+        }
+    }
+
     fn can_undo(&self) -> Option<CollectionOp> {
         self.undo_steps.last().map(|s| s.kind.clone())
     }
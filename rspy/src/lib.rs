@@ -42,8 +42,24 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::StripAVTags => false,
             BackendMethod::SearchCards => true,
             BackendMethod::SearchNotes => true,
+            BackendMethod::SearchNotesWithMatches => true,
+            BackendMethod::CountCards => true,
+            BackendMethod::CountNotes => true,
+            BackendMethod::SearchSql => false,
+            BackendMethod::SetFullTextSearch => true,
+            BackendMethod::NormalizeSearch => false,
+            BackendMethod::ConcatenateSearches => false,
+            BackendMethod::ReplaceSearchNode => false,
+            BackendMethod::GetSavedSearches => true,
+            BackendMethod::SetSavedSearch => true,
+            BackendMethod::RenameSavedSearch => true,
+            BackendMethod::RemoveSavedSearch => true,
+            BackendMethod::FindDupes => true,
+            BackendMethod::GetSearchHistory => true,
+            BackendMethod::ClearSearchHistory => true,
             BackendMethod::LocalMinutesWest => false,
             BackendMethod::SchedTimingToday => false,
+            BackendMethod::DayRolledOver => false,
             BackendMethod::CheckMedia => true,
             BackendMethod::SyncMedia => true,
             BackendMethod::TrashMediaFiles => true,
@@ -61,6 +77,8 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::GetDeckConfigLegacy => true,
             BackendMethod::NewDeckConfigLegacy => false,
             BackendMethod::RemoveDeckConfig => true,
+            BackendMethod::ParseLearningSteps => false,
+            BackendMethod::FormatLearningSteps => false,
             BackendMethod::GetCard => true,
             BackendMethod::UpdateCard => true,
             BackendMethod::AddCard => true,
@@ -81,6 +99,9 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::GetNotetypeIDByName => true,
             BackendMethod::RemoveNotetype => true,
             BackendMethod::CheckDatabase => true,
+            BackendMethod::OptimizeDatabase => true,
+            BackendMethod::AttachDatabase => true,
+            BackendMethod::DetachDatabase => true,
             BackendMethod::FindAndReplace => true,
             BackendMethod::SetLocalMinutesWest => false,
             BackendMethod::StudiedToday => false,
@@ -90,6 +111,10 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::RestoreTrash => true,
             BackendMethod::OpenCollection => true,
             BackendMethod::CloseCollection => true,
+            BackendMethod::BackupCollection => true,
+            BackendMethod::ListBackups => true,
+            BackendMethod::RestoreBackup => true,
+            BackendMethod::RecoverCollection => true,
             BackendMethod::AbortSync => true,
             BackendMethod::AbortMediaSync => true,
             BackendMethod::BeforeUpload => true,
@@ -97,6 +122,7 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::FormatTimespan => false,
             BackendMethod::RegisterTags => true,
             BackendMethod::AllTags => true,
+            BackendMethod::ClearUnusedTags => true,
             BackendMethod::GetConfigJson => true,
             BackendMethod::SetConfigJson => true,
             BackendMethod::RemoveConfig => true,
@@ -109,6 +135,7 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::SyncCollection => true,
             BackendMethod::LatestProgress => false,
             BackendMethod::SetWantsAbort => false,
+            BackendMethod::AbortSearch => false,
             BackendMethod::SyncStatus => true,
             BackendMethod::FullUpload => true,
             BackendMethod::FullDownload => true,
@@ -117,6 +144,26 @@ fn want_release_gil(method: u32) -> bool {
             BackendMethod::UpdateStats => true,
             BackendMethod::ExtendLimits => true,
             BackendMethod::CountsForDeckToday => true,
+            BackendMethod::AnswerCard => true,
+            BackendMethod::GetQueuedCards => true,
+            BackendMethod::SetDueDate => true,
+            BackendMethod::ForgetCards => true,
+            BackendMethod::ShiftDueDates => true,
+            BackendMethod::RepositionNewCards => true,
+            BackendMethod::BuryCards => true,
+            BackendMethod::UnburyCardsInCurrentDeck => true,
+            BackendMethod::UnburyOnDayRollover => true,
+            BackendMethod::SuspendCards => true,
+            BackendMethod::UnsuspendCards => true,
+            BackendMethod::RebuildFilteredDeck => true,
+            BackendMethod::EmptyFilteredDeck => true,
+            BackendMethod::CustomStudy => true,
+            BackendMethod::AnswerButtonLabels => true,
+            BackendMethod::GetSchedulingStates => true,
+            BackendMethod::UndoReview => true,
+            BackendMethod::UpgradeScheduler => true,
+            BackendMethod::SimulateReviews => true,
+            BackendMethod::ReconstructCardsFromRevlog => true,
             BackendMethod::CardStats => true,
             BackendMethod::Graphs => true,
             BackendMethod::I18nResources => false,